@@ -1,6 +1,5 @@
 use anyhow::Result;
 
-#[cfg(feature = "cgroupsv2_devices")]
 mod bpf {
     use std::os::unix::io::AsRawFd;
     use std::path::Path;
@@ -81,7 +80,7 @@ mod bpf {
                 let mut emulator = emulator::Emulator::with_default_allow(false);
                 emulator.add_rules(&rules);
                 let prog = program::Program::from_rules(&emulator.rules, emulator.default_allow)?;
-                let prog_fd = bpf::prog::load(LICENSE, prog.bytecodes())?;
+                let prog_fd = bpf::prog::load(LICENSE, &prog.bytecodes())?;
                 bpf::prog::attach(prog_fd, cgroup_fd.as_raw_fd())?;
                 println!("attach ok");
             }
@@ -92,19 +91,6 @@ mod bpf {
     }
 }
 
-#[cfg(not(feature = "cgroupsv2_devices"))]
-mod bpf {
-    use anyhow::{Result, bail};
-
-    pub fn run() -> Result<()> {
-        if !cfg!(feature = "cgroupsv2_devices") {
-            bail!("cgroupsv2_devices feature is not enabled");
-        }
-
-        unreachable!()
-    }
-}
-
 fn main() -> Result<()> {
     env_logger::init();
     bpf::run()?;
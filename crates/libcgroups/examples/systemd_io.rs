@@ -12,6 +12,8 @@ fn main() -> Result<()> {
         cgroup_path: PathBuf::from("system.slice:youki:test"),
         systemd_cgroup: true,
         container_name: "test".to_owned(),
+        cgroup_root: None,
+        rootless: false,
     };
     let manager = create_cgroup_manager(cfg)?;
     let mem_limit = 256 * 1024 * 1024;
@@ -7,7 +7,7 @@ use std::time::Duration;
 use nix::sys::statfs::{CGROUP2_SUPER_MAGIC, TMPFS_MAGIC, statfs};
 use nix::unistd::Pid;
 use oci_spec::runtime::LinuxResources;
-#[cfg(any(feature = "cgroupsv2_devices", feature = "v1"))]
+#[cfg(any(feature = "v2", feature = "v1"))]
 use oci_spec::runtime::{
     LinuxDevice, LinuxDeviceBuilder, LinuxDeviceCgroup, LinuxDeviceCgroupBuilder, LinuxDeviceType,
 };
@@ -272,8 +272,8 @@ pub fn read_cgroup_file<P: AsRef<Path>>(path: P) -> Result<String, WrappedIoErro
 pub enum GetCgroupSetupError {
     #[error("io error: {0}")]
     WrappedIo(#[from] WrappedIoError),
-    #[error("non default cgroup root not supported")]
-    NonDefault,
+    #[error("cgroup root {0} does not exist")]
+    RootNotFound(PathBuf),
     #[error("failed to detect cgroup setup")]
     FailedToDetect,
 }
@@ -314,7 +314,7 @@ pub fn get_cgroup_setup_with_root(root_path: &Path) -> Result<CgroupSetup, GetCg
                 return Ok(CgroupSetup::Legacy);
             }
         }
-        false => return Err(GetCgroupSetupError::NonDefault),
+        false => return Err(GetCgroupSetupError::RootNotFound(root_path.to_path_buf())),
     }
 
     Err(GetCgroupSetupError::FailedToDetect)
@@ -328,8 +328,8 @@ pub fn get_cgroup_setup() -> Result<CgroupSetup, GetCgroupSetupError> {
 pub enum CreateCgroupSetupError {
     #[error("io error: {0}")]
     WrappedIo(#[from] WrappedIoError),
-    #[error("non default cgroup root not supported")]
-    NonDefault,
+    #[error("cgroup root {0} does not exist")]
+    RootNotFound(PathBuf),
     #[error("failed to detect cgroup setup")]
     FailedToDetect,
     #[error("v1 error: {0}")]
@@ -345,39 +345,50 @@ pub struct CgroupConfig {
     pub cgroup_path: PathBuf,
     pub systemd_cgroup: bool,
     pub container_name: String,
+    /// Mount point of the cgroup hierarchy. If None, it defaults to /sys/fs/cgroup.
+    pub cgroup_root: Option<PathBuf>,
+    /// The container runs rootless. Limits of controllers which have not been
+    /// delegated to the user are not enforced, and the systemd user instance
+    /// is used instead of the system instance.
+    pub rootless: bool,
 }
 
-// Create any cgroup manager with customize root path. If root_path provided
-// is None, then it defaults to /sys/fs/cgroup.
+// Create any cgroup manager with customize root path. The root path takes
+// precedence over the one carried by the config. If neither is provided, it
+// defaults to /sys/fs/cgroup.
 pub fn create_cgroup_manager_with_root(
     root_path: Option<&Path>,
     config: CgroupConfig,
 ) -> Result<AnyCgroupManager, CreateCgroupSetupError> {
-    let root = match root_path {
-        Some(p) => p,
-        None => Path::new(DEFAULT_CGROUP_ROOT),
-    };
+    let root = root_path
+        .or(config.cgroup_root.as_deref())
+        .unwrap_or(Path::new(DEFAULT_CGROUP_ROOT));
 
     let cgroup_setup = get_cgroup_setup_with_root(root).map_err(|err| match err {
         GetCgroupSetupError::WrappedIo(err) => CreateCgroupSetupError::WrappedIo(err),
-        GetCgroupSetupError::NonDefault => CreateCgroupSetupError::NonDefault,
+        GetCgroupSetupError::RootNotFound(path) => CreateCgroupSetupError::RootNotFound(path),
         GetCgroupSetupError::FailedToDetect => CreateCgroupSetupError::FailedToDetect,
     })?;
     let cgroup_path = config.cgroup_path.as_path();
 
     match cgroup_setup {
         CgroupSetup::Legacy | CgroupSetup::Hybrid => {
-            Ok(create_v1_cgroup_manager(cgroup_path)?.any())
+            Ok(create_v1_cgroup_manager(root, cgroup_path)?.any())
         }
         CgroupSetup::Unified => {
             // ref https://github.com/opencontainers/runtime-spec/blob/main/config-linux.md#cgroups-path
             if cgroup_path.is_absolute() || !config.systemd_cgroup {
-                return Ok(create_v2_cgroup_manager(root, cgroup_path)?.any());
+                return Ok(create_v2_cgroup_manager(root, cgroup_path)?
+                    .any()
+                    .with_rootless(config.rootless));
             }
-            Ok(
-                create_systemd_cgroup_manager(root, cgroup_path, config.container_name.as_str())?
-                    .any(),
-            )
+            Ok(create_systemd_cgroup_manager(
+                root,
+                cgroup_path,
+                config.container_name.as_str(),
+                config.rootless,
+            )?
+            .any())
         }
     }
 }
@@ -385,19 +396,21 @@ pub fn create_cgroup_manager_with_root(
 pub fn create_cgroup_manager(
     config: CgroupConfig,
 ) -> Result<AnyCgroupManager, CreateCgroupSetupError> {
-    create_cgroup_manager_with_root(Some(Path::new(DEFAULT_CGROUP_ROOT)), config)
+    create_cgroup_manager_with_root(None, config)
 }
 
 #[cfg(feature = "v1")]
 fn create_v1_cgroup_manager(
+    root_path: &Path,
     cgroup_path: &Path,
 ) -> Result<v1::manager::Manager, v1::manager::V1ManagerError> {
     tracing::info!("cgroup manager V1 will be used");
-    v1::manager::Manager::new(cgroup_path)
+    v1::manager::Manager::with_root(root_path, cgroup_path)
 }
 
 #[cfg(not(feature = "v1"))]
 fn create_v1_cgroup_manager(
+    _root_path: &Path,
     _cgroup_path: &Path,
 ) -> Result<v1::manager::Manager, v1::manager::V1ManagerError> {
     Err(v1::manager::V1ManagerError::NotEnabled)
@@ -425,6 +438,7 @@ fn create_systemd_cgroup_manager(
     root_path: &Path,
     cgroup_path: &Path,
     container_name: &str,
+    rootless: bool,
 ) -> Result<systemd::manager::Manager, systemd::manager::SystemdManagerError> {
     use crate::systemd::manager::PROCESS_IN_CGROUP_TIMEOUT_DURATION;
 
@@ -432,7 +446,9 @@ fn create_systemd_cgroup_manager(
         return Err(systemd::manager::SystemdManagerError::SystemdNotAvailable);
     }
 
-    let use_system = is_true_root().map_err(systemd::manager::SystemdManagerError::WrappedIo)?;
+    // rootless containers are placed in the session of the user
+    let use_system =
+        !rootless && is_true_root().map_err(systemd::manager::SystemdManagerError::WrappedIo)?;
 
     tracing::info!(
         "systemd cgroup manager with system bus {} will be used",
@@ -452,6 +468,7 @@ fn create_systemd_cgroup_manager(
     _root_path: &Path,
     _cgroup_path: &Path,
     _container_name: &str,
+    _rootless: bool,
 ) -> Result<systemd::manager::Manager, systemd::manager::SystemdManagerError> {
     Err(systemd::manager::SystemdManagerError::NotEnabled)
 }
@@ -523,7 +540,7 @@ impl PathBufExt for PathBuf {
     }
 }
 
-#[cfg(any(feature = "cgroupsv2_devices", feature = "v1"))]
+#[cfg(any(feature = "v2", feature = "v1"))]
 pub(crate) fn default_allow_devices() -> Vec<LinuxDeviceCgroup> {
     vec![
         LinuxDeviceCgroupBuilder::default()
@@ -575,7 +592,7 @@ pub(crate) fn default_allow_devices() -> Vec<LinuxDeviceCgroup> {
     ]
 }
 
-#[cfg(any(feature = "cgroupsv2_devices", feature = "v1"))]
+#[cfg(any(feature = "v2", feature = "v1"))]
 pub(crate) fn default_devices() -> Vec<LinuxDevice> {
     vec![
         LinuxDeviceBuilder::default()
@@ -743,3 +760,34 @@ impl Display for MustBePowerOfTwo {
         f.write_str("page size must be in the format of 2^(integer)")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_cgroup_setup_with_missing_root() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path().join("missing");
+
+        let result = get_cgroup_setup_with_root(&root);
+        assert!(matches!(result, Err(GetCgroupSetupError::RootNotFound(path)) if path == root));
+
+        let config = CgroupConfig {
+            cgroup_path: PathBuf::from("youki"),
+            systemd_cgroup: false,
+            container_name: "test".to_owned(),
+            cgroup_root: Some(root.clone()),
+            rootless: false,
+        };
+        let result = create_cgroup_manager(config.clone());
+        assert!(matches!(result, Err(CreateCgroupSetupError::RootNotFound(path)) if path == root));
+
+        // Without an explicit root, the root of the config is used.
+        let result = create_cgroup_manager_with_root(None, config.clone());
+        assert!(matches!(result, Err(CreateCgroupSetupError::RootNotFound(path)) if path == root));
+        let other = tmp.path().join("other");
+        let result = create_cgroup_manager_with_root(Some(&other), config);
+        assert!(matches!(result, Err(CreateCgroupSetupError::RootNotFound(path)) if path == other));
+    }
+}
@@ -87,6 +87,8 @@ pub struct MemoryStats {
     pub hierarchy: bool,
     /// Various memory statistics
     pub stats: HashMap<String, u64>,
+    /// Number of processes killed by the OOM killer
+    pub oom_kill_count: u64,
     /// Pressure Stall Information
     pub psi: PSIStats,
 }
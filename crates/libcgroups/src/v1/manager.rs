@@ -85,9 +85,16 @@ pub enum V1ManagerError {
 impl Manager {
     /// Constructs a new cgroup manager with cgroups_path being relative to the root of the subsystem
     pub fn new(cgroup_path: &Path) -> Result<Self, V1ManagerError> {
+        Self::with_root(Path::new(common::DEFAULT_CGROUP_ROOT), cgroup_path)
+    }
+
+    /// Constructs a new cgroup manager for the hierarchy mounted at root_path with
+    /// cgroups_path being relative to the root of the subsystem
+    pub fn with_root(root_path: &Path, cgroup_path: &Path) -> Result<Self, V1ManagerError> {
         let mut subsystems = HashMap::new();
         for subsystem in CONTROLLERS {
-            if let Ok(subsystem_path) = Self::get_subsystem_path(cgroup_path, subsystem) {
+            if let Ok(subsystem_path) = Self::get_subsystem_path(root_path, cgroup_path, subsystem)
+            {
                 subsystems.insert(*subsystem, subsystem_path);
             } else {
                 tracing::warn!("cgroup {} not supported on this system", subsystem);
@@ -98,11 +105,12 @@ impl Manager {
     }
 
     fn get_subsystem_path(
+        root_path: &Path,
         cgroup_path: &Path,
         subsystem: &CtrlType,
     ) -> Result<PathBuf, V1ManagerError> {
         tracing::debug!("Get path for subsystem: {}", subsystem);
-        let mount_point = util::get_subsystem_mount_point(subsystem)?;
+        let mount_point = util::get_subsystem_mount_point_with_root(root_path, subsystem)?;
 
         let cgroup = ProcessCGroups::from_read(ProcfsHandle::new()?.open(
             ProcfsBase::ProcSelf,
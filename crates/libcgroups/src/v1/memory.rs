@@ -167,6 +167,7 @@ impl StatsProvider for Memory {
         let kernel_tcp = Self::get_memory_data(cgroup_path, MEMORY_KERNEL_TCP_PREFIX)?;
         let hierarchy = Self::hierarchy_enabled(cgroup_path)?;
         let stats = Self::get_stat_data(cgroup_path)?;
+        let oom_kill_count = Self::get_oom_kill_count(cgroup_path)?;
 
         Ok(MemoryStats {
             memory,
@@ -176,6 +177,7 @@ impl StatsProvider for Memory {
             cache: stats["cache"],
             hierarchy,
             stats,
+            oom_kill_count,
             ..Default::default()
         })
     }
@@ -224,6 +226,13 @@ impl Memory {
         stats::parse_flat_keyed_data(&cgroup_path.join(MEMORY_STAT))
     }
 
+    // The oom_kill counter is only reported by kernels since 4.13.
+    fn get_oom_kill_count(cgroup_path: &Path) -> Result<u64, ParseFlatKeyedDataError> {
+        let oom_control =
+            stats::parse_flat_keyed_data(&cgroup_path.join(CGROUP_MEMORY_OOM_CONTROL))?;
+        Ok(oom_control.get("oom_kill").copied().unwrap_or_default())
+    }
+
     fn get_memory_usage(cgroup_root: &Path) -> Result<u64, V1MemoryControllerError> {
         let path = cgroup_root.join(CGROUP_MEMORY_USAGE);
         let mut contents = String::new();
@@ -701,6 +710,26 @@ mod tests {
         assert!(!enabled)
     }
 
+    #[test]
+    fn test_stat_oom_kill_count() {
+        let tmp = tempfile::tempdir().unwrap();
+        let content = ["oom_kill_disable 0", "under_oom 0", "oom_kill 2"].join("\n");
+        set_fixture(tmp.path(), CGROUP_MEMORY_OOM_CONTROL, &content).unwrap();
+
+        let count = Memory::get_oom_kill_count(tmp.path()).expect("get oom kill count");
+        assert_eq!(count, 2);
+
+        // Kernels older than 4.13 do not report the counter.
+        set_fixture(
+            tmp.path(),
+            CGROUP_MEMORY_OOM_CONTROL,
+            "oom_kill_disable 0\nunder_oom 0",
+        )
+        .unwrap();
+        let count = Memory::get_oom_kill_count(tmp.path()).expect("get oom kill count");
+        assert_eq!(count, 0);
+    }
+
     #[test]
     fn test_stat_memory_stats() {
         let tmp = tempfile::tempdir().unwrap();
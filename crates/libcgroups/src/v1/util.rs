@@ -1,6 +1,7 @@
 use std::collections::HashMap;
+use std::fs;
 use std::io::{BufRead, BufReader};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use pathrs::flags::OpenFlags;
 use pathrs::procfs::{ProcfsBase, ProcfsHandle};
@@ -62,6 +63,64 @@ pub fn list_supported_mount_points() -> Result<HashMap<ControllerType, PathBuf>,
 }
 
 pub fn get_subsystem_mount_point(subsystem: &ControllerType) -> Result<PathBuf, V1MountPointError> {
+    find_subsystem_mount_point(subsystem, |_| true)
+}
+
+/// Returns the mount point of the subsystem inside the hierarchy mounted at `root`.
+///
+/// If no cgroup filesystem is mounted for the subsystem below `root`, a
+/// directory named after the subsystem is used instead. This allows the v1
+/// manager to operate on hierarchies which are not backed by a cgroup
+/// filesystem, e.g. a tmpfs based fake hierarchy.
+pub fn get_subsystem_mount_point_with_root(
+    root: &Path,
+    subsystem: &ControllerType,
+) -> Result<PathBuf, V1MountPointError> {
+    match find_subsystem_mount_point(subsystem, |mount_point| mount_point.starts_with(root)) {
+        Err(V1MountPointError::NotFound { .. }) => {}
+        result => return result,
+    }
+
+    let subsystem_name = subsystem.to_string();
+    fs::read_dir(root)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .find(|path| path.is_dir() && is_subsystem_mount_point(path, &subsystem_name))
+        .ok_or(V1MountPointError::NotFound {
+            subsystem: *subsystem,
+        })
+}
+
+/// Returns the mount point of the subsystem hierarchy that contains `cgroup_path`.
+///
+/// Falls back to the closest ancestor of `cgroup_path` that is named after the
+/// subsystem if no matching cgroup filesystem is mounted.
+pub(crate) fn get_subsystem_mount_point_for(
+    cgroup_path: &Path,
+    subsystem: &ControllerType,
+) -> Result<PathBuf, V1MountPointError> {
+    match find_subsystem_mount_point(subsystem, |mount_point| {
+        cgroup_path.starts_with(mount_point)
+    }) {
+        Err(V1MountPointError::NotFound { .. }) => {}
+        result => return result,
+    }
+
+    let subsystem_name = subsystem.to_string();
+    cgroup_path
+        .ancestors()
+        .find(|path| is_subsystem_mount_point(path, &subsystem_name))
+        .map(Path::to_path_buf)
+        .ok_or(V1MountPointError::NotFound {
+            subsystem: *subsystem,
+        })
+}
+
+/// Returns the first mount point of the subsystem for which `filter` returns true.
+pub(crate) fn find_subsystem_mount_point<F: Fn(&Path) -> bool>(
+    subsystem: &ControllerType,
+    filter: F,
+) -> Result<PathBuf, V1MountPointError> {
     let subsystem_name = subsystem.to_string();
     let reader = BufReader::new(ProcfsHandle::new()?.open(
         ProcfsBase::ProcSelf,
@@ -77,26 +136,12 @@ pub fn get_subsystem_mount_point(subsystem: &ControllerType) -> Result<PathBuf,
         })
         .find_map(|r| match r {
             Err(e) => Some(Err(e)),
-            Ok(m) if m.fs_type == "cgroup" => {
-                // Some systems mount net_prio and net_cls in the same directory
-                // other systems mount them in their own directories. This
-                // should handle both cases.
-                let ok = match subsystem_name.as_str() {
-                    "net_cls" => ["net_cls,net_prio", "net_prio,net_cls", "net_cls"]
-                        .iter()
-                        .any(|s| m.mount_point.ends_with(s)),
-                    "net_prio" => ["net_cls,net_prio", "net_prio,net_cls", "net_prio"]
-                        .iter()
-                        .any(|s| m.mount_point.ends_with(s)),
-                    "cpu" => ["cpu,cpuacct", "cpu"]
-                        .iter()
-                        .any(|s| m.mount_point.ends_with(s)),
-                    "cpuacct" => ["cpu,cpuacct", "cpuacct"]
-                        .iter()
-                        .any(|s| m.mount_point.ends_with(s)),
-                    _ => m.mount_point.ends_with(&subsystem_name),
-                };
-                if ok { Some(Ok(m.mount_point)) } else { None }
+            Ok(m)
+                if m.fs_type == "cgroup"
+                    && is_subsystem_mount_point(&m.mount_point, &subsystem_name)
+                    && filter(&m.mount_point) =>
+            {
+                Some(Ok(m.mount_point))
             }
             Ok(_) => None,
         })
@@ -105,3 +150,57 @@ pub fn get_subsystem_mount_point(subsystem: &ControllerType) -> Result<PathBuf,
             subsystem: *subsystem,
         })
 }
+
+fn is_subsystem_mount_point(mount_point: &Path, subsystem_name: &str) -> bool {
+    // Some systems mount net_prio and net_cls in the same directory
+    // other systems mount them in their own directories. This
+    // should handle both cases.
+    match subsystem_name {
+        "net_cls" => ["net_cls,net_prio", "net_prio,net_cls", "net_cls"]
+            .iter()
+            .any(|s| mount_point.ends_with(s)),
+        "net_prio" => ["net_cls,net_prio", "net_prio,net_cls", "net_prio"]
+            .iter()
+            .any(|s| mount_point.ends_with(s)),
+        "cpu" => ["cpu,cpuacct", "cpu"]
+            .iter()
+            .any(|s| mount_point.ends_with(s)),
+        "cpuacct" => ["cpu,cpuacct", "cpuacct"]
+            .iter()
+            .any(|s| mount_point.ends_with(s)),
+        _ => mount_point.ends_with(subsystem_name),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_subsystem_mount_point_with_fake_root() {
+        let tmp = tempfile::tempdir().unwrap();
+        fs::create_dir(tmp.path().join("cpu,cpuacct")).unwrap();
+        fs::create_dir(tmp.path().join("memory")).unwrap();
+
+        let cpu = get_subsystem_mount_point_with_root(tmp.path(), &ControllerType::Cpu).unwrap();
+        assert_eq!(cpu, tmp.path().join("cpu,cpuacct"));
+        let cpuacct =
+            get_subsystem_mount_point_with_root(tmp.path(), &ControllerType::CpuAcct).unwrap();
+        assert_eq!(cpuacct, tmp.path().join("cpu,cpuacct"));
+        let memory =
+            get_subsystem_mount_point_with_root(tmp.path(), &ControllerType::Memory).unwrap();
+        assert_eq!(memory, tmp.path().join("memory"));
+
+        let cgroup_path = tmp.path().join("memory").join("youki").join("container");
+        let memory = get_subsystem_mount_point_for(&cgroup_path, &ControllerType::Memory).unwrap();
+        assert_eq!(memory, tmp.path().join("memory"));
+
+        let result = get_subsystem_mount_point_with_root(tmp.path(), &ControllerType::Pids);
+        assert!(matches!(
+            result,
+            Err(V1MountPointError::NotFound {
+                subsystem: ControllerType::Pids
+            })
+        ));
+    }
+}
@@ -10,116 +10,196 @@ pub enum BpfError {
     Errno(#[from] errno::Errno),
     #[error("Failed to increase rlimit")]
     FailedToIncreaseRLimit,
+    #[error("license contains a nul byte")]
+    InvalidLicense,
+}
+
+/// Minimal bindings for the bpf(2) syscall, covering the commands needed to
+/// manage BPF_PROG_TYPE_CGROUP_DEVICE programs.
+/// https://elixir.bootlin.com/linux/v5.3.6/source/include/uapi/linux/bpf.h
+pub mod sys {
+    pub const BPF_PROG_LOAD: u32 = 5;
+    pub const BPF_PROG_ATTACH: u32 = 8;
+    pub const BPF_PROG_DETACH: u32 = 9;
+    pub const BPF_PROG_GET_FD_BY_ID: u32 = 13;
+    pub const BPF_PROG_QUERY: u32 = 16;
+
+    pub const BPF_PROG_TYPE_CGROUP_DEVICE: u32 = 15;
+    pub const BPF_CGROUP_DEVICE: u32 = 6;
+    pub const BPF_F_ALLOW_MULTI: u32 = 2;
+
+    /// Attributes of BPF_PROG_LOAD
+    #[repr(C)]
+    #[derive(Debug, Default)]
+    pub struct ProgLoadAttr {
+        pub prog_type: u32,
+        pub insn_cnt: u32,
+        pub insns: u64,
+        pub license: u64,
+        pub log_level: u32,
+        pub log_size: u32,
+        pub log_buf: u64,
+        pub kern_version: u32,
+        pub prog_flags: u32,
+    }
+
+    /// Attributes of BPF_PROG_ATTACH and BPF_PROG_DETACH
+    #[repr(C)]
+    #[derive(Debug, Default)]
+    pub struct ProgAttachAttr {
+        pub target_fd: u32,
+        pub attach_bpf_fd: u32,
+        pub attach_type: u32,
+        pub attach_flags: u32,
+        pub replace_bpf_fd: u32,
+    }
+
+    /// Attributes of BPF_PROG_QUERY
+    #[repr(C)]
+    #[derive(Debug, Default)]
+    pub struct ProgQueryAttr {
+        pub target_fd: u32,
+        pub attach_type: u32,
+        pub query_flags: u32,
+        pub attach_flags: u32,
+        pub prog_ids: u64,
+        pub prog_cnt: u32,
+    }
+
+    /// Attributes of BPF_PROG_GET_FD_BY_ID
+    #[repr(C)]
+    #[derive(Debug, Default)]
+    pub struct GetFdByIdAttr {
+        pub prog_id: u32,
+        pub next_id: u32,
+        pub open_flags: u32,
+    }
+
+    /// Invokes bpf(2) with `attr` pointing to a struct of `size` bytes.
+    ///
+    /// # Safety
+    ///
+    /// `attr` must point to a valid attribute struct for `cmd`, and any
+    /// pointers stored in it must be valid for the duration of the call.
+    pub unsafe fn bpf(cmd: u32, attr: *mut libc::c_void, size: u32) -> libc::c_long {
+        unsafe { libc::syscall(libc::SYS_bpf, cmd, attr, size) }
+    }
+}
+
+// mocks
+#[cfg(not(test))]
+use sys::bpf;
+
+#[cfg(test)]
+use crate::v2::devices::mocks::mock_sys::bpf;
+
+fn call<T>(cmd: u32, attr: &mut T) -> libc::c_long {
+    #[allow(unused_unsafe)]
+    unsafe {
+        bpf(
+            cmd,
+            attr as *mut T as *mut libc::c_void,
+            std::mem::size_of::<T>() as u32,
+        )
+    }
 }
 
 #[cfg_attr(test, automock)]
 pub mod prog {
+    use std::ffi::CString;
     use std::os::unix::io::RawFd;
-    use std::ptr;
 
-    use libbpf_sys::{BPF_CGROUP_DEVICE, BPF_F_ALLOW_MULTI, BPF_PROG_TYPE_CGROUP_DEVICE, bpf_insn};
-    #[cfg(not(test))]
-    use libbpf_sys::{
-        bpf_prog_attach, bpf_prog_detach2, bpf_prog_get_fd_by_id, bpf_prog_load, bpf_prog_query,
-    };
     #[cfg(not(test))]
     use libc::setrlimit;
     use libc::{ENOSPC, RLIMIT_MEMLOCK, rlimit};
 
-    use super::ProgramInfo;
-    // TODO: consider use of #[mockall_double]
-    #[cfg(test)]
-    use crate::v2::devices::mocks::mock_libbpf_sys::{
-        bpf_prog_attach, bpf_prog_detach2, bpf_prog_get_fd_by_id, bpf_prog_load, bpf_prog_query,
+    use super::sys::{
+        BPF_CGROUP_DEVICE, BPF_F_ALLOW_MULTI, BPF_PROG_ATTACH, BPF_PROG_DETACH,
+        BPF_PROG_GET_FD_BY_ID, BPF_PROG_LOAD, BPF_PROG_QUERY, BPF_PROG_TYPE_CGROUP_DEVICE,
+        GetFdByIdAttr, ProgAttachAttr, ProgLoadAttr, ProgQueryAttr,
     };
+    use super::{ProgramInfo, call};
+    use crate::v2::devices::program::INSN_SIZE;
     // mocks
     // TODO: consider use of #[mockall_double]
     #[cfg(test)]
     use crate::v2::devices::mocks::mock_libc::setrlimit;
 
     pub fn load(license: &str, insns: &[u8]) -> Result<RawFd, super::BpfError> {
-        let insns_cnt = insns.len() / std::mem::size_of::<bpf_insn>();
-        let insns = insns as *const _ as *const bpf_insn;
-        let mut opts = libbpf_sys::bpf_prog_load_opts {
-            sz: std::mem::size_of::<libbpf_sys::bpf_prog_load_opts>() as libbpf_sys::size_t,
-            kern_version: 0,
-            log_buf: ptr::null_mut::<::std::os::raw::c_char>(),
-            log_size: 0,
+        let license = CString::new(license).map_err(|_| super::BpfError::InvalidLicense)?;
+        let mut attr = ProgLoadAttr {
+            prog_type: BPF_PROG_TYPE_CGROUP_DEVICE,
+            insn_cnt: (insns.len() / INSN_SIZE) as u32,
+            insns: insns.as_ptr() as u64,
+            license: license.as_ptr() as u64,
             ..Default::default()
         };
-        #[allow(unused_unsafe)]
-        let prog_fd = unsafe {
-            bpf_prog_load(
-                BPF_PROG_TYPE_CGROUP_DEVICE,
-                ptr::null::<::std::os::raw::c_char>(),
-                license as *const _ as *const ::std::os::raw::c_char,
-                insns,
-                insns_cnt as u64,
-                &mut opts as *mut libbpf_sys::bpf_prog_load_opts,
-            )
-        };
 
+        let prog_fd = call(BPF_PROG_LOAD, &mut attr);
         if prog_fd < 0 {
             return Err(errno::errno().into());
         }
-        Ok(prog_fd)
+        Ok(prog_fd as RawFd)
     }
 
     /// Given a fd for a cgroup, collect the programs associated with it
     pub fn query(cgroup_fd: RawFd) -> Result<Vec<ProgramInfo>, super::BpfError> {
         let mut prog_ids: Vec<u32> = vec![0_u32; 64];
-        let mut attach_flags = 0_u32;
         for _ in 0..10 {
-            let mut prog_cnt = prog_ids.len() as u32;
-            #[allow(unused_unsafe)]
-            let ret = unsafe {
-                // collect ids for bpf programs
-                bpf_prog_query(
-                    cgroup_fd,
-                    BPF_CGROUP_DEVICE,
-                    0,
-                    &mut attach_flags,
-                    &prog_ids[0] as *const u32 as *mut u32,
-                    &mut prog_cnt,
-                )
+            let mut attr = ProgQueryAttr {
+                target_fd: cgroup_fd as u32,
+                attach_type: BPF_CGROUP_DEVICE,
+                prog_ids: prog_ids.as_mut_ptr() as u64,
+                prog_cnt: prog_ids.len() as u32,
+                ..Default::default()
             };
+            // collect ids for bpf programs
+            let ret = call(BPF_PROG_QUERY, &mut attr);
             if ret != 0 {
                 let err = errno::errno();
                 if err.0 == ENOSPC {
-                    assert!(prog_cnt as usize > prog_ids.len());
+                    assert!(attr.prog_cnt as usize > prog_ids.len());
 
                     // allocate more space and try again
-                    prog_ids.resize(prog_cnt as usize, 0);
+                    prog_ids.resize(attr.prog_cnt as usize, 0);
                     continue;
                 }
 
                 return Err(err.into());
             }
 
-            prog_ids.resize(prog_cnt as usize, 0);
+            prog_ids.resize(attr.prog_cnt as usize, 0);
             break;
         }
 
         let mut prog_fds = Vec::with_capacity(prog_ids.len());
         for prog_id in &prog_ids {
             // collect fds for programs by getting their ids
-            #[allow(unused_unsafe)]
-            let prog_fd = unsafe { bpf_prog_get_fd_by_id(*prog_id) };
+            let mut attr = GetFdByIdAttr {
+                prog_id: *prog_id,
+                ..Default::default()
+            };
+            let prog_fd = call(BPF_PROG_GET_FD_BY_ID, &mut attr);
             if prog_fd < 0 {
-                tracing::debug!("bpf_prog_get_fd_by_id failed: {}", errno::errno());
+                tracing::debug!("BPF_PROG_GET_FD_BY_ID failed: {}", errno::errno());
                 continue;
             }
             prog_fds.push(ProgramInfo {
                 id: *prog_id,
-                fd: prog_fd,
+                fd: prog_fd as RawFd,
             });
         }
         Ok(prog_fds)
     }
 
     pub fn detach2(prog_fd: RawFd, cgroup_fd: RawFd) -> Result<(), super::BpfError> {
-        #[allow(unused_unsafe)]
-        let ret = unsafe { bpf_prog_detach2(prog_fd, cgroup_fd, BPF_CGROUP_DEVICE) };
+        let mut attr = ProgAttachAttr {
+            target_fd: cgroup_fd as u32,
+            attach_bpf_fd: prog_fd as u32,
+            attach_type: BPF_CGROUP_DEVICE,
+            ..Default::default()
+        };
+        let ret = call(BPF_PROG_DETACH, &mut attr);
         if ret != 0 {
             return Err(errno::errno().into());
         }
@@ -127,10 +207,14 @@ pub mod prog {
     }
 
     pub fn attach(prog_fd: RawFd, cgroup_fd: RawFd) -> Result<(), super::BpfError> {
-        #[allow(unused_unsafe)]
-        let ret =
-            unsafe { bpf_prog_attach(prog_fd, cgroup_fd, BPF_CGROUP_DEVICE, BPF_F_ALLOW_MULTI) };
-
+        let mut attr = ProgAttachAttr {
+            target_fd: cgroup_fd as u32,
+            attach_bpf_fd: prog_fd as u32,
+            attach_type: BPF_CGROUP_DEVICE,
+            attach_flags: BPF_F_ALLOW_MULTI,
+            ..Default::default()
+        };
+        let ret = call(BPF_PROG_ATTACH, &mut attr);
         if ret != 0 {
             return Err(errno::errno().into());
         }
@@ -159,22 +243,32 @@ mod tests {
     use serial_test::serial;
 
     use super::prog;
-    use crate::v2::devices::mocks::{mock_libbpf_sys, mock_libc};
+    use super::sys::*;
+    use crate::v2::devices::mocks::{mock_libc, mock_sys};
 
     #[test]
-    #[serial(libbpf_sys)] // mock contexts are shared
+    #[serial(bpf_sys)] // mock contexts are shared
     fn test_bpf_load() {
         // eBPF uses 64-bit instructions
-        let instruction_zero: &[u8] = &[0x0, 0x0, 0x0, 0x0];
-        let instruction_one: &[u8] = &[0xF, 0xF, 0xF, 0xF];
+        let instruction_zero: &[u8] = &[0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0];
+        let instruction_one: &[u8] = &[0xF, 0xF, 0xF, 0xF, 0xF, 0xF, 0xF, 0xF];
 
         // arrange
         let license = "Apache";
         let instructions = [instruction_zero, instruction_one].concat();
-        let load = mock_libbpf_sys::bpf_prog_load_context();
+        let bpf = mock_sys::bpf_context();
 
         // expect
-        load.expect().once().returning(|_, _, _, _, _, _| 32);
+        bpf.expect()
+            .once()
+            .withf(|cmd, attr, size| {
+                let attr = unsafe { &*(*attr as *const ProgLoadAttr) };
+                *cmd == BPF_PROG_LOAD
+                    && *size as usize == std::mem::size_of::<ProgLoadAttr>()
+                    && attr.prog_type == BPF_PROG_TYPE_CGROUP_DEVICE
+                    && attr.insn_cnt == 2
+            })
+            .returning(|_, _, _| 32);
 
         // act
         let fd = prog::load(license, &instructions).expect("successfully calls load");
@@ -184,35 +278,45 @@ mod tests {
     }
 
     #[test]
-    #[serial(libbpf_sys)] // mock contexts are shared
+    #[serial(bpf_sys)] // mock contexts are shared
     fn test_bpf_attach() {
         // arrange
-        let attach = mock_libbpf_sys::bpf_prog_attach_context();
+        let bpf = mock_sys::bpf_context();
 
         // expect
-        attach.expect().once().returning(|_, _, _, _| 0);
+        bpf.expect()
+            .once()
+            .withf(|cmd, attr, _| {
+                let attr = unsafe { &*(*attr as *const ProgAttachAttr) };
+                *cmd == BPF_PROG_ATTACH
+                    && attr.target_fd == 4
+                    && attr.attach_bpf_fd == 3
+                    && attr.attach_type == BPF_CGROUP_DEVICE
+                    && attr.attach_flags == BPF_F_ALLOW_MULTI
+            })
+            .returning(|_, _, _| 0);
 
         // act
-        let r = prog::attach(0, 0);
+        let r = prog::attach(3, 4);
 
         // assert
         assert!(r.is_ok());
     }
 
     #[test]
-    #[serial(libbpf_sys)] // mock contexts are shared
+    #[serial(bpf_sys)] // mock contexts are shared
     fn test_bpf_load_error() {
         // eBPF uses 64-bit instructions
-        let instruction_zero: &[u8] = &[0x0, 0x0, 0x0, 0x0];
-        let instruction_one: &[u8] = &[0xF, 0xF, 0xF, 0xF];
+        let instruction_zero: &[u8] = &[0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0];
+        let instruction_one: &[u8] = &[0xF, 0xF, 0xF, 0xF, 0xF, 0xF, 0xF, 0xF];
 
         // arrange
         let license = "Apache";
         let instructions = [instruction_zero, instruction_one].concat();
-        let load = mock_libbpf_sys::bpf_prog_load_context();
+        let bpf = mock_sys::bpf_context();
 
         // expect
-        load.expect().once().returning(|_, _, _, _, _, _| -1);
+        bpf.expect().once().returning(|_, _, _| -1);
 
         // act
         let error_result = prog::load(license, &instructions);
@@ -222,88 +326,78 @@ mod tests {
     }
 
     #[test]
-    #[serial(libbpf_sys)] // mock contexts are shared
+    #[serial(bpf_sys)] // mock contexts are shared
     fn test_bpf_query() {
         // arrange
-        let query = mock_libbpf_sys::bpf_prog_query_context();
-        let get_fd_by_id = mock_libbpf_sys::bpf_prog_get_fd_by_id_context();
+        let bpf = mock_sys::bpf_context();
 
         // expect
-        query.expect().once().returning(
-            |_target_fd: std::os::raw::c_int,
-             _type_: libbpf_sys::bpf_attach_type,
-             _query_flags: libbpf_sys::__u32,
-             _attach_flags: *mut libbpf_sys::__u32,
-             prog_ids: *mut libbpf_sys::__u32,
-             prog_cnt: *mut libbpf_sys::__u32|
-             -> ::std::os::raw::c_int {
+        bpf.expect()
+            .once()
+            .withf(|cmd, _, _| *cmd == BPF_PROG_QUERY)
+            .returning(|_, attr, _| {
                 // deref the ptr and fill it with some "ids"
                 // also set the prog_cnt to 4
                 set_errno(Errno(0));
                 unsafe {
-                    *prog_cnt = 4;
-                    let id_array = std::slice::from_raw_parts_mut(prog_ids, 4_usize);
+                    let attr = &mut *(attr as *mut ProgQueryAttr);
+                    attr.prog_cnt = 4;
+                    let id_array = std::slice::from_raw_parts_mut(attr.prog_ids as *mut u32, 4);
                     id_array[0] = 1;
                     id_array[1] = 2;
                     id_array[2] = 3;
                     id_array[3] = 4;
                 }
                 0
-            },
-        );
-        get_fd_by_id.expect().times(4).returning(|fd| {
-            // return the same fd if it's not 0
-            if fd > 0 {
-                return fd as std::os::raw::c_int;
-            }
-            -1
-        });
+            });
+        bpf.expect()
+            .times(4)
+            .withf(|cmd, _, _| *cmd == BPF_PROG_GET_FD_BY_ID)
+            .returning(|_, attr, _| {
+                // return the id as fd if it's not 0
+                let attr = unsafe { &*(attr as *const GetFdByIdAttr) };
+                if attr.prog_id > 0 {
+                    return attr.prog_id as libc::c_long;
+                }
+                -1
+            });
 
         // act
         let info = prog::query(0).expect("Able to successfully query");
 
         // assert
         assert_eq!(info.first().unwrap().id, 1);
+        assert_eq!(info.first().unwrap().fd, 1);
         assert_eq!(info.len(), 4);
     }
 
     #[test]
-    #[serial(libbpf_sys)] // mock contexts are shared
+    #[serial(bpf_sys)] // mock contexts are shared
     fn test_bpf_query_recoverable_error() {
         // arrange
-        let query = mock_libbpf_sys::bpf_prog_query_context();
-        let get_fd_by_id = mock_libbpf_sys::bpf_prog_get_fd_by_id_context();
+        let bpf = mock_sys::bpf_context();
 
         // expect
-        query.expect().times(2).returning(
-            |_target_fd: std::os::raw::c_int,
-             _type_: libbpf_sys::bpf_attach_type,
-             _query_flags: libbpf_sys::__u32,
-             _attach_flags: *mut libbpf_sys::__u32,
-             prog_ids: *mut libbpf_sys::__u32,
-             prog_cnt: *mut libbpf_sys::__u32|
-             -> ::std::os::raw::c_int {
-                unsafe {
-                    if *prog_cnt == 64 {
-                        set_errno(Errno(ENOSPC));
-                        *prog_cnt = 128;
-                        return 1;
-                    }
-                    let id_array = std::slice::from_raw_parts_mut(prog_ids, 128_usize);
-                    for (i, item) in id_array.iter_mut().enumerate() {
-                        *item = (i + 1) as u32;
-                    }
+        bpf.expect()
+            .times(2)
+            .withf(|cmd, _, _| *cmd == BPF_PROG_QUERY)
+            .returning(|_, attr, _| unsafe {
+                let attr = &mut *(attr as *mut ProgQueryAttr);
+                if attr.prog_cnt == 64 {
+                    set_errno(Errno(ENOSPC));
+                    attr.prog_cnt = 128;
+                    return 1;
+                }
+                let id_array = std::slice::from_raw_parts_mut(attr.prog_ids as *mut u32, 128);
+                for (i, item) in id_array.iter_mut().enumerate() {
+                    *item = (i + 1) as u32;
                 }
                 0
-            },
-        );
-        get_fd_by_id.expect().times(128).returning(|fd| {
-            // return the same fd if it's not 0
-            if fd > 0 {
-                return fd as std::os::raw::c_int;
-            }
-            -1
-        });
+            });
+        bpf.expect()
+            .times(128)
+            .withf(|cmd, _, _| *cmd == BPF_PROG_GET_FD_BY_ID)
+            .returning(|_, _, _| 10);
 
         // act
         let info = prog::query(0).expect("Able to successfully query");
@@ -314,26 +408,19 @@ mod tests {
     }
 
     #[test]
-    #[serial(libbpf_sys)] // mock contexts are shared
+    #[serial(bpf_sys)] // mock contexts are shared
     fn test_bpf_query_other_error() {
         // arrange
-        let query = mock_libbpf_sys::bpf_prog_query_context();
-        let get_fd_by_id = mock_libbpf_sys::bpf_prog_get_fd_by_id_context();
+        let bpf = mock_sys::bpf_context();
 
         // expect
-        query.expect().times(1).returning(
-            |_target_fd: std::os::raw::c_int,
-             _type_: libbpf_sys::bpf_attach_type,
-             _query_flags: libbpf_sys::__u32,
-             _attach_flags: *mut libbpf_sys::__u32,
-             _prog_ids: *mut libbpf_sys::__u32,
-             _prog_cnt: *mut libbpf_sys::__u32|
-             -> ::std::os::raw::c_int {
+        bpf.expect()
+            .times(1)
+            .withf(|cmd, _, _| *cmd == BPF_PROG_QUERY)
+            .returning(|_, _, _| {
                 set_errno(Errno(ENOSYS));
                 1
-            },
-        );
-        get_fd_by_id.expect().never();
+            });
 
         // act
         let error = prog::query(0);
@@ -343,13 +430,19 @@ mod tests {
     }
 
     #[test]
-    #[serial(libbpf_sys)] // mock contexts are shared
+    #[serial(bpf_sys)] // mock contexts are shared
     fn test_bpf_detach2() {
         // arrange
-        let detach2 = mock_libbpf_sys::bpf_prog_detach2_context();
+        let bpf = mock_sys::bpf_context();
 
         // expect
-        detach2.expect().once().returning(|_, _, _| 0);
+        bpf.expect()
+            .once()
+            .withf(|cmd, attr, _| {
+                let attr = unsafe { &*(*attr as *const ProgAttachAttr) };
+                *cmd == BPF_PROG_DETACH && attr.attach_type == BPF_CGROUP_DEVICE
+            })
+            .returning(|_, _, _| 0);
 
         // act
         let r = prog::detach2(0, 0);
@@ -359,13 +452,13 @@ mod tests {
     }
 
     #[test]
-    #[serial(libbpf_sys)] // mock contexts are shared
+    #[serial(bpf_sys)] // mock contexts are shared
     fn test_bpf_detach2_error() {
         // arrange
-        let detach2 = mock_libbpf_sys::bpf_prog_detach2_context();
+        let bpf = mock_sys::bpf_context();
 
         // expect
-        detach2.expect().once().returning(|_, _, _| 1);
+        bpf.expect().once().returning(|_, _, _| 1);
 
         // act
         let r = prog::detach2(0, 0);
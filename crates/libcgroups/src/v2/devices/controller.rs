@@ -36,21 +36,14 @@ impl Controller for Devices {
         controller_opt: &ControllerOpt,
         cgroup_root: &Path,
     ) -> Result<(), DevicesControllerError> {
-        #[cfg(not(feature = "cgroupsv2_devices"))]
-        return Ok(());
-
-        #[cfg(feature = "cgroupsv2_devices")]
-        return Self::apply_devices(cgroup_root, controller_opt.resources.devices());
+        Self::apply_devices(cgroup_root, controller_opt.resources.devices())
     }
 }
 
 impl Devices {
-    pub fn apply_devices(
-        cgroup_root: &Path,
-        linux_devices: &Option<Vec<LinuxDeviceCgroup>>,
-    ) -> Result<(), DevicesControllerError> {
-        tracing::debug!("Apply Devices cgroup config");
-
+    /// Returns the emulator holding the rules that are enforced for the given
+    /// user-defined rules, followed by the default devices which are always allowed.
+    pub fn emulator(linux_devices: &Option<Vec<LinuxDeviceCgroup>>) -> emulator::Emulator {
         // FIXME: should we start as "deny all"?
         let mut emulator = emulator::Emulator::with_default_allow(false);
 
@@ -72,12 +65,22 @@ impl Devices {
             emulator.add_rule(&d);
         }
 
+        emulator
+    }
+
+    pub fn apply_devices(
+        cgroup_root: &Path,
+        linux_devices: &Option<Vec<LinuxDeviceCgroup>>,
+    ) -> Result<(), DevicesControllerError> {
+        tracing::debug!("Apply Devices cgroup config");
+
+        let emulator = Self::emulator(linux_devices);
         let prog = program::Program::from_rules(&emulator.rules, emulator.default_allow)?;
 
         // Increase `ulimit -l` limit to avoid BPF_PROG_LOAD error (#2167).
         // This limit is not inherited into the container.
         bpf_prog::bump_memlock_rlimit()?;
-        let prog_fd = bpf_prog::load(LICENSE, prog.bytecodes())?;
+        let prog_fd = bpf_prog::load(LICENSE, &prog.bytecodes())?;
 
         // FIXME: simple way to attach BPF program
         //  1. get list of existing attached programs
@@ -187,4 +190,32 @@ mod tests {
         // act
         Devices::apply_devices(tmp.path(), &Some(vec![a_type])).expect("Could not apply devices");
     }
+
+    #[test]
+    fn test_emulator_keeps_default_devices() {
+        // arrange
+        let deny_all = LinuxDeviceCgroupBuilder::default()
+            .allow(false)
+            .typ(LinuxDeviceType::A)
+            .build()
+            .unwrap();
+        let allow_fuse = LinuxDeviceCgroupBuilder::default()
+            .allow(true)
+            .typ(LinuxDeviceType::C)
+            .major(10)
+            .minor(229)
+            .access("rw")
+            .build()
+            .unwrap();
+
+        // act
+        let emulator = Devices::emulator(&Some(vec![deny_all, allow_fuse]));
+
+        // assert
+        assert!(emulator.allows(LinuxDeviceType::C, 10, 229, "rw"));
+        assert!(!emulator.allows(LinuxDeviceType::C, 10, 229, "rwm"));
+        assert!(emulator.allows(LinuxDeviceType::C, 10, 200, "rwm"));
+        assert!(emulator.allows(LinuxDeviceType::C, 1, 3, "rw"));
+        assert!(!emulator.allows(LinuxDeviceType::B, 8, 0, "r"));
+    }
 }
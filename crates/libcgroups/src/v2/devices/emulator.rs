@@ -49,6 +49,36 @@ impl Emulator {
 
         self.rules.push(rule.clone());
     }
+
+    /// Returns the rule deciding an access to the device, or None if no rule matches
+    /// and the default action applies. This mirrors the checks of the generated bpf
+    /// program, so it can be used to verify the program without loading it.
+    pub fn matching_rule(
+        &self,
+        typ: LinuxDeviceType,
+        major: u32,
+        minor: u32,
+        access: &str,
+    ) -> Option<&LinuxDeviceCgroup> {
+        // only block and char devices are reported to the bpf program
+        if !matches!(typ, LinuxDeviceType::B | LinuxDeviceType::C) {
+            return None;
+        }
+
+        self.rules.iter().rev().find(|rule| {
+            let rule_access = rule.access().as_deref().unwrap_or_default();
+            rule.typ().unwrap_or_default() == typ
+                && access.chars().all(|c| rule_access.contains(c))
+                && rule.major().is_none_or(|m| m < 0 || m as u32 == major)
+                && rule.minor().is_none_or(|m| m < 0 || m as u32 == minor)
+        })
+    }
+
+    pub fn allows(&self, typ: LinuxDeviceType, major: u32, minor: u32, access: &str) -> bool {
+        self.matching_rule(typ, major, minor, access)
+            .map(|rule| rule.allow())
+            .unwrap_or(self.default_allow)
+    }
 }
 
 #[cfg(test)]
@@ -117,4 +147,47 @@ mod tests {
         assert_eq!(top_rule.access(), &Some(permission.to_string()));
         assert!(!emulator.default_allow);
     }
+
+    #[test]
+    fn test_allows_last_matching_rule() {
+        // arrange
+        let mut emulator = Emulator::with_default_allow(false);
+        emulator.add_rules(&[
+            LinuxDeviceCgroupBuilder::default()
+                .allow(true)
+                .typ(LinuxDeviceType::C)
+                .major(1)
+                .access("rwm")
+                .build()
+                .unwrap(),
+            LinuxDeviceCgroupBuilder::default()
+                .allow(false)
+                .typ(LinuxDeviceType::C)
+                .major(1)
+                .minor(3)
+                .access("w")
+                .build()
+                .unwrap(),
+        ]);
+
+        // assert
+        assert!(emulator.allows(LinuxDeviceType::C, 1, 3, "r"));
+        assert!(!emulator.allows(LinuxDeviceType::C, 1, 3, "w"));
+        // a rule only matches if it covers every requested access
+        assert!(emulator.allows(LinuxDeviceType::C, 1, 3, "rw"));
+        assert!(emulator.allows(LinuxDeviceType::C, 1, 5, "rw"));
+        assert!(!emulator.allows(LinuxDeviceType::B, 1, 5, "r"));
+        assert!(!emulator.allows(LinuxDeviceType::U, 1, 5, "r"));
+        assert_eq!(
+            emulator
+                .matching_rule(LinuxDeviceType::C, 1, 3, "w")
+                .and_then(|rule| rule.minor()),
+            Some(3)
+        );
+        assert!(
+            emulator
+                .matching_rule(LinuxDeviceType::C, 2, 3, "w")
+                .is_none()
+        );
+    }
 }
@@ -1,21 +1,112 @@
 use oci_spec::runtime::*;
-use rbpf::disassembler::disassemble;
-use rbpf::insn_builder::{Arch as RbpfArch, *};
+
+// Device types and access flags of struct bpf_cgroup_dev_ctx
+// https://elixir.bootlin.com/linux/v5.3.6/source/include/uapi/linux/bpf.h#L3416
+pub const BPF_DEVCG_ACC_MKNOD: u32 = 1;
+pub const BPF_DEVCG_ACC_READ: u32 = 2;
+pub const BPF_DEVCG_ACC_WRITE: u32 = 4;
+pub const BPF_DEVCG_DEV_BLOCK: u32 = 1;
+pub const BPF_DEVCG_DEV_CHAR: u32 = 2;
+
+// Opcodes of the instructions emitted for device programs
+// https://www.kernel.org/doc/html/latest/bpf/standardization/instruction-set.html
+const BPF_LDX_MEM_W: u8 = 0x61;
+const BPF_ALU_AND_K: u8 = 0x54;
+const BPF_ALU_RSH_K: u8 = 0x74;
+const BPF_ALU_MOV_K: u8 = 0xb4;
+const BPF_ALU_MOV_X: u8 = 0xbc;
+const BPF_JMP_JNE_K: u8 = 0x55;
+const BPF_JMP_JNE_X: u8 = 0x5d;
+const BPF_JMP_EXIT: u8 = 0x95;
+
+/// Size in bytes of a single eBPF instruction
+pub const INSN_SIZE: usize = 8;
+// Size in bytes of struct bpf_cgroup_dev_ctx
+const DEV_CTX_SIZE: usize = 12;
+
+/// A single eBPF instruction as defined by struct bpf_insn
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Instruction {
+    pub opcode: u8,
+    pub dst: u8,
+    pub src: u8,
+    pub off: i16,
+    pub imm: i32,
+}
+
+impl Instruction {
+    fn new(opcode: u8, dst: u8, src: u8, off: i16, imm: i32) -> Self {
+        Self {
+            opcode,
+            dst,
+            src,
+            off,
+            imm,
+        }
+    }
+
+    pub fn to_bytes(&self) -> [u8; INSN_SIZE] {
+        // dst_reg and src_reg are 4 bit wide bitfields sharing a byte
+        #[cfg(target_endian = "little")]
+        let regs = (self.src << 4) | (self.dst & 0x0f);
+        #[cfg(target_endian = "big")]
+        let regs = (self.dst << 4) | (self.src & 0x0f);
+
+        let mut bytes = [0_u8; INSN_SIZE];
+        bytes[0] = self.opcode;
+        bytes[1] = regs;
+        bytes[2..4].copy_from_slice(&self.off.to_ne_bytes());
+        bytes[4..8].copy_from_slice(&self.imm.to_ne_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8; INSN_SIZE]) -> Self {
+        #[cfg(target_endian = "little")]
+        let (dst, src) = (bytes[1] & 0x0f, bytes[1] >> 4);
+        #[cfg(target_endian = "big")]
+        let (dst, src) = (bytes[1] >> 4, bytes[1] & 0x0f);
+
+        Self {
+            opcode: bytes[0],
+            dst,
+            src,
+            off: i16::from_ne_bytes([bytes[2], bytes[3]]),
+            imm: i32::from_ne_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]),
+        }
+    }
+
+    /// Returns the instruction in the assembly syntax used by the kernel and bpftool
+    pub fn disassemble(&self) -> String {
+        match self.opcode {
+            BPF_LDX_MEM_W => format!("ldxw r{}, [r{}{:+#x}]", self.dst, self.src, self.off),
+            BPF_ALU_AND_K => format!("and32 r{}, {:#x}", self.dst, self.imm),
+            BPF_ALU_RSH_K => format!("rsh32 r{}, {:#x}", self.dst, self.imm),
+            BPF_ALU_MOV_K => format!("mov32 r{}, {:#x}", self.dst, self.imm),
+            BPF_ALU_MOV_X => format!("mov32 r{}, r{}", self.dst, self.src),
+            BPF_JMP_JNE_K => format!("jne r{}, {:#x}, {:+#x}", self.dst, self.imm, self.off),
+            BPF_JMP_JNE_X => format!("jne r{}, r{}, {:+#x}", self.dst, self.src, self.off),
+            BPF_JMP_EXIT => "exit".to_owned(),
+            opcode => format!("unknown opcode {opcode:#x}"),
+        }
+    }
+}
 
 pub struct Program {
-    prog: BpfCode,
+    insns: Vec<Instruction>,
 }
 
 #[derive(thiserror::Error, Debug)]
 pub enum ProgramError {
-    #[error("io error: {0}")]
-    Io(#[from] std::io::Error),
     #[error("invalid access: {0}")]
     InvalidAccess(char),
     #[error("{0} device not supported")]
     DeviceNotSupported(&'static str),
     #[error("wildcard device type should be removed when cleaning rules")]
     WildcardDevice,
+    #[error("unsupported instruction {opcode:#x} at {pc}")]
+    UnsupportedInstruction { opcode: u8, pc: usize },
+    #[error("out of bounds access at {pc}")]
+    OutOfBounds { pc: usize },
 }
 
 impl Program {
@@ -23,9 +114,7 @@ impl Program {
         rules: &[LinuxDeviceCgroup],
         default_allow: bool,
     ) -> Result<Self, ProgramError> {
-        let mut prog = Program {
-            prog: BpfCode::new(),
-        };
+        let mut prog = Program { insns: Vec::new() };
         prog.init();
 
         for rule in rules.iter().rev() {
@@ -35,18 +124,27 @@ impl Program {
         Ok(prog)
     }
 
-    pub fn bytecodes(&self) -> &[u8] {
-        self.prog.into_bytes()
+    pub fn instructions(&self) -> &[Instruction] {
+        &self.insns
     }
 
-    fn finalize(&mut self, default_allow: bool) {
-        self.prog
-            .mov(Source::Imm, RbpfArch::X32)
-            .set_dst(0)
-            .set_imm(default_allow as i32)
-            .push();
+    pub fn bytecodes(&self) -> Vec<u8> {
+        self.insns.iter().flat_map(|insn| insn.to_bytes()).collect()
+    }
 
-        self.prog.exit().push();
+    fn push(&mut self, insn: Instruction) {
+        self.insns.push(insn);
+    }
+
+    fn finalize(&mut self, default_allow: bool) {
+        self.push(Instruction::new(
+            BPF_ALU_MOV_K,
+            0,
+            0,
+            0,
+            default_allow as i32,
+        ));
+        self.push(Instruction::new(BPF_JMP_EXIT, 0, 0, 0, 0));
     }
 
     // struct bpf_cgroup_dev_ctx: https://elixir.bootlin.com/linux/v5.3.6/source/include/uapi/linux/bpf.h#L3423
@@ -60,54 +158,18 @@ impl Program {
     // R4 <- major (u32 major at R1[4])
     // R5 <- minor (u32 minor at R1[8])
     fn init(&mut self) {
-        self.prog
-            .load_x(MemSize::Word)
-            .set_src(1)
-            .set_off(0)
-            .set_dst(2)
-            .push();
-
-        self.prog
-            .bit_and(Source::Imm, RbpfArch::X32)
-            .set_dst(2)
-            .set_imm(0xFFFF)
-            .push();
-
-        self.prog
-            .load_x(MemSize::Word)
-            .set_src(1)
-            .set_off(0)
-            .set_dst(3)
-            .push();
-
-        self.prog
-            .right_shift(Source::Imm, RbpfArch::X32)
-            .set_imm(16)
-            .set_dst(3)
-            .push();
-
-        self.prog
-            .load_x(MemSize::Word)
-            .set_src(1)
-            .set_off(4)
-            .set_dst(4)
-            .push();
-
-        self.prog
-            .load_x(MemSize::Word)
-            .set_src(1)
-            .set_off(8)
-            .set_dst(5)
-            .push();
+        self.push(Instruction::new(BPF_LDX_MEM_W, 2, 1, 0, 0));
+        self.push(Instruction::new(BPF_ALU_AND_K, 2, 0, 0, 0xFFFF));
+        self.push(Instruction::new(BPF_LDX_MEM_W, 3, 1, 0, 0));
+        self.push(Instruction::new(BPF_ALU_RSH_K, 3, 0, 0, 16));
+        self.push(Instruction::new(BPF_LDX_MEM_W, 4, 1, 4, 0));
+        self.push(Instruction::new(BPF_LDX_MEM_W, 5, 1, 8, 0));
     }
 
     fn add_rule(&mut self, rule: &LinuxDeviceCgroup) -> Result<(), ProgramError> {
         let dev_type = bpf_dev_type(rule.typ().unwrap_or_default())?;
         let access = bpf_access(rule.access().clone().unwrap_or_default())?;
-        let has_access = access
-            != (libbpf_sys::BPF_DEVCG_ACC_READ
-                | libbpf_sys::BPF_DEVCG_ACC_WRITE
-                | libbpf_sys::BPF_DEVCG_ACC_MKNOD);
+        let has_access = access != (BPF_DEVCG_ACC_READ | BPF_DEVCG_ACC_WRITE | BPF_DEVCG_ACC_MKNOD);
 
         let has_major = rule.major().is_some() && rule.major().unwrap() >= 0;
         let has_minor = rule.minor().is_some() && rule.minor().unwrap() >= 0;
@@ -127,73 +189,74 @@ impl Program {
 
         // if (R2 != dev_type) goto next rule
         let mut next_rule_offset = instruction_count - 1;
-        self.prog
-            .jump_conditional(Cond::NotEquals, Source::Imm)
-            .set_dst(2)
-            .set_imm(dev_type as i32)
-            .set_off(next_rule_offset)
-            .push();
+        self.push(Instruction::new(
+            BPF_JMP_JNE_K,
+            2,
+            0,
+            next_rule_offset,
+            dev_type as i32,
+        ));
 
         if has_access {
             next_rule_offset -= 3;
             // if (R3 & access != R3 /* use R1 as a temp var */) goto next rule
-            self.prog
-                .mov(Source::Reg, RbpfArch::X32)
-                .set_dst(1)
-                .set_src(3)
-                .push();
-
-            self.prog
-                .bit_and(Source::Imm, RbpfArch::X32)
-                .set_dst(1)
-                .set_imm(access as i32)
-                .push();
-
-            self.prog
-                .jump_conditional(Cond::NotEquals, Source::Reg)
-                .set_dst(1)
-                .set_src(3)
-                .set_off(next_rule_offset)
-                .push();
+            self.push(Instruction::new(BPF_ALU_MOV_X, 1, 3, 0, 0));
+            self.push(Instruction::new(BPF_ALU_AND_K, 1, 0, 0, access as i32));
+            self.push(Instruction::new(BPF_JMP_JNE_X, 1, 3, next_rule_offset, 0));
         }
 
         if has_major {
             next_rule_offset -= 1;
             // if (R4 != major) goto next rule
-            self.prog
-                .jump_conditional(Cond::NotEquals, Source::Imm)
-                .set_dst(4)
-                .set_imm(rule.major().unwrap() as i32)
-                .set_off(next_rule_offset)
-                .push();
+            self.push(Instruction::new(
+                BPF_JMP_JNE_K,
+                4,
+                0,
+                next_rule_offset,
+                rule.major().unwrap() as i32,
+            ));
         }
 
         if has_minor {
             next_rule_offset -= 1;
             // if (R5 != minor) goto next rule
-            self.prog
-                .jump_conditional(Cond::NotEquals, Source::Imm)
-                .set_dst(5)
-                .set_imm(rule.minor().unwrap() as i32)
-                .set_off(next_rule_offset)
-                .push();
+            self.push(Instruction::new(
+                BPF_JMP_JNE_K,
+                5,
+                0,
+                next_rule_offset,
+                rule.minor().unwrap() as i32,
+            ));
         }
 
         // matched, return rule.allow
-        self.prog
-            .mov(Source::Imm, RbpfArch::X32)
-            .set_dst(0)
-            .set_imm(rule.allow() as i32)
-            .push();
-        self.prog.exit().push();
+        self.push(Instruction::new(
+            BPF_ALU_MOV_K,
+            0,
+            0,
+            0,
+            rule.allow() as i32,
+        ));
+        self.push(Instruction::new(BPF_JMP_EXIT, 0, 0, 0, 0));
 
         Ok(())
     }
 
+    /// Returns the disassembly of the program, one instruction per line
+    pub fn disassemble(&self) -> Vec<String> {
+        self.insns.iter().map(Instruction::disassemble).collect()
+    }
+
     pub fn dump(&self) {
-        disassemble(self.prog.into_bytes());
+        for (pc, line) in self.disassemble().iter().enumerate() {
+            println!("{pc:4}: {line}");
+        }
     }
 
+    /// Runs the program against a device access, returning 1 if the access is allowed.
+    ///
+    /// Only the subset of eBPF emitted by [`Program::from_rules`] is supported, which
+    /// makes it possible to check the generated bytecode without loading it into the kernel.
     pub fn execute(
         &self,
         typ: LinuxDeviceType,
@@ -201,18 +264,62 @@ impl Program {
         minor: u32,
         access: String,
     ) -> Result<u64, ProgramError> {
-        let mut mem = bpf_cgroup_dev_ctx(typ, major, minor, access)?;
-        let vm = rbpf::EbpfVmRaw::new(Some(self.prog.into_bytes()))?;
-        let result = vm.execute_program(&mut mem[..])?;
-        Ok(result)
+        let mem = bpf_cgroup_dev_ctx(typ, major, minor, access)?;
+        // R1 holds the pointer to the context, which is represented by offset 0
+        let mut regs = [0_u64; 11];
+        let mut pc = 0;
+
+        loop {
+            let insn = self.insns.get(pc).ok_or(ProgramError::OutOfBounds { pc })?;
+            let dst = insn.dst as usize;
+            let src = insn.src as usize;
+            if dst >= regs.len() || src >= regs.len() {
+                return Err(ProgramError::OutOfBounds { pc });
+            }
+            pc += 1;
+
+            match insn.opcode {
+                BPF_LDX_MEM_W => {
+                    let addr = regs[src] as i64 + insn.off as i64;
+                    if addr < 0 || addr as usize + 4 > DEV_CTX_SIZE {
+                        return Err(ProgramError::OutOfBounds { pc: pc - 1 });
+                    }
+                    let addr = addr as usize;
+                    let word = [mem[addr], mem[addr + 1], mem[addr + 2], mem[addr + 3]];
+                    regs[dst] = u32::from_ne_bytes(word) as u64;
+                }
+                BPF_ALU_AND_K => regs[dst] = (regs[dst] as u32 & insn.imm as u32) as u64,
+                BPF_ALU_RSH_K => regs[dst] = ((regs[dst] as u32) >> (insn.imm as u32 & 31)) as u64,
+                BPF_ALU_MOV_K => regs[dst] = insn.imm as u32 as u64,
+                BPF_ALU_MOV_X => regs[dst] = regs[src] as u32 as u64,
+                BPF_JMP_JNE_K | BPF_JMP_JNE_X => {
+                    let operand = if insn.opcode == BPF_JMP_JNE_K {
+                        insn.imm as i64 as u64
+                    } else {
+                        regs[src]
+                    };
+                    if regs[dst] != operand {
+                        let target = pc as i64 + insn.off as i64;
+                        if target < 0 {
+                            return Err(ProgramError::OutOfBounds { pc: pc - 1 });
+                        }
+                        pc = target as usize;
+                    }
+                }
+                BPF_JMP_EXIT => return Ok(regs[0]),
+                opcode => {
+                    return Err(ProgramError::UnsupportedInstruction { opcode, pc: pc - 1 });
+                }
+            }
+        }
     }
 }
 
 fn bpf_dev_type(typ: LinuxDeviceType) -> Result<u32, ProgramError> {
     let dev_type: u32 = match typ {
-        LinuxDeviceType::C => libbpf_sys::BPF_DEVCG_DEV_CHAR,
+        LinuxDeviceType::C => BPF_DEVCG_DEV_CHAR,
         LinuxDeviceType::U => return Err(ProgramError::DeviceNotSupported("unbuffered char")),
-        LinuxDeviceType::B => libbpf_sys::BPF_DEVCG_DEV_BLOCK,
+        LinuxDeviceType::B => BPF_DEVCG_DEV_BLOCK,
         LinuxDeviceType::P => return Err(ProgramError::DeviceNotSupported("pipe device")),
         LinuxDeviceType::A => return Err(ProgramError::WildcardDevice),
     };
@@ -223,9 +330,9 @@ fn bpf_access(access: String) -> Result<u32, ProgramError> {
     let mut v = 0_u32;
     for c in access.chars() {
         let cur_access = match c {
-            'r' => libbpf_sys::BPF_DEVCG_ACC_READ,
-            'w' => libbpf_sys::BPF_DEVCG_ACC_WRITE,
-            'm' => libbpf_sys::BPF_DEVCG_ACC_MKNOD,
+            'r' => BPF_DEVCG_ACC_READ,
+            'w' => BPF_DEVCG_ACC_WRITE,
+            'm' => BPF_DEVCG_ACC_MKNOD,
             _ => return Err(ProgramError::InvalidAccess(c)),
         };
         v |= cur_access;
@@ -239,7 +346,7 @@ fn bpf_cgroup_dev_ctx(
     minor: u32,
     access: String,
 ) -> Result<Vec<u8>, ProgramError> {
-    let mut mem = Vec::with_capacity(12);
+    let mut mem = Vec::with_capacity(DEV_CTX_SIZE);
 
     let mut type_access = 0_u32;
     if let Ok(t) = bpf_dev_type(typ) {
@@ -271,6 +378,26 @@ mod tests {
         Ok(Program::from_rules(&em.rules, em.default_allow)?)
     }
 
+    fn device_rule(
+        allow: bool,
+        typ: LinuxDeviceType,
+        major: Option<i64>,
+        minor: Option<i64>,
+        access: &str,
+    ) -> LinuxDeviceCgroup {
+        let mut builder = LinuxDeviceCgroupBuilder::default()
+            .allow(allow)
+            .typ(typ)
+            .access(access);
+        if let Some(major) = major {
+            builder = builder.major(major);
+        }
+        if let Some(minor) = minor {
+            builder = builder.minor(minor);
+        }
+        builder.build().unwrap()
+    }
+
     #[test]
     fn test_devices_allow_single() {
         let rules = vec![
@@ -472,4 +599,189 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn test_instruction_roundtrip() {
+        let insn = Instruction::new(BPF_JMP_JNE_X, 1, 3, -2, 0x7fff_0000);
+        let bytes = insn.to_bytes();
+        assert_eq!(bytes[0], BPF_JMP_JNE_X);
+        assert_eq!(Instruction::from_bytes(&bytes), insn);
+    }
+
+    #[test]
+    fn test_disassemble() {
+        let rules = vec![
+            LinuxDeviceCgroupBuilder::default()
+                .allow(true)
+                .typ(LinuxDeviceType::C)
+                .major(10)
+                .minor(20)
+                .access("r")
+                .build()
+                .unwrap(),
+        ];
+        let prog = build_bpf_program(&Some(rules)).unwrap();
+
+        assert_eq!(
+            prog.disassemble(),
+            vec![
+                "ldxw r2, [r1+0x0]",
+                "and32 r2, 0xffff",
+                "ldxw r3, [r1+0x0]",
+                "rsh32 r3, 0x10",
+                "ldxw r4, [r1+0x4]",
+                "ldxw r5, [r1+0x8]",
+                "jne r2, 0x2, +0x7",
+                "mov32 r1, r3",
+                "and32 r1, 0x2",
+                "jne r1, r3, +0x4",
+                "jne r4, 0xa, +0x3",
+                "jne r5, 0x14, +0x2",
+                "mov32 r0, 0x1",
+                "exit",
+                "mov32 r0, 0x0",
+                "exit",
+            ]
+        );
+        assert_eq!(
+            prog.bytecodes().len(),
+            prog.instructions().len() * INSN_SIZE
+        );
+    }
+
+    #[test]
+    fn test_program_instructions() {
+        let prologue = [
+            "ldxw r2, [r1+0x0]",
+            "and32 r2, 0xffff",
+            "ldxw r3, [r1+0x0]",
+            "rsh32 r3, 0x10",
+            "ldxw r4, [r1+0x4]",
+            "ldxw r5, [r1+0x8]",
+        ];
+        let cases: Vec<(Vec<LinuxDeviceCgroup>, Vec<&str>)> = vec![
+            (vec![], vec!["mov32 r0, 0x0", "exit"]),
+            // all accesses and any minor, only the type and major are checked
+            (
+                vec![device_rule(true, LinuxDeviceType::C, Some(1), None, "rwm")],
+                vec![
+                    "jne r2, 0x2, +0x3",
+                    "jne r4, 0x1, +0x2",
+                    "mov32 r0, 0x1",
+                    "exit",
+                    "mov32 r0, 0x0",
+                    "exit",
+                ],
+            ),
+            // a wildcard allow becomes the default, denials are checked
+            (
+                vec![
+                    device_rule(true, LinuxDeviceType::A, None, None, "rwm"),
+                    device_rule(false, LinuxDeviceType::B, Some(8), Some(3), "rw"),
+                ],
+                vec![
+                    "jne r2, 0x1, +0x7",
+                    "mov32 r1, r3",
+                    "and32 r1, 0x6",
+                    "jne r1, r3, +0x4",
+                    "jne r4, 0x8, +0x3",
+                    "jne r5, 0x3, +0x2",
+                    "mov32 r0, 0x0",
+                    "exit",
+                    "mov32 r0, 0x1",
+                    "exit",
+                ],
+            ),
+            // the last rule is checked first
+            (
+                vec![
+                    device_rule(true, LinuxDeviceType::C, Some(1), Some(3), "rwm"),
+                    device_rule(true, LinuxDeviceType::B, Some(8), None, "r"),
+                ],
+                vec![
+                    "jne r2, 0x1, +0x6",
+                    "mov32 r1, r3",
+                    "and32 r1, 0x2",
+                    "jne r1, r3, +0x3",
+                    "jne r4, 0x8, +0x2",
+                    "mov32 r0, 0x1",
+                    "exit",
+                    "jne r2, 0x2, +0x4",
+                    "jne r4, 0x1, +0x3",
+                    "jne r5, 0x3, +0x2",
+                    "mov32 r0, 0x1",
+                    "exit",
+                    "mov32 r0, 0x0",
+                    "exit",
+                ],
+            ),
+        ];
+
+        for (rules, expected) in cases {
+            let prog = build_bpf_program(&Some(rules.clone())).unwrap();
+            let expected: Vec<&str> = prologue.iter().copied().chain(expected).collect();
+            assert_eq!(prog.disassemble(), expected, "{rules:?}");
+        }
+    }
+
+    #[cfg(target_endian = "little")]
+    #[test]
+    fn test_program_bytecodes() {
+        let prog = build_bpf_program(&Some(vec![])).unwrap();
+        let expected: [[u8; INSN_SIZE]; 8] = [
+            [0x61, 0x12, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
+            [0x54, 0x02, 0x00, 0x00, 0xff, 0xff, 0x00, 0x00],
+            [0x61, 0x13, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
+            [0x74, 0x03, 0x00, 0x00, 0x10, 0x00, 0x00, 0x00],
+            [0x61, 0x14, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00],
+            [0x61, 0x15, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00],
+            [0xb4, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
+            [0x95, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
+        ];
+        assert_eq!(prog.bytecodes(), expected.concat());
+    }
+
+    #[test]
+    fn test_devices_match_emulator() {
+        let rule_sets = vec![
+            vec![],
+            vec![device_rule(true, LinuxDeviceType::C, Some(1), None, "rwm")],
+            vec![
+                device_rule(true, LinuxDeviceType::C, None, None, "m"),
+                device_rule(true, LinuxDeviceType::B, Some(8), Some(-1), "rw"),
+                device_rule(false, LinuxDeviceType::B, Some(8), Some(1), "w"),
+            ],
+            vec![
+                device_rule(true, LinuxDeviceType::A, None, None, "rwm"),
+                device_rule(false, LinuxDeviceType::C, Some(1), Some(3), "rw"),
+                device_rule(true, LinuxDeviceType::C, Some(1), Some(3), "r"),
+            ],
+        ];
+
+        for rules in rule_sets {
+            let mut em = crate::v2::devices::emulator::Emulator::with_default_allow(false);
+            em.add_rules(&rules);
+            let prog = Program::from_rules(&em.rules, em.default_allow).unwrap();
+
+            for ty in [
+                LinuxDeviceType::C,
+                LinuxDeviceType::U,
+                LinuxDeviceType::P,
+                LinuxDeviceType::B,
+            ] {
+                for major in [1_u32, 8, 99] {
+                    for minor in [0_u32, 1, 3] {
+                        for access in ["", "r", "w", "m", "rw", "rwm"] {
+                            let ret = prog.execute(ty, major, minor, access.to_string()).unwrap();
+                            assert_eq!(
+                                ret == 1,
+                                em.allows(ty, major, minor, access),
+                                "{rules:?}: {ty:?} {major}:{minor} {access}"
+                            );
+                        }
+                    }
+                }
+            }
+        }
+    }
 }
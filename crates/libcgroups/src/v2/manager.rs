@@ -6,6 +6,7 @@ use std::time::Duration;
 
 use nix::errno::Errno;
 use nix::unistd::Pid;
+use oci_spec::runtime::LinuxResources;
 
 use super::controller::Controller;
 use super::controller_type::{
@@ -13,7 +14,6 @@ use super::controller_type::{
 };
 use super::cpu::{Cpu, V2CpuControllerError, V2CpuStatsError};
 use super::cpuset::CpuSet;
-#[cfg(feature = "cgroupsv2_devices")]
 use super::devices::Devices;
 use super::freezer::{Freezer, V2FreezerError};
 use super::hugetlb::{HugeTlb, V2HugeTlbControllerError, V2HugeTlbStatsError};
@@ -29,6 +29,8 @@ use crate::common::{
 use crate::stats::{PidStatsError, Stats, StatsProvider};
 
 pub const CGROUP_KILL: &str = "cgroup.kill";
+/// Name of the leaf cgroup the processes of a populated root cgroup are moved into
+pub const CGROUP_INIT_LEAF: &str = "init";
 
 #[derive(thiserror::Error, Debug)]
 pub enum V2ManagerError {
@@ -55,7 +57,6 @@ pub enum V2ManagerError {
     UnifiedController(#[from] V2UnifiedError),
     #[error(transparent)]
     FreezerController(#[from] V2FreezerError),
-    #[cfg(feature = "cgroupsv2_devices")]
     #[error(transparent)]
     DevicesController(#[from] super::devices::controller::DevicesControllerError),
 
@@ -71,6 +72,35 @@ pub enum V2ManagerError {
     IoStats(#[from] V2IoStatsError),
 }
 
+/// Controllers which could be delegated to a cgroup
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Delegation {
+    /// Controllers enabled for the cgroup
+    pub controllers: Vec<ControllerType>,
+    /// Controllers available at the root which could not be enabled for the cgroup
+    pub missing: Vec<ControllerType>,
+    /// If the cgroup exists and processes can be attached to it
+    pub created: bool,
+}
+
+impl Delegation {
+    fn new(
+        available: Vec<ControllerType>,
+        controllers: Vec<ControllerType>,
+        created: bool,
+    ) -> Self {
+        let missing = available
+            .into_iter()
+            .filter(|c| !controllers.contains(c))
+            .collect();
+        Self {
+            controllers,
+            missing,
+            created,
+        }
+    }
+}
+
 /// Represents a management interface for a cgroup located at `{root_path}/{cgroup_path}`
 ///
 /// This struct does not have ownership of the cgroup
@@ -116,12 +146,41 @@ impl Manager {
 
     /// Creates a unified cgroup at `self.full_path` and attaches a process to it
     fn create_unified_cgroup(&self, pid: Pid) -> Result<(), V2ManagerError> {
-        let controllers: Vec<String> = util::get_available_controllers(&self.root_path)?
-            .iter()
-            .map(|c| format!("+{c}"))
-            .collect();
+        let delegation = self.delegate()?;
+        if !delegation.created {
+            return Ok(());
+        }
+
+        tracing::debug!(
+            "delegated controllers to {:?}: {:?}",
+            self.full_path,
+            delegation.controllers
+        );
+        if !delegation.missing.is_empty() {
+            tracing::warn!(
+                "controllers {:?} could not be delegated to {:?}; limits requiring them will fail when applied",
+                delegation.missing,
+                self.full_path
+            );
+        }
 
-        Self::enable_controllers(&self.root_path, &controllers);
+        self.attach_pid(pid)
+    }
+
+    /// Prepares the hierarchy from the root up to the parent of `self.full_path`,
+    /// enabling the available controllers in the subtree of every cgroup along the way.
+    ///
+    /// When running nested inside another container, the root of the hierarchy is
+    /// usually populated. Due to the "no internal processes" rule, controllers cannot
+    /// be enabled in the subtree of such a cgroup, so the existing processes are moved
+    /// into a leaf cgroup named `init` first.
+    pub fn delegate(&self) -> Result<Delegation, V2ManagerError> {
+        let available = util::get_available_controllers(&self.root_path)?;
+
+        if !util::is_host_root_cgroup(&self.root_path) {
+            self.evacuate_root()?;
+        }
+        let mut controllers = Self::enable_controllers(&self.root_path, &available);
 
         let mut current_path = self.root_path.clone();
         let mut components = self
@@ -147,7 +206,7 @@ impl Manager {
                             "rootless cgroup: cannot create {current_path:?}: {err}; \
                              leaving process in its parent cgroup"
                         );
-                        return Ok(());
+                        return Ok(Delegation::new(available, Vec::new(), false));
                     }
                     Err(err) => return Err(err.into()),
                 }
@@ -156,11 +215,56 @@ impl Manager {
             // last component cannot have subtree_control enabled due to internal process constraint
             // if this were set, writing to the cgroups.procs file will fail with Erno 16 (device or resource busy)
             if components.peek().is_some() {
-                Self::enable_controllers(&current_path, &controllers);
+                controllers = Self::enable_controllers(&current_path, &controllers);
             }
         }
 
-        self.attach_pid(pid)
+        Ok(Delegation::new(available, controllers, true))
+    }
+
+    // Moves the processes of the root of the hierarchy into the `init` leaf cgroup,
+    // so that controllers can be enabled in the subtree of the root. This is what
+    // container engines do when running nested, e.g. the docker-in-docker entrypoint.
+    fn evacuate_root(&self) -> Result<(), V2ManagerError> {
+        let procs_path = self.root_path.join(CGROUP_PROCS);
+        let procs = match common::read_cgroup_file(&procs_path) {
+            Ok(procs) => procs,
+            Err(err) if self.rootless && Self::is_permission_error(&err) => return Ok(()),
+            Err(err) => return Err(err.into()),
+        };
+        let pids: Vec<&str> = procs.lines().filter(|l| !l.trim().is_empty()).collect();
+        if pids.is_empty() {
+            return Ok(());
+        }
+
+        let init_path = self.root_path.join(CGROUP_INIT_LEAF);
+        tracing::debug!(
+            "root cgroup {:?} has {} processes, moving them into {:?}",
+            self.root_path,
+            pids.len(),
+            init_path
+        );
+        if !init_path.exists() {
+            match fs::create_dir(&init_path).wrap_create_dir(&init_path) {
+                Ok(()) => {}
+                Err(err) if self.rootless && Self::is_permission_error(&err) => {
+                    tracing::debug!("rootless cgroup: cannot create {init_path:?}: {err}");
+                    return Ok(());
+                }
+                Err(err) => return Err(err.into()),
+            }
+        }
+
+        let init_procs_path = init_path.join(CGROUP_PROCS);
+        for pid in pids {
+            if let Err(err) = common::write_cgroup_file_str(&init_procs_path, pid) {
+                // the process may have exited in the meantime or may not be movable,
+                // e.g. when it belongs to another user.
+                tracing::debug!("could not move process {pid} into {init_path:?}: {err}");
+            }
+        }
+
+        Ok(())
     }
 
     fn attach_pid(&self, pid: Pid) -> Result<(), V2ManagerError> {
@@ -182,24 +286,95 @@ impl Manager {
 
     // best-effort enabling of `controllers` in `{path}/cgroup.subtree_control`.
     // Writing an already-enabled controller is idempotent in cgroup v2, so we
-    // simply attempt to write each one and ignore errors.
+    // simply attempt to write each one and ignore errors. Returns the controllers
+    // that have been enabled.
     // See https://github.com/youki-dev/youki/issues/3597#issuecomment-4749947856
-    fn enable_controllers(path: &Path, controllers: &[String]) {
+    fn enable_controllers(path: &Path, controllers: &[ControllerType]) -> Vec<ControllerType> {
+        let mut enabled = Vec::with_capacity(controllers.len());
         for controller in controllers {
-            if let Err(err) =
-                common::write_cgroup_file_str(path.join(CGROUP_SUBTREE_CONTROL), controller)
-            {
-                tracing::debug!(
+            match common::write_cgroup_file_str(
+                path.join(CGROUP_SUBTREE_CONTROL),
+                &format!("+{controller}"),
+            ) {
+                Ok(()) => enabled.push(*controller),
+                Err(err) => tracing::debug!(
                     "could not enable {controller} in {path:?}/{CGROUP_SUBTREE_CONTROL}: {err}; \
                      a limit requiring it will fail when applied"
-                );
+                ),
             }
         }
+
+        enabled
     }
 
     pub fn any(self) -> AnyCgroupManager {
         AnyCgroupManager::V2(self)
     }
+
+    // Applies the limits of all controllers. In rootless mode only the
+    // controllers delegated to the cgroup can be used, limits of the others are
+    // skipped.
+    fn apply_controllers(&self, controller_opt: &ControllerOpt) -> Result<(), V2ManagerError> {
+        let delegated = if self.rootless {
+            Some(util::get_available_controllers(&self.full_path).unwrap_or_default())
+        } else {
+            None
+        };
+
+        for controller in CONTROLLER_TYPES {
+            if let Some(delegated) = &delegated
+                && !delegated.contains(controller)
+            {
+                if Self::has_limits(*controller, controller_opt.resources) {
+                    tracing::warn!(
+                        "rootless cgroup: {controller} controller is not delegated to {:?}, \
+                         its limits are not enforced",
+                        self.full_path
+                    );
+                }
+                continue;
+            }
+
+            match controller {
+                ControllerType::Cpu => Cpu::apply(controller_opt, &self.full_path)?,
+                ControllerType::CpuSet => CpuSet::apply(controller_opt, &self.full_path)?,
+                ControllerType::HugeTlb => HugeTlb::apply(controller_opt, &self.full_path)?,
+                ControllerType::Io => Io::apply(controller_opt, &self.full_path)?,
+                ControllerType::Memory => Memory::apply(controller_opt, &self.full_path)?,
+                ControllerType::Pids => Pids::apply(controller_opt, &self.full_path)?,
+            }
+        }
+
+        Ok(())
+    }
+
+    // Returns true if the resources request limits enforced by `controller`
+    fn has_limits(controller: ControllerType, resources: &LinuxResources) -> bool {
+        match controller {
+            ControllerType::Cpu => resources.cpu().as_ref().is_some_and(|cpu| {
+                cpu.shares().is_some()
+                    || cpu.quota().is_some()
+                    || cpu.period().is_some()
+                    || cpu.idle().is_some()
+                    || cpu.burst().is_some()
+            }),
+            ControllerType::CpuSet => resources
+                .cpu()
+                .as_ref()
+                .is_some_and(|cpu| cpu.cpus().is_some() || cpu.mems().is_some()),
+            ControllerType::HugeTlb => resources
+                .hugepage_limits()
+                .as_ref()
+                .is_some_and(|limits| !limits.is_empty()),
+            ControllerType::Io => resources.block_io().is_some(),
+            ControllerType::Memory => resources.memory().as_ref().is_some_and(|memory| {
+                memory.limit().is_some()
+                    || memory.reservation().is_some()
+                    || memory.swap().is_some()
+            }),
+            ControllerType::Pids => resources.pids().is_some(),
+        }
+    }
 }
 
 impl CgroupManager for Manager {
@@ -214,20 +389,28 @@ impl CgroupManager for Manager {
     }
 
     fn apply(&self, controller_opt: &ControllerOpt) -> Result<(), Self::Error> {
-        for controller in CONTROLLER_TYPES {
-            match controller {
-                ControllerType::Cpu => Cpu::apply(controller_opt, &self.full_path)?,
-                ControllerType::CpuSet => CpuSet::apply(controller_opt, &self.full_path)?,
-                ControllerType::HugeTlb => HugeTlb::apply(controller_opt, &self.full_path)?,
-                ControllerType::Io => Io::apply(controller_opt, &self.full_path)?,
-                ControllerType::Memory => Memory::apply(controller_opt, &self.full_path)?,
-                ControllerType::Pids => Pids::apply(controller_opt, &self.full_path)?,
+        self.apply_controllers(controller_opt)?;
+
+        match Devices::apply(controller_opt, &self.full_path) {
+            Ok(()) => {}
+            // attaching a BPF program needs privileges in the initial user namespace
+            Err(err) if self.rootless => {
+                if controller_opt
+                    .resources
+                    .devices()
+                    .as_ref()
+                    .is_some_and(|devices| !devices.is_empty())
+                {
+                    tracing::warn!("rootless cgroup: device rules are not enforced: {err}");
+                } else {
+                    tracing::debug!(
+                        "rootless cgroup: default device rules are not enforced: {err}"
+                    );
+                }
             }
+            Err(err) => return Err(err.into()),
         }
 
-        #[cfg(feature = "cgroupsv2_devices")]
-        Devices::apply(controller_opt, &self.full_path)?;
-
         for pseudoctlr in PSEUDO_CONTROLLER_TYPES {
             if let PseudoControllerType::Unified = pseudoctlr {
                 Unified::apply(
@@ -299,3 +482,117 @@ impl CgroupManager for Manager {
         Ok(common::get_all_pids(&self.full_path)?)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use oci_spec::runtime::{LinuxMemoryBuilder, LinuxPidsBuilder, LinuxResourcesBuilder};
+
+    use super::*;
+    use crate::test::set_fixture;
+
+    fn setup_root(root: &Path, procs: &str) {
+        set_fixture(root, util::CGROUP_CONTROLLERS, "cpu memory pids").unwrap();
+        set_fixture(root, CGROUP_SUBTREE_CONTROL, "").unwrap();
+        set_fixture(root, CGROUP_PROCS, procs).unwrap();
+    }
+
+    #[test]
+    fn test_delegate_creates_cgroup_path() {
+        let tmp = tempfile::tempdir().unwrap();
+        setup_root(tmp.path(), "");
+        let manager =
+            Manager::new(tmp.path().to_path_buf(), PathBuf::from("/youki/container")).unwrap();
+
+        let delegation = manager.delegate().unwrap();
+
+        assert!(delegation.created);
+        assert!(tmp.path().join("youki").join("container").is_dir());
+        assert!(!tmp.path().join(CGROUP_INIT_LEAF).exists());
+        // the intermediate cgroup is not a real cgroup, so nothing can be enabled in it
+        assert!(delegation.controllers.is_empty());
+        assert_eq!(
+            delegation.missing,
+            vec![
+                ControllerType::Cpu,
+                ControllerType::Memory,
+                ControllerType::Pids
+            ]
+        );
+    }
+
+    #[test]
+    fn test_delegate_evacuates_populated_namespace_root() {
+        let tmp = tempfile::tempdir().unwrap();
+        setup_root(tmp.path(), "1\n42\n");
+        set_fixture(tmp.path(), util::CGROUP_TYPE, "domain").unwrap();
+        let init_path = tmp.path().join(CGROUP_INIT_LEAF);
+        fs::create_dir(&init_path).unwrap();
+        set_fixture(&init_path, CGROUP_PROCS, "").unwrap();
+        let manager = Manager::new(tmp.path().to_path_buf(), PathBuf::from("/container")).unwrap();
+
+        let delegation = manager.delegate().unwrap();
+
+        assert!(delegation.created);
+        // a regular file keeps only the last written pid
+        assert_eq!(
+            fs::read_to_string(init_path.join(CGROUP_PROCS)).unwrap(),
+            "42"
+        );
+        assert_eq!(
+            delegation.controllers,
+            vec![
+                ControllerType::Cpu,
+                ControllerType::Memory,
+                ControllerType::Pids
+            ]
+        );
+        assert!(delegation.missing.is_empty());
+    }
+
+    #[test]
+    fn test_rootless_skips_controllers_not_delegated() {
+        let tmp = tempfile::tempdir().unwrap();
+        let cgroup_path = tmp.path().join("container");
+        fs::create_dir(&cgroup_path).unwrap();
+        set_fixture(&cgroup_path, util::CGROUP_CONTROLLERS, "pids").unwrap();
+        set_fixture(&cgroup_path, "pids.max", "").unwrap();
+        let resources = LinuxResourcesBuilder::default()
+            .memory(
+                LinuxMemoryBuilder::default()
+                    .limit(1024 * 1024)
+                    .build()
+                    .unwrap(),
+            )
+            .pids(LinuxPidsBuilder::default().limit(10).build().unwrap())
+            .build()
+            .unwrap();
+        let controller_opt = ControllerOpt {
+            resources: &resources,
+            freezer_state: None,
+            oom_score_adj: None,
+            disable_oom_killer: false,
+        };
+
+        let manager = Manager::new(tmp.path().to_path_buf(), PathBuf::from("/container")).unwrap();
+        assert!(manager.apply_controllers(&controller_opt).is_err());
+
+        let manager = manager.with_rootless(true);
+        manager.apply_controllers(&controller_opt).unwrap();
+        assert_eq!(
+            fs::read_to_string(cgroup_path.join("pids.max")).unwrap(),
+            "10"
+        );
+        assert!(!cgroup_path.join("memory.max").exists());
+    }
+
+    #[test]
+    fn test_delegate_does_not_evacuate_host_root() {
+        let tmp = tempfile::tempdir().unwrap();
+        setup_root(tmp.path(), "1\n42\n");
+        let manager = Manager::new(tmp.path().to_path_buf(), PathBuf::from("/container")).unwrap();
+
+        manager.delegate().unwrap();
+
+        assert!(!tmp.path().join(CGROUP_INIT_LEAF).exists());
+    }
+}
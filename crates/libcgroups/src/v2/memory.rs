@@ -11,6 +11,7 @@ const CGROUP_MEMORY_MAX: &str = "memory.max";
 const CGROUP_MEMORY_LOW: &str = "memory.low";
 const MEMORY_STAT: &str = "memory.stat";
 const MEMORY_PSI: &str = "memory.pressure";
+const MEMORY_EVENTS: &str = "memory.events";
 
 #[derive(thiserror::Error, Debug)]
 pub enum V2MemoryControllerError {
@@ -59,6 +60,7 @@ impl StatsProvider for Memory {
             memswap: Self::get_memory_data(cgroup_path, "memory.swap", "fail")?,
             hierarchy: true,
             stats: stats::parse_flat_keyed_data(&cgroup_path.join(MEMORY_STAT))?,
+            oom_kill_count: Self::get_oom_kill_count(cgroup_path)?,
             psi: stats::psi_stats(&cgroup_path.join(MEMORY_PSI))?,
             ..Default::default()
         };
@@ -98,6 +100,11 @@ impl Memory {
         })
     }
 
+    fn get_oom_kill_count(cgroup_path: &Path) -> Result<u64, V2MemoryStatsError> {
+        let events = stats::parse_flat_keyed_data(&cgroup_path.join(MEMORY_EVENTS))?;
+        Ok(events.get("oom_kill").copied().unwrap_or_default())
+    }
+
     fn set<P: AsRef<Path>>(path: P, val: i64) -> Result<(), WrappedIoError> {
         if val == 0 {
             Ok(())
@@ -401,4 +408,14 @@ mod tests {
 
         assert_eq!(actual, expected);
     }
+
+    #[test]
+    fn test_get_oom_kill_count() {
+        let tmp = tempfile::tempdir().unwrap();
+        let events = ["low 0", "high 0", "max 4", "oom 2", "oom_kill 1"].join("\n");
+        set_fixture(tmp.path(), "memory.events", &events).unwrap();
+
+        let actual = Memory::get_oom_kill_count(tmp.path()).expect("get oom kill count");
+        assert_eq!(actual, 1);
+    }
 }
@@ -2,7 +2,6 @@ mod controller;
 pub mod controller_type;
 mod cpu;
 mod cpuset;
-#[cfg(feature = "cgroupsv2_devices")]
 pub mod devices;
 mod freezer;
 mod hugetlb;
@@ -11,6 +11,7 @@ use crate::common::{self, WrappedIoError};
 
 pub const CGROUP_CONTROLLERS: &str = "cgroup.controllers";
 pub const CGROUP_SUBTREE_CONTROL: &str = "cgroup.subtree_control";
+pub const CGROUP_TYPE: &str = "cgroup.type";
 
 #[derive(thiserror::Error, Debug)]
 pub enum V2UtilError {
@@ -51,6 +52,13 @@ pub fn get_unified_mount_point() -> Result<PathBuf, V2UtilError> {
         .ok_or(V2UtilError::CouldNotFind)
 }
 
+/// Checks if `path` is the root cgroup of the host. Only the host root cgroup is exempt from
+/// the "no internal processes" rule and, unlike any other cgroup, has no `cgroup.type` file.
+/// The root of a cgroup namespace, e.g. when running inside a container, is not a host root.
+pub fn is_host_root_cgroup<P: AsRef<Path>>(path: P) -> bool {
+    !path.as_ref().join(CGROUP_TYPE).exists()
+}
+
 /// Reads the `{root_path}/cgroup.controllers` file to get the list of the controllers that are
 /// available in this cgroup
 pub fn get_available_controllers<P: AsRef<Path>>(
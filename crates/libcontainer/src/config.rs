@@ -1,10 +1,12 @@
+use std::collections::HashMap;
 use std::fs;
 use std::io::{BufReader, BufWriter, Write};
 use std::path::{Path, PathBuf};
 
-use oci_spec::runtime::{Hooks, Spec};
+use oci_spec::runtime::{Hooks, LinuxNamespaceType, LinuxNetDevice, Spec};
 use serde::{Deserialize, Serialize};
 
+use crate::network::network_device::NetDeviceConfig;
 use crate::utils;
 
 pub enum PersonalityDomain {
@@ -49,19 +51,46 @@ const YOUKI_CONFIG_NAME: &str = "youki_config.json";
 pub struct YoukiConfig {
     pub hooks: Option<Hooks>,
     pub cgroup_path: PathBuf,
+    /// Network devices moved into a network namespace that outlives the container,
+    /// keyed by their name on the host. They are moved back on delete.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub net_devices: Option<HashMap<String, LinuxNetDevice>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub net_ns_path: Option<PathBuf>,
+    /// Configuration of the network devices on the host before they were
+    /// moved, keyed by their name on the host. It is restored when they are
+    /// moved back.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub net_device_configs: HashMap<String, NetDeviceConfig>,
+    /// The container processes stay in the session keyring of the caller.
+    /// Processes executed later in the container follow the same choice.
+    #[serde(default)]
+    pub no_new_keyring: bool,
 }
 
 impl YoukiConfig {
     pub fn from_spec(spec: &Spec, container_id: &str) -> Result<Self> {
+        let linux = spec.linux().as_ref().ok_or(ConfigError::MissingLinux)?;
+        // A network namespace created for the container is destroyed together with it,
+        // and the kernel then moves physical devices back on its own.
+        let net_ns_path = linux.namespaces().as_ref().and_then(|namespaces| {
+            namespaces
+                .iter()
+                .find(|ns| ns.typ() == LinuxNamespaceType::Network)
+                .and_then(|ns| ns.path().clone())
+        });
+        let net_devices = linux
+            .net_devices()
+            .clone()
+            .filter(|devices| !devices.is_empty() && net_ns_path.is_some());
+
         Ok(YoukiConfig {
             hooks: spec.hooks().clone(),
-            cgroup_path: utils::get_cgroup_path(
-                spec.linux()
-                    .as_ref()
-                    .ok_or(ConfigError::MissingLinux)?
-                    .cgroups_path(),
-                container_id,
-            ),
+            cgroup_path: utils::get_cgroup_path(linux.cgroups_path(), container_id),
+            net_ns_path: net_devices.as_ref().and(net_ns_path),
+            net_devices,
+            net_device_configs: HashMap::new(),
+            no_new_keyring: false,
         })
     }
 
@@ -154,4 +183,52 @@ mod tests {
         assert!(matches!(result, Err(ConfigError::LoadIO { .. })));
         Ok(())
     }
+
+    #[test]
+    fn test_config_from_spec_net_devices() -> Result<()> {
+        use oci_spec::runtime::{LinuxBuilder, LinuxNamespaceBuilder, SpecBuilder};
+
+        let net_devices = HashMap::from([("eth1".to_string(), LinuxNetDevice::default())]);
+        let namespace = |path: Option<&str>| {
+            let builder = LinuxNamespaceBuilder::default().typ(LinuxNamespaceType::Network);
+            match path {
+                Some(path) => builder.path(path).build(),
+                None => builder.build(),
+            }
+        };
+        let spec_with = |ns| -> Result<Spec> {
+            Ok(SpecBuilder::default()
+                .linux(
+                    LinuxBuilder::default()
+                        .namespaces(vec![ns])
+                        .net_devices(net_devices.clone())
+                        .build()?,
+                )
+                .build()?)
+        };
+
+        let config = YoukiConfig::from_spec(&spec_with(namespace(None)?)?, "sample")?;
+        assert_eq!(config.net_devices, None);
+        assert_eq!(config.net_ns_path, None);
+
+        let config =
+            YoukiConfig::from_spec(&spec_with(namespace(Some("/run/netns/test"))?)?, "sample")?;
+        assert_eq!(config.net_devices, Some(net_devices));
+        assert_eq!(config.net_ns_path, Some(PathBuf::from("/run/netns/test")));
+
+        // The configuration of the devices on the host is recorded once they
+        // are moved.
+        let mut config = config;
+        config.net_device_configs.insert(
+            "eth1".to_string(),
+            NetDeviceConfig {
+                mtu: Some(9000),
+                ..Default::default()
+            },
+        );
+        let tmp = tempfile::tempdir()?;
+        config.save(&tmp)?;
+        assert_eq!(YoukiConfig::load(&tmp)?, config);
+        Ok(())
+    }
 }
@@ -7,7 +7,7 @@ use super::init_builder::InitContainerBuilder;
 use super::tenant_builder::TenantContainerBuilder;
 use crate::error::{ErrInvalidID, ErrInvalidSpec, LibcontainerError};
 use crate::syscall::syscall::SyscallType;
-use crate::utils::PathBufExt;
+use crate::utils::{PathBufExt, RootlessMode};
 use crate::workload::{self, Executor};
 
 pub struct ContainerBuilder {
@@ -22,11 +22,18 @@ pub struct ContainerBuilder {
     pub(super) pid_file: Option<PathBuf>,
     /// Socket to communicate the file descriptor of the ptty
     pub(super) console_socket: Option<PathBuf>,
+    /// Socket to communicate the file descriptor of the ptty to the caller
+    /// itself when the container runs in the foreground
+    pub(super) terminal_socket: Option<OwnedFd>,
     /// File descriptors to be passed into the container process
     pub(super) preserve_fds: i32,
     /// The function that actually runs on the container init process. Default
     /// is to execute the specified command in the oci spec.
     pub(super) executor: Box<dyn Executor>,
+    /// Do not create a new session keyring for the container
+    pub(super) no_new_keyring: bool,
+    /// Whether the container runs in rootless mode
+    pub(super) rootless: RootlessMode,
     // RawFd set to stdin of the container init process.
     pub stdin: Option<OwnedFd>,
     // RawFd set to stdout of the container init process.
@@ -77,8 +84,11 @@ impl ContainerBuilder {
             syscall,
             pid_file: None,
             console_socket: None,
+            terminal_socket: None,
             preserve_fds: 0,
             executor: workload::default::get_executor(),
+            no_new_keyring: false,
+            rootless: RootlessMode::default(),
             stdin: None,
             stdout: None,
             stderr: None,
@@ -232,6 +242,30 @@ impl ContainerBuilder {
         self
     }
 
+    /// Sets the socket on which the pseudoterminal master is sent when the
+    /// container runs in the foreground and its process requests a terminal.
+    /// This lets the caller proxy the terminal itself instead of passing a
+    /// console socket. The socket is closed without sending anything if no
+    /// terminal is needed.
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use libcontainer::container::builder::ContainerBuilder;
+    /// # use libcontainer::syscall::syscall::SyscallType;
+    /// # use libcontainer::tty;
+    ///
+    /// let (socket, container_socket) = tty::setup_console_socketpair().unwrap();
+    /// ContainerBuilder::new(
+    ///     "74f1a4cb3801".to_owned(),
+    ///     SyscallType::default(),
+    /// )
+    /// .with_terminal_socket(Some(container_socket));
+    /// ```
+    pub fn with_terminal_socket(mut self, socket: Option<OwnedFd>) -> Self {
+        self.terminal_socket = socket;
+        self
+    }
+
     /// Sets the number of additional file descriptors which will be passed into
     /// the container process.
     /// # Example
@@ -270,6 +304,49 @@ impl ContainerBuilder {
         self
     }
 
+    /// Sets whether the container processes should stay in the session
+    /// keyring of the caller. By default every container gets its own
+    /// session keyring named `_ses.<container id>`.
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use libcontainer::container::builder::ContainerBuilder;
+    /// # use libcontainer::syscall::syscall::SyscallType;
+    ///
+    /// ContainerBuilder::new(
+    ///     "74f1a4cb3801".to_owned(),
+    ///     SyscallType::default(),
+    /// )
+    /// .with_no_new_keyring(true);
+    /// ```
+    pub fn with_no_new_keyring(mut self, no_new_keyring: bool) -> Self {
+        self.no_new_keyring = no_new_keyring;
+        self
+    }
+
+    /// Sets whether the container runs in rootless mode. In rootless mode
+    /// cgroup limits that cannot be enforced because the controller has not
+    /// been delegated to the user are skipped, and the systemd user instance
+    /// manages the cgroup. By default, rootless mode is used when the caller
+    /// is not root or runs in a user namespace.
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use libcontainer::container::builder::ContainerBuilder;
+    /// # use libcontainer::syscall::syscall::SyscallType;
+    /// # use libcontainer::utils::RootlessMode;
+    ///
+    /// ContainerBuilder::new(
+    ///     "74f1a4cb3801".to_owned(),
+    ///     SyscallType::default(),
+    /// )
+    /// .with_rootless(RootlessMode::Enabled);
+    /// ```
+    pub fn with_rootless(mut self, rootless: RootlessMode) -> Self {
+        self.rootless = rootless;
+        self
+    }
+
     /// Sets the stdin of the container, for those who use libcontainer as a library,
     /// the container stdin may have to be set to an opened file descriptor
     /// rather than the stdin of the current process.
@@ -339,6 +416,22 @@ impl ContainerBuilder {
         self
     }
 
+    /// Returns the socket to send the pty master on, if the container process
+    /// runs in the foreground with a terminal and no console socket is given.
+    pub(super) fn take_terminal_socket(&mut self, spec: &Spec, detached: bool) -> Option<OwnedFd> {
+        let terminal = spec
+            .process()
+            .as_ref()
+            .and_then(|p| p.terminal())
+            .unwrap_or(false);
+        let socket = self.terminal_socket.take();
+        if terminal && !detached && self.console_socket.is_none() {
+            socket
+        } else {
+            None
+        }
+    }
+
     pub(super) fn check_terminal(
         &self,
         spec: &Spec,
@@ -27,6 +27,10 @@ pub(super) struct ContainerBuilderImpl {
     pub syscall: SyscallType,
     /// Flag indicating if systemd should be used for cgroup management
     pub use_systemd: bool,
+    /// Mount point of the cgroup hierarchy, defaults to /sys/fs/cgroup if None
+    pub cgroup_root: Option<PathBuf>,
+    /// Flag indicating if the container runs in rootless mode
+    pub rootless: bool,
     /// Id of the container
     pub container_id: String,
     /// OCI compliant runtime spec
@@ -62,6 +66,8 @@ pub(super) struct ContainerBuilderImpl {
     pub as_sibling: bool,
     // Run the process in an (existing) sub-cgroup(s)
     pub sub_cgroup_path: Option<String>,
+    // Do not create a new session keyring for the container
+    pub no_new_keyring: bool,
     // Asm process label for the process commonly used with selinux.
     // TODO: youki does not support selinux yet
     #[allow(dead_code)]
@@ -114,6 +120,8 @@ impl ContainerBuilderImpl {
             cgroup_path: final_cgroups_path,
             systemd_cgroup: self.use_systemd || self.user_ns_config.is_some(),
             container_name: self.container_id.to_owned(),
+            cgroup_root: self.cgroup_root.to_owned(),
+            rootless: self.rootless,
         };
         let process = self
             .spec
@@ -174,6 +182,7 @@ impl ContainerBuilderImpl {
         let container_args = ContainerArgs {
             container_type: self.container_type,
             syscall: self.syscall,
+            container_id: self.container_id.to_owned(),
             spec: Rc::clone(&self.spec),
             rootfs: self.rootfs.to_owned(),
             console_socket: self.console_socket.as_ref().map(|c| c.as_raw_fd()),
@@ -190,13 +199,16 @@ impl ContainerBuilderImpl {
             stderr: self.stderr.as_ref().map(|x| x.as_raw_fd()),
             as_sibling: self.as_sibling,
             pid_file: self.pid_file.to_owned(),
+            no_new_keyring: self.no_new_keyring,
         };
 
-        let init_pid = process::container_main_process::container_main_process(&container_args)
-            .map_err(|err| {
-                tracing::error!("failed to run container process {}", err);
-                LibcontainerError::MainProcess(err)
-            })?;
+        let (init_pid, startup_timings) = process::container_main_process::container_main_process(
+            &container_args,
+        )
+        .map_err(|err| {
+            tracing::error!("failed to run container process {}", err);
+            LibcontainerError::MainProcess(err)
+        })?;
 
         let mut intel_rdt_dir = None;
         let mut intel_rdt_monitoring_dir = None;
@@ -218,6 +230,12 @@ impl ContainerBuilderImpl {
                 .set_intel_rdt_dir(intel_rdt_dir)
                 .set_intel_rdt_monitoring_dir(intel_rdt_monitoring_dir)
                 .save()?;
+
+            // The timings only help to diagnose slow containers, they are not
+            // worth failing the creation for.
+            if let Err(err) = startup_timings.save(&container.root) {
+                tracing::warn!(?err, "failed to save startup timings");
+            }
         }
 
         Ok(init_pid)
@@ -231,6 +249,8 @@ impl ContainerBuilderImpl {
                 cgroup_path: cgroups_path,
                 systemd_cgroup: self.use_systemd || self.user_ns_config.is_some(),
                 container_name: self.container_id.to_string(),
+                cgroup_root: self.cgroup_root.to_owned(),
+                rootless: self.rootless,
             })?;
 
         let mut errors = Vec::new();
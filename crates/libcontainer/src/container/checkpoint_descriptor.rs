@@ -0,0 +1,326 @@
+//! Descriptor that youki writes next to the CRIU images of a checkpoint. It
+//! records which container and bundle the images belong to and the host they
+//! were taken on, so that a checkpoint can be validated before it is restored.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use libcgroups::common::DEFAULT_CGROUP_ROOT;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use super::container_checkpoint::{CheckpointError, DESCRIPTORS_JSON};
+use super::container_criu::{check_criu_version, criu_path};
+
+/// File name of the descriptor in the image directory
+pub const CHECKPOINT_DESCRIPTOR_FILE: &str = "youki-checkpoint.json";
+/// Version of the descriptor format written by this version of youki
+pub const CHECKPOINT_DESCRIPTOR_VERSION: u32 = 1;
+
+// CRIU writes the inventory of a dump last, a pre-dump or dump without it is
+// incomplete.
+const CRIU_INVENTORY_FILE: &str = "inventory.img";
+const KERNEL_RELEASE_PATH: &str = "/proc/sys/kernel/osrelease";
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct CheckpointDescriptor {
+    pub version: u32,
+    pub container_id: String,
+    pub bundle: PathBuf,
+    /// SHA-256 of the config.json of the bundle
+    pub spec_digest: String,
+    pub cgroup_path: PathBuf,
+    /// Cgroup setup of the host, one of unified, legacy or hybrid
+    pub cgroup_setup: String,
+    pub systemd_cgroup: bool,
+    /// Namespaces of the container, as named in /proc/<pid>/ns
+    pub namespaces: Vec<String>,
+    pub criu_version: u32,
+    pub kernel_release: String,
+    /// The images only contain the memory of the container
+    pub pre_dump: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent_path: Option<PathBuf>,
+    pub created: DateTime<Utc>,
+}
+
+/// Reason why a checkpoint may not be restorable on this host
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum CheckpointIssue {
+    #[error("descriptor version {0} is not supported")]
+    UnsupportedVersion(u32),
+    #[error("image file {0:?} is missing")]
+    MissingFile(PathBuf),
+    #[error("parent images {0:?} are missing")]
+    MissingParent(PathBuf),
+    #[error("config.json of bundle {0:?} changed since the checkpoint")]
+    SpecChanged(PathBuf),
+    #[error("cgroup setup of the host is {host}, checkpoint was taken on {checkpoint}")]
+    CgroupSetup { checkpoint: String, host: String },
+    #[error("namespace {0} is not supported by the kernel")]
+    Namespace(String),
+    #[error("criu is not available")]
+    CriuUnavailable,
+    #[error("criu version {host} is older than version {checkpoint} used for the checkpoint")]
+    CriuVersion { checkpoint: u32, host: u32 },
+    #[error("kernel of the host is {host}, checkpoint was taken on {checkpoint}")]
+    KernelRelease { checkpoint: String, host: String },
+}
+
+impl CheckpointIssue {
+    /// Returns true if the checkpoint cannot be restored at all. Other issues
+    /// only make a restore more likely to fail.
+    pub fn is_fatal(&self) -> bool {
+        !matches!(
+            self,
+            CheckpointIssue::SpecChanged(_)
+                | CheckpointIssue::CriuVersion { .. }
+                | CheckpointIssue::KernelRelease { .. }
+        )
+    }
+}
+
+/// Properties of the host that a checkpoint depends on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HostInfo {
+    pub cgroup_setup: String,
+    pub kernel_release: String,
+    /// None if criu could not be run
+    pub criu_version: Option<u32>,
+    /// Namespaces supported by the kernel
+    pub namespaces: Vec<String>,
+}
+
+impl HostInfo {
+    /// Gathers the properties of this host, using the given criu binary or
+    /// `criu` from PATH, and the cgroup hierarchy mounted at `cgroup_root` or
+    /// else /sys/fs/cgroup.
+    pub fn current(
+        criu: Option<&Path>,
+        cgroup_root: Option<&Path>,
+    ) -> Result<Self, CheckpointError> {
+        let cgroup_root = cgroup_root.unwrap_or(Path::new(DEFAULT_CGROUP_ROOT));
+        let cgroup_setup = libcgroups::common::get_cgroup_setup_with_root(cgroup_root)
+            .map_err(|err| CheckpointError::CgroupSetup(err.to_string()))?
+            .to_string();
+        let criu_version = check_criu_version(&criu_path(criu), 0).ok();
+        let namespaces = fs::read_dir("/proc/self/ns")
+            .map_err(CheckpointError::Host)?
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| entry.file_name().into_string().ok())
+            .collect();
+
+        Ok(Self {
+            cgroup_setup,
+            kernel_release: kernel_release()?,
+            criu_version,
+            namespaces,
+        })
+    }
+}
+
+impl CheckpointDescriptor {
+    pub fn file_path(image_path: &Path) -> PathBuf {
+        image_path.join(CHECKPOINT_DESCRIPTOR_FILE)
+    }
+
+    pub fn save(&self, image_path: &Path) -> Result<(), CheckpointError> {
+        let path = Self::file_path(image_path);
+        let content =
+            serde_json::to_vec_pretty(self).map_err(CheckpointError::SerializeDescriptor)?;
+        fs::write(&path, content).map_err(|err| {
+            tracing::error!(?path, ?err, "failed to write checkpoint descriptor");
+            CheckpointError::WriteDescriptor { path, source: err }
+        })
+    }
+
+    pub fn load(image_path: &Path) -> Result<Self, CheckpointError> {
+        let path = Self::file_path(image_path);
+        let content = fs::read(&path).map_err(|err| CheckpointError::ReadDescriptor {
+            path: path.clone(),
+            source: err,
+        })?;
+        serde_json::from_slice(&content)
+            .map_err(|err| CheckpointError::ParseDescriptor { path, source: err })
+    }
+
+    /// Checks that the images in `image_path` are complete and can be
+    /// restored on the given host.
+    pub fn validate(&self, image_path: &Path, host: &HostInfo) -> Vec<CheckpointIssue> {
+        let mut issues = Vec::new();
+        if self.version > CHECKPOINT_DESCRIPTOR_VERSION {
+            issues.push(CheckpointIssue::UnsupportedVersion(self.version));
+        }
+
+        let mut required = vec![CRIU_INVENTORY_FILE];
+        if !self.pre_dump {
+            required.push(DESCRIPTORS_JSON);
+        }
+        for file in required {
+            let path = image_path.join(file);
+            if !path.exists() {
+                issues.push(CheckpointIssue::MissingFile(path));
+            }
+        }
+        if let Some(parent_path) = &self.parent_path
+            && !image_path.join(parent_path).is_dir()
+        {
+            issues.push(CheckpointIssue::MissingParent(parent_path.clone()));
+        }
+
+        // The bundle may have been removed after the checkpoint, which is
+        // fine as long as an identical one is used for the restore.
+        if let Ok(digest) = spec_digest(&self.bundle)
+            && digest != self.spec_digest
+        {
+            issues.push(CheckpointIssue::SpecChanged(self.bundle.clone()));
+        }
+
+        if host.cgroup_setup != self.cgroup_setup {
+            issues.push(CheckpointIssue::CgroupSetup {
+                checkpoint: self.cgroup_setup.clone(),
+                host: host.cgroup_setup.clone(),
+            });
+        }
+        for ns in &self.namespaces {
+            if !host.namespaces.contains(ns) {
+                issues.push(CheckpointIssue::Namespace(ns.clone()));
+            }
+        }
+        match host.criu_version {
+            None => issues.push(CheckpointIssue::CriuUnavailable),
+            Some(version) if version < self.criu_version => {
+                issues.push(CheckpointIssue::CriuVersion {
+                    checkpoint: self.criu_version,
+                    host: version,
+                })
+            }
+            Some(_) => {}
+        }
+        if host.kernel_release != self.kernel_release {
+            issues.push(CheckpointIssue::KernelRelease {
+                checkpoint: self.kernel_release.clone(),
+                host: host.kernel_release.clone(),
+            });
+        }
+
+        issues
+    }
+}
+
+/// Returns the hex encoded SHA-256 of the config.json of a bundle.
+pub fn spec_digest(bundle: &Path) -> Result<String, std::io::Error> {
+    let content = fs::read(bundle.join("config.json"))?;
+    Ok(format!("{:x}", Sha256::digest(content)))
+}
+
+pub fn kernel_release() -> Result<String, CheckpointError> {
+    Ok(fs::read_to_string(KERNEL_RELEASE_PATH)
+        .map_err(CheckpointError::Host)?
+        .trim()
+        .to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use super::*;
+
+    fn descriptor(bundle: &Path) -> Result<CheckpointDescriptor> {
+        Ok(CheckpointDescriptor {
+            version: CHECKPOINT_DESCRIPTOR_VERSION,
+            container_id: "74f1a4cb3801".to_owned(),
+            bundle: bundle.to_owned(),
+            spec_digest: spec_digest(bundle)?,
+            cgroup_path: PathBuf::from("/youki/74f1a4cb3801"),
+            cgroup_setup: "unified".to_owned(),
+            systemd_cgroup: false,
+            namespaces: vec!["net".to_owned(), "pid".to_owned()],
+            criu_version: 31700,
+            kernel_release: "6.8.0".to_owned(),
+            pre_dump: false,
+            parent_path: None,
+            created: Utc::now(),
+        })
+    }
+
+    fn host() -> HostInfo {
+        HostInfo {
+            cgroup_setup: "unified".to_owned(),
+            kernel_release: "6.8.0".to_owned(),
+            criu_version: Some(31700),
+            namespaces: vec!["mnt".to_owned(), "net".to_owned(), "pid".to_owned()],
+        }
+    }
+
+    #[test]
+    fn test_save_and_load() -> Result<()> {
+        let bundle = tempfile::tempdir()?;
+        fs::write(bundle.path().join("config.json"), "{}")?;
+        let images = tempfile::tempdir()?;
+
+        let descriptor = descriptor(bundle.path())?;
+        descriptor.save(images.path())?;
+        assert_eq!(CheckpointDescriptor::load(images.path())?, descriptor);
+        Ok(())
+    }
+
+    #[test]
+    fn test_validate() -> Result<()> {
+        let bundle = tempfile::tempdir()?;
+        fs::write(bundle.path().join("config.json"), "{}")?;
+        let images = tempfile::tempdir()?;
+        let mut descriptor = descriptor(bundle.path())?;
+
+        assert_eq!(
+            descriptor.validate(images.path(), &host()),
+            vec![
+                CheckpointIssue::MissingFile(images.path().join(CRIU_INVENTORY_FILE)),
+                CheckpointIssue::MissingFile(images.path().join(DESCRIPTORS_JSON)),
+            ]
+        );
+
+        fs::write(images.path().join(CRIU_INVENTORY_FILE), "")?;
+        fs::write(images.path().join(DESCRIPTORS_JSON), "[]")?;
+        assert!(descriptor.validate(images.path(), &host()).is_empty());
+
+        fs::write(
+            bundle.path().join("config.json"),
+            "{\"ociVersion\":\"1.0.2\"}",
+        )?;
+        descriptor.parent_path = Some(PathBuf::from("../pre-dump"));
+        descriptor.namespaces.push("time".to_owned());
+        let mut host = host();
+        host.cgroup_setup = "hybrid".to_owned();
+        host.criu_version = Some(31500);
+        host.kernel_release = "6.9.0".to_owned();
+
+        let issues = descriptor.validate(images.path(), &host);
+        assert_eq!(
+            issues,
+            vec![
+                CheckpointIssue::MissingParent(PathBuf::from("../pre-dump")),
+                CheckpointIssue::SpecChanged(bundle.path().to_owned()),
+                CheckpointIssue::CgroupSetup {
+                    checkpoint: "unified".to_owned(),
+                    host: "hybrid".to_owned(),
+                },
+                CheckpointIssue::Namespace("time".to_owned()),
+                CheckpointIssue::CriuVersion {
+                    checkpoint: 31700,
+                    host: 31500,
+                },
+                CheckpointIssue::KernelRelease {
+                    checkpoint: "6.8.0".to_owned(),
+                    host: "6.9.0".to_owned(),
+                },
+            ]
+        );
+        let fatal: Vec<_> = issues.iter().filter(|issue| issue.is_fatal()).collect();
+        assert_eq!(fatal.len(), 3);
+        Ok(())
+    }
+}
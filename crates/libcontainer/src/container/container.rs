@@ -4,13 +4,16 @@ use std::fs;
 use std::path::{Path, PathBuf};
 
 use chrono::{DateTime, Utc};
+use libcgroups::common::DEFAULT_CGROUP_ROOT;
 use nix::unistd::Pid;
 use procfs::process::Process;
 
 use crate::config::YoukiConfig;
 use crate::container::{ContainerStatus, State};
 use crate::error::LibcontainerError;
+use crate::exec_session::ExecSession;
 use crate::syscall::syscall::create_syscall;
+use crate::timings::StartupTimings;
 
 /// Structure representing the container data
 #[derive(Debug, Clone)]
@@ -130,6 +133,31 @@ impl Container {
         self
     }
 
+    pub fn rootless(&self) -> bool {
+        self.state.rootless
+    }
+
+    pub fn set_rootless(&mut self, rootless: bool) -> &mut Self {
+        self.state.rootless = rootless;
+        self
+    }
+
+    pub fn cgroup_root(&self) -> Option<&PathBuf> {
+        self.state.cgroup_root.as_ref()
+    }
+
+    /// Mount point of the cgroup hierarchy of the container, /sys/fs/cgroup
+    /// unless another one was given
+    pub(crate) fn cgroup_root_or_default(&self) -> &Path {
+        self.cgroup_root()
+            .map_or(Path::new(DEFAULT_CGROUP_ROOT), |root| root.as_path())
+    }
+
+    pub fn set_cgroup_root(&mut self, cgroup_root: Option<PathBuf>) -> &mut Self {
+        self.state.cgroup_root = cgroup_root;
+        self
+    }
+
     pub fn set_clean_up_intel_rdt_directory(&mut self, clean_up: bool) -> &mut Self {
         self.state.clean_up_intel_rdt_subdirectory = Some(clean_up);
         self
@@ -174,6 +202,13 @@ impl Container {
     }
 
     pub fn refresh_status(&mut self) -> Result<(), LibcontainerError> {
+        // Once the exit of the container process is recorded, its pid may
+        // already be reused by another process.
+        if self.state.finished_at.is_some() {
+            self.set_status(ContainerStatus::Stopped);
+            return Ok(());
+        }
+
         let new_status = match self.pid() {
             Some(pid) => {
                 // Note that Process::new does not spawn a new process
@@ -230,6 +265,22 @@ impl Container {
         let spec = YoukiConfig::load(&self.root)?;
         Ok(spec)
     }
+
+    /// Returns the start-up latency breakdown of the container, if it was
+    /// recorded when the container was created
+    pub fn startup_timings(&self) -> Result<Option<StartupTimings>, LibcontainerError> {
+        Ok(StartupTimings::load(&self.root)?)
+    }
+
+    /// Returns the processes executed in the container under an exec ID,
+    /// oldest first
+    pub fn exec_sessions(&self) -> Result<Vec<ExecSession>, LibcontainerError> {
+        Ok(ExecSession::list(&self.root)?)
+    }
+
+    pub fn exec_session(&self, id: &str) -> Result<ExecSession, LibcontainerError> {
+        Ok(ExecSession::load(&self.root, id)?)
+    }
 }
 
 /// Checkpoint parameter structure
@@ -244,6 +295,19 @@ pub struct CheckpointOptions {
     pub work_path: Option<PathBuf>,
     pub manage_cgroups_mode: rust_criu::CgMode,
     pub link_remap: bool,
+    /// Path of the criu binary, `criu` is looked up in PATH if None
+    pub criu_path: Option<PathBuf>,
+    /// Only dump the memory of the container and leave it running, so that
+    /// a later dump with `parent_path` set saves just the pages changed since
+    pub pre_dump: bool,
+    /// Images of a previous pre-dump, relative to `image_path`
+    pub parent_path: Option<PathBuf>,
+    /// Remove pages from the parent images that are dumped again
+    pub auto_dedup: bool,
+    /// Send the memory pages to a page server at the given address and port
+    pub page_server: Option<(String, i32)>,
+    /// Serve the memory pages on demand to a lazy restore, requires `page_server`
+    pub lazy_pages: bool,
 }
 
 #[cfg(test)]
@@ -313,6 +377,22 @@ mod tests {
         assert!(!container.systemd());
     }
 
+    #[test]
+    fn test_get_set_rootless() {
+        let mut container = Container::default();
+        assert!(!container.rootless());
+        container.set_rootless(true);
+        assert!(container.rootless());
+    }
+
+    #[test]
+    fn test_get_set_cgroup_root() {
+        let mut container = Container::default();
+        assert_eq!(container.cgroup_root(), None);
+        container.set_cgroup_root(Some(PathBuf::from("/tmp/cgroup")));
+        assert_eq!(container.cgroup_root(), Some(&PathBuf::from("/tmp/cgroup")));
+    }
+
     #[test]
     fn test_get_set_creator() {
         let mut container = Container::default();
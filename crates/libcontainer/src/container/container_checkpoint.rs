@@ -2,14 +2,21 @@ use std::fs::{DirBuilder, File, read_link};
 use std::io::{ErrorKind, Write};
 use std::os::unix::fs::DirBuilderExt;
 use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+
+use chrono::Utc;
 
 use libcgroups::common::CgroupSetup::{Hybrid, Legacy};
-#[cfg(feature = "v1")]
-use libcgroups::common::DEFAULT_CGROUP_ROOT;
 use oci_spec::runtime::{LinuxNamespaceType, Spec};
 
+use rust_criu::rust_criu_protobuf::rpc::{Criu_opts, Criu_page_server_info, Ext_mount_map};
+
+use super::checkpoint_descriptor::{
+    CHECKPOINT_DESCRIPTOR_VERSION, CheckpointDescriptor, kernel_release, spec_digest,
+};
 use super::container_criu::{
-    CRIU_VERSION_MINIMUM, check_criu_version, handle_checkpointing_external_namespaces,
+    CRIU_VERSION_MINIMUM, cg_mode, check_criu_features, check_criu_version, criu_path, dump,
+    handle_checkpointing_external_namespaces, ns_name, pre_dump,
 };
 use super::{Container, ContainerStatus};
 use crate::container::container::CheckpointOptions;
@@ -17,12 +24,68 @@ use crate::error::LibcontainerError;
 use crate::rootfs::utils::is_bind;
 
 const CRIU_CHECKPOINT_LOG_FILE: &str = "dump.log";
-const DESCRIPTORS_JSON: &str = "descriptors.json";
+const CRIU_PRE_DUMP_LOG_FILE: &str = "pre-dump.log";
+// Standard streams of the container process, saved for full dumps
+pub(super) const DESCRIPTORS_JSON: &str = "descriptors.json";
 
 #[derive(thiserror::Error, Debug)]
 pub enum CheckpointError {
     #[error("criu error: {0}")]
     CriuError(String),
+    #[error("invalid checkpoint options: {0}")]
+    InvalidOptions(String),
+    #[error("failed to get cgroup setup: {0}")]
+    CgroupSetup(String),
+    #[error("failed to read host information")]
+    Host(#[source] std::io::Error),
+    #[error("failed to serialize checkpoint descriptor")]
+    SerializeDescriptor(#[source] serde_json::Error),
+    #[error("failed to write checkpoint descriptor {path:?}")]
+    WriteDescriptor {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("failed to read checkpoint descriptor {path:?}")]
+    ReadDescriptor {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("failed to parse checkpoint descriptor {path:?}")]
+    ParseDescriptor {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+}
+
+// Rejects combinations of options CRIU cannot handle, before the container is
+// touched.
+fn validate_options(opts: &CheckpointOptions) -> Result<(), CheckpointError> {
+    if opts.pre_dump && opts.lazy_pages {
+        return Err(CheckpointError::InvalidOptions(
+            "lazy pages cannot be used with a pre-dump".into(),
+        ));
+    }
+    if opts.lazy_pages && opts.page_server.is_none() {
+        return Err(CheckpointError::InvalidOptions(
+            "lazy pages require a page server".into(),
+        ));
+    }
+    if let Some(parent_path) = &opts.parent_path {
+        // CRIU links the parent images from the image directory, so the path
+        // has to be relative to it.
+        if parent_path.is_absolute() {
+            return Err(CheckpointError::InvalidOptions(format!(
+                "parent path {parent_path:?} must be relative to the image path"
+            )));
+        }
+        if !opts.image_path.join(parent_path).is_dir() {
+            return Err(CheckpointError::InvalidOptions(format!(
+                "parent path {parent_path:?} does not exist in the image path {:?}",
+                opts.image_path
+            )));
+        }
+    }
+    Ok(())
 }
 
 impl Container {
@@ -37,8 +100,15 @@ impl Container {
             return Err(LibcontainerError::IncorrectStatus(self.status()));
         }
 
+        validate_options(opts)?;
+        let criu_path = criu_path(opts.criu_path.as_deref());
+
         // Require CRIU >= 3.15.0, matching crun's LIBCRIU_MIN_VERSION requirement.
-        check_criu_version(CRIU_VERSION_MINIMUM)?;
+        let criu_version = check_criu_version(&criu_path, CRIU_VERSION_MINIMUM)?;
+        // Pre-dumps and dumps on top of them rely on dirty memory tracking,
+        // and lazy pages on userfaultfd, both need support from the kernel.
+        let mem_track = opts.pre_dump || opts.parent_path.is_some();
+        check_criu_features(&criu_path, mem_track, opts.lazy_pages)?;
 
         // Create checkpoint image directory if it doesn't exist (mode 0o700 like crun).
         if let Err(err) = DirBuilder::new().mode(0o700).create(&opts.image_path) {
@@ -48,12 +118,6 @@ impl Container {
             }
         }
 
-        let mut criu = rust_criu::Criu::new().map_err(|e| {
-            LibcontainerError::Checkpoint(CheckpointError::CriuError(format!(
-                "error in creating criu struct: {}",
-                e
-            )))
-        })?;
         // We need to tell CRIU that all bind mounts are external. CRIU will fail checkpointing
         // if it does not know that these bind mounts are coming from the outside of the container.
         // This information is needed during restore again. The external location of the bind
@@ -61,7 +125,10 @@ impl Container {
         // information found in 'config.json'.
         let source_spec_path = self.bundle().join("config.json");
         let spec = Spec::load(source_spec_path)?;
+        let descriptor = self.checkpoint_descriptor(&spec, opts, criu_version)?;
         let mounts = spec.mounts().clone();
+        let cgroup_root = self.cgroup_root_or_default();
+        let mut external_mounts = Vec::new();
         for m in mounts.unwrap_or_default() {
             if is_bind(&m) {
                 let dest = m
@@ -70,9 +137,9 @@ impl Container {
                     .into_os_string()
                     .into_string()
                     .expect("failed to convert mount destination");
-                criu.set_external_mount(dest.clone(), dest);
+                external_mounts.push(dest);
             } else if m.typ().as_deref() == Some("cgroup") {
-                match libcgroups::common::get_cgroup_setup()? {
+                match libcgroups::common::get_cgroup_setup_with_root(cgroup_root)? {
                     // For v1 it is necessary to list all cgroup mounts as external mounts
                     Legacy | Hybrid => {
                         #[cfg(not(feature = "v1"))]
@@ -91,8 +158,8 @@ impl Container {
                                 .into_os_string()
                                 .into_string()
                                 .expect("failed to convert mount point");
-                            if cgroup_mount.starts_with(DEFAULT_CGROUP_ROOT) {
-                                criu.set_external_mount(cgroup_mount.clone(), cgroup_mount);
+                            if Path::new(&cgroup_mount).starts_with(cgroup_root) {
+                                external_mounts.push(cgroup_mount);
                             }
                         }
                     }
@@ -105,11 +172,11 @@ impl Container {
             tracing::error!(path = ?opts.image_path, ?err, "failed to open checkpoint directory");
             LibcontainerError::OtherIO(err)
         })?;
-        criu.set_images_dir_fd(directory.as_raw_fd());
 
         // It seems to be necessary to be defined outside of 'if' to
         // keep the FD open until CRIU uses it.
         let work_dir: File;
+        let mut work_dir_fd = None;
         if let Some(wp) = &opts.work_path {
             // Create work directory if it doesn't exist (mode 0o700 like crun).
             if let Err(err) = DirBuilder::new().mode(0o700).create(wp) {
@@ -119,7 +186,7 @@ impl Container {
                 }
             }
             work_dir = File::open(wp).map_err(LibcontainerError::OtherIO)?;
-            criu.set_work_dir_fd(work_dir.as_raw_fd());
+            work_dir_fd = Some(work_dir.as_raw_fd());
         }
 
         let pid: i32 = self
@@ -130,46 +197,68 @@ impl Container {
             .into();
 
         // Remember original stdin, stdout, stderr for container restore.
-        let mut descriptors = Vec::new();
-        for n in 0..3 {
-            let link_path = match read_link(format!("/proc/{pid}/fd/{n}")) {
-                // it should not have any non utf-8 or non os safe path,
-                // as we are reading from os , so ok to unwrap
-                Ok(lp) => lp.into_os_string().into_string().unwrap(),
-                Err(..) => "/dev/null".to_string(),
-            };
-            descriptors.push(link_path);
+        if !opts.pre_dump {
+            let mut descriptors = Vec::new();
+            for n in 0..3 {
+                let link_path = match read_link(format!("/proc/{pid}/fd/{n}")) {
+                    // it should not have any non utf-8 or non os safe path,
+                    // as we are reading from os , so ok to unwrap
+                    Ok(lp) => lp.into_os_string().into_string().unwrap(),
+                    Err(..) => "/dev/null".to_string(),
+                };
+                descriptors.push(link_path);
+            }
+            let descriptors_json_path = opts.image_path.join(DESCRIPTORS_JSON);
+            let mut descriptors_json =
+                File::create(descriptors_json_path).map_err(LibcontainerError::OtherIO)?;
+            write!(
+                descriptors_json,
+                "{}",
+                serde_json::to_string(&descriptors)
+                    .map_err(LibcontainerError::OtherSerialization)?
+            )
+            .map_err(LibcontainerError::OtherIO)?;
+        }
+
+        // Pre-dumps and dumps share their options, so that a dump is
+        // consistent with the pre-dumps it uses as parents. Both are sent over
+        // the RPC socket, rust_criu cannot set track_mem on a dump.
+        let mut criu_opts = Criu_opts::new();
+        criu_opts.set_pid(pid);
+        criu_opts.set_images_dir_fd(directory.as_raw_fd());
+        if let Some(fd) = work_dir_fd {
+            criu_opts.set_work_dir_fd(fd);
+        }
+        criu_opts.set_log_level(4);
+        criu_opts.ext_mnt = external_mounts
+            .into_iter()
+            .map(|dest| {
+                let mut m = Ext_mount_map::new();
+                m.set_key(dest.clone());
+                m.set_val(dest);
+                m
+            })
+            .collect();
+        criu_opts.set_ext_unix_sk(opts.ext_unix_sk);
+        criu_opts.set_shell_job(opts.shell_job);
+        criu_opts.set_tcp_established(opts.tcp_established);
+        criu_opts.set_tcp_skip_in_flight(opts.tcp_skip_in_flight);
+        criu_opts.set_file_locks(opts.file_locks);
+        criu_opts.set_orphan_pts_master(true);
+        criu_opts.set_manage_cgroups(true);
+        criu_opts.set_manage_cgroups_mode(cg_mode(&opts.manage_cgroups_mode));
+        criu_opts.set_root(self.bundle().to_string_lossy().into_owned());
+        criu_opts.set_link_remap(opts.link_remap);
+        if let Some(parent_path) = &opts.parent_path {
+            criu_opts.set_parent_img(parent_path.to_string_lossy().into_owned());
+        }
+        criu_opts.set_auto_dedup(opts.auto_dedup);
+        if let Some((address, port)) = &opts.page_server {
+            let mut ps = Criu_page_server_info::new();
+            ps.set_address(address.clone());
+            ps.set_port(*port);
+            criu_opts.ps = protobuf::MessageField::some(ps);
         }
-        let descriptors_json_path = opts.image_path.join(DESCRIPTORS_JSON);
-        let mut descriptors_json =
-            File::create(descriptors_json_path).map_err(LibcontainerError::OtherIO)?;
-        write!(
-            descriptors_json,
-            "{}",
-            serde_json::to_string(&descriptors).map_err(LibcontainerError::OtherSerialization)?
-        )
-        .map_err(LibcontainerError::OtherIO)?;
-
-        criu.set_log_file(CRIU_CHECKPOINT_LOG_FILE.to_string());
-        criu.set_log_level(4);
-        criu.set_pid(pid);
-        criu.set_leave_running(opts.leave_running);
-        criu.set_ext_unix_sk(opts.ext_unix_sk);
-        criu.set_shell_job(opts.shell_job);
-        criu.set_tcp_established(opts.tcp_established);
-        criu.set_tcp_skip_in_flight(opts.tcp_skip_in_flight);
-        criu.set_file_locks(opts.file_locks);
-        criu.set_orphan_pts_master(true);
-        criu.set_manage_cgroups(true);
-        criu.set_root(
-            self.bundle()
-                .clone()
-                .into_os_string()
-                .into_string()
-                .unwrap(),
-        );
-        criu.cgroups_mode(opts.manage_cgroups_mode.clone());
-        criu.set_link_remap(opts.link_remap);
 
         // Register network and PID namespaces as external to CRIU.
         //
@@ -192,13 +281,44 @@ impl Container {
         // process inside the same existing PID namespace.
         //
         // This follows runc's handleCheckpointingExternalNamespaces.
-        handle_checkpointing_external_namespaces(&mut criu, &spec, LinuxNamespaceType::Network)?;
-        handle_checkpointing_external_namespaces(&mut criu, &spec, LinuxNamespaceType::Pid)?;
+        handle_checkpointing_external_namespaces(
+            &mut criu_opts,
+            &spec,
+            LinuxNamespaceType::Network,
+        )?;
+        handle_checkpointing_external_namespaces(&mut criu_opts, &spec, LinuxNamespaceType::Pid)?;
+
+        if opts.pre_dump {
+            // A pre-dump only saves memory, the container keeps running
+            // whether or not leave_running is set, so that it can be dumped
+            // again with these images as parent.
+            criu_opts.set_log_file(CRIU_PRE_DUMP_LOG_FILE.to_string());
+            criu_opts.set_leave_running(true);
+
+            pre_dump(&criu_path, criu_opts).map_err(|err| {
+                tracing::error!(?err, id = ?self.id(), logfile = ?opts.image_path.join(CRIU_PRE_DUMP_LOG_FILE), "pre-dumping container failed");
+                err
+            })?;
+            descriptor.save(&opts.image_path)?;
 
-        criu.dump().map_err(|err| {
+            tracing::debug!("container {} pre-dumped", self.id());
+            return Ok(());
+        }
+
+        criu_opts.set_log_file(CRIU_CHECKPOINT_LOG_FILE.to_string());
+        criu_opts.set_leave_running(opts.leave_running);
+        criu_opts.set_lazy_pages(opts.lazy_pages);
+        // On top of a pre-dump, only the pages changed since are written,
+        // which keeps the container frozen for a shorter time.
+        if opts.parent_path.is_some() {
+            criu_opts.set_track_mem(true);
+        }
+
+        dump(&criu_path, criu_opts).map_err(|err| {
             tracing::error!(?err, id = ?self.id(), logfile = ?opts.image_path.join(CRIU_CHECKPOINT_LOG_FILE), "checkpointing container failed");
-            LibcontainerError::Other(err.to_string())
+            err
         })?;
+        descriptor.save(&opts.image_path)?;
 
         if !opts.leave_running {
             // Set status to Stopped first so delete() can proceed without force.
@@ -212,4 +332,97 @@ impl Container {
         tracing::debug!("container {} checkpointed", self.id());
         Ok(())
     }
+
+    // Describes the container and host, saved next to the images so that
+    // they can be validated before a restore.
+    fn checkpoint_descriptor(
+        &self,
+        spec: &Spec,
+        opts: &CheckpointOptions,
+        criu_version: u32,
+    ) -> Result<CheckpointDescriptor, LibcontainerError> {
+        let namespaces = spec
+            .linux()
+            .as_ref()
+            .and_then(|linux| linux.namespaces().as_ref())
+            .map(|namespaces| {
+                namespaces
+                    .iter()
+                    .map(|ns| ns_name(ns.typ()).to_owned())
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Ok(CheckpointDescriptor {
+            version: CHECKPOINT_DESCRIPTOR_VERSION,
+            container_id: self.id().to_owned(),
+            bundle: self.bundle().to_owned(),
+            spec_digest: spec_digest(self.bundle()).map_err(LibcontainerError::OtherIO)?,
+            cgroup_path: self.spec()?.cgroup_path,
+            cgroup_setup: libcgroups::common::get_cgroup_setup_with_root(
+                self.cgroup_root_or_default(),
+            )?
+            .to_string(),
+            systemd_cgroup: self.systemd(),
+            namespaces,
+            criu_version,
+            kernel_release: kernel_release()?,
+            pre_dump: opts.pre_dump,
+            parent_path: opts.parent_path.clone(),
+            created: Utc::now(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    fn options(image_path: PathBuf) -> CheckpointOptions {
+        CheckpointOptions {
+            ext_unix_sk: false,
+            file_locks: false,
+            image_path,
+            leave_running: false,
+            shell_job: false,
+            tcp_established: false,
+            tcp_skip_in_flight: false,
+            work_path: None,
+            manage_cgroups_mode: rust_criu::CgMode::SOFT,
+            link_remap: false,
+            criu_path: None,
+            pre_dump: false,
+            parent_path: None,
+            auto_dedup: false,
+            page_server: None,
+            lazy_pages: false,
+        }
+    }
+
+    #[test]
+    fn test_validate_options() {
+        let tmp = tempfile::tempdir().unwrap();
+        std::fs::create_dir(tmp.path().join("pre-dump-1")).unwrap();
+        let image_path = tmp.path().to_path_buf();
+
+        assert!(validate_options(&options(image_path.clone())).is_ok());
+
+        let mut opts = options(image_path.clone());
+        opts.parent_path = Some(PathBuf::from("pre-dump-1"));
+        assert!(validate_options(&opts).is_ok());
+        opts.parent_path = Some(tmp.path().join("pre-dump-1"));
+        assert!(validate_options(&opts).is_err());
+        opts.parent_path = Some(PathBuf::from("pre-dump-2"));
+        assert!(validate_options(&opts).is_err());
+
+        let mut opts = options(image_path.clone());
+        opts.lazy_pages = true;
+        assert!(validate_options(&opts).is_err());
+        opts.page_server = Some(("127.0.0.1".to_owned(), 27_000));
+        assert!(validate_options(&opts).is_ok());
+        opts.pre_dump = true;
+        assert!(validate_options(&opts).is_err());
+    }
 }
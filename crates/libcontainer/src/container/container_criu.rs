@@ -4,11 +4,21 @@
 //! following the patterns established by runc's CRIU integration.
 
 use std::fs::File;
+use std::io::{Read, Write};
+use std::os::fd::OwnedFd;
 use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use std::process::{Command, Stdio};
 
+use nix::fcntl::{FcntlArg, FdFlag, fcntl};
+use nix::sys::socket::{AddressFamily, SockFlag, SockType, socketpair};
 use nix::sys::stat::fstat;
 use oci_spec::runtime::{LinuxNamespaceType, Spec};
-use rust_criu::{Criu, criu_ns_to_key};
+use protobuf::{Message, MessageField};
+use rust_criu::rust_criu_protobuf::rpc::{
+    Criu_cg_mode, Criu_features, Criu_opts, Criu_req, Criu_req_type, Criu_resp,
+};
+use rust_criu::{CgMode, Criu, criu_ns_to_key};
 
 use crate::error::LibcontainerError;
 
@@ -17,6 +27,18 @@ use crate::error::LibcontainerError;
 /// Version format: MAJOR * 10000 + MINOR * 100 + PATCH
 pub const CRIU_VERSION_MINIMUM: u32 = 31500; // 3.15.0
 
+/// Name of the criu binary looked up in PATH if no path is configured.
+pub const CRIU_DEFAULT_PATH: &str = "criu";
+
+// Upper bound of a response of criu swrk, they only carry a few fields.
+const CRIU_RESPONSE_MAX_SIZE: usize = 64 * 1024;
+
+/// Returns the criu binary to run.
+pub fn criu_path(path: Option<&Path>) -> String {
+    path.map(|p| p.to_string_lossy().into_owned())
+        .unwrap_or_else(|| CRIU_DEFAULT_PATH.to_owned())
+}
+
 fn compare_criu_version(version: u32, min_version: u32) -> Result<(), LibcontainerError> {
     if version < min_version {
         return Err(LibcontainerError::Other(format!(
@@ -27,19 +49,160 @@ fn compare_criu_version(version: u32, min_version: u32) -> Result<(), Libcontain
     Ok(())
 }
 
-/// Check if CRIU version is greater than or equal to min_version.
-pub fn check_criu_version(min_version: u32) -> Result<(), LibcontainerError> {
-    let mut criu = Criu::new()
+/// Check if CRIU version is greater than or equal to min_version, and
+/// return the version.
+pub fn check_criu_version(criu_path: &str, min_version: u32) -> Result<u32, LibcontainerError> {
+    let mut criu = Criu::new_with_criu_path(criu_path.to_owned())
         .map_err(|e| LibcontainerError::Other(format!("failed to create CRIU instance: {}", e)))?;
 
     let version = criu
         .get_criu_version()
         .map_err(|e| LibcontainerError::Other(format!("CRIU version check failed: {}", e)))?;
 
-    compare_criu_version(version, min_version)
+    compare_criu_version(version, min_version)?;
+    Ok(version)
+}
+
+// Lists the features that were requested but are not supported by CRIU or the
+// kernel.
+fn unsupported_features(requested: &Criu_features, supported: &Criu_features) -> Vec<&'static str> {
+    let mut unsupported = Vec::new();
+    if requested.mem_track() && !supported.mem_track() {
+        unsupported.push("mem_track");
+    }
+    if requested.lazy_pages() && !supported.lazy_pages() {
+        unsupported.push("lazy_pages");
+    }
+    unsupported
+}
+
+/// Checks that CRIU and the kernel support dirty memory tracking and lazy
+/// pages, if requested. Follows runc's `checkCriuFeatures`.
+pub fn check_criu_features(
+    criu_path: &str,
+    mem_track: bool,
+    lazy_pages: bool,
+) -> Result<(), LibcontainerError> {
+    let mut features = Criu_features::new();
+    if mem_track {
+        features.set_mem_track(true);
+    }
+    if lazy_pages {
+        features.set_lazy_pages(true);
+    }
+
+    let mut req = Criu_req::new();
+    req.set_type(Criu_req_type::FEATURE_CHECK);
+    req.features = MessageField::some(features.clone());
+    let resp = criu_swrk(criu_path, req)?;
+
+    let unsupported = unsupported_features(&features, &resp.features);
+    if !unsupported.is_empty() {
+        return Err(LibcontainerError::Other(format!(
+            "CRIU feature check failed, not supported: {}",
+            unsupported.join(", ")
+        )));
+    }
+    Ok(())
+}
+
+/// Dumps the memory of a process while leaving it running, with dirty memory
+/// tracking enabled so that the next dump only saves the pages changed since.
+pub fn pre_dump(criu_path: &str, mut opts: Criu_opts) -> Result<(), LibcontainerError> {
+    opts.set_track_mem(true);
+
+    let mut req = Criu_req::new();
+    req.set_type(Criu_req_type::PRE_DUMP);
+    req.opts = MessageField::some(opts);
+    criu_swrk(criu_path, req)?;
+    Ok(())
 }
 
-fn ns_name(ns_type: LinuxNamespaceType) -> &'static str {
+/// Dumps a process. With `track_mem` and a parent image set, only the pages
+/// changed since the pre-dump of the parent are saved.
+pub fn dump(criu_path: &str, opts: Criu_opts) -> Result<(), LibcontainerError> {
+    let mut req = Criu_req::new();
+    req.set_type(Criu_req_type::DUMP);
+    req.opts = MessageField::some(opts);
+    criu_swrk(criu_path, req)?;
+    Ok(())
+}
+
+/// Converts the cgroups mode of rust_criu to its RPC value
+pub fn cg_mode(mode: &CgMode) -> Criu_cg_mode {
+    match mode {
+        CgMode::IGNORE => Criu_cg_mode::IGNORE,
+        CgMode::NONE => Criu_cg_mode::CG_NONE,
+        CgMode::PROPS => Criu_cg_mode::PROPS,
+        CgMode::SOFT => Criu_cg_mode::SOFT,
+        CgMode::FULL => Criu_cg_mode::FULL,
+        CgMode::STRICT => Criu_cg_mode::STRICT,
+        CgMode::DEFAULT => Criu_cg_mode::DEFAULT,
+    }
+}
+
+// Sends a single request to `criu swrk` and returns its response. rust_criu
+// cannot set all the options of dumps, and does not issue pre-dumps and
+// feature checks, so these are sent over the RPC socket here, the same way
+// runc does.
+fn criu_swrk(criu_path: &str, req: Criu_req) -> Result<Criu_resp, LibcontainerError> {
+    let (socket, criu_socket) = socketpair(
+        AddressFamily::Unix,
+        SockType::SeqPacket,
+        None,
+        SockFlag::SOCK_CLOEXEC,
+    )
+    .map_err(LibcontainerError::OtherSyscall)?;
+    // criu inherits its end of the socket
+    fcntl(criu_socket.as_raw_fd(), FcntlArg::F_SETFD(FdFlag::empty()))
+        .map_err(LibcontainerError::OtherSyscall)?;
+
+    let mut criu = Command::new(criu_path)
+        .arg("swrk")
+        .arg(criu_socket.as_raw_fd().to_string())
+        .stdin(Stdio::null())
+        .spawn()
+        .map_err(|err| {
+            tracing::error!(?err, criu_path, "failed to start criu");
+            LibcontainerError::OtherIO(err)
+        })?;
+    drop(criu_socket);
+
+    let req_type = req.type_();
+    let resp = send_criu_request(socket, &req);
+    let _ = criu.wait();
+    let resp = resp?;
+
+    if !resp.success() {
+        return Err(LibcontainerError::Other(format!(
+            "CRIU {:?} request failed: {} (errno {})",
+            req_type,
+            resp.cr_errmsg(),
+            resp.cr_errno()
+        )));
+    }
+    Ok(resp)
+}
+
+fn send_criu_request(socket: OwnedFd, req: &Criu_req) -> Result<Criu_resp, LibcontainerError> {
+    let mut socket = File::from(socket);
+    let req = req
+        .write_to_bytes()
+        .map_err(|err| LibcontainerError::Other(format!("failed to encode CRIU request: {err}")))?;
+    socket.write_all(&req).map_err(LibcontainerError::OtherIO)?;
+
+    let mut buf = vec![0; CRIU_RESPONSE_MAX_SIZE];
+    let len = socket.read(&mut buf).map_err(LibcontainerError::OtherIO)?;
+    if len == 0 {
+        return Err(LibcontainerError::Other(
+            "CRIU exited without a response".into(),
+        ));
+    }
+    Criu_resp::parse_from_bytes(&buf[..len])
+        .map_err(|err| LibcontainerError::Other(format!("failed to decode CRIU response: {err}")))
+}
+
+pub(super) fn ns_name(ns_type: LinuxNamespaceType) -> &'static str {
     match ns_type {
         LinuxNamespaceType::Network => "net",
         LinuxNamespaceType::Pid => "pid",
@@ -80,7 +243,7 @@ fn get_namespace_path(spec: &Spec, ns_type: LinuxNamespaceType) -> Option<String
 /// Follows runc's `handleCheckpointingExternalNamespaces` and crun's
 /// `libcrun_criu_add_external_namespaces`.
 pub fn handle_checkpointing_external_namespaces(
-    criu: &mut Criu,
+    opts: &mut Criu_opts,
     spec: &Spec,
     ns_type: LinuxNamespaceType,
 ) -> Result<(), LibcontainerError> {
@@ -103,13 +266,15 @@ pub fn handle_checkpointing_external_namespaces(
     let external = format!("{}[{}]:{}", name, stat.st_ino, criu_ns_to_key(name));
 
     tracing::debug!(?external, "adding external namespace for checkpoint");
-    criu.add_external(external);
+    opts.external.push(external);
 
     Ok(())
 }
 
 #[cfg(test)]
 mod tests {
+    use std::os::unix::fs::MetadataExt;
+
     use super::*;
 
     #[test]
@@ -124,4 +289,60 @@ mod tests {
         assert!(compare_criu_version(31499, 31500).is_err());
         assert!(compare_criu_version(30000, 31500).is_err());
     }
+
+    #[test]
+    fn test_unsupported_features() {
+        let mut requested = Criu_features::new();
+        requested.set_mem_track(true);
+        requested.set_lazy_pages(true);
+
+        let mut supported = Criu_features::new();
+        supported.set_mem_track(true);
+        supported.set_lazy_pages(false);
+        assert_eq!(
+            unsupported_features(&requested, &supported),
+            vec!["lazy_pages"]
+        );
+
+        supported.set_lazy_pages(true);
+        assert!(unsupported_features(&requested, &supported).is_empty());
+        assert!(unsupported_features(&Criu_features::new(), &Criu_features::new()).is_empty());
+    }
+
+    #[test]
+    fn test_handle_checkpointing_external_namespaces() -> anyhow::Result<()> {
+        use oci_spec::runtime::{LinuxBuilder, LinuxNamespaceBuilder, SpecBuilder};
+
+        let spec = SpecBuilder::default()
+            .linux(
+                LinuxBuilder::default()
+                    .namespaces(vec![
+                        LinuxNamespaceBuilder::default()
+                            .typ(LinuxNamespaceType::Network)
+                            .path("/proc/self/ns/net")
+                            .build()?,
+                        LinuxNamespaceBuilder::default()
+                            .typ(LinuxNamespaceType::Pid)
+                            .build()?,
+                    ])
+                    .build()?,
+            )
+            .build()?;
+
+        let mut opts = Criu_opts::new();
+        handle_checkpointing_external_namespaces(&mut opts, &spec, LinuxNamespaceType::Network)?;
+        handle_checkpointing_external_namespaces(&mut opts, &spec, LinuxNamespaceType::Pid)?;
+        let ino = std::fs::metadata("/proc/self/ns/net")?.ino();
+        assert_eq!(opts.external, vec![format!("net[{ino}]:extRootNetNS")]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_criu_path() {
+        assert_eq!(criu_path(None), CRIU_DEFAULT_PATH);
+        assert_eq!(
+            criu_path(Some(Path::new("/usr/local/sbin/criu"))),
+            "/usr/local/sbin/criu"
+        );
+    }
 }
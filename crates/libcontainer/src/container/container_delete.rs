@@ -1,16 +1,54 @@
-use std::fs;
+use std::fs::{self, File};
+use std::io;
+use std::os::unix::fs::MetadataExt;
 
 use libcgroups::common::CgroupManager;
 use libcgroups::{self};
+use nix::fcntl::{Flock, FlockArg};
 use nix::sys::signal;
 
 use super::{Container, ContainerStatus};
 use crate::config::YoukiConfig;
 use crate::error::LibcontainerError;
 use crate::hooks;
+use crate::network::network_device::{dev_return_to_host, resolve_device_name};
 use crate::process::intel_rdt::cleanup_intel_rdt;
+use crate::subid::SubIdAllocator;
+
+/// Lock of the container directory, see [`Container::lock`]
+pub struct ContainerLock {
+    _dir: Flock<File>,
+}
 
 impl Container {
+    /// Locks the container directory. The lock is held while the container
+    /// is deleted, so that the process reaping the container does not record
+    /// its exit or restart it meanwhile. Returns `None` if the container was
+    /// deleted, possibly while waiting for the lock.
+    pub fn lock(&self) -> Result<Option<ContainerLock>, LibcontainerError> {
+        loop {
+            let dir = match File::open(&self.root) {
+                Ok(dir) => dir,
+                Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+                Err(err) => return Err(LibcontainerError::OtherIO(err)),
+            };
+            let dir = Flock::lock(dir, FlockArg::LockExclusive)
+                .map_err(|(_, err)| LibcontainerError::OtherSyscall(err))?;
+
+            // The directory may have been removed, or replaced by a restart
+            // of the container, while waiting for the lock.
+            let locked = dir.metadata().map_err(LibcontainerError::OtherIO)?;
+            match fs::metadata(&self.root) {
+                Ok(current) if current.dev() == locked.dev() && current.ino() == locked.ino() => {
+                    return Ok(Some(ContainerLock { _dir: dir }));
+                }
+                Ok(_) => {}
+                Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+                Err(err) => return Err(LibcontainerError::OtherIO(err)),
+            }
+        }
+    }
+
     /// Deletes the container
     ///
     /// # Example
@@ -31,14 +69,35 @@ impl Container {
     /// # Ok(())
     /// # }
     /// ```
+    #[tracing::instrument(level = "info", name = "delete", skip_all, fields(container_id = %self.id()))]
     pub fn delete(&mut self, force: bool) -> Result<(), LibcontainerError> {
+        let Some(lock) = self.lock()? else {
+            tracing::debug!("container was already deleted");
+            return Ok(());
+        };
+        self.delete_locked(force, &lock)
+    }
+
+    /// Deletes the container while holding its lock, taken with
+    /// [`Container::lock`]
+    pub fn delete_locked(
+        &mut self,
+        force: bool,
+        _lock: &ContainerLock,
+    ) -> Result<(), LibcontainerError> {
+        // The state may have changed while waiting for the lock.
+        self.refresh_state()?;
         self.refresh_status()?;
 
         tracing::debug!("container status: {:?}", self.status());
 
         // Check if container is allowed to be deleted based on container status.
         match self.status() {
-            ContainerStatus::Stopped => {}
+            ContainerStatus::Stopped => {
+                // Keep the exit observed here for the poststop hooks, unless
+                // the process reaping the container recorded it.
+                self.record_stop()?;
+            }
             ContainerStatus::Created => {
                 // Here, we differ from the OCI spec, but matches the same
                 // behavior as `runc` and `crun`. The OCI spec does not allow
@@ -46,7 +105,7 @@ impl Container {
                 // `runc` and `crun` allows deleting `created`. Therefore we
                 // decided to follow `runc` and `crun`.
                 self.do_kill(signal::Signal::SIGKILL, true)?;
-                self.set_status(ContainerStatus::Stopped).save()?;
+                self.record_killed()?;
             }
             ContainerStatus::Creating | ContainerStatus::Running | ContainerStatus::Paused => {
                 // Containers can't be deleted while in these status, unless
@@ -54,7 +113,7 @@ impl Container {
                 // processes associated with containers.
                 if force {
                     self.do_kill(signal::Signal::SIGKILL, true)?;
-                    self.set_status(ContainerStatus::Stopped).save()?;
+                    self.record_killed()?;
                 } else {
                     tracing::error!(
                         id = ?self.id(),
@@ -91,6 +150,8 @@ impl Container {
                             cgroup_path: config.cgroup_path.to_owned(),
                             systemd_cgroup: self.systemd(),
                             container_name: self.id().to_string(),
+                            cgroup_root: self.cgroup_root().cloned(),
+                            rootless: self.rootless(),
                         },
                     )?;
                     cmanager.remove().map_err(|err| {
@@ -98,7 +159,34 @@ impl Container {
                         err
                     })?;
 
-                    if let Some(hooks) = config.hooks.as_ref() {
+                    if let (Some(devices), Some(netns_path)) =
+                        (config.net_devices.as_ref(), config.net_ns_path.as_ref())
+                    {
+                        // The network namespace outlives the container, so the devices
+                        // have to be moved back to the host explicitly.
+                        for (host_name, device) in devices {
+                            let name = resolve_device_name(device, host_name);
+                            let host_config = config
+                                .net_device_configs
+                                .get(host_name)
+                                .cloned()
+                                .unwrap_or_default();
+                            if let Err(err) =
+                                dev_return_to_host(name, host_name, netns_path, &host_config)
+                            {
+                                tracing::warn!(
+                                    ?err,
+                                    "failed to move network device {name} back to the host, continue to delete"
+                                );
+                            }
+                        }
+                    }
+
+                    // The hooks may already have run when the container
+                    // process exited.
+                    if let Some(hooks) = config.hooks.as_ref()
+                        && !self.state.poststop_hooks_run
+                    {
                         hooks::run_hooks(
                             hooks.poststop().as_ref(),
                             Some(&self.state),
@@ -131,6 +219,65 @@ impl Container {
             })?;
         }
 
+        if let Some(root_path) = self.root.parent()
+            && let Err(err) = SubIdAllocator::new(root_path).release(self.id())
+        {
+            tracing::warn!(
+                ?err,
+                "failed to release subordinate ids, continue to delete"
+            );
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+    use std::time::Duration;
+
+    use anyhow::Result;
+
+    use super::*;
+    use crate::container::ContainerStatus;
+
+    #[test]
+    fn test_lock() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let root = tmp.path().join("container");
+        fs::create_dir(&root)?;
+        let container = Container::new(
+            "container",
+            ContainerStatus::Stopped,
+            None,
+            tmp.path(),
+            &root,
+        )?;
+
+        // The container is deleted while another process waits for the lock.
+        let lock = container.lock()?.expect("container exists");
+        let waiter = {
+            let container = container.clone();
+            thread::spawn(move || container.lock().map(|lock| lock.is_some()))
+        };
+        thread::sleep(Duration::from_millis(100));
+        fs::remove_dir(&root)?;
+        drop(lock);
+        assert!(!waiter.join().unwrap()?);
+
+        // Or recreated, by a restart.
+        fs::create_dir(&root)?;
+        let lock = container.lock()?.expect("container exists");
+        let waiter = {
+            let container = container.clone();
+            thread::spawn(move || container.lock().map(|lock| lock.is_some()))
+        };
+        thread::sleep(Duration::from_millis(100));
+        fs::remove_dir(&root)?;
+        fs::create_dir(&root)?;
+        drop(lock);
+        assert!(waiter.join().unwrap()?);
         Ok(())
     }
 }
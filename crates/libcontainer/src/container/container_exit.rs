@@ -0,0 +1,241 @@
+use std::os::fd::{AsFd, FromRawFd, OwnedFd};
+
+use chrono::{DateTime, Utc};
+use libcgroups::common::CgroupManager;
+use nix::errno::Errno;
+use nix::poll::{PollFd, PollFlags, PollTimeout, poll};
+use nix::sys::signal::Signal;
+use nix::sys::wait::WaitStatus;
+
+use super::{Container, ContainerStatus};
+use crate::error::LibcontainerError;
+use crate::hooks;
+
+impl Container {
+    /// Records the exit of the container process in the state of the
+    /// container. This is meant for the process reaping the container
+    /// process, which is the only one to know its exit status.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use libcontainer::container::builder::ContainerBuilder;
+    /// use libcontainer::syscall::syscall::SyscallType;
+    /// use nix::sys::wait::waitpid;
+    ///
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut container = ContainerBuilder::new(
+    ///     "74f1a4cb3801".to_owned(),
+    ///     SyscallType::default(),
+    /// )
+    /// .as_init("/var/run/docker/bundle")
+    /// .build()?;
+    ///
+    /// container.start()?;
+    /// let status = waitpid(container.pid().unwrap(), None)?;
+    /// container.record_exit(status)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn record_exit(&mut self, status: WaitStatus) -> Result<(), LibcontainerError> {
+        let (code, signal) = match status {
+            WaitStatus::Exited(_, code) => (code, None),
+            WaitStatus::Signaled(_, signal, _) => (128 + signal as i32, Some(signal as i32)),
+            _ => {
+                return Err(LibcontainerError::InvalidInput(format!(
+                    "{status:?} is not an exit status"
+                )));
+            }
+        };
+
+        self.state.exit_code = Some(code);
+        self.state.exit_signal = signal;
+        self.state.finished_at = Some(Utc::now());
+        self.state.oom_killed = self.read_oom_killed();
+        self.set_status(ContainerStatus::Stopped).save()
+    }
+
+    /// Records that the container process exited, for a process that
+    /// observed the exit without knowing its status. An exit already
+    /// recorded is kept.
+    pub fn record_stop(&mut self) -> Result<(), LibcontainerError> {
+        if self.state.finished_at.is_none() {
+            self.state.finished_at = Some(Utc::now());
+            self.state.oom_killed = self.read_oom_killed();
+        }
+        self.set_status(ContainerStatus::Stopped).save()
+    }
+
+    /// Records that the container process was killed with SIGKILL, which
+    /// it can't survive.
+    pub(crate) fn record_killed(&mut self) -> Result<(), LibcontainerError> {
+        match self.pid() {
+            Some(pid) => self.record_exit(WaitStatus::Signaled(pid, Signal::SIGKILL, false)),
+            None => self.record_stop(),
+        }
+    }
+
+    /// Waits until the container process exits. The caller does not need to
+    /// be the parent of the container process, so the state is reloaded
+    /// afterwards to pick up the exit recorded by the monitor of the
+    /// container, if any.
+    pub fn wait(&mut self) -> Result<(), LibcontainerError> {
+        self.refresh_status()?;
+        let Some(pid) = self.pid() else {
+            return Ok(());
+        };
+        if self.status() == ContainerStatus::Stopped {
+            return Ok(());
+        }
+
+        let pidfd = match unsafe { libc::syscall(libc::SYS_pidfd_open, pid.as_raw(), 0) } {
+            -1 if Errno::last() == Errno::ESRCH => return Ok(()),
+            -1 => return Err(LibcontainerError::OtherSyscall(Errno::last())),
+            fd => unsafe { OwnedFd::from_raw_fd(fd as i32) },
+        };
+        // The pidfd becomes readable once the process exits.
+        loop {
+            let mut fds = [PollFd::new(pidfd.as_fd(), PollFlags::POLLIN)];
+            match poll(&mut fds, PollTimeout::NONE) {
+                Ok(_) => break,
+                Err(Errno::EINTR) => continue,
+                Err(err) => return Err(LibcontainerError::OtherSyscall(err)),
+            }
+        }
+
+        self.refresh_state()?;
+        Ok(())
+    }
+
+    pub fn exit_code(&self) -> Option<i32> {
+        self.state.exit_code
+    }
+
+    pub fn exit_signal(&self) -> Option<i32> {
+        self.state.exit_signal
+    }
+
+    pub fn finished_at(&self) -> Option<DateTime<Utc>> {
+        self.state.finished_at
+    }
+
+    pub fn oom_killed(&self) -> bool {
+        self.state.oom_killed
+    }
+
+    /// Runs the poststop hooks of the container, unless they already ran
+    /// since the container process exited
+    pub fn run_poststop_hooks(&mut self) -> Result<(), LibcontainerError> {
+        if self.state.poststop_hooks_run {
+            return Ok(());
+        }
+
+        let config = self.spec()?;
+        if let Some(hooks) = config.hooks.as_ref() {
+            hooks::run_hooks(
+                hooks.poststop().as_ref(),
+                Some(&self.state),
+                None,
+                None,
+                None,
+            )?;
+        }
+
+        self.state.poststop_hooks_run = true;
+        self.save()
+    }
+
+    // The memory controller counts the OOM kills until the cgroup is
+    // removed, so this has to be checked before the container is deleted.
+    fn read_oom_killed(&self) -> bool {
+        let stats = self.spec().and_then(|config| {
+            let cmanager =
+                libcgroups::common::create_cgroup_manager(libcgroups::common::CgroupConfig {
+                    cgroup_path: config.cgroup_path,
+                    systemd_cgroup: self.systemd(),
+                    container_name: self.id().to_string(),
+                    cgroup_root: self.cgroup_root().cloned(),
+                    rootless: self.rootless(),
+                })?;
+            Ok(cmanager.stats()?)
+        });
+
+        match stats {
+            Ok(stats) => stats.memory.oom_kill_count > 0,
+            Err(err) => {
+                tracing::warn!(?err, "failed to read the oom kills of the container");
+                false
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use nix::unistd::Pid;
+
+    use super::*;
+    use crate::container::State;
+
+    #[test]
+    fn test_record_exit() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let mut container = Container::new(
+            "container",
+            ContainerStatus::Running,
+            Some(1),
+            tmp.path(),
+            tmp.path(),
+        )?;
+
+        // Without a config, the oom kills can't be read and are not reported.
+        container.record_exit(WaitStatus::Signaled(
+            Pid::from_raw(1),
+            Signal::SIGKILL,
+            false,
+        ))?;
+        let state = State::load(tmp.path())?;
+        assert_eq!(state.status, ContainerStatus::Stopped);
+        assert_eq!(state.exit_code, Some(137));
+        assert_eq!(state.exit_signal, Some(9));
+        assert!(state.finished_at.is_some());
+        assert!(!state.oom_killed);
+
+        container.record_exit(WaitStatus::Exited(Pid::from_raw(1), 3))?;
+        assert_eq!(container.exit_code(), Some(3));
+        assert_eq!(container.exit_signal(), None);
+
+        assert!(container.record_exit(WaitStatus::StillAlive).is_err());
+
+        // An exit already recorded is kept.
+        let finished_at = container.finished_at();
+        container.record_stop()?;
+        assert_eq!(container.finished_at(), finished_at);
+        assert_eq!(container.exit_code(), Some(3));
+        Ok(())
+    }
+
+    #[test]
+    fn test_wait() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let mut child = std::process::Command::new("sleep").arg("0.2").spawn()?;
+        let mut container = Container::new(
+            "container",
+            ContainerStatus::Running,
+            Some(child.id() as i32),
+            tmp.path(),
+            tmp.path(),
+        )?;
+        container.save()?;
+
+        container.wait()?;
+        child.wait()?;
+        container.record_stop()?;
+        let state = State::load(tmp.path())?;
+        assert_eq!(state.status, ContainerStatus::Stopped);
+        assert!(state.finished_at.is_some());
+        assert_eq!(state.exit_code, None);
+        Ok(())
+    }
+}
@@ -1,7 +1,7 @@
-use libcgroups::common::{CgroupManager, get_cgroup_setup};
+use libcgroups::common::{CgroupManager, get_cgroup_setup_with_root};
 use nix::sys::signal::{self};
 
-use super::{Container, ContainerStatus};
+use super::{Container, ContainerStatus, State};
 use crate::error::LibcontainerError;
 use crate::signal::Signal;
 
@@ -41,6 +41,13 @@ impl Container {
                 return Err(LibcontainerError::IncorrectStatus(self.status()));
             }
         }
+        // The exit of the container process may have been recorded meanwhile
+        // by the process reaping it, which must not be overwritten.
+        if let Ok(state) = State::load(&self.root)
+            && state.finished_at.is_some()
+        {
+            self.state = state;
+        }
         self.set_status(ContainerStatus::Stopped).save()?;
         Ok(())
     }
@@ -79,7 +86,7 @@ impl Container {
         // For cgroup V1, a frozon process cannot respond to signals,
         // so we need to thaw it. Only thaw the cgroup for SIGKILL.
         if self.status() == ContainerStatus::Paused && signal == signal::Signal::SIGKILL {
-            match get_cgroup_setup()? {
+            match get_cgroup_setup_with_root(self.cgroup_root_or_default())? {
                 libcgroups::common::CgroupSetup::Legacy
                 | libcgroups::common::CgroupSetup::Hybrid => {
                     let cmanager = libcgroups::common::create_cgroup_manager(
@@ -87,6 +94,8 @@ impl Container {
                             cgroup_path: self.spec()?.cgroup_path,
                             systemd_cgroup: self.systemd(),
                             container_name: self.id().to_string(),
+                            cgroup_root: self.cgroup_root().cloned(),
+                            rootless: self.rootless(),
                         },
                     )?;
                     cmanager.freeze(libcgroups::common::FreezerState::Thawed)?;
@@ -104,6 +113,8 @@ impl Container {
                 cgroup_path: self.spec()?.cgroup_path,
                 systemd_cgroup: self.systemd(),
                 container_name: self.id().to_string(),
+                cgroup_root: self.cgroup_root().cloned(),
+                rootless: self.rootless(),
             })?;
 
         if let Err(e) = cmanager.freeze(libcgroups::common::FreezerState::Frozen) {
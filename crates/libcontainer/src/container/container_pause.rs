@@ -37,6 +37,8 @@ impl Container {
                 cgroup_path: self.spec()?.cgroup_path,
                 systemd_cgroup: self.systemd(),
                 container_name: self.id().to_string(),
+                cgroup_root: self.cgroup_root().cloned(),
+                rootless: self.rootless(),
             })?;
         cmanager.freeze(FreezerState::Frozen)?;
 
@@ -1,8 +1,30 @@
+use std::path::PathBuf;
+use std::thread;
+
+use nix::sys::signal::{SigSet, SigmaskHow, pthread_sigmask};
+use nix::unistd::{ForkResult, fork};
+
 use super::{Container, ContainerStatus};
 use crate::config::YoukiConfig;
 use crate::error::LibcontainerError;
 use crate::hooks;
-use crate::notify_socket::{NOTIFY_FILE, NotifySocket};
+use crate::notify_socket::{NOTIFY_FILE, NOTIFY_SOCKET_DIR, NotifyRelay, NotifySocket};
+use crate::timings::{self, Phase, PhaseTiming, Recorder, StartupTimings};
+
+/// Options of [`Container::start_with_options`]
+#[derive(Debug, Clone, Default)]
+pub struct StartOptions {
+    /// sd_notify socket of the host service manager. If the container was
+    /// created with a notify socket, its messages are relayed to this one.
+    pub notify_socket: Option<PathBuf>,
+    /// Wait until the container reports that it is ready, instead of relaying
+    /// its messages from a background process
+    pub wait_ready: bool,
+    /// The caller waits for the container to exit, its messages are relayed
+    /// from a thread of the caller. Otherwise the relay is forked, which
+    /// requires the caller to have no other thread.
+    pub foreground: bool,
+}
 
 impl Container {
     /// Starts a previously created container
@@ -26,6 +48,38 @@ impl Container {
     /// # }
     /// ```
     pub fn start(&mut self) -> Result<(), LibcontainerError> {
+        self.start_with_options(&StartOptions::default())
+    }
+
+    /// Starts a previously created container, relaying its sd_notify
+    /// messages to the notify socket of the host
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use libcontainer::container::StartOptions;
+    /// use libcontainer::container::builder::ContainerBuilder;
+    /// use libcontainer::syscall::syscall::SyscallType;
+    ///
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut container = ContainerBuilder::new(
+    ///     "74f1a4cb3801".to_owned(),
+    ///     SyscallType::default(),
+    /// )
+    /// .as_init("/var/run/docker/bundle")
+    /// .with_notify_socket(Some("/run/systemd/notify"))
+    /// .build()?;
+    ///
+    /// container.start_with_options(&StartOptions {
+    ///     notify_socket: Some("/run/systemd/notify".into()),
+    ///     wait_ready: true,
+    ///     foreground: false,
+    /// })?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[tracing::instrument(level = "info", name = "start", skip_all, fields(container_id = %self.id()))]
+    pub fn start_with_options(&mut self, options: &StartOptions) -> Result<(), LibcontainerError> {
         self.refresh_status()?;
 
         if !self.can_start() {
@@ -42,8 +96,16 @@ impl Container {
             err
         })?;
 
+        // The relay socket is bound before the container starts, so that no
+        // message is lost.
+        let socket_dir = self.root.join(NOTIFY_SOCKET_DIR);
+        let relay = match &options.notify_socket {
+            Some(host) if socket_dir.is_dir() => Some(NotifyRelay::bind(&socket_dir, host)?),
+            _ => None,
+        };
+
         let mut notify_socket = NotifySocket::new(self.root.join(NOTIFY_FILE));
-        notify_socket.notify_container_start()?;
+        let reply = notify_socket.notify_container_start()?;
         self.set_status(ContainerStatus::Running)
             .save()
             .map_err(|err| {
@@ -53,7 +115,9 @@ impl Container {
 
         // Run post start hooks. It runs after the container process is started.
         // It is called in the runtime namespace.
+        let mut recorder = Recorder::new(timings::Process::Runtime);
         if let Some(hooks) = config.hooks.as_ref() {
+            let hooks_start = recorder.start();
             hooks::run_hooks(
                 hooks.poststart().as_ref(),
                 Some(&self.state),
@@ -65,8 +129,99 @@ impl Container {
                 tracing::error!("failed to run post start hooks: {}", err);
                 err
             })?;
+            recorder.record(Phase::Hooks, hooks_start);
+        }
+        self.save_start_timings(&reply, recorder.into_phases());
+
+        if let Some(relay) = relay {
+            self.relay_notify(relay, options)?;
         }
 
         Ok(())
     }
+
+    // Adds the phases of the start to the timings recorded at create. The init
+    // process replies to the start with the phases it ran since then.
+    fn save_start_timings(&self, reply: &[u8], phases: Vec<PhaseTiming>) {
+        let mut startup_timings = match StartupTimings::load(&self.root) {
+            Ok(Some(startup_timings)) => startup_timings,
+            Ok(None) => return,
+            Err(err) => {
+                tracing::warn!(?err, "failed to load startup timings");
+                return;
+            }
+        };
+        if !reply.is_empty() {
+            match serde_json::from_slice::<Vec<PhaseTiming>>(reply) {
+                Ok(init_phases) => startup_timings.extend(init_phases),
+                Err(err) => tracing::warn!(?err, "invalid startup timings of the init process"),
+            }
+        }
+        startup_timings.extend(phases);
+        if let Err(err) = startup_timings.save(&self.root) {
+            tracing::warn!(?err, "failed to save startup timings");
+        }
+    }
+
+    fn relay_notify(
+        &self,
+        relay: NotifyRelay,
+        options: &StartOptions,
+    ) -> Result<(), LibcontainerError> {
+        let pid = self.pid().ok_or(LibcontainerError::Other(
+            "container has no pid to relay notify messages for".into(),
+        ))?;
+        if options.wait_ready {
+            return Ok(relay.relay(pid)?);
+        }
+
+        if options.foreground {
+            thread::Builder::new()
+                .name("notify-relay".to_owned())
+                .spawn(move || {
+                    // signals are handled by the caller
+                    let _ = pthread_sigmask(SigmaskHow::SIG_BLOCK, Some(&SigSet::all()), None);
+                    if let Err(err) = relay.relay(pid) {
+                        tracing::error!(?err, "failed to relay notify messages");
+                    }
+                })
+                .map_err(LibcontainerError::OtherIO)?;
+            return Ok(());
+        }
+
+        // The child of a multi-threaded process may only make async-signal
+        // safe calls, which relaying messages is not.
+        let threads = procfs::process::Process::myself()
+            .and_then(|process| process.stat())
+            .map(|stat| stat.num_threads)
+            .map_err(|err| LibcontainerError::Other(format!("failed to count threads: {err}")))?;
+        if threads > 1 {
+            return Err(LibcontainerError::Other(
+                "cannot fork the notify relay of a multi-threaded process, start the container in the foreground".into(),
+            ));
+        }
+
+        // SAFETY: the process has a single thread, and the child only relays
+        // messages before it exits.
+        match unsafe { fork() } {
+            Ok(ForkResult::Child) => {
+                let code = match relay.relay(pid) {
+                    Ok(()) => 0,
+                    Err(err) => {
+                        tracing::error!(?err, "failed to relay notify messages");
+                        1
+                    }
+                };
+                std::process::exit(code)
+            }
+            Ok(ForkResult::Parent { child }) => {
+                tracing::debug!(?child, "relaying notify messages in the background");
+                Ok(())
+            }
+            Err(err) => {
+                tracing::error!(?err, "failed to fork notify relay");
+                Err(LibcontainerError::OtherSyscall(err))
+            }
+        }
+    }
 }
@@ -2,7 +2,7 @@ use std::fs;
 use std::path::{Path, PathBuf};
 use std::rc::Rc;
 
-use oci_spec::runtime::Spec;
+use oci_spec::runtime::{MountBuilder, Spec};
 use user_ns::UserNamespaceConfig;
 
 use super::builder::ContainerBuilder;
@@ -11,8 +11,9 @@ use super::mount_validation::validate_idmapped_mounts;
 use super::{Container, ContainerStatus};
 use crate::config::YoukiConfig;
 use crate::error::{ErrInvalidSpec, LibcontainerError, MissingSpecError};
-use crate::notify_socket::NOTIFY_FILE;
+use crate::notify_socket::{CONTAINER_NOTIFY_SOCKET, NOTIFY_FILE, NOTIFY_SOCKET_DIR};
 use crate::process::args::ContainerType;
+use crate::subid::{self, SubIdAllocator};
 use crate::syscall::syscall::create_syscall;
 use crate::validator::Validator;
 use crate::{apparmor, tty, user_ns, utils};
@@ -22,9 +23,11 @@ pub struct InitContainerBuilder {
     base: ContainerBuilder,
     bundle: PathBuf,
     use_systemd: bool,
+    cgroup_root: Option<PathBuf>,
     detached: bool,
     no_pivot: bool,
     as_sibling: bool,
+    notify_socket: Option<PathBuf>,
 }
 
 impl InitContainerBuilder {
@@ -35,9 +38,11 @@ impl InitContainerBuilder {
             base: builder,
             bundle,
             use_systemd: true,
+            cgroup_root: None,
             detached: true,
             no_pivot: false,
             as_sibling: false,
+            notify_socket: None,
         }
     }
 
@@ -47,6 +52,13 @@ impl InitContainerBuilder {
         self
     }
 
+    /// Sets the mount point of the cgroup hierarchy used for the container.
+    /// Defaults to /sys/fs/cgroup if not set.
+    pub fn with_cgroup_root<P: Into<PathBuf>>(mut self, cgroup_root: Option<P>) -> Self {
+        self.cgroup_root = cgroup_root.map(|p| p.into());
+        self
+    }
+
     /// Sets if the init process should be run as a child or a sibling of
     /// the calling process
     pub fn as_sibling(mut self, as_sibling: bool) -> Self {
@@ -64,6 +76,15 @@ impl InitContainerBuilder {
         self
     }
 
+    /// Sets the sd_notify socket of the host service manager, usually the
+    /// `NOTIFY_SOCKET` environment variable. The container gets a notify
+    /// socket of its own, whose messages are relayed to the host socket by
+    /// [`Container::start_with_options`].
+    pub fn with_notify_socket<P: Into<PathBuf>>(mut self, path: Option<P>) -> Self {
+        self.notify_socket = path.map(|p| p.into());
+        self
+    }
+
     /// Overrides the OCI bundle path for the container
     ///                                                                                                                                                                             
     /// Replaces the bundle set by [`ContainerBuilder::as_init`].  
@@ -73,17 +94,53 @@ impl InitContainerBuilder {
     }
 
     /// Creates a new container
-    pub fn build(self) -> Result<Container, LibcontainerError> {
-        let spec = self.load_spec()?;
+    #[tracing::instrument(level = "info", name = "create", skip_all, fields(container_id = %self.base.container_id))]
+    pub fn build(mut self) -> Result<Container, LibcontainerError> {
+        let mut spec = self.load_spec()?;
+        // the mappings of automatic subordinate ids are only known once the
+        // container directory holds the lease, the spec is validated then
+        let userns_size = subid::requested_size(&spec)?;
+        if userns_size.is_none() {
+            Self::validate_spec(&spec)?;
+        }
         // validate terminal field against console socket presence before any side effects
         // (mirrors runc's checkTerminal called at the top of runner.run())
         self.base.check_terminal(&spec, self.detached)?;
 
         let container_dir = self.create_container_dir()?;
+        if let Some(size) = userns_size {
+            let allocator = SubIdAllocator::new(&self.base.root_path);
+            let leased = allocator
+                .allocate(&self.base.container_id, create_syscall().get_euid(), size)
+                .map_err(LibcontainerError::from)
+                .and_then(|lease| Ok(lease.apply(&mut spec)?))
+                .and_then(|_| Self::validate_spec(&spec));
+            // Nothing else refers to the container yet, it is removed
+            // together with its lease.
+            if let Err(err) = leased {
+                if let Err(err) = allocator.release(&self.base.container_id) {
+                    tracing::warn!(?err, "failed to release subordinate ids");
+                }
+                if let Err(err) = fs::remove_dir_all(&container_dir) {
+                    tracing::warn!(?err, ?container_dir, "failed to remove container dir");
+                }
+                return Err(err);
+            }
+        }
+        if self.notify_socket.is_some() {
+            Self::setup_notify_socket(&mut spec, &container_dir)?;
+        }
 
+        let rootless = self
+            .base
+            .rootless
+            .resolve(&*self.base.syscall.create_syscall())
+            .map_err(LibcontainerError::OtherIO)?;
         let mut container = self.create_container_state(&container_dir)?;
         container
             .set_systemd(self.use_systemd)
+            .set_rootless(rootless)
+            .set_cgroup_root(self.cgroup_root.clone())
             .set_annotations(spec.annotations().clone());
 
         let notify_path = container_dir.join(NOTIFY_FILE);
@@ -100,12 +157,24 @@ impl InitContainerBuilder {
                 "console-socket",
             )?)
         } else {
-            None
+            self.base.take_terminal_socket(&spec, self.detached)
         };
 
-        let user_ns_config = UserNamespaceConfig::new(&spec)?;
+        let mut user_ns_config = UserNamespaceConfig::new(&spec)?;
+        // an unprivileged owner needs the setuid helpers to map its
+        // subordinate ids, even with a single mapping
+        if let Some(config) = user_ns_config.as_mut()
+            && userns_size.is_some()
+            && !config.privileged
+        {
+            let (newuidmap, newgidmap) =
+                user_ns::require_map_binaries().map_err(user_ns::UserNamespaceError::from)?;
+            config.newuidmap = Some(newuidmap);
+            config.newgidmap = Some(newgidmap);
+        }
 
-        let config = YoukiConfig::from_spec(&spec, container.id())?;
+        let mut config = YoukiConfig::from_spec(&spec, container.id())?;
+        config.no_new_keyring = self.base.no_new_keyring;
         config.save(&container_dir).map_err(|err| {
             tracing::error!(?container_dir, "failed to save config: {}", err);
             err
@@ -118,6 +187,8 @@ impl InitContainerBuilder {
             pid_file: self.base.pid_file,
             console_socket: csocketfd,
             use_systemd: self.use_systemd,
+            cgroup_root: self.cgroup_root,
+            rootless,
             spec: Rc::new(spec),
             rootfs,
             user_ns_config,
@@ -131,6 +202,7 @@ impl InitContainerBuilder {
             stdout: self.base.stdout,
             stderr: self.base.stderr,
             as_sibling: self.as_sibling,
+            no_new_keyring: self.base.no_new_keyring,
             sub_cgroup_path: None,
             process_label: None,
         };
@@ -163,11 +235,55 @@ impl InitContainerBuilder {
         Ok(container_dir)
     }
 
+    /// Bind mounts the directory of the container notify socket into the
+    /// container and points `NOTIFY_SOCKET` of the process to it
+    fn setup_notify_socket(spec: &mut Spec, container_dir: &Path) -> Result<(), LibcontainerError> {
+        let socket_dir = container_dir.join(NOTIFY_SOCKET_DIR);
+        fs::create_dir_all(&socket_dir).map_err(|err| {
+            tracing::error!(
+                ?socket_dir,
+                "failed to create notify socket directory: {}",
+                err
+            );
+            LibcontainerError::OtherIO(err)
+        })?;
+
+        let destination = Path::new(CONTAINER_NOTIFY_SOCKET)
+            .parent()
+            .expect("notify socket path has a parent");
+        let mount = MountBuilder::default()
+            .destination(destination)
+            .typ("bind")
+            .source(&socket_dir)
+            .options(
+                ["bind", "nosuid", "noexec", "nodev", "ro"]
+                    .map(String::from)
+                    .to_vec(),
+            )
+            .build()?;
+        let mut mounts = spec.mounts().clone().unwrap_or_default();
+        mounts.push(mount);
+        spec.set_mounts(Some(mounts));
+
+        if let Some(mut process) = spec.process().clone() {
+            let mut env: Vec<String> = process
+                .env()
+                .iter()
+                .flatten()
+                .filter(|e| !e.starts_with("NOTIFY_SOCKET="))
+                .cloned()
+                .collect();
+            env.push(format!("NOTIFY_SOCKET={CONTAINER_NOTIFY_SOCKET}"));
+            process.set_env(Some(env));
+            spec.set_process(Some(process));
+        }
+
+        Ok(())
+    }
+
     fn load_spec(&self) -> Result<Spec, LibcontainerError> {
         let source_spec_path = self.bundle.join("config.json");
         let mut spec = Spec::load(source_spec_path)?;
-        Self::validate_spec(&spec)?;
-
         spec.canonicalize_rootfs(&self.bundle).map_err(|err| {
             tracing::error!(bundle = ?self.bundle, "failed to canonicalize rootfs: {}", err);
             err
@@ -245,4 +361,42 @@ mod tests {
 
         assert_eq!(builder.bundle, PathBuf::from("/new/bundle"));
     }
+
+    #[test]
+    fn test_with_cgroup_root() {
+        let builder = ContainerBuilder::new("test-id".to_owned(), SyscallType::default())
+            .as_init("/original/bundle");
+        assert_eq!(builder.cgroup_root, None);
+
+        let builder = builder.with_cgroup_root(Some("/tmp/cgroup"));
+        assert_eq!(builder.cgroup_root, Some(PathBuf::from("/tmp/cgroup")));
+    }
+
+    #[test]
+    fn test_setup_notify_socket() -> anyhow::Result<()> {
+        let container_dir = tempfile::tempdir()?;
+        let mut spec = Spec::default();
+        let mut process = spec.process().clone().unwrap();
+        process.set_env(Some(vec![
+            "PATH=/bin".to_owned(),
+            "NOTIFY_SOCKET=/host.sock".to_owned(),
+        ]));
+        spec.set_process(Some(process));
+
+        InitContainerBuilder::setup_notify_socket(&mut spec, container_dir.path())?;
+
+        let socket_dir = container_dir.path().join(NOTIFY_SOCKET_DIR);
+        assert!(socket_dir.is_dir());
+        let mount = spec.mounts().as_ref().unwrap().last().unwrap();
+        assert_eq!(mount.destination(), Path::new("/run/notify"));
+        assert_eq!(mount.source().as_deref(), Some(socket_dir.as_path()));
+        assert_eq!(
+            spec.process().as_ref().unwrap().env(),
+            &Some(vec![
+                "PATH=/bin".to_owned(),
+                format!("NOTIFY_SOCKET={CONTAINER_NOTIFY_SOCKET}"),
+            ])
+        );
+        Ok(())
+    }
 }
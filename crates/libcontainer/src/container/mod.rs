@@ -6,12 +6,14 @@
 /// the exec command).
 pub mod builder;
 mod builder_impl;
+pub mod checkpoint_descriptor;
 #[allow(clippy::module_inception)]
 mod container;
 mod container_checkpoint;
 mod container_criu;
 mod container_delete;
 mod container_events;
+mod container_exit;
 mod container_kill;
 mod container_pause;
 mod container_resume;
@@ -22,6 +24,8 @@ pub mod state;
 pub mod tenant_builder;
 pub use container::{CheckpointOptions, Container};
 pub use container_checkpoint::CheckpointError;
+pub use container_delete::ContainerLock;
+pub use container_start::StartOptions;
 #[allow(deprecated)]
 pub use state::ContainerProcessState;
 pub use state::{ContainerStatus, State, StateConversionError};
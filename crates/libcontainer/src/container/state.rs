@@ -15,6 +15,8 @@ use oci_spec::runtime::{
 use serde::{Deserialize, Serialize};
 use tracing::instrument;
 
+use crate::health::Health;
+
 /// Indicates status of the container
 #[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq, Default)]
 #[serde(rename_all = "camelCase")]
@@ -119,6 +121,12 @@ pub struct State {
     pub creator: Option<u32>,
     // Specifies if systemd should be used to manage cgroups
     pub use_systemd: bool,
+    // Specifies if the container runs in rootless mode
+    #[serde(default)]
+    pub rootless: bool,
+    // Mount point of the cgroup hierarchy, if it is not the default one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cgroup_root: Option<PathBuf>,
     // DEPRECATED: Keep for backwards compatibility with older state.json files.
     // Use `intel_rdt_dir` instead.
     // Specifies if the Intel RDT subdirectory needs be cleaned up.
@@ -132,6 +140,28 @@ pub struct State {
     /// create this group and MUST remove it when the container is deleted.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub intel_rdt_monitoring_dir: Option<PathBuf>,
+    // Exit code of the container process, 128 + the signal number if it was
+    // killed by a signal
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exit_code: Option<i32>,
+    // Signal that killed the container process
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exit_signal: Option<i32>,
+    // Time the container process exited
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub finished_at: Option<DateTime<Utc>>,
+    // Specifies if a process of the container was killed by the OOM killer
+    #[serde(default)]
+    pub oom_killed: bool,
+    // Specifies if the poststop hooks already ran after the container process exited
+    #[serde(default)]
+    pub poststop_hooks_run: bool,
+    // Number of times the monitor restarted the container
+    #[serde(default)]
+    pub restart_count: u32,
+    // Outcome of the health check of the container, if it has one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub health: Option<Health>,
 }
 
 impl State {
@@ -153,9 +183,18 @@ impl State {
             created: None,
             creator: None,
             use_systemd: false,
+            rootless: false,
+            cgroup_root: None,
             clean_up_intel_rdt_subdirectory: None,
             intel_rdt_dir: None,
             intel_rdt_monitoring_dir: None,
+            exit_code: None,
+            exit_signal: None,
+            finished_at: None,
+            oom_killed: false,
+            poststop_hooks_run: false,
+            restart_count: 0,
+            health: None,
         }
     }
 
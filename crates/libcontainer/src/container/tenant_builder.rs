@@ -25,10 +25,12 @@ use crate::capabilities::CapabilityExt;
 use crate::container::ContainerStatus;
 use crate::container::builder_impl::ContainerBuilderImpl;
 use crate::error::{ErrInvalidSpec, LibcontainerError, MissingSpecError};
+use crate::exec_session::ExecSession;
 use crate::notify_socket::NotifySocket;
 use crate::process::args::ContainerType;
 use crate::syscall::syscall::create_syscall;
 use crate::user_ns::UserNamespaceConfig;
+use crate::utils::RootlessMode;
 use crate::validator::Validator;
 use crate::{tty, utils};
 
@@ -55,6 +57,7 @@ pub struct TenantContainerBuilder {
     sub_cgroup: Option<String>,
     process_label: Option<String>,
     apparmor: Option<String>,
+    exec_id: Option<String>,
 }
 
 /// This is a helper function to get capabilities for tenant container, based on
@@ -154,6 +157,7 @@ impl TenantContainerBuilder {
             sub_cgroup: None,
             process_label: None,
             apparmor: None,
+            exec_id: None,
         }
     }
 
@@ -237,27 +241,93 @@ impl TenantContainerBuilder {
         self
     }
 
+    /// Records the process in the container directory under `exec_id`, so
+    /// that it can be listed and signalled later on. The ID is reserved
+    /// before the process is started, building fails if it is used by
+    /// another process of the container.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use libcontainer::container::builder::ContainerBuilder;
+    /// # use libcontainer::exec_session::ExecSession;
+    /// # use libcontainer::syscall::syscall::SyscallType;
+    ///
+    /// # fn main() -> anyhow::Result<()> {
+    /// ContainerBuilder::new(
+    ///     "74f1a4cb3801".to_owned(),
+    ///     SyscallType::default(),
+    /// )
+    /// .as_tenant()
+    /// .with_exec_id(Some(ExecSession::generate_id()))
+    /// .with_container_args(vec!["sh".to_owned()])
+    /// .build()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_exec_id(mut self, exec_id: Option<String>) -> Self {
+        self.exec_id = exec_id;
+        self
+    }
+
     /// Joins an existing container
+    #[tracing::instrument(level = "info", name = "join", skip_all, fields(container_id = %self.base.container_id))]
     pub fn build(self) -> Result<Pid, LibcontainerError> {
         let container_dir = self.lookup_container_dir()?;
         let container = self.load_container_state(container_dir.clone())?;
+        let Some(exec_id) = self.exec_id.clone() else {
+            return self.join(&container_dir, container).map(|(pid, _)| pid);
+        };
+
+        ExecSession::reserve(&container_dir, &exec_id)?;
+        match self.join(&container_dir, container) {
+            Ok((pid, process)) => {
+                ExecSession::new(&exec_id, pid, &process).save(&container_dir)?;
+                Ok(pid)
+            }
+            Err(err) => {
+                if let Err(err) = ExecSession::release(&container_dir, &exec_id) {
+                    tracing::warn!(?err, exec_id, "failed to release exec session");
+                }
+                Err(err)
+            }
+        }
+    }
+
+    // Starts the process in the container, returns its pid and the process
+    // it runs
+    fn join(
+        mut self,
+        container_dir: &Path,
+        container: Container,
+    ) -> Result<(Pid, Process), LibcontainerError> {
         let mut spec = self.load_init_spec(&container)?;
         self.adapt_spec_for_tenant(&mut spec, &container)?;
+        let process = spec.process().clone().ok_or(MissingSpecError::Process)?;
         // validate terminal field against console socket presence before any side effects
         self.base.check_terminal(&spec, self.detached)?;
 
         tracing::debug!("{:#?}", spec);
 
-        let notify_path = Self::setup_notify_listener(&container_dir)?;
+        let notify_path = Self::setup_notify_listener(container_dir)?;
         // convert path of root file system of the container to absolute path
         let rootfs = fs::canonicalize(spec.root().as_ref().ok_or(MissingSpecError::Root)?.path())
             .map_err(LibcontainerError::OtherIO)?;
 
         // if socket file path is given in commandline options,
         // get file descriptors of console socket
-        let csocketfd = self.setup_tty_socket(&container_dir)?;
+        let csocketfd = self.setup_tty_socket(container_dir, &spec)?;
 
         let use_systemd = self.should_use_systemd(&container);
+        // the cgroup of the container was set up when it was created, so
+        // keep its mode unless the caller asked for a specific one
+        let rootless = match self.base.rootless {
+            RootlessMode::Auto => container.rootless(),
+            mode => mode
+                .resolve(&*self.base.syscall.create_syscall())
+                .map_err(LibcontainerError::OtherIO)?,
+        };
+        let no_new_keyring = self.base.no_new_keyring || container.spec()?.no_new_keyring;
         let user_ns_config = UserNamespaceConfig::new(&spec)?;
 
         let (read_end, write_end) =
@@ -272,6 +342,8 @@ impl TenantContainerBuilder {
             pid_file: self.base.pid_file,
             console_socket: csocketfd,
             use_systemd,
+            cgroup_root: container.cgroup_root().cloned(),
+            rootless,
             spec: Rc::new(spec),
             rootfs,
             user_ns_config,
@@ -285,6 +357,7 @@ impl TenantContainerBuilder {
             stdout: self.base.stdout,
             stderr: self.base.stderr,
             as_sibling: self.as_sibling,
+            no_new_keyring,
             sub_cgroup_path: self.sub_cgroup,
             process_label: self.process_label,
         };
@@ -309,7 +382,7 @@ impl TenantContainerBuilder {
             match read(read_end.as_raw_fd(), &mut buf).map_err(LibcontainerError::OtherSyscall)? {
                 0 => {
                     if err_str_buf.is_empty() {
-                        return Ok(pid);
+                        return Ok((pid, process));
                     } else {
                         return Err(LibcontainerError::Other(
                             String::from_utf8_lossy(&err_str_buf).to_string(),
@@ -581,7 +654,11 @@ impl TenantContainerBuilder {
         Ok(socket_path)
     }
 
-    fn setup_tty_socket(&self, container_dir: &Path) -> Result<Option<OwnedFd>, LibcontainerError> {
+    fn setup_tty_socket(
+        &mut self,
+        container_dir: &Path,
+        spec: &Spec,
+    ) -> Result<Option<OwnedFd>, LibcontainerError> {
         let tty_name = Self::generate_name(container_dir, TENANT_TTY);
         let csocketfd = if let Some(console_socket) = &self.base.console_socket {
             Some(tty::setup_console_socket(
@@ -590,7 +667,7 @@ impl TenantContainerBuilder {
                 &tty_name,
             )?)
         } else {
-            None
+            self.base.take_terminal_socket(spec, self.detached)
         };
 
         Ok(csocketfd)
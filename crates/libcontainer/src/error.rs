@@ -41,6 +41,12 @@ pub enum LibcontainerError {
     #[error(transparent)]
     UserNamespace(#[from] crate::user_ns::UserNamespaceError),
     #[error(transparent)]
+    SubId(#[from] crate::subid::SubIdError),
+    #[error(transparent)]
+    Timings(#[from] crate::timings::TimingsError),
+    #[error(transparent)]
+    ExecSession(#[from] crate::exec_session::ExecSessionError),
+    #[error(transparent)]
     NotifyListener(#[from] crate::notify_socket::NotifyListenerError),
     #[error(transparent)]
     Config(#[from] crate::config::ConfigError),
@@ -179,20 +185,20 @@ mod tests {
     #[test]
     fn test_create_container() {
         let create_container_err =
-            CreateContainerError::new(CreateCgroupSetupError::NonDefault.into(), None);
+            CreateContainerError::new(CreateCgroupSetupError::FailedToDetect.into(), None);
         let msg = format!("{}", create_container_err);
         assert_eq!(
-            "failed to create container: non default cgroup root not supported",
+            "failed to create container: failed to detect cgroup setup",
             msg
         );
 
         let create_container_err = CreateContainerError::new(
-            CreateCgroupSetupError::NonDefault.into(),
+            CreateCgroupSetupError::FailedToDetect.into(),
             Some(ErrInvalidID::Empty.into()),
         );
         let msg = format!("{}", create_container_err);
         assert_eq!(
-            "failed to create container: non default cgroup root not supported. \
+            "failed to create container: failed to detect cgroup setup. \
          error during cleanup: container id can't be empty",
             msg
         );
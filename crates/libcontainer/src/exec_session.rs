@@ -0,0 +1,341 @@
+//! Processes executed in a running container (`exec`). Each of them is
+//! recorded under an ID in the container directory, so that it can be
+//! listed and signalled after the runtime returned, and its exit status
+//! collected by the process reaping it.
+use std::fs;
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use nix::sys::signal;
+use nix::sys::wait::WaitStatus;
+use nix::unistd::Pid;
+use oci_spec::runtime::Process;
+use procfs::process::ProcState;
+use serde::{Deserialize, Serialize};
+
+use crate::signal::Signal;
+
+/// Name of the directory holding the exec sessions in the container directory
+pub const EXEC_SESSIONS_DIR: &str = "exec";
+
+#[derive(Debug, thiserror::Error)]
+pub enum ExecSessionError {
+    #[error("exec id {0:?} is invalid, expected letters, digits, '_', '+', '-' or '.'")]
+    InvalidId(String),
+    #[error("exec session {0} already exists")]
+    Exist(String),
+    #[error("exec session {0} does not exist")]
+    NotFound(String),
+    #[error("exec session {0} is not running")]
+    NotRunning(String),
+    #[error("{0:?} is not an exit status")]
+    NotAnExit(WaitStatus),
+    #[error("failed to access exec session {path}")]
+    Io { source: io::Error, path: PathBuf },
+    #[error("failed to encode exec session {path}")]
+    Encode {
+        source: serde_json::Error,
+        path: PathBuf,
+    },
+    #[error("failed to parse exec session {path}")]
+    Parse {
+        source: serde_json::Error,
+        path: PathBuf,
+    },
+    #[error("failed to signal exec session {0}")]
+    Signal(String, #[source] nix::Error),
+}
+
+type Result<T> = std::result::Result<T, ExecSessionError>;
+
+/// A process executed in a container
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ExecSession {
+    pub id: String,
+    pub pid: i32,
+    // Start time of the process in clock ticks after boot, to tell it from
+    // a process reusing its pid
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start_ticks: Option<u64>,
+    pub started_at: DateTime<Utc>,
+    pub args: Vec<String>,
+    pub uid: u32,
+    pub gid: u32,
+    pub tty: bool,
+    // Exit code of the process, 128 + the signal number if it was killed by
+    // a signal
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exit_code: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exit_signal: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub finished_at: Option<DateTime<Utc>>,
+}
+
+impl ExecSession {
+    /// Describes the process `pid` executed in a container per `process`
+    pub fn new(id: &str, pid: Pid, process: &Process) -> Self {
+        let start_ticks = procfs::process::Process::new(pid.as_raw())
+            .and_then(|proc| proc.stat())
+            .map(|stat| stat.starttime)
+            .ok();
+        Self {
+            id: id.to_owned(),
+            pid: pid.as_raw(),
+            start_ticks,
+            started_at: Utc::now(),
+            args: process.args().clone().unwrap_or_default(),
+            uid: process.user().uid(),
+            gid: process.user().gid(),
+            tty: process.terminal().unwrap_or(false),
+            exit_code: None,
+            exit_signal: None,
+            finished_at: None,
+        }
+    }
+
+    /// Returns a random ID for a new exec session
+    pub fn generate_id() -> String {
+        format!("{:016x}", fastrand::u64(..))
+    }
+
+    /// Reserves `id` for a new exec session of the container, before its
+    /// process is started. The record is created empty and exclusively, so
+    /// that only one of concurrent execs with the same ID gets it. It is
+    /// filled by [`ExecSession::save`] once the process runs, or removed by
+    /// [`ExecSession::release`] if it fails to start.
+    pub fn reserve(container_root: &Path, id: &str) -> Result<()> {
+        let valid = !id.is_empty()
+            && id != "."
+            && id != ".."
+            && id
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '+' | '-' | '.'));
+        if !valid {
+            return Err(ExecSessionError::InvalidId(id.to_owned()));
+        }
+
+        let dir = container_root.join(EXEC_SESSIONS_DIR);
+        fs::create_dir_all(&dir).map_err(|source| ExecSessionError::Io {
+            source,
+            path: dir.to_owned(),
+        })?;
+        let path = Self::file_path(container_root, id);
+        match fs::File::options().write(true).create_new(true).open(&path) {
+            Ok(_) => Ok(()),
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {
+                Err(ExecSessionError::Exist(id.to_owned()))
+            }
+            Err(source) => Err(ExecSessionError::Io { source, path }),
+        }
+    }
+
+    /// Removes the reservation of an exec session whose process did not start
+    pub fn release(container_root: &Path, id: &str) -> Result<()> {
+        let path = Self::file_path(container_root, id);
+        match fs::remove_file(&path) {
+            Ok(()) => Ok(()),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+            Err(source) => Err(ExecSessionError::Io { source, path }),
+        }
+    }
+
+    pub fn save(&self, container_root: &Path) -> Result<()> {
+        // The session is written aside and renamed, so that it is never read
+        // half written.
+        let path = Self::file_path(container_root, &self.id);
+        let tmp_path = path.with_extension("json.tmp");
+        let file = fs::File::create(&tmp_path).map_err(|source| ExecSessionError::Io {
+            source,
+            path: tmp_path.to_owned(),
+        })?;
+        let mut writer = BufWriter::new(file);
+        serde_json::to_writer(&mut writer, self).map_err(|source| ExecSessionError::Encode {
+            source,
+            path: tmp_path.to_owned(),
+        })?;
+        writer.flush().map_err(|source| ExecSessionError::Io {
+            source,
+            path: tmp_path.to_owned(),
+        })?;
+        fs::rename(&tmp_path, &path).map_err(|source| ExecSessionError::Io { source, path })
+    }
+
+    pub fn load(container_root: &Path, id: &str) -> Result<Self> {
+        let path = Self::file_path(container_root, id);
+        let file = match fs::File::open(&path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                return Err(ExecSessionError::NotFound(id.to_owned()));
+            }
+            Err(source) => return Err(ExecSessionError::Io { source, path }),
+        };
+        // reserved, its process is not started yet
+        if file
+            .metadata()
+            .map_err(|source| ExecSessionError::Io {
+                source,
+                path: path.to_owned(),
+            })?
+            .len()
+            == 0
+        {
+            return Err(ExecSessionError::NotFound(id.to_owned()));
+        }
+        serde_json::from_reader(BufReader::new(file))
+            .map_err(|source| ExecSessionError::Parse { source, path })
+    }
+
+    /// Returns the exec sessions of a container, oldest first
+    pub fn list(container_root: &Path) -> Result<Vec<Self>> {
+        let dir = container_root.join(EXEC_SESSIONS_DIR);
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(source) => return Err(ExecSessionError::Io { source, path: dir }),
+        };
+
+        let mut sessions = Vec::new();
+        for entry in entries {
+            let path = entry
+                .map_err(|source| ExecSessionError::Io {
+                    source,
+                    path: dir.to_owned(),
+                })?
+                .path();
+            if let Some(id) = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.strip_suffix(".json"))
+            {
+                match Self::load(container_root, id) {
+                    Ok(session) => sessions.push(session),
+                    // reserved, or released since it was listed
+                    Err(ExecSessionError::NotFound(_)) => {}
+                    Err(err) => return Err(err),
+                }
+            }
+        }
+        sessions.sort_by_key(|session| session.started_at);
+        Ok(sessions)
+    }
+
+    /// Records the exit of the process, for the process reaping it
+    pub fn record_exit(&mut self, status: WaitStatus) -> Result<()> {
+        let (code, signal) = match status {
+            WaitStatus::Exited(_, code) => (code, None),
+            WaitStatus::Signaled(_, signal, _) => (128 + signal as i32, Some(signal as i32)),
+            _ => return Err(ExecSessionError::NotAnExit(status)),
+        };
+
+        self.exit_code = Some(code);
+        self.exit_signal = signal;
+        self.finished_at = Some(Utc::now());
+        Ok(())
+    }
+
+    /// Whether the process is still running. A process whose exit was not
+    /// recorded is looked up by its pid and start time.
+    pub fn is_running(&self) -> bool {
+        if self.finished_at.is_some() {
+            return false;
+        }
+        let Some(start_ticks) = self.start_ticks else {
+            return false;
+        };
+
+        procfs::process::Process::new(self.pid)
+            .and_then(|proc| proc.stat())
+            .is_ok_and(|stat| {
+                stat.starttime == start_ticks
+                    && !matches!(stat.state(), Ok(ProcState::Zombie | ProcState::Dead))
+            })
+    }
+
+    /// Sends `signal` to the process, which must still be running
+    pub fn kill<S: Into<Signal>>(&self, signal: S) -> Result<()> {
+        if !self.is_running() {
+            return Err(ExecSessionError::NotRunning(self.id.to_owned()));
+        }
+        signal::kill(Pid::from_raw(self.pid), signal.into().into_raw())
+            .map_err(|err| ExecSessionError::Signal(self.id.to_owned(), err))
+    }
+
+    fn file_path(container_root: &Path, id: &str) -> PathBuf {
+        container_root
+            .join(EXEC_SESSIONS_DIR)
+            .join(format!("{id}.json"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::process::ExitStatusExt;
+
+    use anyhow::Result;
+    use nix::sys::signal::Signal as NixSignal;
+    use oci_spec::runtime::ProcessBuilder;
+
+    use super::*;
+
+    #[test]
+    fn test_exec_session() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        assert!(ExecSession::list(tmp.path())?.is_empty());
+
+        let mut child = std::process::Command::new("sleep").arg("10").spawn()?;
+        let process = ProcessBuilder::default()
+            .args(vec!["sleep".to_owned(), "10".to_owned()])
+            .build()?;
+        let id = ExecSession::generate_id();
+        ExecSession::reserve(tmp.path(), &id)?;
+        // a reserved session is not listed, and cannot be reserved again
+        assert!(ExecSession::list(tmp.path())?.is_empty());
+        assert!(matches!(
+            ExecSession::reserve(tmp.path(), &id),
+            Err(ExecSessionError::Exist(_))
+        ));
+        assert!(ExecSession::reserve(tmp.path(), "../escape").is_err());
+
+        let mut session = ExecSession::new(&id, Pid::from_raw(child.id() as i32), &process);
+        session.save(tmp.path())?;
+        assert!(matches!(
+            ExecSession::reserve(tmp.path(), &id),
+            Err(ExecSessionError::Exist(_))
+        ));
+
+        let loaded = ExecSession::list(tmp.path())?;
+        assert_eq!(loaded, vec![session.clone()]);
+        assert!(loaded[0].is_running());
+
+        session.kill(NixSignal::SIGKILL)?;
+        let status = child.wait()?;
+        assert!(!session.is_running());
+        assert!(matches!(
+            session.kill(NixSignal::SIGKILL),
+            Err(ExecSessionError::NotRunning(_))
+        ));
+
+        assert_eq!(status.signal(), Some(9));
+        session.record_exit(WaitStatus::Signaled(
+            Pid::from_raw(session.pid),
+            NixSignal::SIGKILL,
+            false,
+        ))?;
+        session.save(tmp.path())?;
+        let loaded = ExecSession::load(tmp.path(), &id)?;
+        assert_eq!(loaded.exit_code, Some(137));
+        assert!(loaded.finished_at.is_some());
+        assert!(matches!(
+            ExecSession::load(tmp.path(), "missing"),
+            Err(ExecSessionError::NotFound(_))
+        ));
+
+        ExecSession::reserve(tmp.path(), "failed")?;
+        ExecSession::release(tmp.path(), "failed")?;
+        ExecSession::reserve(tmp.path(), "failed")?;
+        Ok(())
+    }
+}
@@ -0,0 +1,215 @@
+//! Exec-based health check of a container, run by the monitor of the
+//! container. The check is declared by annotations of the bundle: a command
+//! run in the container at an interval, which is failing if it exits with a
+//! non-zero code or outlives its timeout. The container is unhealthy once the
+//! check fails a number of times in a row.
+use std::collections::HashMap;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Command of the health check, either a JSON array of the arguments or a
+/// string run by `/bin/sh -c`
+pub const HEALTH_CMD_ANNOTATION: &str = "io.github.youki-dev.youki.health.cmd";
+/// Time between two runs of the check, e.g. 30s
+pub const HEALTH_INTERVAL_ANNOTATION: &str = "io.github.youki-dev.youki.health.interval";
+/// Time after which a run of the check is killed and considered failing
+pub const HEALTH_TIMEOUT_ANNOTATION: &str = "io.github.youki-dev.youki.health.timeout";
+/// Number of failing runs in a row after which the container is unhealthy
+pub const HEALTH_RETRIES_ANNOTATION: &str = "io.github.youki-dev.youki.health.retries";
+
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(30);
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+const DEFAULT_RETRIES: u32 = 3;
+
+#[derive(Debug, thiserror::Error)]
+pub enum HealthCheckError {
+    #[error("invalid health check command {0:?}")]
+    Command(String),
+    #[error("invalid duration {value:?} for {annotation}, expected e.g. 500ms, 30s or 1m")]
+    Duration { value: String, annotation: String },
+    #[error("invalid health check retries {0:?}")]
+    Retries(String),
+}
+
+type Result<T> = std::result::Result<T, HealthCheckError>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HealthCheck {
+    pub command: Vec<String>,
+    pub interval: Duration,
+    pub timeout: Duration,
+    pub retries: u32,
+}
+
+impl HealthCheck {
+    /// Reads the health check from the annotations of a container, `None` if
+    /// the container has no health check
+    pub fn from_annotations(annotations: &HashMap<String, String>) -> Result<Option<Self>> {
+        let Some(command) = annotations.get(HEALTH_CMD_ANNOTATION) else {
+            return Ok(None);
+        };
+        let command = parse_command(command)?;
+
+        let duration = |annotation: &str, default| {
+            annotations.get(annotation).map_or(Ok(default), |value| {
+                parse_duration(value).ok_or_else(|| HealthCheckError::Duration {
+                    value: value.to_owned(),
+                    annotation: annotation.to_owned(),
+                })
+            })
+        };
+        let interval = duration(HEALTH_INTERVAL_ANNOTATION, DEFAULT_INTERVAL)?;
+        let timeout = duration(HEALTH_TIMEOUT_ANNOTATION, DEFAULT_TIMEOUT)?;
+        let retries = match annotations.get(HEALTH_RETRIES_ANNOTATION) {
+            Some(retries) => retries
+                .parse()
+                .ok()
+                .filter(|retries| *retries > 0)
+                .ok_or_else(|| HealthCheckError::Retries(retries.to_owned()))?,
+            None => DEFAULT_RETRIES,
+        };
+
+        Ok(Some(Self {
+            command,
+            interval,
+            timeout,
+            retries,
+        }))
+    }
+}
+
+fn parse_command(command: &str) -> Result<Vec<String>> {
+    let args = if command.trim_start().starts_with('[') {
+        serde_json::from_str::<Vec<String>>(command)
+            .map_err(|_| HealthCheckError::Command(command.to_owned()))?
+    } else {
+        vec!["/bin/sh".to_owned(), "-c".to_owned(), command.to_owned()]
+    };
+
+    if args.first().is_none_or(|arg| arg.trim().is_empty()) {
+        return Err(HealthCheckError::Command(command.to_owned()));
+    }
+    Ok(args)
+}
+
+/// Parses a duration with a unit: ms, s, m or h
+fn parse_duration(value: &str) -> Option<Duration> {
+    let split = value.find(|c: char| !c.is_ascii_digit())?;
+    let (number, unit) = value.split_at(split);
+    let number: u64 = number.parse().ok()?;
+    match unit {
+        "ms" => Some(Duration::from_millis(number)),
+        "s" => Some(Duration::from_secs(number)),
+        "m" => Some(Duration::from_secs(number.checked_mul(60)?)),
+        "h" => Some(Duration::from_secs(number.checked_mul(3600)?)),
+        _ => None,
+    }
+    .filter(|duration| !duration.is_zero())
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum HealthStatus {
+    /// The check has not passed yet, nor failed enough times
+    #[default]
+    Starting,
+    Healthy,
+    Unhealthy,
+}
+
+/// Outcome of the health check of a container, recorded in its state
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct Health {
+    pub status: HealthStatus,
+    /// Number of failing runs of the check in a row
+    pub failing_streak: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_check: Option<DateTime<Utc>>,
+    /// Exit code of the last run of the check, `None` if it timed out or
+    /// could not be run
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_exit_code: Option<i32>,
+}
+
+impl Health {
+    /// Records a run of the check of a container, which is unhealthy once
+    /// the check failed `retries` times in a row
+    pub fn record(&mut self, exit_code: Option<i32>, retries: u32) {
+        self.last_check = Some(Utc::now());
+        self.last_exit_code = exit_code;
+        if exit_code == Some(0) {
+            self.failing_streak = 0;
+            self.status = HealthStatus::Healthy;
+        } else {
+            self.failing_streak += 1;
+            if self.failing_streak >= retries {
+                self.status = HealthStatus::Unhealthy;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use super::*;
+
+    #[test]
+    fn test_health_check_from_annotations() -> Result<()> {
+        assert_eq!(HealthCheck::from_annotations(&HashMap::new())?, None);
+
+        let mut annotations = HashMap::from([
+            (
+                HEALTH_CMD_ANNOTATION.to_owned(),
+                r#"["/bin/check", "--quick"]"#.to_owned(),
+            ),
+            (HEALTH_INTERVAL_ANNOTATION.to_owned(), "1m".to_owned()),
+            (HEALTH_TIMEOUT_ANNOTATION.to_owned(), "500ms".to_owned()),
+        ]);
+        let check = HealthCheck::from_annotations(&annotations)?.unwrap();
+        assert_eq!(check.command, vec!["/bin/check", "--quick"]);
+        assert_eq!(check.interval, Duration::from_secs(60));
+        assert_eq!(check.timeout, Duration::from_millis(500));
+        assert_eq!(check.retries, DEFAULT_RETRIES);
+
+        annotations.insert(
+            HEALTH_CMD_ANNOTATION.to_owned(),
+            "test -f /ready".to_owned(),
+        );
+        annotations.insert(HEALTH_RETRIES_ANNOTATION.to_owned(), "5".to_owned());
+        let check = HealthCheck::from_annotations(&annotations)?.unwrap();
+        assert_eq!(check.command, vec!["/bin/sh", "-c", "test -f /ready"]);
+        assert_eq!(check.retries, 5);
+
+        for (annotation, value) in [
+            (HEALTH_CMD_ANNOTATION, "[]"),
+            (HEALTH_INTERVAL_ANNOTATION, "30"),
+            (HEALTH_TIMEOUT_ANNOTATION, "0s"),
+            (HEALTH_RETRIES_ANNOTATION, "0"),
+        ] {
+            let mut invalid = annotations.clone();
+            invalid.insert(annotation.to_owned(), value.to_owned());
+            assert!(HealthCheck::from_annotations(&invalid).is_err(), "{value}");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_health_record() {
+        let mut health = Health::default();
+        health.record(Some(1), 2);
+        assert_eq!(health.status, HealthStatus::Starting);
+        health.record(None, 2);
+        assert_eq!(health.status, HealthStatus::Unhealthy);
+        assert_eq!(health.failing_streak, 2);
+
+        health.record(Some(0), 2);
+        assert_eq!(health.status, HealthStatus::Healthy);
+        assert_eq!(health.failing_streak, 0);
+        assert!(health.last_check.is_some());
+    }
+}
@@ -57,6 +57,7 @@ pub fn run_hooks(
 
     if let Some(hooks) = hooks {
         for hook in hooks {
+            let _span = tracing::info_span!("hook", path = ?hook.path()).entered();
             let mut hook_command = process::Command::new(hook.path());
 
             if let Some(cwd) = cwd {
@@ -4,17 +4,23 @@ pub mod channel;
 pub mod config;
 pub mod container;
 pub mod error;
+pub mod exec_session;
+pub mod health;
 pub mod hooks;
+pub mod log_sink;
 pub mod namespaces;
 pub mod network;
 pub mod notify_socket;
 pub mod process;
+pub mod restart;
 pub mod rootfs;
 #[cfg(feature = "libseccomp")]
 pub mod seccomp;
 pub mod signal;
+pub mod subid;
 pub mod syscall;
 pub mod test_utils;
+pub mod timings;
 pub mod tty;
 pub mod user_ns;
 pub mod utils;
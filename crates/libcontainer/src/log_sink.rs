@@ -0,0 +1,612 @@
+//! Forwarding of the stdout and stderr of a container to a log file, for
+//! detached containers without a shim collecting their output. The output is
+//! read from pipes by a small monitor process, which writes it in the CRI log
+//! format, rotates the file when it grows over a size, and reopens it on
+//! SIGUSR1, e.g. after an external logrotate.
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, ErrorKind, Read, Write};
+use std::os::fd::{AsFd, AsRawFd, OwnedFd, RawFd};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+
+use chrono::{SecondsFormat, Utc};
+use nix::fcntl::OFlag;
+use nix::poll::{PollFd, PollFlags, PollTimeout, poll};
+use nix::sys::signal::{SIGUSR1, SigSet, SigmaskHow, pthread_sigmask};
+use nix::sys::signalfd::{SfdFlags, SignalFd};
+use nix::unistd::{ForkResult, Pid, close, dup2, fork, pipe2, setsid};
+
+/// Annotation naming the log file of the container
+pub const LOG_PATH_ANNOTATION: &str = "io.github.youki-dev.youki.log.path";
+/// Annotation setting the size over which the log file is rotated, in bytes
+/// or with a `k`, `m` or `g` suffix
+pub const LOG_MAX_SIZE_ANNOTATION: &str = "io.github.youki-dev.youki.log.max-size";
+/// Annotation setting how many rotated log files are kept
+pub const LOG_MAX_FILES_ANNOTATION: &str = "io.github.youki-dev.youki.log.max-files";
+/// Number of rotated log files kept when none is configured
+pub const DEFAULT_MAX_FILES: usize = 1;
+
+// Longer lines are split into partial entries, as done by CRI runtimes.
+const MAX_LINE_SIZE: usize = 16 * 1024;
+
+#[derive(Debug, thiserror::Error)]
+pub enum LogSinkError {
+    #[error("invalid log size: {0}")]
+    InvalidSize(String),
+    #[error("invalid number of log files: {0}")]
+    InvalidMaxFiles(String),
+    #[error("failed to create log pipe")]
+    Pipe(#[source] nix::Error),
+    #[error("failed to fork log monitor")]
+    Fork(#[source] nix::Error),
+    #[error("failed to count the threads of the process")]
+    Threads(#[source] procfs::ProcError),
+    #[error("cannot fork the log monitor of a multi-threaded process")]
+    MultiThreaded,
+    #[error("failed to set up the log monitor")]
+    Setup(#[source] nix::Error),
+    #[error("failed to open log file {path}")]
+    Open { source: io::Error, path: PathBuf },
+    #[error("failed to rotate log file {path}")]
+    Rotate { source: io::Error, path: PathBuf },
+    #[error("failed to write log file {path}")]
+    Write { source: io::Error, path: PathBuf },
+    #[error("failed to read container output")]
+    Read(#[source] io::Error),
+}
+
+type Result<T> = std::result::Result<T, LogSinkError>;
+
+/// Log file receiving the output of a container
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogSink {
+    path: PathBuf,
+    max_size: Option<u64>,
+    max_files: usize,
+}
+
+/// A running log monitor, with the ends of the pipes to pass to the
+/// container as its stdout and stderr
+#[derive(Debug)]
+pub struct LogMonitor {
+    pub pid: Pid,
+    pub stdout: OwnedFd,
+    pub stderr: OwnedFd,
+}
+
+impl LogSink {
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        Self {
+            path: path.into(),
+            max_size: None,
+            max_files: DEFAULT_MAX_FILES,
+        }
+    }
+
+    /// Rotates the log file when writing to it would make it larger than
+    /// `max_size` bytes. Without a size, the file is never rotated.
+    pub fn with_max_size(mut self, max_size: Option<u64>) -> Self {
+        self.max_size = max_size;
+        self
+    }
+
+    /// Sets how many rotated files are kept, as `<path>.1` being the most
+    /// recent up to `<path>.<max_files>`.
+    pub fn with_max_files(mut self, max_files: usize) -> Self {
+        self.max_files = max_files;
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the sink configured by the annotations of a container, if any
+    pub fn from_annotations(annotations: &HashMap<String, String>) -> Result<Option<Self>> {
+        let Some(path) = annotations.get(LOG_PATH_ANNOTATION) else {
+            return Ok(None);
+        };
+        let max_size = annotations
+            .get(LOG_MAX_SIZE_ANNOTATION)
+            .map(|size| parse_size(size))
+            .transpose()?;
+        let max_files = annotations
+            .get(LOG_MAX_FILES_ANNOTATION)
+            .map(|files| {
+                files
+                    .parse()
+                    .map_err(|_| LogSinkError::InvalidMaxFiles(files.to_owned()))
+            })
+            .transpose()?
+            .unwrap_or(DEFAULT_MAX_FILES);
+
+        Ok(Some(
+            Self::new(path)
+                .with_max_size(max_size)
+                .with_max_files(max_files),
+        ))
+    }
+
+    /// Forks the monitor process forwarding the output to the log file. The
+    /// monitor exits once every process holding the returned pipes has
+    /// closed them.
+    ///
+    /// The monitor is forked and keeps running Rust code, which is only
+    /// sound in the child of a single threaded process, so this fails if the
+    /// caller started other threads. youki spawns it before `run` starts the
+    /// threads proxying the terminal.
+    pub fn spawn(&self) -> Result<LogMonitor> {
+        let threads = procfs::process::Process::myself()
+            .and_then(|process| process.stat())
+            .map(|stat| stat.num_threads)
+            .map_err(LogSinkError::Threads)?;
+        if threads > 1 {
+            return Err(LogSinkError::MultiThreaded);
+        }
+
+        // The file is opened before forking, so that an unusable path fails
+        // the command rather than the monitor.
+        let file = LogFile::open(self)?;
+        let (stdout_read, stdout) = pipe2(OFlag::O_CLOEXEC).map_err(LogSinkError::Pipe)?;
+        let (stderr_read, stderr) = pipe2(OFlag::O_CLOEXEC).map_err(LogSinkError::Pipe)?;
+
+        // SIGUSR1 is blocked before forking, so that the monitor is not
+        // killed by a SIGUSR1 sent before it reads them from a signalfd.
+        let mut mask = SigSet::empty();
+        mask.add(SIGUSR1);
+        let mut old_mask = SigSet::empty();
+        pthread_sigmask(SigmaskHow::SIG_BLOCK, Some(&mask), Some(&mut old_mask))
+            .map_err(LogSinkError::Setup)?;
+
+        // SAFETY: the process has a single thread, and the child only
+        // forwards the output before it exits.
+        let forked = unsafe { fork() };
+        if !matches!(forked, Ok(ForkResult::Child)) {
+            pthread_sigmask(SigmaskHow::SIG_SETMASK, Some(&old_mask), None)
+                .map_err(LogSinkError::Setup)?;
+        }
+        match forked.map_err(LogSinkError::Fork)? {
+            ForkResult::Child => {
+                drop(stdout);
+                drop(stderr);
+                let code = match detach(&[
+                    stdout_read.as_raw_fd(),
+                    stderr_read.as_raw_fd(),
+                    file.file.as_raw_fd(),
+                ])
+                .and_then(|_| forward(file, stdout_read, stderr_read))
+                {
+                    Ok(()) => 0,
+                    Err(err) => {
+                        tracing::error!(?err, "failed to forward container output");
+                        1
+                    }
+                };
+                std::process::exit(code)
+            }
+            ForkResult::Parent { child } => {
+                tracing::debug!(?child, path = ?self.path, "forwarding container output");
+                Ok(LogMonitor {
+                    pid: child,
+                    stdout,
+                    stderr,
+                })
+            }
+        }
+    }
+}
+
+/// Parses a size in bytes, with an optional `k`, `m` or `g` suffix
+pub fn parse_size(size: &str) -> Result<u64> {
+    let invalid = || LogSinkError::InvalidSize(size.to_owned());
+    let lower = size.trim().to_ascii_lowercase();
+    let lower = lower.strip_suffix('b').unwrap_or(&lower);
+    let (digits, unit) = match lower.char_indices().last() {
+        Some((i, 'k')) => (&lower[..i], 1 << 10),
+        Some((i, 'm')) => (&lower[..i], 1 << 20),
+        Some((i, 'g')) => (&lower[..i], 1 << 30),
+        _ => (lower, 1),
+    };
+    let value: u64 = digits.parse().map_err(|_| invalid())?;
+    match value.checked_mul(unit) {
+        Some(0) | None => Err(invalid()),
+        Some(bytes) => Ok(bytes),
+    }
+}
+
+// Leaves the session of the command and the files it inherited from it, so
+// that a caller waiting for the output of youki is not kept waiting by the
+// monitor.
+fn detach(keep: &[RawFd]) -> Result<()> {
+    let _ = prctl::set_name("youki:[LOG]");
+    setsid().map_err(LogSinkError::Setup)?;
+    let null = File::options()
+        .read(true)
+        .write(true)
+        .open("/dev/null")
+        .map_err(|source| LogSinkError::Open {
+            source,
+            path: PathBuf::from("/dev/null"),
+        })?;
+    for fd in 0..3 {
+        dup2(null.as_raw_fd(), fd).map_err(LogSinkError::Setup)?;
+    }
+    drop(null);
+
+    let fds: Vec<RawFd> = fs::read_dir("/proc/self/fd")
+        .map_err(LogSinkError::Read)?
+        .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse().ok())
+        .collect();
+    for fd in fds {
+        if fd > 2 && !keep.contains(&fd) {
+            // The fd of the directory listing is already closed.
+            let _ = close(fd);
+        }
+    }
+    Ok(())
+}
+
+// Forwards the output until both streams are closed.
+// SIGUSR1 is already blocked by `LogSink::spawn`.
+fn forward(mut file: LogFile, stdout: OwnedFd, stderr: OwnedFd) -> Result<()> {
+    let mut mask = SigSet::empty();
+    mask.add(SIGUSR1);
+    let signals =
+        SignalFd::with_flags(&mask, SfdFlags::SFD_NONBLOCK).map_err(LogSinkError::Setup)?;
+
+    let mut streams = [Stream::new("stdout", stdout), Stream::new("stderr", stderr)];
+    let mut buf = vec![0u8; 32 * 1024];
+    while streams.iter().any(|stream| stream.source.is_some()) {
+        let (reopen, ready) = {
+            let mut fds = vec![PollFd::new(signals.as_fd(), PollFlags::POLLIN)];
+            fds.extend(
+                streams
+                    .iter()
+                    .filter_map(|stream| stream.source.as_ref())
+                    .map(|source| PollFd::new(source.as_fd(), PollFlags::POLLIN)),
+            );
+            match poll(&mut fds, PollTimeout::NONE) {
+                Ok(_) => {}
+                Err(nix::errno::Errno::EINTR) => continue,
+                Err(err) => return Err(LogSinkError::Setup(err)),
+            }
+            let ready: Vec<bool> = fds
+                .iter()
+                .map(|fd| fd.revents().is_some_and(|events| !events.is_empty()))
+                .collect();
+            (ready[0], ready[1..].to_vec())
+        };
+
+        if reopen {
+            while let Ok(Some(_)) = signals.read_signal() {}
+            tracing::debug!(path = ?file.path, "reopen log file");
+            file.reopen()?;
+        }
+
+        let open = streams.iter_mut().filter(|stream| stream.source.is_some());
+        for (stream, ready) in open.zip(ready) {
+            if ready {
+                stream.read(&mut buf, &mut file)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+struct Stream {
+    name: &'static str,
+    source: Option<File>,
+    pending: Vec<u8>,
+}
+
+impl Stream {
+    fn new(name: &'static str, source: OwnedFd) -> Self {
+        Self {
+            name,
+            source: Some(File::from(source)),
+            pending: Vec::new(),
+        }
+    }
+
+    fn read(&mut self, buf: &mut [u8], file: &mut LogFile) -> Result<()> {
+        let Some(source) = self.source.as_mut() else {
+            return Ok(());
+        };
+        let len = match source.read(buf) {
+            Ok(len) => len,
+            Err(err) if err.kind() == ErrorKind::Interrupted => return Ok(()),
+            Err(err) => return Err(LogSinkError::Read(err)),
+        };
+        if len == 0 {
+            self.source = None;
+            if !self.pending.is_empty() {
+                let line = std::mem::take(&mut self.pending);
+                file.write_entry(self.name, false, &line)?;
+            }
+            return Ok(());
+        }
+
+        self.pending.extend_from_slice(&buf[..len]);
+        self.flush_lines(file)
+    }
+
+    fn flush_lines(&mut self, file: &mut LogFile) -> Result<()> {
+        loop {
+            if let Some(end) = self.pending.iter().position(|&b| b == b'\n') {
+                let line: Vec<u8> = self.pending.drain(..=end).collect();
+                file.write_entry(self.name, false, &line[..end])?;
+            } else if self.pending.len() >= MAX_LINE_SIZE {
+                let line: Vec<u8> = self.pending.drain(..MAX_LINE_SIZE).collect();
+                file.write_entry(self.name, true, &line)?;
+            } else {
+                return Ok(());
+            }
+        }
+    }
+}
+
+struct LogFile {
+    path: PathBuf,
+    max_size: Option<u64>,
+    max_files: usize,
+    file: File,
+    size: u64,
+}
+
+impl LogFile {
+    fn open(sink: &LogSink) -> Result<Self> {
+        let (file, size) = Self::open_path(&sink.path)?;
+        Ok(Self {
+            path: sink.path.clone(),
+            max_size: sink.max_size,
+            max_files: sink.max_files,
+            file,
+            size,
+        })
+    }
+
+    fn open_path(path: &Path) -> Result<(File, u64)> {
+        let open_err = |source| LogSinkError::Open {
+            source,
+            path: path.to_owned(),
+        };
+        let file = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .mode(0o640)
+            .open(path)
+            .map_err(open_err)?;
+        let size = file.metadata().map_err(open_err)?.len();
+        Ok((file, size))
+    }
+
+    fn reopen(&mut self) -> Result<()> {
+        (self.file, self.size) = Self::open_path(&self.path)?;
+        Ok(())
+    }
+
+    fn rotated_path(&self, index: usize) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{index}"));
+        PathBuf::from(path)
+    }
+
+    fn rotate(&mut self) -> Result<()> {
+        let rotate_err = |source| LogSinkError::Rotate {
+            source,
+            path: self.path.clone(),
+        };
+        if self.max_files == 0 {
+            fs::remove_file(&self.path).map_err(rotate_err)?;
+        } else {
+            for index in (1..self.max_files).rev() {
+                match fs::rename(self.rotated_path(index), self.rotated_path(index + 1)) {
+                    Err(err) if err.kind() != ErrorKind::NotFound => return Err(rotate_err(err)),
+                    _ => {}
+                }
+            }
+            fs::rename(&self.path, self.rotated_path(1)).map_err(rotate_err)?;
+        }
+        self.reopen()
+    }
+
+    fn write_entry(&mut self, stream: &str, partial: bool, content: &[u8]) -> Result<()> {
+        let timestamp = Utc::now().to_rfc3339_opts(SecondsFormat::Nanos, true);
+        let tag = if partial { "P" } else { "F" };
+        let mut entry = format!("{timestamp} {stream} {tag} ").into_bytes();
+        entry.extend_from_slice(content);
+        entry.push(b'\n');
+
+        let len = entry.len() as u64;
+        if self
+            .max_size
+            .is_some_and(|max_size| self.size > 0 && self.size + len > max_size)
+        {
+            self.rotate()?;
+        }
+        self.file
+            .write_all(&entry)
+            .map_err(|source| LogSinkError::Write {
+                source,
+                path: self.path.clone(),
+            })?;
+        self.size += len;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use nix::sys::wait::{WaitStatus, waitpid};
+
+    use super::*;
+
+    fn entries(path: &Path) -> Vec<(String, String, String)> {
+        fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .map(|line| {
+                let mut parts = line.splitn(4, ' ');
+                let timestamp = parts.next().unwrap();
+                assert!(chrono::DateTime::parse_from_rfc3339(timestamp).is_ok());
+                let stream = parts.next().unwrap().to_owned();
+                let tag = parts.next().unwrap().to_owned();
+                (stream, tag, parts.next().unwrap_or_default().to_owned())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("512").unwrap(), 512);
+        assert_eq!(parse_size("10k").unwrap(), 10 * 1024);
+        assert_eq!(parse_size("2MB").unwrap(), 2 * 1024 * 1024);
+        assert_eq!(parse_size("1G").unwrap(), 1024 * 1024 * 1024);
+        assert!(parse_size("0").is_err());
+        assert!(parse_size("ten").is_err());
+        assert!(parse_size("").is_err());
+    }
+
+    #[test]
+    fn test_from_annotations() -> Result<()> {
+        assert_eq!(LogSink::from_annotations(&HashMap::new())?, None);
+
+        let annotations: HashMap<String, String> = [
+            (LOG_PATH_ANNOTATION.to_owned(), "/var/log/c.log".to_owned()),
+            (LOG_MAX_SIZE_ANNOTATION.to_owned(), "1m".to_owned()),
+            (LOG_MAX_FILES_ANNOTATION.to_owned(), "3".to_owned()),
+        ]
+        .into();
+        assert_eq!(
+            LogSink::from_annotations(&annotations)?,
+            Some(
+                LogSink::new("/var/log/c.log")
+                    .with_max_size(Some(1 << 20))
+                    .with_max_files(3)
+            )
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_lines_and_rotate() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let path = tmp.path().join("container.log");
+        let sink = LogSink::new(&path)
+            .with_max_size(Some(200))
+            .with_max_files(2);
+        let mut file = LogFile::open(&sink)?;
+        let (read, write) = nix::unistd::pipe()?;
+        let mut write = File::from(write);
+        let mut stream = Stream::new("stdout", read);
+        let mut buf = [0u8; 64];
+
+        write.write_all(b"hello\nwor")?;
+        stream.read(&mut buf, &mut file)?;
+        write.write_all(b"ld\n")?;
+        stream.read(&mut buf, &mut file)?;
+        assert_eq!(
+            entries(&path),
+            vec![
+                ("stdout".into(), "F".into(), "hello".into()),
+                ("stdout".into(), "F".into(), "world".into())
+            ]
+        );
+
+        // A long line is split into partial entries, and the file is rotated
+        // once it would grow over the maximum size.
+        let long = vec![b'x'; MAX_LINE_SIZE + 10];
+        stream.pending.extend_from_slice(&long);
+        stream.flush_lines(&mut file)?;
+        assert_eq!(entries(&sink.path.with_extension("log.1")).len(), 2);
+        assert_eq!(entries(&path)[0].1, "P");
+
+        // The remaining output is written once the stream is closed.
+        drop(write);
+        stream.read(&mut buf, &mut file)?;
+        assert!(stream.source.is_none());
+        assert_eq!(
+            entries(&path).last().unwrap(),
+            &("stdout".into(), "F".into(), "x".repeat(10))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_spawn() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let path = tmp.path().join("container.log");
+
+        // The monitor can't be forked while another thread runs.
+        let (stop, stopped) = std::sync::mpsc::channel::<()>();
+        let thread = std::thread::spawn(move || stopped.recv());
+        assert!(matches!(
+            LogSink::new(&path).spawn(),
+            Err(LogSinkError::MultiThreaded)
+        ));
+        drop(stop);
+        let _ = thread.join();
+
+        // The monitor is forked from a single threaded child of the test.
+        match unsafe { fork()? } {
+            ForkResult::Parent { child } => {
+                assert_eq!(waitpid(child, None)?, WaitStatus::Exited(child, 0));
+            }
+            ForkResult::Child => {
+                let forwarded = std::panic::catch_unwind(|| forward_to(tmp.path()));
+                std::process::exit(if matches!(forwarded, Ok(Ok(()))) {
+                    0
+                } else {
+                    1
+                });
+            }
+        }
+        Ok(())
+    }
+
+    fn forward_to(dir: &Path) -> Result<()> {
+        let path = dir.join("container.log");
+        let moved = dir.join("container.log.old");
+        let wait_for = |path: &Path, len: usize| {
+            for _ in 0..500 {
+                if fs::read_to_string(path).is_ok_and(|log| log.lines().count() == len) {
+                    return;
+                }
+                std::thread::sleep(std::time::Duration::from_millis(10));
+            }
+            panic!("timed out waiting for {path:?}");
+        };
+
+        let monitor = LogSink::new(&path).spawn()?;
+        // A SIGUSR1 sent right away does not kill the monitor.
+        nix::sys::signal::kill(monitor.pid, SIGUSR1)?;
+        let mut stdout = File::from(monitor.stdout);
+        stdout.write_all(b"out\n")?;
+        wait_for(&path, 1);
+
+        // After the file is moved away, SIGUSR1 makes the monitor write to a
+        // new file at the path.
+        fs::rename(&path, &moved)?;
+        nix::sys::signal::kill(monitor.pid, SIGUSR1)?;
+        wait_for(&path, 0);
+        File::from(monitor.stderr).write_all(b"err\n")?;
+        drop(stdout);
+        assert_eq!(
+            waitpid(monitor.pid, None)?,
+            WaitStatus::Exited(monitor.pid, 0)
+        );
+
+        assert_eq!(
+            entries(&moved),
+            vec![("stdout".into(), "F".into(), "out".into())]
+        );
+        assert_eq!(
+            entries(&path),
+            vec![("stderr".into(), "F".into(), "err".into())]
+        );
+        Ok(())
+    }
+}
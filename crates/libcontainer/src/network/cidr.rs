@@ -3,7 +3,7 @@ use std::net::IpAddr;
 use netlink_packet_route::address::{AddressAttribute, AddressMessage};
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct CidrAddress {
     pub prefix_len: u8,
     pub address: IpAddr,
@@ -145,6 +145,49 @@ impl LinkClient {
         Ok(())
     }
 
+    /// Sets the MTU of a network interface.
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The index of the network interface to modify
+    /// * `mtu` - The MTU to set
+    ///
+    /// # Returns
+    ///
+    /// A Result indicating success or failure of the operation
+    pub fn set_mtu(&mut self, index: u32, mtu: u32) -> Result<()> {
+        self.set_attribute(index, LinkAttribute::Mtu(mtu))
+    }
+
+    /// Sets the hardware (MAC) address of a network interface.
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The index of the network interface to modify
+    /// * `address` - The hardware address to set
+    ///
+    /// # Returns
+    ///
+    /// A Result indicating success or failure of the operation
+    pub fn set_hardware_address(&mut self, index: u32, address: &[u8]) -> Result<()> {
+        self.set_attribute(index, LinkAttribute::Address(address.to_vec()))
+    }
+
+    fn set_attribute(&mut self, index: u32, attribute: LinkAttribute) -> Result<()> {
+        let mut message = LinkMessage::default();
+        message.header.index = index;
+        message.attributes.push(attribute);
+
+        let mut req = NetlinkMessage::from(RouteNetlinkMessage::SetLink(message));
+        // NLM_F_REQUEST: This is a request to the kernel
+        // NLM_F_ACK: Request an acknowledgment from the kernel
+        req.header.flags = NLM_F_REQUEST | NLM_F_ACK;
+        req.finalize();
+
+        self.client.send_and_receive(&req, LinkMessageHandler)?;
+        Ok(())
+    }
+
     /// Moves a network interface to a different network namespace.
     ///
     /// # Arguments
@@ -486,4 +529,47 @@ mod tests {
             panic!("Expected Fake client");
         }
     }
+
+    #[test]
+    #[serial]
+    fn test_link_client_set_mtu_and_hardware_address() {
+        let mut fake_client = FakeNetlinkClient::new();
+
+        let responses = vec![
+            RouteNetlinkMessage::NewLink(LinkMessage::default()),
+            RouteNetlinkMessage::NewLink(LinkMessage::default()),
+        ];
+        fake_client.set_expected_responses(responses);
+
+        let client_wrapper = ClientWrapper::Fake(fake_client);
+        let mut link_client = LinkClient::new(client_wrapper).unwrap();
+
+        link_client.set_mtu(42, 9000).unwrap();
+        link_client
+            .set_hardware_address(42, &[0x02, 0, 0, 0, 0, 0x01])
+            .unwrap();
+
+        if let ClientWrapper::Fake(fake_client) = &mut link_client.client {
+            let send_calls = fake_client.get_send_calls();
+            assert_eq!(send_calls.len(), 2);
+
+            let attributes: Vec<_> = send_calls
+                .iter()
+                .map(|call| match &call.payload {
+                    NetlinkPayload::InnerMessage(RouteNetlinkMessage::SetLink(link)) => {
+                        assert_eq!(link.header.index, 42);
+                        link.attributes.clone()
+                    }
+                    _ => panic!("Expected SetLink message"),
+                })
+                .collect();
+            assert_eq!(attributes[0], vec![LinkAttribute::Mtu(9000)]);
+            assert_eq!(
+                attributes[1],
+                vec![LinkAttribute::Address(vec![0x02, 0, 0, 0, 0, 0x01])]
+            );
+        } else {
+            panic!("Expected Fake client");
+        }
+    }
 }
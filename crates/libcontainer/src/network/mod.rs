@@ -4,6 +4,7 @@ mod client;
 mod fake;
 pub mod link;
 pub mod network_device;
+pub mod route;
 mod traits;
 pub mod wrapper;
 
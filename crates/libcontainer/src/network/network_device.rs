@@ -1,14 +1,33 @@
-use std::os::fd::RawFd;
-
-use netlink_packet_route::address::{AddressHeaderFlags, AddressScope};
+use std::fs::File;
+use std::os::fd::{AsRawFd, RawFd};
+use std::path::Path;
+
+use netlink_packet_route::address::{AddressHeaderFlags, AddressMessage, AddressScope};
+use netlink_packet_route::link::{LinkAttribute, LinkMessage};
+use netlink_packet_route::route::{
+    RouteAttribute, RouteHeader, RouteMessage, RouteProtocol, RouteType,
+};
+use nix::sched::{CloneFlags, setns};
 use oci_spec::runtime::LinuxNetDevice;
+use serde::{Deserialize, Serialize};
 
-use super::Result;
 use super::address::AddressClient;
 use super::link::LinkClient;
+use super::route::{RouteClient, RouteConfig};
 use super::wrapper::create_network_client;
+use super::{NetworkError, Result};
 use crate::network::cidr::CidrAddress;
 
+/// The configuration of a network device which has to be restored after the device
+/// is moved to another network namespace, as the kernel drops it on the move.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NetDeviceConfig {
+    pub addresses: Vec<CidrAddress>,
+    pub routes: Vec<RouteConfig>,
+    pub mtu: Option<u32>,
+    pub hardware_address: Option<Vec<u8>>,
+}
+
 /// Resolves the final name for a network device.
 /// If the device has a configured name (non-empty), use it; otherwise use the original name.
 pub fn resolve_device_name<'a>(device: &'a LinuxNetDevice, original_name: &'a str) -> &'a str {
@@ -23,12 +42,13 @@ pub fn resolve_device_name<'a>(device: &'a LinuxNetDevice, original_name: &'a st
 /// and optionally change the device name.
 /// The device name will be kept the same if device.Name is None or an empty string.
 /// This function ensures that the move and rename operations occur atomically.
-/// It preserves existing interface attributes, including IP addresses.
+/// It returns the configuration of the device, including IP addresses, routes,
+/// MTU and hardware address, so it can be restored in the new namespace.
 pub fn dev_change_net_namespace(
     name: &str,
     netns_fd: RawFd,
     device: &LinuxNetDevice,
-) -> Result<Vec<CidrAddress>> {
+) -> Result<NetDeviceConfig> {
     tracing::debug!(
         "attaching network device {} to network namespace fd {}",
         name,
@@ -37,6 +57,7 @@ pub fn dev_change_net_namespace(
 
     let mut link_client = LinkClient::new(create_network_client())?;
     let mut addr_client = AddressClient::new(create_network_client())?;
+    let mut route_client = RouteClient::new(create_network_client())?;
 
     let new_name = resolve_device_name(device, name);
 
@@ -44,14 +65,15 @@ pub fn dev_change_net_namespace(
 
     let index = link.header.index;
 
+    // Collect the configuration before the link goes down, since the kernel flushes
+    // routes and, unless keep_addr_on_down is set, IPv6 addresses of a down link.
+    let config = get_device_config(&link, new_name, &mut addr_client, &mut route_client)?;
+
     // Set the interface link state to DOWN before modifying attributes like namespace or name.
     // This prevents potential conflicts or disruptions on the host network during the transition,
     // particularly if other host components depend on this specific interface or its properties.
     link_client.set_down(index)?;
 
-    // Get the existing IP addresses on the interface.
-    let addrs = addr_client.get_by_index(index)?;
-
     link_client
         .set_ns_fd(index, new_name, netns_fd)
         .map_err(|err| {
@@ -59,9 +81,95 @@ pub fn dev_change_net_namespace(
             err
         })?;
 
-    // Filter addresses before sending to init process:
-    // Only include IP addresses with global scope and permanent flag.
-    let cidr_addrs: Vec<CidrAddress> = addrs
+    Ok(config)
+}
+
+/// Moves a network device from the network namespace at `netns_path` back to the
+/// network namespace of the caller, renaming it from `name` to `host_name` and
+/// restoring `host_config`, the configuration it had on the host.
+///
+/// This is needed on delete when the container uses a network namespace that
+/// outlives it, otherwise the device would stay in that namespace.
+pub fn dev_return_to_host(
+    name: &str,
+    host_name: &str,
+    netns_path: &Path,
+    host_config: &NetDeviceConfig,
+) -> Result<()> {
+    tracing::debug!(
+        "returning network device {} from network namespace {} as {}",
+        name,
+        netns_path.display(),
+        host_name
+    );
+
+    let host_netns = File::open("/proc/thread-self/ns/net")?;
+    let container_netns = File::open(netns_path)?;
+
+    // Netlink sockets operate on the network namespace of the thread creating them,
+    // so the device is looked up and moved from a thread that joins the namespace.
+    std::thread::scope(|s| {
+        s.spawn(|| -> Result<()> {
+            setns(&container_netns, CloneFlags::CLONE_NEWNET)?;
+
+            let mut link_client = LinkClient::new(create_network_client())?;
+            let index = link_client.get_by_name(name)?.header.index;
+            link_client.set_down(index)?;
+            link_client.set_ns_fd(index, host_name, host_netns.as_raw_fd())?;
+            Ok(())
+        })
+        .join()
+        .map_err(|_| {
+            NetworkError::IO(std::io::Error::other(
+                "thread moving the network device panicked",
+            ))
+        })?
+    })?;
+
+    let mut link_client = LinkClient::new(create_network_client())?;
+    let mut addr_client = AddressClient::new(create_network_client())?;
+    let mut route_client = RouteClient::new(create_network_client())?;
+    configure_network_device(
+        host_config,
+        host_name,
+        &mut link_client,
+        &mut addr_client,
+        &mut route_client,
+    )
+}
+
+/// Collects the configuration of a network device which is lost when it is moved.
+pub fn get_device_config(
+    link: &LinkMessage,
+    name: &str,
+    addr_client: &mut AddressClient,
+    route_client: &mut RouteClient,
+) -> Result<NetDeviceConfig> {
+    let index = link.header.index;
+    let addrs = addr_client.get_by_index(index)?;
+    let routes = route_client.get_by_index(index)?;
+
+    let mut config = NetDeviceConfig {
+        addresses: filter_addresses(&addrs, name),
+        routes: filter_routes(&routes, name),
+        ..Default::default()
+    };
+    for attr in &link.attributes {
+        match attr {
+            LinkAttribute::Mtu(mtu) => config.mtu = Some(*mtu),
+            LinkAttribute::Address(addr) if !addr.is_empty() => {
+                config.hardware_address = Some(addr.clone())
+            }
+            _ => {}
+        }
+    }
+
+    Ok(config)
+}
+
+// Only include IP addresses with global scope and permanent flag.
+fn filter_addresses(addrs: &[AddressMessage], name: &str) -> Vec<CidrAddress> {
+    addrs
         .iter()
         .filter(|addr| {
             // Only move IP addresses with global scope because those are not host-specific, auto-configured,
@@ -71,7 +179,7 @@ pub fn dev_change_net_namespace(
                 tracing::debug!(
                     "skipping address with scope {:?} from network device {}",
                     addr.header.scope,
-                    new_name
+                    name
                 );
                 return false;
             }
@@ -83,7 +191,7 @@ pub fn dev_change_net_namespace(
             if !addr.header.flags.contains(AddressHeaderFlags::Permanent) {
                 tracing::debug!(
                     "skipping non-permanent address from network device {}",
-                    new_name
+                    name
                 );
                 return false;
             }
@@ -91,9 +199,111 @@ pub fn dev_change_net_namespace(
             true
         })
         .map(CidrAddress::from)
-        .collect();
+        .collect()
+}
+
+// Only include unicast routes of the main table which were not created by the kernel
+// or learned from router advertisements, as those are set up again on their own.
+fn filter_routes(routes: &[RouteMessage], name: &str) -> Vec<RouteConfig> {
+    routes
+        .iter()
+        .filter(|route| {
+            let table = route
+                .attributes
+                .iter()
+                .find_map(|attr| match attr {
+                    RouteAttribute::Table(table) => Some(*table),
+                    _ => None,
+                })
+                .unwrap_or(route.header.table as u32);
+            if table != RouteHeader::RT_TABLE_MAIN as u32 || route.header.kind != RouteType::Unicast
+            {
+                return false;
+            }
+
+            if matches!(
+                route.header.protocol,
+                RouteProtocol::Kernel | RouteProtocol::Ra
+            ) {
+                tracing::debug!(
+                    "skipping {:?} route from network device {}",
+                    route.header.protocol,
+                    name
+                );
+                return false;
+            }
+
+            true
+        })
+        .filter_map(RouteConfig::from_message)
+        .collect()
+}
+
+/// Restores the configuration of a network device in the current network namespace
+/// and brings it up.
+pub fn configure_network_device(
+    config: &NetDeviceConfig,
+    name: &str,
+    link_client: &mut LinkClient,
+    addr_client: &mut AddressClient,
+    route_client: &mut RouteClient,
+) -> Result<()> {
+    let index = link_client
+        .get_by_name(name)
+        .map_err(|err| {
+            tracing::error!(?err, "failed to get device by name: {}", name);
+            err
+        })?
+        .header
+        .index;
+
+    setup_link_attributes_in_network_namespace(config, index, name, link_client).map_err(
+        |err| {
+            tracing::error!(?err, "failed to setup link attributes for device: {}", name);
+            err
+        },
+    )?;
+
+    setup_addresses_in_network_namespace(&config.addresses, index, name, addr_client).map_err(
+        |err| {
+            tracing::error!(?err, "failed to setup addresses for device: {}", name);
+            err
+        },
+    )?;
+
+    link_client.set_up(index).map_err(|err| {
+        tracing::error!(?err, "failed to bring up device: {}", name);
+        err
+    })?;
+
+    // Routes can only be added once the link is up.
+    setup_routes_in_network_namespace(&config.routes, index, name, route_client).map_err(|err| {
+        tracing::error!(?err, "failed to setup routes for device: {}", name);
+        err
+    })
+}
+
+/// Restores the MTU and hardware address of a device, which must still be down.
+pub fn setup_link_attributes_in_network_namespace(
+    config: &NetDeviceConfig,
+    link_index: u32,
+    new_name: &str,
+    link_client: &mut LinkClient,
+) -> Result<()> {
+    if let Some(mtu) = config.mtu {
+        tracing::debug!("setting mtu {} of network device {}", mtu, new_name);
+        link_client.set_mtu(link_index, mtu)?;
+    }
+    if let Some(address) = &config.hardware_address {
+        tracing::debug!(
+            "setting hardware address {:02x?} of network device {}",
+            address,
+            new_name
+        );
+        link_client.set_hardware_address(link_index, address)?;
+    }
 
-    Ok(cidr_addrs)
+    Ok(())
 }
 
 /// Core logic for setting up addresses in the new network namespace
@@ -122,6 +332,24 @@ pub fn setup_addresses_in_network_namespace(
     Ok(())
 }
 
+/// Re-adds the routes of a device.
+/// Routes without a gateway are added first, since gateway routes depend on them.
+pub fn setup_routes_in_network_namespace(
+    routes: &[RouteConfig],
+    link_index: u32,
+    new_name: &str,
+    route_client: &mut RouteClient,
+) -> Result<()> {
+    let (direct, via_gateway): (Vec<_>, Vec<_>) =
+        routes.iter().partition(|route| route.gateway.is_none());
+    for route in direct.into_iter().chain(via_gateway) {
+        tracing::debug!("adding route {:?} to network device {}", route, new_name);
+        route_client.add(link_index, route)?;
+    }
+
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use std::net::{IpAddr, Ipv4Addr};
@@ -197,4 +425,146 @@ mod tests {
         let result = resolve_device_name(&device, original);
         assert_eq!(result, "veth0");
     }
+
+    fn route_message(protocol: RouteProtocol, gateway: Option<Ipv4Addr>) -> RouteMessage {
+        use netlink_packet_route::AddressFamily;
+        use netlink_packet_route::route::RouteAddress;
+
+        let mut msg = RouteMessage::default();
+        msg.header.address_family = AddressFamily::Inet;
+        msg.header.table = RouteHeader::RT_TABLE_MAIN;
+        msg.header.kind = RouteType::Unicast;
+        msg.header.protocol = protocol;
+        msg.header.destination_prefix_length = if gateway.is_some() { 0 } else { 24 };
+        if let Some(gateway) = gateway {
+            msg.attributes
+                .push(RouteAttribute::Gateway(RouteAddress::Inet(gateway)));
+        } else {
+            msg.attributes
+                .push(RouteAttribute::Destination(RouteAddress::Inet(
+                    Ipv4Addr::new(192, 168, 1, 0),
+                )));
+        }
+        msg.attributes.push(RouteAttribute::Oif(5));
+        msg
+    }
+
+    #[test]
+    fn test_get_device_config() {
+        let mut addr_msg = AddressMessage::default();
+        addr_msg.header.index = 5;
+        addr_msg.header.scope = AddressScope::Universe;
+        addr_msg.header.prefix_len = 24;
+        addr_msg.header.flags = AddressHeaderFlags::Permanent;
+        addr_msg
+            .attributes
+            .push(AddressAttribute::Address(IpAddr::V4(Ipv4Addr::new(
+                192, 168, 1, 1,
+            ))));
+        let mut link_local = addr_msg.clone();
+        link_local.header.scope = AddressScope::Link;
+
+        let mut fake_addr_client = FakeNetlinkClient::new();
+        fake_addr_client.set_expected_responses(vec![
+            RouteNetlinkMessage::NewAddress(addr_msg),
+            RouteNetlinkMessage::NewAddress(link_local),
+        ]);
+        let mut addr_client = AddressClient::new(ClientWrapper::Fake(fake_addr_client)).unwrap();
+
+        let mut fake_route_client = FakeNetlinkClient::new();
+        fake_route_client.set_expected_responses(vec![
+            RouteNetlinkMessage::NewRoute(route_message(RouteProtocol::Kernel, None)),
+            RouteNetlinkMessage::NewRoute(route_message(
+                RouteProtocol::Boot,
+                Some(Ipv4Addr::new(192, 168, 1, 254)),
+            )),
+        ]);
+        let mut route_client = RouteClient::new(ClientWrapper::Fake(fake_route_client)).unwrap();
+
+        let mut link = LinkMessage::default();
+        link.header.index = 5;
+        link.attributes.push(LinkAttribute::Mtu(9000));
+        link.attributes
+            .push(LinkAttribute::Address(vec![0x02, 0, 0, 0, 0, 0x01]));
+
+        let config = get_device_config(&link, "eth1", &mut addr_client, &mut route_client).unwrap();
+
+        assert_eq!(config.addresses.len(), 1);
+        assert_eq!(config.routes.len(), 1);
+        assert_eq!(
+            config.routes[0].gateway,
+            Some(IpAddr::V4(Ipv4Addr::new(192, 168, 1, 254)))
+        );
+        assert_eq!(config.mtu, Some(9000));
+        assert_eq!(config.hardware_address, Some(vec![0x02, 0, 0, 0, 0, 0x01]));
+    }
+
+    #[test]
+    fn test_setup_routes_in_network_namespace_order() {
+        let mut fake_client = FakeNetlinkClient::new();
+        fake_client.set_expected_responses(vec![
+            RouteNetlinkMessage::NewRoute(RouteMessage::default()),
+            RouteNetlinkMessage::NewRoute(RouteMessage::default()),
+        ]);
+        let mut route_client = RouteClient::new(ClientWrapper::Fake(fake_client)).unwrap();
+
+        let routes: Vec<RouteConfig> = [
+            route_message(RouteProtocol::Boot, Some(Ipv4Addr::new(192, 168, 1, 254))),
+            route_message(RouteProtocol::Boot, None),
+        ]
+        .iter()
+        .filter_map(RouteConfig::from_message)
+        .collect();
+
+        setup_routes_in_network_namespace(&routes, 7, "eth1", &mut route_client).unwrap();
+
+        let send_calls = route_client.get_send_calls().unwrap();
+        assert_eq!(send_calls.len(), 2);
+        let has_gateway =
+            |call: &netlink_packet_core::NetlinkMessage<RouteNetlinkMessage>| match &call.payload {
+                netlink_packet_core::NetlinkPayload::InnerMessage(
+                    RouteNetlinkMessage::NewRoute(msg),
+                ) => msg
+                    .attributes
+                    .iter()
+                    .any(|attr| matches!(attr, RouteAttribute::Gateway(_))),
+                _ => panic!("Expected NewRoute message"),
+            };
+        assert!(!has_gateway(&send_calls[0]));
+        assert!(has_gateway(&send_calls[1]));
+    }
+
+    #[test]
+    fn test_configure_network_device() {
+        let mut link = LinkMessage::default();
+        link.header.index = 7;
+        let mut fake_link_client = FakeNetlinkClient::new();
+        // get_by_name, set_mtu, set_up
+        fake_link_client.set_expected_responses(vec![
+            RouteNetlinkMessage::NewLink(link),
+            RouteNetlinkMessage::NewLink(LinkMessage::default()),
+            RouteNetlinkMessage::NewLink(LinkMessage::default()),
+        ]);
+        let mut link_client = LinkClient::new(ClientWrapper::Fake(fake_link_client)).unwrap();
+        let mut addr_client =
+            AddressClient::new(ClientWrapper::Fake(FakeNetlinkClient::new())).unwrap();
+        let mut route_client =
+            RouteClient::new(ClientWrapper::Fake(FakeNetlinkClient::new())).unwrap();
+
+        let config = NetDeviceConfig {
+            mtu: Some(1400),
+            ..Default::default()
+        };
+        configure_network_device(
+            &config,
+            "eth1",
+            &mut link_client,
+            &mut addr_client,
+            &mut route_client,
+        )
+        .unwrap();
+
+        assert_eq!(addr_client.get_send_calls().unwrap().len(), 0);
+        assert_eq!(route_client.get_send_calls().unwrap().len(), 0);
+    }
 }
@@ -0,0 +1,365 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use netlink_packet_core::{
+    NLM_F_ACK, NLM_F_CREATE, NLM_F_DUMP, NLM_F_REPLACE, NLM_F_REQUEST, NetlinkMessage,
+    NetlinkPayload,
+};
+use netlink_packet_route::route::{
+    RouteAddress, RouteAttribute, RouteHeader, RouteMessage, RouteProtocol, RouteScope, RouteType,
+};
+use netlink_packet_route::{AddressFamily, RouteNetlinkMessage};
+use serde::{Deserialize, Serialize};
+
+use crate::network::cidr::CidrAddress;
+use crate::network::traits::{Client, NetlinkMessageHandler};
+use crate::network::wrapper::ClientWrapper;
+use crate::network::{NetlinkResponse, NetworkError, Result};
+
+/// A route of a network device, in a form that can be sent to the init process.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RouteConfig {
+    /// The destination of the route, `0.0.0.0/0` or `::/0` for default routes
+    pub destination: CidrAddress,
+    pub gateway: Option<IpAddr>,
+    pub priority: Option<u32>,
+    pub scope: u8,
+}
+
+impl RouteConfig {
+    /// Converts a route message into a RouteConfig.
+    ///
+    /// Returns None for routes which are neither IPv4 nor IPv6.
+    pub fn from_message(msg: &RouteMessage) -> Option<Self> {
+        let unspecified = match msg.header.address_family {
+            AddressFamily::Inet => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            AddressFamily::Inet6 => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+            _ => return None,
+        };
+
+        let mut route = RouteConfig {
+            destination: CidrAddress {
+                prefix_len: msg.header.destination_prefix_length,
+                address: unspecified,
+            },
+            gateway: None,
+            priority: None,
+            scope: msg.header.scope.into(),
+        };
+        for attr in &msg.attributes {
+            match attr {
+                RouteAttribute::Destination(addr) => {
+                    route.destination.address = route_address_to_ip(addr)?;
+                }
+                RouteAttribute::Gateway(addr) => route.gateway = route_address_to_ip(addr),
+                RouteAttribute::Priority(priority) => route.priority = Some(*priority),
+                _ => {}
+            }
+        }
+
+        Some(route)
+    }
+}
+
+fn route_address_to_ip(addr: &RouteAddress) -> Option<IpAddr> {
+    match addr {
+        RouteAddress::Inet(ip) => Some(IpAddr::V4(*ip)),
+        RouteAddress::Inet6(ip) => Some(IpAddr::V6(*ip)),
+        _ => None,
+    }
+}
+
+/// Handler for Route messages in Netlink communication.
+///
+/// This handler processes Netlink messages related to routes
+/// and converts them into RouteMessage responses.
+pub struct RouteMessageHandler {
+    target_index: Option<u32>,
+}
+
+impl RouteMessageHandler {
+    pub fn new() -> Self {
+        Self { target_index: None }
+    }
+
+    pub fn with_index(index: u32) -> Self {
+        Self {
+            target_index: Some(index),
+        }
+    }
+}
+
+impl Default for RouteMessageHandler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl NetlinkMessageHandler for RouteMessageHandler {
+    type Response = RouteMessage;
+
+    fn handle_payload(
+        &self,
+        payload: NetlinkPayload<RouteNetlinkMessage>,
+    ) -> Result<NetlinkResponse<Self::Response>> {
+        match payload {
+            NetlinkPayload::InnerMessage(RouteNetlinkMessage::NewRoute(route)) => {
+                if let Some(target_index) = self.target_index {
+                    let oif = route.attributes.iter().find_map(|attr| match attr {
+                        RouteAttribute::Oif(oif) => Some(*oif),
+                        _ => None,
+                    });
+                    if oif == Some(target_index) {
+                        Ok(NetlinkResponse::Success(route))
+                    } else {
+                        Ok(NetlinkResponse::None)
+                    }
+                } else {
+                    Ok(NetlinkResponse::Success(route))
+                }
+            }
+            NetlinkPayload::Error(e) => match e.code {
+                None => Ok(NetlinkResponse::Success(RouteMessage::default())),
+                Some(code) => Ok(NetlinkResponse::Error(code.get())),
+            },
+            NetlinkPayload::Done(_) => Ok(NetlinkResponse::Done),
+            _ => Err(NetworkError::IO(std::io::Error::other(format!(
+                "Unexpected message type: {:?}",
+                payload
+            )))),
+        }
+    }
+}
+
+/// Client for managing routes.
+///
+/// This client provides methods for querying and adding routes
+/// through Netlink communication.
+pub struct RouteClient {
+    client: ClientWrapper,
+}
+
+impl RouteClient {
+    /// Creates a new RouteClient instance.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing either a new RouteClient or an IO error
+    pub fn new(client: ClientWrapper) -> Result<Self> {
+        Ok(Self { client })
+    }
+
+    /// Retrieves all IPv4 and IPv6 routes going through a network interface.
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The index of the network interface
+    ///
+    /// # Returns
+    ///
+    /// A Result containing either a vector of RouteMessages or an error
+    pub fn get_by_index(&mut self, index: u32) -> Result<Vec<RouteMessage>> {
+        let message = RouteMessage::default();
+        let mut req = NetlinkMessage::from(RouteNetlinkMessage::GetRoute(message));
+        // NLM_F_REQUEST: This is a request to the kernel
+        // NLM_F_DUMP: Request a dump of all matching entries
+        req.header.flags = NLM_F_REQUEST | NLM_F_DUMP;
+        req.finalize();
+
+        let handler = RouteMessageHandler::with_index(index);
+
+        self.client.send_and_receive_multiple(&req, handler)
+    }
+
+    /// Adds a route through a network interface to the main table, replacing
+    /// an existing route to the same destination.
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The index of the network interface
+    /// * `route` - The route to add
+    ///
+    /// # Returns
+    ///
+    /// A Result indicating success or failure of the operation
+    pub fn add(&mut self, index: u32, route: &RouteConfig) -> Result<()> {
+        let message = Self::create_route_request(index, route);
+
+        let mut req = NetlinkMessage::from(RouteNetlinkMessage::NewRoute(message));
+        // NLM_F_REQUEST: This is a request to the kernel
+        // NLM_F_ACK: Request an acknowledgment from the kernel
+        // NLM_F_REPLACE: Replace the route if it already exists, e.g. the one
+        // the kernel adds for the prefix of an address
+        // NLM_F_CREATE: Create the route if it doesn't exist
+        req.header.flags = NLM_F_REQUEST | NLM_F_ACK | NLM_F_REPLACE | NLM_F_CREATE;
+        req.finalize();
+
+        self.client
+            .send_and_receive(&req, RouteMessageHandler::new())?;
+        Ok(())
+    }
+
+    /// Creates a route request message.
+    /// ref: https://github.com/rust-netlink/rtnetlink/blob/v0.17.0/src/route/builder.rs
+    fn create_route_request(index: u32, route: &RouteConfig) -> RouteMessage {
+        let mut message = RouteMessage::default();
+        message.header.address_family = match route.destination.address {
+            IpAddr::V4(_) => AddressFamily::Inet,
+            IpAddr::V6(_) => AddressFamily::Inet6,
+        };
+        message.header.destination_prefix_length = route.destination.prefix_len;
+        message.header.table = RouteHeader::RT_TABLE_MAIN;
+        message.header.protocol = RouteProtocol::Boot;
+        message.header.scope = RouteScope::from(route.scope);
+        message.header.kind = RouteType::Unicast;
+
+        if route.destination.prefix_len > 0 {
+            message.attributes.push(RouteAttribute::Destination(
+                route.destination.address.into(),
+            ));
+        }
+        if let Some(gateway) = route.gateway {
+            message
+                .attributes
+                .push(RouteAttribute::Gateway(gateway.into()));
+        }
+        if let Some(priority) = route.priority {
+            message.attributes.push(RouteAttribute::Priority(priority));
+        }
+        message.attributes.push(RouteAttribute::Oif(index));
+
+        message
+    }
+
+    #[cfg(test)]
+    /// Test method to get send calls from the fake client
+    pub fn get_send_calls(
+        &self,
+    ) -> Option<&[netlink_packet_core::NetlinkMessage<RouteNetlinkMessage>]> {
+        if let ClientWrapper::Fake(fake_client) = &self.client {
+            Some(fake_client.get_send_calls())
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::fake::FakeNetlinkClient;
+
+    fn route_message(index: u32, gateway: Option<Ipv4Addr>) -> RouteMessage {
+        let mut msg = RouteMessage::default();
+        msg.header.address_family = AddressFamily::Inet;
+        msg.header.destination_prefix_length = 24;
+        msg.header.scope = RouteScope::Universe;
+        msg.attributes
+            .push(RouteAttribute::Destination(RouteAddress::Inet(
+                Ipv4Addr::new(10, 1, 0, 0),
+            )));
+        if let Some(gateway) = gateway {
+            msg.attributes
+                .push(RouteAttribute::Gateway(RouteAddress::Inet(gateway)));
+        }
+        msg.attributes.push(RouteAttribute::Priority(100));
+        msg.attributes.push(RouteAttribute::Oif(index));
+        msg
+    }
+
+    #[test]
+    fn test_route_config_from_message() {
+        let msg = route_message(3, Some(Ipv4Addr::new(10, 0, 0, 1)));
+
+        let route = RouteConfig::from_message(&msg).unwrap();
+        assert_eq!(route.destination.prefix_len, 24);
+        assert_eq!(
+            route.destination.address,
+            IpAddr::V4(Ipv4Addr::new(10, 1, 0, 0))
+        );
+        assert_eq!(route.gateway, Some(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1))));
+        assert_eq!(route.priority, Some(100));
+    }
+
+    #[test]
+    fn test_route_config_default_route() {
+        let mut msg = RouteMessage::default();
+        msg.header.address_family = AddressFamily::Inet6;
+        msg.attributes
+            .push(RouteAttribute::Gateway(RouteAddress::Inet6(
+                "fe80::1".parse().unwrap(),
+            )));
+
+        let route = RouteConfig::from_message(&msg).unwrap();
+        assert_eq!(route.destination.prefix_len, 0);
+        assert_eq!(route.destination.address, IpAddr::V6(Ipv6Addr::UNSPECIFIED));
+    }
+
+    #[test]
+    fn test_route_message_handler_filters_index() {
+        let handler = RouteMessageHandler::with_index(3);
+
+        let result = handler
+            .handle_payload(NetlinkPayload::InnerMessage(RouteNetlinkMessage::NewRoute(
+                route_message(3, None),
+            )))
+            .unwrap();
+        assert!(matches!(result, NetlinkResponse::Success(_)));
+
+        let result = handler
+            .handle_payload(NetlinkPayload::InnerMessage(RouteNetlinkMessage::NewRoute(
+                route_message(4, None),
+            )))
+            .unwrap();
+        assert!(matches!(result, NetlinkResponse::None));
+    }
+
+    #[test]
+    fn test_route_client_get_by_index() {
+        let mut fake_client = FakeNetlinkClient::new();
+        fake_client.set_expected_responses(vec![
+            RouteNetlinkMessage::NewRoute(route_message(3, None)),
+            RouteNetlinkMessage::NewRoute(route_message(4, None)),
+        ]);
+
+        let mut route_client = RouteClient::new(ClientWrapper::Fake(fake_client)).unwrap();
+        let routes = route_client.get_by_index(3).unwrap();
+        assert_eq!(routes.len(), 1);
+
+        let send_calls = route_client.get_send_calls().unwrap();
+        assert_eq!(send_calls[0].header.flags, NLM_F_REQUEST | NLM_F_DUMP);
+    }
+
+    #[test]
+    fn test_route_client_add() {
+        let mut fake_client = FakeNetlinkClient::new();
+        fake_client
+            .set_expected_responses(vec![RouteNetlinkMessage::NewRoute(RouteMessage::default())]);
+
+        let mut route_client = RouteClient::new(ClientWrapper::Fake(fake_client)).unwrap();
+        let route =
+            RouteConfig::from_message(&route_message(3, Some(Ipv4Addr::new(10, 0, 0, 1)))).unwrap();
+        route_client.add(7, &route).unwrap();
+
+        let send_calls = route_client.get_send_calls().unwrap();
+        assert_eq!(send_calls.len(), 1);
+        assert_eq!(
+            send_calls[0].header.flags,
+            NLM_F_REQUEST | NLM_F_ACK | NLM_F_REPLACE | NLM_F_CREATE
+        );
+        if let NetlinkPayload::InnerMessage(RouteNetlinkMessage::NewRoute(msg)) =
+            &send_calls[0].payload
+        {
+            assert_eq!(msg.header.table, RouteHeader::RT_TABLE_MAIN);
+            assert_eq!(msg.header.destination_prefix_length, 24);
+            assert!(msg.attributes.contains(&RouteAttribute::Oif(7)));
+            assert!(
+                msg.attributes
+                    .contains(&RouteAttribute::Gateway(RouteAddress::Inet(Ipv4Addr::new(
+                        10, 0, 0, 1
+                    ))))
+            );
+        } else {
+            panic!("Expected NewRoute message");
+        }
+    }
+}
@@ -1,13 +1,30 @@
 use std::env;
+use std::fs::{self, Permissions};
+use std::io::ErrorKind;
 use std::io::prelude::*;
+use std::net::Shutdown;
 use std::os::fd::FromRawFd;
+use std::os::linux::net::SocketAddrExt;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::PermissionsExt;
 use std::os::unix::io::AsRawFd;
-use std::os::unix::net::{UnixListener, UnixStream};
+use std::os::unix::net::{SocketAddr, UnixDatagram, UnixListener, UnixStream};
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 
-use nix::unistd::{self, close};
+use nix::sys::signal::kill;
+use nix::unistd::{self, Pid, close};
 
 pub const NOTIFY_FILE: &str = "notify.sock";
+/// Directory in the container state that holds the sd_notify socket of the
+/// container. It is bind mounted into the container at the parent of
+/// [`CONTAINER_NOTIFY_SOCKET`].
+pub const NOTIFY_SOCKET_DIR: &str = "notify";
+/// Path of the sd_notify socket inside the container
+pub const CONTAINER_NOTIFY_SOCKET: &str = "/run/notify/notify.sock";
+
+// How often the relay checks that the container is still running
+const RELAY_POLL_INTERVAL: Duration = Duration::from_millis(500);
 
 #[derive(Debug, thiserror::Error)]
 pub enum NotifyListenerError {
@@ -35,6 +52,23 @@ pub enum NotifyListenerError {
     Read(#[source] std::io::Error),
     #[error("failed to send start container")]
     SendStartContainer(#[source] std::io::Error),
+    #[error("failed to reply to start container")]
+    ReplyStartContainer(#[source] std::io::Error),
+    #[error("failed to remove stale notify socket {path}")]
+    RemoveStale {
+        source: std::io::Error,
+        path: PathBuf,
+    },
+    #[error("failed to set permissions of notify socket {path}")]
+    Permissions {
+        source: std::io::Error,
+        path: PathBuf,
+    },
+    #[error("failed to send to host notify socket {path}")]
+    SendHost {
+        source: std::io::Error,
+        path: PathBuf,
+    },
 }
 
 type Result<T> = std::result::Result<T, NotifyListenerError>;
@@ -77,7 +111,9 @@ impl NotifyListener {
         Ok(Self { socket: stream })
     }
 
-    pub fn wait_for_container_start(&self) -> Result<()> {
+    /// Waits for the start command. The returned connection is kept open
+    /// until the container process is about to be executed.
+    pub fn wait_for_container_start(&self) -> Result<StartConnection> {
         match self.socket.accept() {
             Ok((mut socket, _)) => {
                 let mut response = String::new();
@@ -85,11 +121,10 @@ impl NotifyListener {
                     .read_to_string(&mut response)
                     .map_err(NotifyListenerError::Read)?;
                 tracing::debug!("received: {}", response);
+                Ok(StartConnection { socket })
             }
-            Err(e) => Err(NotifyListenerError::Accept(e))?,
+            Err(e) => Err(NotifyListenerError::Accept(e)),
         }
-
-        Ok(())
     }
 
     pub fn close(&self) -> Result<()> {
@@ -115,6 +150,21 @@ impl Clone for NotifyListener {
     }
 }
 
+/// Connection of the start command to the container init process
+pub struct StartConnection {
+    socket: UnixStream,
+}
+
+impl StartConnection {
+    /// Replies to the start command and closes the connection, which lets
+    /// the start command return.
+    pub fn reply(mut self, message: &[u8]) -> Result<()> {
+        self.socket
+            .write_all(message)
+            .map_err(NotifyListenerError::ReplyStartContainer)
+    }
+}
+
 pub struct NotifySocket {
     path: PathBuf,
 }
@@ -126,7 +176,10 @@ impl NotifySocket {
         }
     }
 
-    pub fn notify_container_start(&mut self) -> Result<()> {
+    /// Notifies the container to start, and returns the reply of the
+    /// container once it is about to execute its process. The reply is empty
+    /// if the container was created by a youki not replying.
+    pub fn notify_container_start(&mut self) -> Result<Vec<u8>> {
         tracing::debug!("notify container start");
         let cwd = env::current_dir().map_err(NotifyListenerError::GetCwd)?;
         let workdir = self
@@ -149,16 +202,149 @@ impl NotifySocket {
             })?;
         stream
             .write_all(b"start container")
+            .and_then(|_| stream.shutdown(Shutdown::Write))
             .map_err(NotifyListenerError::SendStartContainer)?;
         tracing::debug!("notify finished");
         unistd::chdir(&cwd).map_err(|e| NotifyListenerError::Chdir {
             source: e,
             path: cwd,
         })?;
+        let mut reply = Vec::new();
+        stream
+            .read_to_end(&mut reply)
+            .map_err(NotifyListenerError::Read)?;
+        Ok(reply)
+    }
+}
+
+/// Relays the sd_notify messages of the container to the notify socket of
+/// the host service manager, e.g. systemd for a unit of `Type=notify`.
+pub struct NotifyRelay {
+    socket: UnixDatagram,
+    host: PathBuf,
+}
+
+impl NotifyRelay {
+    /// Binds the notify socket of the container in `socket_dir`, replacing
+    /// the socket of a previous relay.
+    pub fn bind(socket_dir: &Path, host: &Path) -> Result<Self> {
+        let path = socket_dir.join(NOTIFY_FILE);
+        match fs::remove_file(&path) {
+            Ok(()) => {}
+            Err(err) if err.kind() == ErrorKind::NotFound => {}
+            Err(err) => return Err(NotifyListenerError::RemoveStale { source: err, path }),
+        }
+
+        // The same workaround for the length limit of socket paths as in
+        // `NotifyListener::new`.
+        let cwd = env::current_dir().map_err(NotifyListenerError::GetCwd)?;
+        unistd::chdir(socket_dir).map_err(|e| NotifyListenerError::Chdir {
+            source: e,
+            path: socket_dir.to_owned(),
+        })?;
+        let socket = UnixDatagram::bind(NOTIFY_FILE);
+        unistd::chdir(&cwd).map_err(|e| NotifyListenerError::Chdir {
+            source: e,
+            path: cwd,
+        })?;
+        let socket = socket.map_err(|e| NotifyListenerError::Bind {
+            source: e,
+            name: NOTIFY_FILE.to_owned(),
+        })?;
+
+        // any user of the container may notify
+        fs::set_permissions(&path, Permissions::from_mode(0o777))
+            .map_err(|source| NotifyListenerError::Permissions { source, path })?;
+        socket
+            .set_read_timeout(Some(RELAY_POLL_INTERVAL))
+            .map_err(NotifyListenerError::Read)?;
+
+        Ok(Self {
+            socket,
+            host: host.to_owned(),
+        })
+    }
+
+    /// Relays messages until the container reports that it is ready or the
+    /// process `pid` exits.
+    pub fn relay(&self, pid: Pid) -> Result<()> {
+        let mut buf = [0u8; 4096];
+        loop {
+            match self.socket.recv(&mut buf) {
+                Ok(len) => {
+                    let message = String::from_utf8_lossy(&buf[..len]);
+                    let Some((message, ready)) = rewrite_notify_message(&message, pid) else {
+                        continue;
+                    };
+                    tracing::debug!(?message, "relay notify message");
+                    self.send(&message)?;
+                    if ready {
+                        return Ok(());
+                    }
+                }
+                Err(err) if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                    if kill(pid, None).is_err() {
+                        tracing::warn!(?pid, "container exited before it was ready");
+                        return Ok(());
+                    }
+                }
+                Err(err) if err.kind() == ErrorKind::Interrupted => {}
+                Err(err) => return Err(NotifyListenerError::Read(err)),
+            }
+        }
+    }
+
+    fn send(&self, message: &str) -> Result<()> {
+        let send_err = |source| NotifyListenerError::SendHost {
+            source,
+            path: self.host.clone(),
+        };
+        let addr = host_address(&self.host).map_err(send_err)?;
+        UnixDatagram::unbound()
+            .and_then(|socket| socket.send_to_addr(message.as_bytes(), &addr))
+            .map_err(send_err)?;
         Ok(())
     }
 }
 
+/// Returns the address of a notify socket, `NOTIFY_SOCKET` names a socket in
+/// the abstract namespace if it starts with `@`.
+fn host_address(path: &Path) -> std::io::Result<SocketAddr> {
+    match path.as_os_str().as_bytes().strip_prefix(b"@") {
+        Some(name) => SocketAddr::from_abstract_name(name),
+        None => SocketAddr::from_pathname(path),
+    }
+}
+
+/// Keeps the lines of a message that are relayed to the host, and returns
+/// whether it reports readiness. `MAINPID` refers to the pid namespace of
+/// the container, so it is replaced by the host pid of the container.
+fn rewrite_notify_message(message: &str, pid: Pid) -> Option<(String, bool)> {
+    let mut lines = Vec::new();
+    let mut ready = false;
+    let mut main_pid = false;
+    for line in message.lines() {
+        if line == "READY=1" {
+            ready = true;
+            lines.push(line.to_owned());
+        } else if line.starts_with("STATUS=") {
+            lines.push(line.to_owned());
+        } else if line.starts_with("MAINPID=") {
+            main_pid = true;
+            lines.push(format!("MAINPID={pid}"));
+        }
+    }
+    if ready && !main_pid {
+        lines.push(format!("MAINPID={pid}"));
+    }
+
+    if lines.is_empty() {
+        None
+    } else {
+        Some((lines.join("\n"), ready))
+    }
+}
+
 #[cfg(test)]
 mod test {
     use tempfile::tempdir;
@@ -181,11 +367,49 @@ mod test {
             move || {
                 // We clone the listener and listen on the cloned listener to
                 // make sure the cloned fd functions correctly.
-                listener.wait_for_container_start().unwrap();
+                let connection = listener.wait_for_container_start().unwrap();
+                connection.reply(b"started").unwrap();
             }
         });
 
-        socket.notify_container_start().unwrap();
+        assert_eq!(socket.notify_container_start().unwrap(), b"started");
         thread_handle.join().unwrap();
     }
+
+    #[test]
+    fn test_rewrite_notify_message() {
+        let pid = Pid::from_raw(4242);
+        assert_eq!(
+            rewrite_notify_message("READY=1\nSTATUS=serving", pid),
+            Some(("READY=1\nSTATUS=serving\nMAINPID=4242".to_owned(), true))
+        );
+        assert_eq!(
+            rewrite_notify_message("MAINPID=1\nWATCHDOG=1", pid),
+            Some(("MAINPID=4242".to_owned(), false))
+        );
+        assert_eq!(rewrite_notify_message("WATCHDOG=1", pid), None);
+    }
+
+    #[test]
+    fn test_notify_relay() {
+        let tempdir = tempdir().unwrap();
+        let host_path = tempdir.path().join("host.sock");
+        let host = UnixDatagram::bind(&host_path).unwrap();
+        let relay = NotifyRelay::bind(tempdir.path(), &host_path).unwrap();
+
+        let container = UnixDatagram::unbound().unwrap();
+        let socket_path = tempdir.path().join(NOTIFY_FILE);
+        container.send_to(b"STATUS=starting", &socket_path).unwrap();
+        container.send_to(b"READY=1", &socket_path).unwrap();
+        relay.relay(unistd::getpid()).unwrap();
+
+        let mut buf = [0u8; 64];
+        let len = host.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"STATUS=starting");
+        let len = host.recv(&mut buf).unwrap();
+        assert_eq!(
+            String::from_utf8_lossy(&buf[..len]),
+            format!("READY=1\nMAINPID={}", unistd::getpid())
+        );
+    }
 }
@@ -22,6 +22,8 @@ pub struct ContainerArgs {
     pub container_type: ContainerType,
     /// Interface to operating system primitives
     pub syscall: SyscallType,
+    /// Id of the container
+    pub container_id: String,
     /// OCI compliant runtime spec
     pub spec: Rc<Spec>,
     /// Root filesystem of the container
@@ -55,4 +57,7 @@ pub struct ContainerArgs {
     /// File path used to communicate the PID of the
     /// container process to the higher-level runtime.
     pub pid_file: Option<PathBuf>,
+    /// If the process should stay in the session keyring of the caller
+    /// instead of joining a new one for the container.
+    pub no_new_keyring: bool,
 }
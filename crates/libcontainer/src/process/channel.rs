@@ -4,8 +4,9 @@ use std::os::unix::prelude::{AsRawFd, FromRawFd, OwnedFd, RawFd};
 use nix::unistd::Pid;
 
 use crate::channel::{Receiver, Sender, channel};
-use crate::network::cidr::CidrAddress;
+use crate::network::network_device::NetDeviceConfig;
 use crate::process::message::{Message, MountMsg};
+use crate::timings::PhaseTiming;
 
 #[derive(Debug, thiserror::Error)]
 pub enum ChannelError {
@@ -102,16 +103,21 @@ impl MainSender {
         Ok(())
     }
 
-    pub fn intermediate_ready(&mut self, pid: Pid) -> Result<(), ChannelError> {
+    pub fn intermediate_ready(
+        &mut self,
+        pid: Pid,
+        timings: Vec<PhaseTiming>,
+    ) -> Result<(), ChannelError> {
         // Send over the IntermediateReady follow by the pid.
         tracing::debug!("sending init pid ({:?})", pid);
-        self.sender.send(Message::IntermediateReady(pid.as_raw()))?;
+        self.sender
+            .send(Message::IntermediateReady(pid.as_raw(), timings))?;
 
         Ok(())
     }
 
-    pub fn init_ready(&mut self) -> Result<(), ChannelError> {
-        self.sender.send(Message::InitReady)?;
+    pub fn init_ready(&mut self, timings: Vec<PhaseTiming>) -> Result<(), ChannelError> {
+        self.sender.send(Message::InitReady(timings))?;
 
         Ok(())
     }
@@ -144,8 +150,9 @@ pub struct MainReceiver {
 
 impl MainReceiver {
     /// Waits for associated intermediate process to send ready message
-    /// and return the pid of init process which is forked by intermediate process
-    pub fn wait_for_intermediate_ready(&mut self) -> Result<Pid, ChannelError> {
+    /// and return the pid of init process which is forked by intermediate process,
+    /// along with the phases the intermediate process recorded
+    pub fn wait_for_intermediate_ready(&mut self) -> Result<(Pid, Vec<PhaseTiming>), ChannelError> {
         let msg = self
             .receiver
             .recv()
@@ -155,7 +162,7 @@ impl MainReceiver {
             })?;
 
         match msg {
-            Message::IntermediateReady(pid) => Ok(Pid::from_raw(pid)),
+            Message::IntermediateReady(pid, timings) => Ok((Pid::from_raw(pid), timings)),
             Message::ExecFailed(err) => Err(ChannelError::ExecError(err)),
             Message::OtherError(err) => Err(ChannelError::OtherError(err)),
             msg => Err(ChannelError::unexpected("IntermediateReady", msg)),
@@ -264,7 +271,7 @@ impl MainReceiver {
 
     /// Waits for associated init process to send ready message
     /// and return the pid of init process which is forked by init process
-    pub fn wait_for_init_ready(&mut self) -> Result<(), ChannelError> {
+    pub fn wait_for_init_ready(&mut self) -> Result<Vec<PhaseTiming>, ChannelError> {
         let msg = self
             .receiver
             .recv()
@@ -273,7 +280,7 @@ impl MainReceiver {
                 source: err,
             })?;
         match msg {
-            Message::InitReady => Ok(()),
+            Message::InitReady(timings) => Ok(timings),
             // this case in unique and known enough to have a special error format
             Message::ExecFailed(err) => Err(ChannelError::ExecError(format!(
                 "error in executing process : {err}"
@@ -403,9 +410,9 @@ impl InitSender {
 
     pub fn move_network_device(
         &mut self,
-        addrs: HashMap<String, Vec<CidrAddress>>,
+        configs: HashMap<String, NetDeviceConfig>,
     ) -> Result<(), ChannelError> {
-        self.sender.send(Message::MoveNetworkDevice(addrs))?;
+        self.sender.send(Message::MoveNetworkDevice(configs))?;
 
         Ok(())
     }
@@ -450,7 +457,7 @@ impl InitReceiver {
 
     pub fn wait_for_move_network_device(
         &mut self,
-    ) -> Result<HashMap<String, Vec<CidrAddress>>, ChannelError> {
+    ) -> Result<HashMap<String, NetDeviceConfig>, ChannelError> {
         let msg = self
             .receiver
             .recv()
@@ -459,7 +466,7 @@ impl InitReceiver {
                 source: err,
             })?;
         match msg {
-            Message::MoveNetworkDevice(addr) => Ok(addr),
+            Message::MoveNetworkDevice(configs) => Ok(configs),
             msg => Err(ChannelError::unexpected("MoveNetworkDevice", msg)),
         }
     }
@@ -518,6 +525,7 @@ mod tests {
     use serial_test::serial;
 
     use super::*;
+    use crate::timings::{Phase, Process, Recorder};
 
     // Note: due to cargo test by default runs tests in parallel using a single
     // process, these tests should not be running in parallel with other tests.
@@ -533,15 +541,19 @@ mod tests {
         match unsafe { unistd::fork()? } {
             unistd::ForkResult::Parent { child } => {
                 wait::waitpid(child, None)?;
-                let pid = receiver
+                let (pid, timings) = receiver
                     .wait_for_intermediate_ready()
                     .with_context(|| "Failed to wait for intermadiate ready")?;
                 receiver.close()?;
                 assert_eq!(pid, child);
+                assert_eq!(timings.len(), 1);
+                assert_eq!(timings[0].phase, Phase::CgroupApply);
             }
             unistd::ForkResult::Child => {
                 let pid = unistd::getpid();
-                sender.intermediate_ready(pid)?;
+                let mut recorder = Recorder::new(Process::Intermediate);
+                recorder.record(Phase::CgroupApply, recorder.start());
+                sender.intermediate_ready(pid, recorder.into_phases())?;
                 sender.close()?;
                 std::process::exit(0);
             }
@@ -719,7 +731,7 @@ mod tests {
             }
             unistd::ForkResult::Child => {
                 sender
-                    .init_ready()
+                    .init_ready(vec![])
                     .with_context(|| "Failed to send init ready")?;
                 sender.close()?;
                 std::process::exit(0);
@@ -786,8 +798,13 @@ mod tests {
             prefix_len: 24,
             address: ip,
         };
+        let config = NetDeviceConfig {
+            addresses: vec![addr],
+            mtu: Some(1400),
+            ..Default::default()
+        };
         let mut addrs = HashMap::new();
-        addrs.insert(device_name.clone(), vec![addr.clone()]);
+        addrs.insert(device_name.clone(), config.clone());
 
         let (sender, receiver) = &mut init_channel()?;
 
@@ -805,10 +822,8 @@ mod tests {
             unistd::ForkResult::Child => {
                 let received_addrs = receiver.wait_for_move_network_device()?;
                 receiver.close()?;
-                if let Some(received_addr) = received_addrs.get(&device_name) {
-                    if !(received_addr[0].prefix_len == addr.prefix_len
-                        && received_addr[0].address == addr.address)
-                    {
+                if let Some(received_config) = received_addrs.get(&device_name) {
+                    if *received_config != config {
                         eprintln!("assertion failed in child");
                         std::process::exit(1);
                     }
@@ -849,9 +864,9 @@ mod tests {
     #[serial]
     fn test_recv_init_message_passes_through_protocol_message() -> Result<()> {
         let (mut sender, mut receiver) = main_channel()?;
-        sender.init_ready()?;
+        sender.init_ready(vec![])?;
         let (msg, fd) = receiver.recv_init_message()?;
-        assert!(matches!(msg, Message::InitReady));
+        assert!(matches!(msg, Message::InitReady(_)));
         assert!(fd.is_none());
         sender.close()?;
         receiver.close()?;
@@ -11,7 +11,7 @@ use super::init::process as init_process;
 use crate::error::MissingSpecError;
 use crate::namespaces::Namespaces;
 use crate::process::{channel, cpu_affinity, fork};
-use crate::utils::rootless_required;
+use crate::timings::{self, Phase, Recorder};
 
 #[derive(Debug, thiserror::Error)]
 pub enum IntermediateProcessError {
@@ -52,11 +52,10 @@ pub fn container_intermediate_process(
     let spec = &args.spec;
     let linux = spec.linux().as_ref().ok_or(MissingSpecError::Linux)?;
     let namespaces = Namespaces::try_from(linux.namespaces().as_ref())?;
-    let rootless = rootless_required(command.as_ref()).unwrap_or(false);
     let cgroup_manager = libcgroups::common::create_cgroup_manager(args.cgroup_config.to_owned())
-        .map_err(|e| IntermediateProcessError::Cgroup(e.to_string()))?
-        .with_rootless(rootless);
+        .map_err(|e| IntermediateProcessError::Cgroup(e.to_string()))?;
 
+    let mut recorder = Recorder::new(timings::Process::Intermediate);
     let current_pid = Pid::this();
     // setting CPU affinity for tenant container before cgroup move
     if matches!(args.container_type, ContainerType::TenantContainer { .. }) {
@@ -82,11 +81,13 @@ pub fn container_intermediate_process(
     // In addition this needs to be done before we enter the cgroup namespace as
     // the cgroup of the process will form the root of the cgroup hierarchy in
     // the cgroup namespace.
+    let cgroup_start = recorder.start();
     apply_cgroups(
         &cgroup_manager,
         linux.resources().as_ref(),
         matches!(args.container_type, ContainerType::InitContainer),
     )?;
+    recorder.record(Phase::CgroupApply, cgroup_start);
 
     // setting CPU affinity for tenant container after cgroup move
     if matches!(args.container_type, ContainerType::TenantContainer { .. }) {
@@ -106,12 +107,14 @@ pub fn container_intermediate_process(
     // https://man7.org/linux/man-pages/man7/user_namespaces.7.html for more
     // information
     if let Some(user_namespace) = namespaces.get(LinuxNamespaceType::User)? {
+        let userns_start = recorder.start();
         setup_userns(
             &namespaces,
             user_namespace,
             intermediate_main_sender,
             inter_receiver,
         )?;
+        recorder.record(Phase::UsernsMapping, userns_start);
 
         // After UID and GID mapping is configured correctly in the Youki main
         // process, We want to make sure continue as the root user inside the
@@ -208,10 +211,12 @@ pub fn container_intermediate_process(
     // configuration. The youki main process can decide what to do with the init
     // process and the intermediate process can just exit safely after the job
     // is done.
+    let clone_start = recorder.start();
     let pid = fork::container_clone_sibling(cb).map_err(|err| {
         tracing::error!("failed to fork init process: {}", err);
         IntermediateProcessError::InitProcess(err)
     })?;
+    recorder.record(Phase::Clone, clone_start);
 
     // Close the exec_notify_fd in this process
     if let ContainerType::TenantContainer { exec_notify_fd } = args.container_type {
@@ -222,7 +227,7 @@ pub fn container_intermediate_process(
     }
 
     intermediate_main_sender
-        .intermediate_ready(pid)
+        .intermediate_ready(pid, recorder.into_phases())
         .map_err(|err| {
             tracing::error!("failed to wait on intermediate process: {}", err);
             err
@@ -319,6 +324,7 @@ fn setup_time_offsets(sender: &mut MainSender, receiver: &mut IntermediateReceiv
     Ok(())
 }
 
+#[tracing::instrument(level = "info", name = "cgroup_apply", skip_all)]
 fn apply_cgroups<
     C: CgroupManager<Error = E> + ?Sized,
     E: std::error::Error + Send + Sync + 'static,
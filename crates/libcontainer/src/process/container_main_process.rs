@@ -10,14 +10,16 @@ use nix::sys::wait::{WaitStatus, waitpid};
 use nix::unistd::Pid;
 use oci_spec::runtime::{Linux, LinuxNamespaceType, Spec};
 
+use crate::config::YoukiConfig;
 use crate::container::Container;
 use crate::hooks;
-use crate::network::network_device::dev_change_net_namespace;
+use crate::network::network_device::{NetDeviceConfig, dev_change_net_namespace};
 use crate::process::args::{ContainerArgs, ContainerType};
 use crate::process::fork::{self, CloneCb};
 use crate::process::message::Message;
 use crate::process::{channel, container_intermediate_process};
 use crate::syscall::SyscallError;
+use crate::timings::{self, Phase, Recorder, StartupTimings};
 use crate::user_ns::UserNamespaceConfig;
 
 #[derive(Debug, thiserror::Error)]
@@ -41,6 +43,8 @@ pub enum ProcessError {
     Network(#[from] crate::network::NetworkError),
     #[error("network device setup requested but {0}")]
     NetworkDeviceSetup(&'static str),
+    #[error("failed to record the network devices")]
+    NetworkDeviceConfig(#[from] crate::config::ConfigError),
     #[error("failed syscall")]
     SyscallOther(#[source] SyscallError),
     #[error("failed hooks {0}")]
@@ -49,7 +53,8 @@ pub enum ProcessError {
 
 type Result<T> = std::result::Result<T, ProcessError>;
 
-pub fn container_main_process(container_args: &ContainerArgs) -> Result<Pid> {
+pub fn container_main_process(container_args: &ContainerArgs) -> Result<(Pid, StartupTimings)> {
+    let mut recorder = Recorder::new(timings::Process::Runtime);
     // We use a set of channels to communicate between parent and child process.
     // Each channel is uni-directional. Because we will pass these channel to
     // cloned process, we have to be deligent about closing any unused channel.
@@ -99,10 +104,12 @@ pub fn container_main_process(container_args: &ContainerArgs) -> Result<Pid> {
         fork::container_clone
     };
 
+    let clone_start = recorder.start();
     let intermediate_pid = container_clone_fn(cb).map_err(|err| {
         tracing::error!("failed to fork intermediate process: {}", err);
         ProcessError::IntermediateProcessFailed(err)
     })?;
+    recorder.record(Phase::Clone, clone_start);
 
     // Close down unused fds. The corresponding fds are duplicated to the
     // child process during clone.
@@ -162,7 +169,10 @@ pub fn container_main_process(container_args: &ContainerArgs) -> Result<Pid> {
 
     // The intermediate process will send the init pid once it forks the init
     // process.  The intermediate process should exit after this point.
-    let init_pid = intermediate_main_receiver.wait_for_intermediate_ready()?;
+    let (init_pid, intermediate_timings) =
+        intermediate_main_receiver.wait_for_intermediate_ready()?;
+    let mut startup_timings = StartupTimings::default();
+    startup_timings.extend(intermediate_timings);
 
     // if file to write the pid to is specified, write pid of the child
     if let Some(pid_file) = &container_args.pid_file {
@@ -176,8 +186,14 @@ pub fn container_main_process(container_args: &ContainerArgs) -> Result<Pid> {
     loop {
         let (msg, fd) = init_main_receiver.recv_init_message()?;
         match sequence.accept(&msg)? {
-            InitRequest::Ready => break,
+            InitRequest::Ready => {
+                if let Message::InitReady(init_timings) = msg {
+                    startup_timings.extend(init_timings);
+                }
+                break;
+            }
             InitRequest::Hooks => {
+                let hooks_start = recorder.start();
                 let hooks = container_args
                     .spec
                     .hooks()
@@ -189,6 +205,7 @@ pub fn container_main_process(container_args: &ContainerArgs) -> Result<Pid> {
                     init_pid,
                     &mut init_sender,
                 )?;
+                recorder.record(Phase::Hooks, hooks_start);
             }
             InitRequest::Network => {
                 let linux = container_args
@@ -196,7 +213,12 @@ pub fn container_main_process(container_args: &ContainerArgs) -> Result<Pid> {
                     .linux()
                     .as_ref()
                     .expect("pending network device setup requires linux in spec");
-                handle_setup_network_device(linux, init_pid, &mut init_sender)?;
+                handle_setup_network_device(
+                    linux,
+                    init_pid,
+                    container_args.container.as_ref(),
+                    &mut init_sender,
+                )?;
             }
             InitRequest::Seccomp => {
                 #[cfg(feature = "libseccomp")]
@@ -277,7 +299,8 @@ pub fn container_main_process(container_args: &ContainerArgs) -> Result<Pid> {
         Err(err) => return Err(ProcessError::WaitIntermediateProcess(err)),
     };
 
-    Ok(init_pid)
+    startup_timings.extend(recorder.into_phases());
+    Ok((init_pid, startup_timings))
 }
 
 /// One-shot init-side setup requests.
@@ -328,7 +351,7 @@ impl TryFrom<&Message> for InitRequest {
             Message::HookRequest => Ok(Self::Hooks),
             Message::SetupNetworkDeviceReady => Ok(Self::Network),
             Message::SeccompNotify => Ok(Self::Seccomp),
-            Message::InitReady => Ok(Self::Ready),
+            Message::InitReady(_) => Ok(Self::Ready),
             _ => Err(unexpected_init_message(message)),
         }
     }
@@ -528,10 +551,11 @@ fn build_timens_offsets(linux: &Linux) -> Option<String> {
 
 /// Moves configured network devices from the host to the container's network namespace.
 /// This runs after the init process has joined its namespace, then transfers each
-/// configured device while preserving network addresses.
+/// configured device while preserving its addresses, routes and link attributes.
 fn handle_setup_network_device(
     linux: &Linux,
     init_pid: Pid,
+    container: Option<&Container>,
     init_sender: &mut channel::InitSender,
 ) -> Result<()> {
     // Builder validation should make these cases unreachable. Return an error
@@ -576,17 +600,27 @@ fn handle_setup_network_device(
     // The runtime spec requires that the kernel handles moving back any devices
     // that were successfully moved before the failure occurred.
     // See: https://github.com/opencontainers/runtime-spec/blob/27cb0027fd92ef81eda1ea3a8153b8337f56d94a/config-linux.md#namespace-lifecycle-and-container-termination
-    let addrs_map = devices
+    let configs = devices
         .iter()
         .map(|(name, net_dev)| {
-            let addrs = dev_change_net_namespace(name, netns_fd, net_dev).map_err(|err| {
+            let config = dev_change_net_namespace(name, netns_fd, net_dev).map_err(|err| {
                 tracing::error!("failed to dev_change_net_namespace: {}", err);
                 err
             })?;
-            Ok((name.clone(), addrs))
+            Ok((name.clone(), config))
         })
-        .collect::<Result<HashMap<String, Vec<crate::network::cidr::CidrAddress>>>>()?;
-    init_sender.move_network_device(addrs_map)?;
+        .collect::<Result<HashMap<String, NetDeviceConfig>>>()?;
+
+    // Devices moved into a network namespace that outlives the container are
+    // moved back on delete, with the configuration they had on the host.
+    if let Some(container) = container {
+        let mut config = YoukiConfig::load(&container.root)?;
+        if config.net_devices.is_some() {
+            config.net_device_configs = configs.clone();
+            config.save(&container.root)?;
+        }
+    }
+    init_sender.move_network_device(configs)?;
     Ok(())
 }
 
@@ -621,7 +655,7 @@ mod tests {
             InitRequest::Seccomp
         );
         assert_eq!(
-            sequence.accept(&Message::InitReady).unwrap(),
+            sequence.accept(&Message::InitReady(vec![])).unwrap(),
             InitRequest::Ready
         );
     }
@@ -643,7 +677,7 @@ mod tests {
             InitRequest::Seccomp
         );
         assert_eq!(
-            sequence.accept(&Message::InitReady).unwrap(),
+            sequence.accept(&Message::InitReady(vec![])).unwrap(),
             InitRequest::Ready
         );
     }
@@ -657,7 +691,7 @@ mod tests {
             InitRequest::Network
         );
         assert_eq!(
-            sequence.accept(&Message::InitReady).unwrap(),
+            sequence.accept(&Message::InitReady(vec![])).unwrap(),
             InitRequest::Ready
         );
     }
@@ -667,7 +701,7 @@ mod tests {
         let mut sequence = InitRequestSequence::from_requirements(false, false, false);
 
         assert_eq!(
-            sequence.accept(&Message::InitReady).unwrap(),
+            sequence.accept(&Message::InitReady(vec![])).unwrap(),
             InitRequest::Ready
         );
     }
@@ -699,7 +733,7 @@ mod tests {
     fn init_request_sequence_rejects_ready_while_setup_is_pending() {
         let mut sequence = InitRequestSequence::from_requirements(false, true, false);
 
-        let err = sequence.accept(&Message::InitReady).unwrap_err();
+        let err = sequence.accept(&Message::InitReady(vec![])).unwrap_err();
         assert!(matches!(
             err,
             channel::ChannelError::UnexpectedInitMessage(_)
@@ -710,7 +744,7 @@ mod tests {
     fn init_request_sequence_rejects_ready_while_seccomp_is_pending() {
         let mut sequence = InitRequestSequence::from_requirements(false, false, true);
 
-        let err = sequence.accept(&Message::InitReady).unwrap_err();
+        let err = sequence.accept(&Message::InitReady(vec![])).unwrap_err();
         assert!(matches!(
             err,
             channel::ChannelError::UnexpectedInitMessage(_)
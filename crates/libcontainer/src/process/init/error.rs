@@ -22,6 +22,8 @@ pub enum InitProcessError {
     SetHostname(#[source] SyscallError),
     #[error("failed to set domainname")]
     SetDomainname(#[source] SyscallError),
+    #[error("failed to setup session keyring")]
+    SessionKeyring(#[source] SyscallError),
     #[error("failed to reopen /dev/null")]
     ReopenDevNull(#[source] std::io::Error),
     #[error("failed to unix syscall")]
@@ -25,7 +25,8 @@ use crate::error::MissingSpecError;
 use crate::namespaces::Namespaces;
 use crate::network::address::AddressClient;
 use crate::network::link::LinkClient;
-use crate::network::network_device::{resolve_device_name, setup_addresses_in_network_namespace};
+use crate::network::network_device::{configure_network_device, resolve_device_name};
+use crate::network::route::RouteClient;
 use crate::network::wrapper::create_network_client;
 use crate::process::args::{ContainerArgs, ContainerType};
 use crate::process::{channel, memory_policy};
@@ -34,6 +35,7 @@ use crate::rootfs::device::{open_device_fd, verify_dev_null};
 #[cfg(feature = "libseccomp")]
 use crate::seccomp;
 use crate::syscall::{Syscall, SyscallError};
+use crate::timings::{self, Phase, Recorder};
 use crate::user_ns::UserNamespaceConfig;
 use crate::{apparmor, capabilities, hooks, tty, utils};
 
@@ -44,13 +46,27 @@ pub fn container_init_process(
     main_sender: &mut channel::MainSender,
     init_receiver: &mut channel::InitReceiver,
 ) -> Result<()> {
+    let init_span = tracing::info_span!("init", container_id = %args.container_id).entered();
     let mut ctx = InitContext::try_from(args)?;
+    // The init process is in the time namespace of the container, whose
+    // monotonic clock may be offset from the one of the host.
+    let mut recorder = Recorder::new(timings::Process::Init)
+        .with_clock_offset(timings::monotonic_offset(ctx.linux));
 
     setsid().map_err(|err| {
         tracing::error!(?err, "failed to setsid to create a session");
         InitProcessError::NixOther(err)
     })?;
 
+    if !args.no_new_keyring {
+        setup_session_keyring(
+            ctx.syscall.as_ref(),
+            &args.container_id,
+            args.container_type,
+            ctx.ns.get(LinuxNamespaceType::User)?.is_some(),
+        )?;
+    }
+
     set_io_priority(ctx.syscall.as_ref(), ctx.process.io_priority())?;
 
     setup_scheduler(ctx.process.scheduler())?;
@@ -83,17 +99,21 @@ pub fn container_init_process(
         let in_user_ns = utils::is_in_new_userns().map_err(InitProcessError::Io)?;
         let bind_service = ctx.ns.get(LinuxNamespaceType::User)?.is_some() || in_user_ns;
         let rootfs = RootFS::new();
+        let mounts_start = recorder.start();
         rootfs
             .prepare_rootfs(
                 ctx.spec,
                 ctx.rootfs,
                 bind_service,
                 ctx.ns.get(LinuxNamespaceType::Cgroup)?.is_some(),
+                args.cgroup_config.cgroup_root.as_deref(),
+                &mut recorder,
             )
             .map_err(|err| {
                 tracing::error!(?err, "failed to prepare rootfs");
                 InitProcessError::RootFS(err)
             })?;
+        recorder.record(Phase::RootfsMounts, mounts_start);
 
         if let Some(hooks) = ctx.hooks {
             // send a request to the main process to run prestart and create_runtime hooks.
@@ -104,6 +124,7 @@ pub fn container_init_process(
 
             // create_container hook needs to be called after the namespace setup, but
             // before pivot_root is called. This runs in the container namespaces.
+            let hooks_start = recorder.start();
             hooks::run_hooks(
                 hooks.create_container().as_ref(),
                 ctx.container.map(|c| &c.state),
@@ -115,13 +136,16 @@ pub fn container_init_process(
                 tracing::error!(?err, "failed to run create container hooks");
                 InitProcessError::Hooks(err)
             })?;
+            recorder.record(Phase::Hooks, hooks_start);
         }
 
         // Entering into the rootfs jail. If mount namespace is specified, then
         // we use pivot_root, but if we are on the host mount namespace, we will
         // use simple chroot. Scary things will happen if you try to pivot_root
         // in the host mount namespace...
+        let pivot_root_start = recorder.start();
         do_pivot_root(ctx.syscall.as_ref(), &ctx.ns, args.no_pivot, ctx.rootfs)?;
+        recorder.record(Phase::PivotRoot, pivot_root_start);
 
         // As we have changed the root mount, from here on
         // logs are no longer visible in journalctl
@@ -354,10 +378,12 @@ pub fn container_init_process(
     #[cfg(feature = "libseccomp")]
     if let Some(seccomp) = ctx.linux.seccomp() {
         if ctx.process.no_new_privileges().is_none() {
+            let seccomp_start = recorder.start();
             let notify_fd = seccomp::initialize_seccomp(seccomp).map_err(|err| {
                 tracing::error!(?err, "failed to initialize seccomp");
                 err
             })?;
+            recorder.record(Phase::SeccompLoad, seccomp_start);
             sync_seccomp(notify_fd, main_sender, init_receiver).map_err(|err| {
                 tracing::error!(?err, "failed to sync seccomp");
                 err
@@ -401,10 +427,12 @@ pub fn container_init_process(
     #[cfg(feature = "libseccomp")]
     if let Some(seccomp) = ctx.linux.seccomp() {
         if ctx.process.no_new_privileges().is_some() {
+            let seccomp_start = recorder.start();
             let notify_fd = seccomp::initialize_seccomp(seccomp).map_err(|err| {
                 tracing::error!(?err, "failed to initialize seccomp");
                 err
             })?;
+            recorder.record(Phase::SeccompLoad, seccomp_start);
             sync_seccomp(notify_fd, main_sender, init_receiver).map_err(|err| {
                 tracing::error!(?err, "failed to sync seccomp");
                 err
@@ -432,34 +460,43 @@ pub fn container_init_process(
     // payload.  Note, because we are already inside the pid namespace, the pid
     // outside the pid namespace should be recorded by the intermediate process
     // already.
-    main_sender.init_ready().map_err(|err| {
-        tracing::error!(
-            ?err,
-            "failed to notify main process that init process is ready"
-        );
-        InitProcessError::Channel(err)
-    })?;
+    main_sender
+        .init_ready(recorder.into_phases())
+        .map_err(|err| {
+            tracing::error!(
+                ?err,
+                "failed to notify main process that init process is ready"
+            );
+            InitProcessError::Channel(err)
+        })?;
     main_sender.close().map_err(|err| {
         tracing::error!(?err, "failed to close down main sender in init process");
         InitProcessError::Channel(err)
     })?;
 
     // listing on the notify socket for container start command
-    ctx.notify_listener
+    let start_connection = ctx
+        .notify_listener
         .wait_for_container_start()
         .map_err(|err| {
             tracing::error!(?err, "failed to wait for container start");
             err
         })?;
+    let mut recorder = Recorder::new(timings::Process::Init)
+        .with_clock_offset(timings::monotonic_offset(ctx.linux));
+    let exec_start = recorder.start();
     ctx.notify_listener.close().map_err(|err| {
         tracing::error!(?err, "failed to close notify socket");
         err
     })?;
 
+    let exec_span = tracing::info_span!("exec").entered();
+
     // start_container hook needs to be called after the namespace setup, but
     // before pivot_root is called. This runs in the container namespaces.
     if matches!(args.container_type, ContainerType::InitContainer) {
         if let Some(hooks) = ctx.hooks {
+            let hooks_start = recorder.start();
             hooks::run_hooks(
                 hooks.start_container().as_ref(),
                 ctx.container.map(|c| &c.state),
@@ -471,6 +508,7 @@ pub fn container_init_process(
                 tracing::error!(?err, "failed to run start container hooks");
                 err
             })?;
+            recorder.record(Phase::Hooks, hooks_start);
         }
     }
 
@@ -479,6 +517,19 @@ pub fn container_init_process(
         Err(MissingSpecError::Args)?;
     }
 
+    // Spans are reported when they close, the spans of the init process have
+    // to close before the payload replaces it.
+    drop(exec_span);
+    drop(init_span);
+
+    // The start command waits for the reply, which also carries the phases
+    // recorded since the start.
+    recorder.record(Phase::Exec, exec_start);
+    let reply = serde_json::to_vec(&recorder.into_phases()).unwrap_or_default();
+    if let Err(err) = start_connection.reply(&reply) {
+        tracing::warn!(?err, "failed to reply to the start command");
+    }
+
     args.executor.exec(ctx.spec).map_err(|err| {
         tracing::error!(?err, "failed to execute payload");
         err
@@ -659,6 +710,53 @@ fn apply_rest_namespaces(
     Ok(())
 }
 
+/// Joins the session keyring `_ses.<container id>`, so that keys of the
+/// caller are not shared with the container. The init process creates the
+/// keyring and processes executed later in the container join the same one.
+fn setup_session_keyring(
+    syscall: &dyn Syscall,
+    container_id: &str,
+    container_type: ContainerType,
+    with_user_ns: bool,
+) -> Result<()> {
+    const KEY_USR_SEARCH: u32 = 0x0008_0000;
+    const KEY_OTH_SEARCH: u32 = 0x0000_0008;
+
+    let name = format!("_ses.{container_id}");
+    match syscall.join_session_keyring(&name) {
+        Ok(()) => {}
+        // Keyrings may be disabled in the kernel or the keyctl syscall may be
+        // blocked by seccomp, e.g. when youki itself runs in a container.
+        Err(SyscallError::Nix(nix::errno::Errno::ENOSYS)) => {
+            tracing::warn!("keyrings are not supported, keep the session keyring of the caller");
+            return Ok(());
+        }
+        Err(err) => {
+            tracing::error!(?err, ?name, "failed to join session keyring");
+            return Err(InitProcessError::SessionKeyring(err));
+        }
+    }
+
+    if matches!(container_type, ContainerType::InitContainer) {
+        // The container processes must be able to search the keyring. Inside
+        // a user namespace they are not the owner of the keyring, so they
+        // need the search permission for 'other'.
+        let new_perms = if with_user_ns {
+            KEY_OTH_SEARCH
+        } else {
+            KEY_USR_SEARCH
+        };
+        syscall
+            .set_session_keyring_perm(u32::MAX, new_perms)
+            .map_err(|err| {
+                tracing::error!(?err, ?name, "failed to set session keyring permissions");
+                InitProcessError::SessionKeyring(err)
+            })?;
+    }
+
+    Ok(())
+}
+
 fn reopen_dev_null() -> Result<()> {
     // At this point we should be inside of the container and now
     // we can re-open /dev/null if it is in use to the /dev/null
@@ -742,6 +840,7 @@ fn move_root(syscall: &dyn Syscall, rootfs: &Path) -> Result<()> {
     Ok(())
 }
 
+#[tracing::instrument(level = "info", name = "pivot_root", skip_all)]
 fn do_pivot_root(
     syscall: &dyn Syscall,
     namespaces: &Namespaces,
@@ -973,9 +1072,9 @@ fn configure_container_network_devices(
 
     main_sender.network_setup_ready()?;
 
-    let addrs_map = init_receiver.wait_for_move_network_device()?;
+    let configs = init_receiver.wait_for_move_network_device()?;
     for (name, net_dev) in net_device {
-        if let Some(cidr_addrs) = addrs_map.get(name) {
+        if let Some(config) = configs.get(name) {
             // Get the device's final name (use configured name if provided, otherwise use original name)
             let new_name = resolve_device_name(net_dev, name.as_str());
 
@@ -988,30 +1087,19 @@ fn configure_container_network_devices(
                 tracing::error!(?err, "failed to create address client");
                 err
             })?;
-
-            // Get the device index
-            let ns_link = link_client.get_by_name(new_name).map_err(|err| {
-                tracing::error!(?err, "failed to get device by name: {}", new_name);
+            let mut route_client = RouteClient::new(create_network_client()).map_err(|err| {
+                tracing::error!(?err, "failed to create route client");
                 err
             })?;
 
-            // Assign IP addresses to the device
-            setup_addresses_in_network_namespace(
-                cidr_addrs,
-                ns_link.header.index,
+            // Restore link attributes, addresses and routes, and bring the device up
+            configure_network_device(
+                config,
                 new_name,
+                &mut link_client,
                 &mut addr_client,
-            )
-            .map_err(|err| {
-                tracing::error!(?err, "failed to setup addresses for device: {}", new_name);
-                err
-            })?;
-
-            // Bring the device up
-            link_client.set_up(ns_link.header.index).map_err(|err| {
-                tracing::error!(?err, "failed to bring up device: {}", new_name);
-                err
-            })?;
+                &mut route_client,
+            )?;
         }
     }
 
@@ -1073,7 +1161,9 @@ mod tests {
 
     use super::*;
     use crate::syscall::syscall::create_syscall;
-    use crate::syscall::test::{ArgName, IoPriorityArgs, MountArgs, TestHelperSyscall};
+    use crate::syscall::test::{
+        ArgName, IoPriorityArgs, KeyringPermArgs, MountArgs, TestHelperSyscall,
+    };
 
     #[test]
     fn test_readonly_path() -> Result<()> {
@@ -1112,6 +1202,71 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_setup_session_keyring() -> Result<()> {
+        let syscall = create_syscall();
+        setup_session_keyring(
+            syscall.as_ref(),
+            "74f1a4cb3801",
+            ContainerType::InitContainer,
+            true,
+        )?;
+        setup_session_keyring(
+            syscall.as_ref(),
+            "74f1a4cb3801",
+            ContainerType::TenantContainer { exec_notify_fd: -1 },
+            true,
+        )?;
+
+        let mocks = syscall
+            .as_any()
+            .downcast_ref::<TestHelperSyscall>()
+            .unwrap();
+        assert_eq!(
+            mocks.get_session_keyring_args(),
+            vec!["_ses.74f1a4cb3801", "_ses.74f1a4cb3801"]
+        );
+        // only the init process changes the permissions of the keyring
+        assert_eq!(
+            mocks.get_keyring_perm_args(),
+            vec![KeyringPermArgs {
+                keep_perms: u32::MAX,
+                new_perms: 0x8,
+            }]
+        );
+
+        // keyrings not being available is not an error
+        let syscall = create_syscall();
+        let mocks = syscall
+            .as_any()
+            .downcast_ref::<TestHelperSyscall>()
+            .unwrap();
+        mocks.set_ret_err(ArgName::SessionKeyring, || {
+            Err(SyscallError::Nix(nix::errno::Errno::ENOSYS))
+        });
+        setup_session_keyring(
+            syscall.as_ref(),
+            "74f1a4cb3801",
+            ContainerType::InitContainer,
+            false,
+        )?;
+        assert!(mocks.get_keyring_perm_args().is_empty());
+
+        mocks.set_ret_err(ArgName::SessionKeyring, || {
+            Err(SyscallError::Nix(nix::errno::Errno::EACCES))
+        });
+        assert!(
+            setup_session_keyring(
+                syscall.as_ref(),
+                "74f1a4cb3801",
+                ContainerType::InitContainer,
+                false,
+            )
+            .is_err()
+        );
+        Ok(())
+    }
+
     #[test]
     fn test_apply_rest_namespaces() -> Result<()> {
         let syscall = create_syscall();
@@ -5,13 +5,14 @@ use std::path::PathBuf;
 use oci_spec::runtime::LinuxIdMapping;
 use serde::{Deserialize, Serialize};
 
-use crate::network::cidr::CidrAddress;
+use crate::network::network_device::NetDeviceConfig;
+use crate::timings::PhaseTiming;
 
 /// Used as a wrapper for messages to be sent between child and parent processes
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub enum Message {
-    IntermediateReady(i32),
-    InitReady,
+    IntermediateReady(i32, Vec<PhaseTiming>),
+    InitReady(Vec<PhaseTiming>),
     WriteMapping,
     MappingWritten,
     WriteTimeOffsets,
@@ -19,7 +20,7 @@ pub enum Message {
     SeccompNotify,
     SeccompNotifyDone,
     SetupNetworkDeviceReady,
-    MoveNetworkDevice(HashMap<String, Vec<CidrAddress>>),
+    MoveNetworkDevice(HashMap<String, NetDeviceConfig>),
     AskMountFd(MountMsg),
     MountFdReply,
     ExecFailed(String),
@@ -32,8 +33,8 @@ pub enum Message {
 impl fmt::Display for Message {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Message::IntermediateReady(pid) => write!(f, "IntermediateReady({})", pid),
-            Message::InitReady => write!(f, "InitReady"),
+            Message::IntermediateReady(pid, _) => write!(f, "IntermediateReady({})", pid),
+            Message::InitReady(_) => write!(f, "InitReady"),
             Message::WriteMapping => write!(f, "WriteMapping"),
             Message::MappingWritten => write!(f, "MappingWritten"),
             Message::SetupNetworkDeviceReady => write!(f, "SetupNetworkDeviceReady"),
@@ -0,0 +1,142 @@
+//! Restart policy of a container, applied by the monitor process reaping the
+//! container process once it exits. The policy is set by an annotation of the
+//! bundle, since the OCI spec has no notion of restarting a container.
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Annotation holding the restart policy: `no`, `on-failure[:max]` or `always`
+pub const RESTART_POLICY_ANNOTATION: &str = "io.github.youki-dev.youki.restart";
+
+/// A container running at least this long before it exits is restarted
+/// without backoff
+pub const BACKOFF_RESET: Duration = Duration::from_secs(10);
+
+const BACKOFF_INITIAL: Duration = Duration::from_millis(100);
+const BACKOFF_MAX: Duration = Duration::from_secs(60);
+
+#[derive(Debug, thiserror::Error)]
+pub enum RestartPolicyError {
+    #[error("invalid restart policy {0:?}, expected no, on-failure[:max] or always")]
+    Invalid(String),
+}
+
+type Result<T> = std::result::Result<T, RestartPolicyError>;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RestartPolicy {
+    /// Never restart the container
+    #[default]
+    No,
+    /// Restart the container when its process exits with a non-zero code,
+    /// at most `max_retries` times if set
+    OnFailure { max_retries: Option<u32> },
+    /// Restart the container whenever its process exits
+    Always,
+}
+
+impl RestartPolicy {
+    /// Reads the policy from the annotations of a container, `No` if unset
+    pub fn from_annotations(annotations: &HashMap<String, String>) -> Result<Self> {
+        annotations
+            .get(RESTART_POLICY_ANNOTATION)
+            .map_or(Ok(Self::No), |policy| policy.parse())
+    }
+
+    /// Whether a container whose process exited with `exit_code`, and which
+    /// was already restarted `restart_count` times, has to be restarted
+    pub fn should_restart(&self, exit_code: i32, restart_count: u32) -> bool {
+        match self {
+            Self::No => false,
+            Self::OnFailure { max_retries } => {
+                exit_code != 0 && max_retries.is_none_or(|max| restart_count < max)
+            }
+            Self::Always => true,
+        }
+    }
+}
+
+/// Delay before restarting a container after `failures` quick exits in a
+/// row, doubling from 100ms up to a minute
+pub fn backoff(failures: u32) -> Duration {
+    BACKOFF_INITIAL
+        .checked_mul(1 << failures.min(16))
+        .map_or(BACKOFF_MAX, |delay| delay.min(BACKOFF_MAX))
+}
+
+impl FromStr for RestartPolicy {
+    type Err = RestartPolicyError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.split_once(':') {
+            None if s == "no" => Ok(Self::No),
+            None if s == "on-failure" => Ok(Self::OnFailure { max_retries: None }),
+            None if s == "always" => Ok(Self::Always),
+            Some(("on-failure", max)) => max
+                .parse()
+                .map(|max| Self::OnFailure {
+                    max_retries: Some(max),
+                })
+                .map_err(|_| RestartPolicyError::Invalid(s.to_owned())),
+            _ => Err(RestartPolicyError::Invalid(s.to_owned())),
+        }
+    }
+}
+
+impl Display for RestartPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::No => write!(f, "no"),
+            Self::OnFailure { max_retries: None } => write!(f, "on-failure"),
+            Self::OnFailure {
+                max_retries: Some(max),
+            } => write!(f, "on-failure:{max}"),
+            Self::Always => write!(f, "always"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use super::*;
+
+    #[test]
+    fn test_restart_policy() -> Result<()> {
+        assert_eq!(
+            RestartPolicy::from_annotations(&HashMap::new())?,
+            RestartPolicy::No
+        );
+
+        let annotations = HashMap::from([(
+            RESTART_POLICY_ANNOTATION.to_owned(),
+            "on-failure".to_owned(),
+        )]);
+        let policy = RestartPolicy::from_annotations(&annotations)?;
+        assert_eq!(policy, RestartPolicy::OnFailure { max_retries: None });
+        assert!(policy.should_restart(137, 100));
+        assert!(!policy.should_restart(0, 0));
+
+        let policy: RestartPolicy = "on-failure:2".parse()?;
+        assert_eq!(policy.to_string(), "on-failure:2");
+        assert!(policy.should_restart(1, 1));
+        assert!(!policy.should_restart(1, 2));
+
+        assert!(RestartPolicy::Always.should_restart(0, 0));
+        assert!(!RestartPolicy::No.should_restart(1, 0));
+        for invalid in ["sometimes", "on-failure:", "on-failure:-1", "always:3"] {
+            assert!(invalid.parse::<RestartPolicy>().is_err(), "{invalid}");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_backoff() {
+        assert_eq!(backoff(0), Duration::from_millis(100));
+        assert_eq!(backoff(3), Duration::from_millis(800));
+        assert_eq!(backoff(10), BACKOFF_MAX);
+        assert_eq!(backoff(u32::MAX), BACKOFF_MAX);
+    }
+}
@@ -9,7 +9,6 @@ use std::{borrow::Cow, collections::HashMap};
 use std::{fs, mem};
 
 use libcgroups::common::CgroupSetup::{Hybrid, Legacy, Unified};
-#[cfg(feature = "v1")]
 use libcgroups::common::DEFAULT_CGROUP_ROOT;
 use nix::NixPath;
 use nix::errno::Errno;
@@ -108,6 +107,8 @@ pub struct MountOptions<'a> {
     pub label: Option<&'a str>,
     #[allow(dead_code)]
     pub cgroup_ns: bool,
+    /// Mount point of the cgroup hierarchy on the host, /sys/fs/cgroup if None
+    pub cgroup_root: Option<&'a Path>,
 }
 
 pub struct Mount {
@@ -140,10 +141,14 @@ impl Mount {
 
         match mount.typ().as_deref() {
             Some("cgroup") => {
-                let cgroup_setup = libcgroups::common::get_cgroup_setup().map_err(|err| {
-                    tracing::error!("failed to determine cgroup setup: {}", err);
-                    MountError::Other(err.into())
-                })?;
+                let cgroup_root = options
+                    .cgroup_root
+                    .unwrap_or(Path::new(DEFAULT_CGROUP_ROOT));
+                let cgroup_setup = libcgroups::common::get_cgroup_setup_with_root(cgroup_root)
+                    .map_err(|err| {
+                        tracing::error!("failed to determine cgroup setup: {}", err);
+                        MountError::Other(err.into())
+                    })?;
                 match cgroup_setup {
                     Legacy | Hybrid => {
                         #[cfg(not(feature = "v1"))]
@@ -267,13 +272,16 @@ impl Mount {
         })?;
 
         // get all cgroup mounts on the host system
+        let host_root = options
+            .cgroup_root
+            .unwrap_or(Path::new(DEFAULT_CGROUP_ROOT));
         let host_mounts: Vec<PathBuf> = libcgroups::v1::util::list_subsystem_mount_points()
             .map_err(|err| {
                 tracing::error!("failed to get subsystem mount points: {}", err);
                 MountError::Other(err.into())
             })?
             .into_iter()
-            .filter(|p| p.as_path().starts_with(DEFAULT_CGROUP_ROOT))
+            .filter(|p| p.as_path().starts_with(host_root))
             .collect();
         tracing::debug!("cgroup mounts: {:?}", host_mounts);
 
@@ -1412,6 +1420,7 @@ mod tests {
             root: tmp.path(),
             label: None,
             cgroup_ns: true,
+            cgroup_root: None,
         };
 
         let mounter = Mount::new();
@@ -1573,6 +1582,7 @@ mod tests {
             root: rootfs,
             label: None,
             cgroup_ns: true,
+            cgroup_root: None,
         };
 
         let m = Mount::new();
@@ -1608,6 +1618,7 @@ mod tests {
             root: rootfs,
             label: None,
             cgroup_ns: true,
+            cgroup_root: None,
         };
 
         let m = Mount::new();
@@ -12,6 +12,7 @@ use super::{Result, RootfsError};
 use crate::error::MissingSpecError;
 use crate::syscall::Syscall;
 use crate::syscall::syscall::create_syscall;
+use crate::timings::{Phase, Recorder};
 
 /// Holds information about rootfs
 pub struct RootFS {
@@ -37,6 +38,7 @@ impl RootFS {
         spec: &Spec,
         rootfs: &Path,
         cgroup_ns: bool,
+        cgroup_root: Option<&Path>,
     ) -> Result<()> {
         let mut flags = MsFlags::MS_REC;
         match linux.rootfs_propagation().as_deref() {
@@ -82,6 +84,7 @@ impl RootFS {
             root: rootfs,
             label: linux.mount_label().as_deref(),
             cgroup_ns,
+            cgroup_root,
         };
 
         if let Some(mounts) = spec.mounts() {
@@ -92,22 +95,26 @@ impl RootFS {
         Ok(())
     }
 
+    #[tracing::instrument(level = "info", name = "mounts", skip_all)]
     pub fn prepare_rootfs(
         &self,
         spec: &Spec,
         rootfs: &Path,
         bind_devices: bool,
         cgroup_ns: bool,
+        cgroup_root: Option<&Path>,
+        recorder: &mut Recorder,
     ) -> Result<()> {
         tracing::debug!(?rootfs, "prepare rootfs");
         let linux = spec.linux().as_ref().ok_or(MissingSpecError::Linux)?;
 
-        self.mount_to_rootfs(linux, spec, rootfs, cgroup_ns)?;
+        self.mount_to_rootfs(linux, spec, rootfs, cgroup_ns, cgroup_root)?;
 
         let symlinker = Symlink::new();
         symlinker.setup_kcore_symlink(rootfs)?;
         symlinker.setup_default_symlinks(rootfs)?;
 
+        let devices_start = recorder.start();
         let devicer = Device::new();
         if let Some(added_devices) = linux.devices() {
             let mut path_set = HashSet::new();
@@ -120,6 +127,7 @@ impl RootFS {
         } else {
             devicer.create_devices(rootfs, &default_devices(), bind_devices)
         }?;
+        recorder.record(Phase::Devices, devices_start);
 
         symlinker.setup_ptmx(rootfs)?;
         Ok(())
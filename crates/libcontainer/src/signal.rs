@@ -5,7 +5,7 @@ use std::convert::TryFrom;
 use nix::sys::signal::Signal as NixSignal;
 
 /// POSIX Signal
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Signal(NixSignal);
 
 #[derive(Debug, thiserror::Error)]
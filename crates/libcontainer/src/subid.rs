@@ -0,0 +1,483 @@
+//! Allocation of subordinate id ranges for containers in a new user
+//! namespace. A container opts in with the [`AUTO_USERNS_ANNOTATION`]
+//! annotation, and gets uid and gid ranges of the subordinate ids that
+//! `/etc/subuid` and `/etc/subgid` grant to its owner. The ranges leased to
+//! containers are recorded in the root directory, so that containers of the
+//! same root directory never share host ids.
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+
+use nix::fcntl::{Flock, FlockArg};
+use nix::unistd::Uid;
+use oci_spec::runtime::{LinuxIdMapping, LinuxIdMappingBuilder, LinuxNamespaceType, Spec};
+use serde::{Deserialize, Serialize};
+
+use crate::utils;
+
+/// Annotation asking for subordinate id ranges to be allocated. Its value is
+/// either `true`, for ranges of [`DEFAULT_RANGE_SIZE`] ids, or the number of
+/// ids of the ranges.
+pub const AUTO_USERNS_ANNOTATION: &str = "io.github.youki-dev.youki.userns.auto";
+/// Number of ids allocated to a container when no size is requested
+pub const DEFAULT_RANGE_SIZE: u32 = 65536;
+
+const SUBUID_PATH: &str = "/etc/subuid";
+const SUBGID_PATH: &str = "/etc/subgid";
+// Container ids cannot contain '@', so the leases never clash with the
+// directory of a container.
+const LEASES_FILE: &str = "@subid-leases.json";
+const LEASES_LOCK_FILE: &str = "@subid-leases.lock";
+
+#[derive(Debug, thiserror::Error)]
+pub enum SubIdError {
+    #[error("invalid value of {AUTO_USERNS_ANNOTATION} annotation: {0}")]
+    InvalidSize(String),
+    #[error("automatic subordinate ids require a new user namespace")]
+    NoUserNamespace,
+    #[error("automatic subordinate ids cannot be combined with uid or gid mappings of the spec")]
+    MappingsGiven,
+    #[error("failed to read {path}")]
+    Read { source: io::Error, path: PathBuf },
+    #[error("no free range of {size} subordinate ids for {owner} in {path}")]
+    Exhausted {
+        size: u32,
+        owner: String,
+        path: PathBuf,
+    },
+    #[error("failed to lock subordinate id leases")]
+    Lock(#[source] nix::Error),
+    #[error("failed to access subordinate id leases")]
+    Leases(#[source] io::Error),
+    #[error("failed to parse subordinate id leases")]
+    Parse(#[source] serde_json::Error),
+    #[error("oci spec error")]
+    Spec(#[from] oci_spec::OciSpecError),
+}
+
+type Result<T> = std::result::Result<T, SubIdError>;
+
+/// A range of host ids
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct IdRange {
+    pub start: u32,
+    pub size: u32,
+}
+
+impl IdRange {
+    fn end(&self) -> u64 {
+        self.start as u64 + self.size as u64
+    }
+
+    fn mapping(&self) -> Result<LinuxIdMapping> {
+        Ok(LinuxIdMappingBuilder::default()
+            .container_id(0u32)
+            .host_id(self.start)
+            .size(self.size)
+            .build()?)
+    }
+}
+
+/// The ranges of host ids leased to a container
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Lease {
+    pub uid: IdRange,
+    pub gid: IdRange,
+}
+
+impl Lease {
+    /// Maps the ids of the container, from 0, to the leased ranges
+    pub fn apply(&self, spec: &mut Spec) -> Result<()> {
+        if let Some(mut linux) = spec.linux().clone() {
+            linux.set_uid_mappings(Some(vec![self.uid.mapping()?]));
+            linux.set_gid_mappings(Some(vec![self.gid.mapping()?]));
+            spec.set_linux(Some(linux));
+        }
+        Ok(())
+    }
+}
+
+/// Returns the number of ids to allocate if the spec opts in to automatic
+/// subordinate ids, and checks that the spec can use them
+pub fn requested_size(spec: &Spec) -> Result<Option<u32>> {
+    let Some(value) = spec
+        .annotations()
+        .as_ref()
+        .and_then(|a| a.get(AUTO_USERNS_ANNOTATION))
+    else {
+        return Ok(None);
+    };
+    let size = match value.as_str() {
+        "false" => return Ok(None),
+        "true" => DEFAULT_RANGE_SIZE,
+        value => match value.parse() {
+            Ok(size) if size > 0 => size,
+            _ => return Err(SubIdError::InvalidSize(value.to_owned())),
+        },
+    };
+
+    let linux = spec.linux().as_ref();
+    let new_user_ns = linux
+        .and_then(|l| l.namespaces().as_ref())
+        .into_iter()
+        .flatten()
+        .any(|ns| ns.typ() == LinuxNamespaceType::User && ns.path().is_none());
+    if !new_user_ns {
+        return Err(SubIdError::NoUserNamespace);
+    }
+    let has_mappings = linux.is_some_and(|l| {
+        l.uid_mappings().as_ref().is_some_and(|m| !m.is_empty())
+            || l.gid_mappings().as_ref().is_some_and(|m| !m.is_empty())
+    });
+    if has_mappings {
+        return Err(SubIdError::MappingsGiven);
+    }
+
+    Ok(Some(size))
+}
+
+/// Leases subordinate id ranges to the containers of a root directory
+#[derive(Debug, Clone)]
+pub struct SubIdAllocator {
+    root_path: PathBuf,
+    subuid: PathBuf,
+    subgid: PathBuf,
+}
+
+impl SubIdAllocator {
+    pub fn new(root_path: impl Into<PathBuf>) -> Self {
+        Self {
+            root_path: root_path.into(),
+            subuid: PathBuf::from(SUBUID_PATH),
+            subgid: PathBuf::from(SUBGID_PATH),
+        }
+    }
+
+    /// Reads the subordinate ids from other files than `/etc/subuid` and
+    /// `/etc/subgid`
+    pub fn with_subid_files(
+        mut self,
+        subuid: impl Into<PathBuf>,
+        subgid: impl Into<PathBuf>,
+    ) -> Self {
+        self.subuid = subuid.into();
+        self.subgid = subgid.into();
+        self
+    }
+
+    /// Leases `size` uids and gids of `owner` to the container. A container
+    /// that already has a lease keeps it. Leases of containers whose
+    /// directory is gone are released first.
+    pub fn allocate(&self, container_id: &str, owner: Uid, size: u32) -> Result<Lease> {
+        let name = utils::get_unix_user(owner).map(|u| u.name);
+        let owner_ids = |path: &Path| -> Result<Vec<IdRange>> {
+            let content = fs::read_to_string(path).map_err(|source| SubIdError::Read {
+                source,
+                path: path.to_owned(),
+            })?;
+            Ok(parse_subid_file(&content, name.as_deref(), owner))
+        };
+        let uid_ranges = owner_ids(&self.subuid)?;
+        let gid_ranges = owner_ids(&self.subgid)?;
+        let exhausted = |path: &Path| SubIdError::Exhausted {
+            size,
+            owner: name.clone().unwrap_or_else(|| owner.to_string()),
+            path: path.to_owned(),
+        };
+
+        self.with_leases(|leases| {
+            leases.retain(|id, _| id == container_id || self.root_path.join(id).exists());
+            if let Some(lease) = leases.get(container_id) {
+                return Ok(*lease);
+            }
+
+            let used_uids: Vec<_> = leases.values().map(|l| l.uid).collect();
+            let used_gids: Vec<_> = leases.values().map(|l| l.gid).collect();
+            let lease = Lease {
+                uid: find_free_range(&uid_ranges, &used_uids, size)
+                    .ok_or_else(|| exhausted(&self.subuid))?,
+                gid: find_free_range(&gid_ranges, &used_gids, size)
+                    .ok_or_else(|| exhausted(&self.subgid))?,
+            };
+            tracing::debug!(container_id, ?lease, "lease subordinate ids");
+            leases.insert(container_id.to_owned(), lease);
+            Ok(lease)
+        })
+    }
+
+    /// Releases the lease of the container, if it has one
+    pub fn release(&self, container_id: &str) -> Result<()> {
+        if !self.root_path.join(LEASES_FILE).exists() {
+            return Ok(());
+        }
+        self.with_leases(|leases| {
+            if let Some(lease) = leases.remove(container_id) {
+                tracing::debug!(container_id, ?lease, "release subordinate ids");
+            }
+            Ok(())
+        })
+    }
+
+    /// Runs `f` on the leases while holding the lock of the leases, and
+    /// writes them back
+    fn with_leases<T>(
+        &self,
+        f: impl FnOnce(&mut BTreeMap<String, Lease>) -> Result<T>,
+    ) -> Result<T> {
+        // The leases file is replaced on each write, so the lock is taken on
+        // a file of its own.
+        let lock = File::options()
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .mode(0o600)
+            .open(self.root_path.join(LEASES_LOCK_FILE))
+            .map_err(SubIdError::Leases)?;
+        let _lock =
+            Flock::lock(lock, FlockArg::LockExclusive).map_err(|(_, err)| SubIdError::Lock(err))?;
+
+        let path = self.root_path.join(LEASES_FILE);
+        let mut leases = match fs::read_to_string(&path) {
+            Ok(content) if !content.is_empty() => {
+                serde_json::from_str(&content).map_err(SubIdError::Parse)?
+            }
+            Ok(_) => BTreeMap::new(),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(err) => return Err(SubIdError::Leases(err)),
+        };
+
+        let result = f(&mut leases)?;
+
+        // The leases are written aside and renamed, so that a crash while
+        // writing them does not lose the other leases.
+        let content = serde_json::to_vec(&leases).map_err(SubIdError::Parse)?;
+        let tmp_path = path.with_extension("json.tmp");
+        let mut file = File::options()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(&tmp_path)
+            .map_err(SubIdError::Leases)?;
+        file.write_all(&content).map_err(SubIdError::Leases)?;
+        file.sync_all().map_err(SubIdError::Leases)?;
+        fs::rename(&tmp_path, &path).map_err(SubIdError::Leases)?;
+        Ok(result)
+    }
+}
+
+/// Returns the ranges of a subuid or subgid file that belong to the user,
+/// given by name or by id
+fn parse_subid_file(content: &str, name: Option<&str>, uid: Uid) -> Vec<IdRange> {
+    let uid = uid.to_string();
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| {
+            let mut fields = line.split(':');
+            let owner = fields.next()?;
+            let start = fields.next()?.parse().ok()?;
+            let size = fields.next()?.parse().ok()?;
+            (owner == uid || Some(owner) == name).then_some(IdRange { start, size })
+        })
+        .collect()
+}
+
+/// Returns the first `size` ids of the ranges that no used range overlaps
+fn find_free_range(ranges: &[IdRange], used: &[IdRange], size: u32) -> Option<IdRange> {
+    let mut used = used.to_vec();
+    used.sort();
+    for range in ranges {
+        let mut start = range.start as u64;
+        for u in &used {
+            if (u.start as u64) < start + size as u64 && u.end() > start {
+                start = u.end();
+            }
+        }
+        if start + size as u64 <= range.end() {
+            return Some(IdRange {
+                start: start as u32,
+                size,
+            });
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use anyhow::Result;
+    use oci_spec::runtime::{LinuxBuilder, LinuxNamespaceBuilder, SpecBuilder};
+
+    use super::*;
+
+    fn spec(annotation: Option<&str>, mappings: bool) -> Result<Spec> {
+        let mut linux = LinuxBuilder::default().namespaces(vec![
+            LinuxNamespaceBuilder::default()
+                .typ(LinuxNamespaceType::User)
+                .build()?,
+        ]);
+        if mappings {
+            linux = linux.uid_mappings(vec![
+                IdRange {
+                    start: 1000,
+                    size: 1,
+                }
+                .mapping()?,
+            ]);
+        }
+        let mut spec = SpecBuilder::default().linux(linux.build()?);
+        if let Some(value) = annotation {
+            spec = spec.annotations(HashMap::from([(
+                AUTO_USERNS_ANNOTATION.to_owned(),
+                value.to_owned(),
+            )]));
+        }
+        Ok(spec.build()?)
+    }
+
+    #[test]
+    fn test_requested_size() -> Result<()> {
+        assert_eq!(requested_size(&spec(None, false)?)?, None);
+        assert_eq!(requested_size(&spec(Some("false"), false)?)?, None);
+        assert_eq!(
+            requested_size(&spec(Some("true"), false)?)?,
+            Some(DEFAULT_RANGE_SIZE)
+        );
+        assert_eq!(requested_size(&spec(Some("1024"), false)?)?, Some(1024));
+        assert!(matches!(
+            requested_size(&spec(Some("0"), false)?),
+            Err(SubIdError::InvalidSize(_))
+        ));
+        assert!(matches!(
+            requested_size(&spec(Some("true"), true)?),
+            Err(SubIdError::MappingsGiven)
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_subid_file() {
+        let content = "# comment\nalice:100000:65536\n1000:200000:1000\nbob:300000:65536\nbroken\n";
+        assert_eq!(
+            parse_subid_file(content, Some("alice"), Uid::from_raw(1000)),
+            vec![
+                IdRange {
+                    start: 100000,
+                    size: 65536
+                },
+                IdRange {
+                    start: 200000,
+                    size: 1000
+                },
+            ]
+        );
+        assert!(parse_subid_file(content, None, Uid::from_raw(1001)).is_empty());
+    }
+
+    #[test]
+    fn test_find_free_range() {
+        let ranges = [
+            IdRange {
+                start: 100,
+                size: 20,
+            },
+            IdRange {
+                start: 1000,
+                size: 100,
+            },
+        ];
+        assert_eq!(
+            find_free_range(&ranges, &[], 10),
+            Some(IdRange {
+                start: 100,
+                size: 10
+            })
+        );
+        let used = [
+            IdRange {
+                start: 105,
+                size: 10,
+            },
+            IdRange {
+                start: 1000,
+                size: 10,
+            },
+        ];
+        assert_eq!(
+            find_free_range(&ranges, &used, 5),
+            Some(IdRange {
+                start: 100,
+                size: 5
+            })
+        );
+        assert_eq!(
+            find_free_range(&ranges, &used[..1], 6),
+            Some(IdRange {
+                start: 1000,
+                size: 6
+            })
+        );
+        assert_eq!(
+            find_free_range(&ranges, &used, 10),
+            Some(IdRange {
+                start: 1010,
+                size: 10
+            })
+        );
+        assert_eq!(find_free_range(&ranges, &used, 100), None);
+    }
+
+    #[test]
+    fn test_allocate_and_release() -> Result<()> {
+        let root = tempfile::tempdir()?;
+        let subid = root.path().join("subid");
+        fs::write(&subid, "0:100000:200\n")?;
+        let allocator = SubIdAllocator::new(root.path()).with_subid_files(&subid, &subid);
+        let owner = Uid::from_raw(0);
+        for id in ["a", "b", "c"] {
+            fs::create_dir(root.path().join(id))?;
+        }
+
+        let a = allocator.allocate("a", owner, 100)?;
+        assert_eq!(
+            a.uid,
+            IdRange {
+                start: 100000,
+                size: 100
+            }
+        );
+        assert_eq!(allocator.allocate("a", owner, 100)?, a);
+        let b = allocator.allocate("b", owner, 100)?;
+        assert_eq!(
+            b.gid,
+            IdRange {
+                start: 100100,
+                size: 100
+            }
+        );
+        assert!(matches!(
+            allocator.allocate("c", owner, 100),
+            Err(SubIdError::Exhausted { .. })
+        ));
+
+        allocator.release("a")?;
+        assert_eq!(allocator.allocate("c", owner, 100)?, a);
+
+        // the lease of a removed container is stale
+        fs::remove_dir(root.path().join("b"))?;
+        assert_eq!(allocator.allocate("d", owner, 100)?, b);
+
+        // the leases are replaced as a whole
+        assert!(!root.path().join("@subid-leases.json.tmp").exists());
+        let leases: BTreeMap<String, Lease> =
+            serde_json::from_str(&fs::read_to_string(root.path().join(LEASES_FILE))?)?;
+        assert_eq!(leases.keys().collect::<Vec<_>>(), vec!["c", "d"]);
+        Ok(())
+    }
+}
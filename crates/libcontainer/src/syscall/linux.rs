@@ -1023,6 +1023,68 @@ impl Syscall for LinuxSyscall {
             .map(|_| ())
             .map_err(|e| e.into())
     }
+
+    /// Joins the named session keyring, creating it if it does not exist yet.
+    fn join_session_keyring(&self, name: &str) -> Result<()> {
+        let name = CString::new(name).map_err(|_| nix::Error::EINVAL)?;
+        keyctl(
+            libc::KEYCTL_JOIN_SESSION_KEYRING,
+            name.as_ptr() as libc::c_ulong,
+            0,
+            0,
+        )?;
+        Ok(())
+    }
+
+    /// Changes the permissions of the current session keyring to
+    /// `(perm & keep_perms) | new_perms`.
+    fn set_session_keyring_perm(&self, keep_perms: u32, new_perms: u32) -> Result<()> {
+        let key_id = libc::KEY_SPEC_SESSION_KEYRING as libc::c_ulong;
+
+        // The description has the format "type;uid;gid;perm;description",
+        // where perm is a hexadecimal value.
+        let mut buf = vec![0u8; 256];
+        loop {
+            let len = keyctl(
+                libc::KEYCTL_DESCRIBE,
+                key_id,
+                buf.as_mut_ptr() as libc::c_ulong,
+                buf.len() as libc::c_ulong,
+            )? as usize;
+            if len <= buf.len() {
+                break;
+            }
+            buf.resize(len, 0);
+        }
+        let desc = CStr::from_bytes_until_nul(&buf)
+            .map_err(|_| nix::Error::EINVAL)?
+            .to_string_lossy();
+        let perm = desc
+            .split(';')
+            .nth(3)
+            .and_then(|perm| u32::from_str_radix(perm, 16).ok())
+            .ok_or(nix::Error::EINVAL)?;
+
+        keyctl(
+            libc::KEYCTL_SETPERM,
+            key_id,
+            ((perm & keep_perms) | new_perms) as libc::c_ulong,
+            0,
+        )?;
+        Ok(())
+    }
+}
+
+fn keyctl(
+    operation: u32,
+    arg2: libc::c_ulong,
+    arg3: libc::c_ulong,
+    arg4: libc::c_ulong,
+) -> Result<libc::c_long> {
+    match unsafe { libc::syscall(libc::SYS_keyctl, operation, arg2, arg3, arg4) } {
+        -1 => Err(nix::Error::last().into()),
+        ret => Ok(ret),
+    }
 }
 
 #[cfg(test)]
@@ -87,6 +87,8 @@ pub trait Syscall {
     fn get_euid(&self) -> Uid;
     fn get_egid(&self) -> Gid;
     fn personality(&self, domain: PersonalityDomain) -> Result<()>;
+    fn join_session_keyring(&self, name: &str) -> Result<()>;
+    fn set_session_keyring_perm(&self, keep_perms: u32, new_perms: u32) -> Result<()>;
 }
 
 #[derive(Clone, Copy)]
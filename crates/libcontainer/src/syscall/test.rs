@@ -110,6 +110,12 @@ pub struct MemPolicyArgs {
     pub maxnode: u64,
 }
 
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct KeyringPermArgs {
+    pub keep_perms: u32,
+    pub new_perms: u32,
+}
+
 #[derive(Clone, PartialEq, Eq, Debug)]
 pub struct UMount2Args {
     pub target: PathBuf,
@@ -145,6 +151,8 @@ pub enum ArgName {
     Fsmount,
     OpenTree,
     MountSetattr,
+    SessionKeyring,
+    KeyringPerm,
 }
 
 impl ArgName {
@@ -169,6 +177,8 @@ impl ArgName {
             ArgName::Fsmount,
             ArgName::OpenTree,
             ArgName::MountSetattr,
+            ArgName::SessionKeyring,
+            ArgName::KeyringPerm,
         ]
         .iter()
         .copied()
@@ -533,6 +543,21 @@ impl Syscall for TestHelperSyscall {
     fn personality(&self, _: PersonalityDomain) -> Result<()> {
         todo!()
     }
+
+    fn join_session_keyring(&self, name: &str) -> Result<()> {
+        self.mocks
+            .act(ArgName::SessionKeyring, Box::new(name.to_owned()))
+    }
+
+    fn set_session_keyring_perm(&self, keep_perms: u32, new_perms: u32) -> Result<()> {
+        self.mocks.act(
+            ArgName::KeyringPerm,
+            Box::new(KeyringPermArgs {
+                keep_perms,
+                new_perms,
+            }),
+        )
+    }
 }
 
 impl TestHelperSyscall {
@@ -707,6 +732,24 @@ impl TestHelperSyscall {
             .collect::<Vec<IoPriorityArgs>>()
     }
 
+    pub fn get_session_keyring_args(&self) -> Vec<String> {
+        self.mocks
+            .fetch(ArgName::SessionKeyring)
+            .values
+            .iter()
+            .map(|x| x.downcast_ref::<String>().unwrap().clone())
+            .collect::<Vec<String>>()
+    }
+
+    pub fn get_keyring_perm_args(&self) -> Vec<KeyringPermArgs> {
+        self.mocks
+            .fetch(ArgName::KeyringPerm)
+            .values
+            .iter()
+            .map(|x| x.downcast_ref::<KeyringPermArgs>().unwrap().clone())
+            .collect::<Vec<KeyringPermArgs>>()
+    }
+
     pub fn get_mempolicy_args(&self) -> Vec<MemPolicyArgs> {
         self.mocks
             .fetch(ArgName::MemPolicy)
@@ -0,0 +1,324 @@
+//! Breakdown of the start-up latency of a container. The processes creating
+//! and starting a container record the phases they run with timestamps of
+//! `CLOCK_MONOTONIC`, which is shared by all the processes on the host. The
+//! phases of the intermediate and init processes are sent back to the main
+//! process, and the runtime keeps the whole breakdown in the container
+//! directory.
+use std::fmt::Display;
+use std::fs;
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use nix::time::{ClockId, clock_gettime};
+use oci_spec::runtime::{Linux, LinuxNamespaceType};
+use serde::{Deserialize, Serialize};
+
+/// Name of the file holding the timings in the container directory
+pub const TIMINGS_FILE: &str = "startup_timings.json";
+
+#[derive(Debug, thiserror::Error)]
+pub enum TimingsError {
+    #[error("failed to access startup timings {path}")]
+    Io { source: io::Error, path: PathBuf },
+    #[error("failed to encode startup timings {path}")]
+    Encode {
+        source: serde_json::Error,
+        path: PathBuf,
+    },
+    #[error("failed to parse startup timings {path}")]
+    Parse {
+        source: serde_json::Error,
+        path: PathBuf,
+    },
+}
+
+type Result<T> = std::result::Result<T, TimingsError>;
+
+/// A phase of creating and starting a container
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Phase {
+    Clone,
+    UsernsMapping,
+    CgroupApply,
+    RootfsMounts,
+    Devices,
+    PivotRoot,
+    SeccompLoad,
+    Hooks,
+    /// From the start of the container until its process is executed
+    Exec,
+}
+
+impl Display for Phase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let print = match self {
+            Self::Clone => "clone",
+            Self::UsernsMapping => "userns_mapping",
+            Self::CgroupApply => "cgroup_apply",
+            Self::RootfsMounts => "rootfs_mounts",
+            Self::Devices => "devices",
+            Self::PivotRoot => "pivot_root",
+            Self::SeccompLoad => "seccomp_load",
+            Self::Hooks => "hooks",
+            Self::Exec => "exec",
+        };
+
+        write!(f, "{print}")
+    }
+}
+
+/// The process that ran a phase
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Process {
+    /// The youki process handling the create or start command
+    Runtime,
+    Intermediate,
+    Init,
+}
+
+impl Display for Process {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let print = match self {
+            Self::Runtime => "runtime",
+            Self::Intermediate => "intermediate",
+            Self::Init => "init",
+        };
+
+        write!(f, "{print}")
+    }
+}
+
+/// A phase with its start and end on the monotonic clock of the host, in
+/// nanoseconds
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PhaseTiming {
+    pub phase: Phase,
+    pub process: Process,
+    pub start_ns: u64,
+    pub end_ns: u64,
+}
+
+impl PhaseTiming {
+    pub fn duration(&self) -> Duration {
+        Duration::from_nanos(self.end_ns.saturating_sub(self.start_ns))
+    }
+}
+
+/// Returns the current time of the monotonic clock in nanoseconds
+pub fn now() -> u64 {
+    // CLOCK_MONOTONIC is always supported, so this never fails.
+    let ts = clock_gettime(ClockId::CLOCK_MONOTONIC).expect("failed to read monotonic clock");
+    ts.tv_sec() as u64 * 1_000_000_000 + ts.tv_nsec() as u64
+}
+
+/// Returns the offset of the monotonic clock in the time namespace created
+/// for the container, in nanoseconds
+pub fn monotonic_offset(linux: &Linux) -> i64 {
+    let new_time_ns = linux.namespaces().as_ref().is_some_and(|namespaces| {
+        namespaces
+            .iter()
+            .any(|ns| ns.typ() == LinuxNamespaceType::Time && ns.path().is_none())
+    });
+    if !new_time_ns {
+        return 0;
+    }
+
+    linux
+        .time_offsets()
+        .as_ref()
+        .and_then(|offsets| offsets.get("monotonic"))
+        .map(|offset| {
+            offset.secs().unwrap_or(0) * 1_000_000_000 + offset.nanosecs().unwrap_or(0) as i64
+        })
+        .unwrap_or(0)
+}
+
+/// Records the phases run by one process
+#[derive(Debug)]
+pub struct Recorder {
+    process: Process,
+    clock_offset: i64,
+    phases: Vec<PhaseTiming>,
+}
+
+impl Recorder {
+    pub fn new(process: Process) -> Self {
+        Self {
+            process,
+            clock_offset: 0,
+            phases: Vec::new(),
+        }
+    }
+
+    /// Sets the offset of the monotonic clock of the process, so that the
+    /// phases are recorded on the clock of the host.
+    pub fn with_clock_offset(mut self, offset: i64) -> Self {
+        self.clock_offset = offset;
+        self
+    }
+
+    /// Returns the current time, to be passed to [`Recorder::record`] once
+    /// the phase is done
+    pub fn start(&self) -> u64 {
+        now().saturating_add_signed(-self.clock_offset)
+    }
+
+    /// Records a phase that started at `start` and ends now
+    pub fn record(&mut self, phase: Phase, start: u64) {
+        self.phases.push(PhaseTiming {
+            phase,
+            process: self.process,
+            start_ns: start,
+            end_ns: self.start(),
+        });
+    }
+
+    pub fn into_phases(self) -> Vec<PhaseTiming> {
+        self.phases
+    }
+}
+
+/// The timings of the phases of a container, ordered by their start
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StartupTimings {
+    pub phases: Vec<PhaseTiming>,
+}
+
+impl StartupTimings {
+    pub fn extend<I: IntoIterator<Item = PhaseTiming>>(&mut self, phases: I) {
+        self.phases.extend(phases);
+        self.phases.sort_by_key(|timing| timing.start_ns);
+    }
+
+    /// Time from the start of the first phase to the end of the last one
+    pub fn total(&self) -> Duration {
+        let start = self.phases.iter().map(|t| t.start_ns).min();
+        let end = self.phases.iter().map(|t| t.end_ns).max();
+        match (start, end) {
+            (Some(start), Some(end)) => Duration::from_nanos(end.saturating_sub(start)),
+            _ => Duration::ZERO,
+        }
+    }
+
+    pub fn save(&self, container_root: &Path) -> Result<()> {
+        let path = container_root.join(TIMINGS_FILE);
+        let file = fs::File::create(&path).map_err(|source| TimingsError::Io {
+            source,
+            path: path.to_owned(),
+        })?;
+        let mut writer = BufWriter::new(file);
+        serde_json::to_writer(&mut writer, self).map_err(|source| TimingsError::Encode {
+            source,
+            path: path.to_owned(),
+        })?;
+        writer
+            .flush()
+            .map_err(|source| TimingsError::Io { source, path })?;
+
+        Ok(())
+    }
+
+    /// Loads the timings of a container, `None` if they were not recorded
+    pub fn load(container_root: &Path) -> Result<Option<Self>> {
+        let path = container_root.join(TIMINGS_FILE);
+        let file = match fs::File::open(&path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(source) => return Err(TimingsError::Io { source, path }),
+        };
+        let timings = serde_json::from_reader(BufReader::new(file))
+            .map_err(|source| TimingsError::Parse { source, path })?;
+
+        Ok(Some(timings))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use oci_spec::runtime::{
+        LinuxBuilder, LinuxNamespaceBuilder, LinuxTimeOffset, LinuxTimeOffsetBuilder,
+    };
+
+    use super::*;
+
+    fn timing(phase: Phase, start_ns: u64, end_ns: u64) -> PhaseTiming {
+        PhaseTiming {
+            phase,
+            process: Process::Init,
+            start_ns,
+            end_ns,
+        }
+    }
+
+    #[test]
+    fn test_recorder() {
+        let mut recorder = Recorder::new(Process::Intermediate);
+        let start = recorder.start();
+        recorder.record(Phase::CgroupApply, start);
+        let phases = recorder.into_phases();
+        assert_eq!(phases.len(), 1);
+        assert_eq!(phases[0].phase, Phase::CgroupApply);
+        assert_eq!(phases[0].process, Process::Intermediate);
+        assert!(phases[0].end_ns >= phases[0].start_ns);
+
+        // A clock running ahead is brought back to the clock of the host.
+        let recorder = Recorder::new(Process::Init).with_clock_offset(3_600_000_000_000);
+        assert!(recorder.start() < now());
+    }
+
+    #[test]
+    fn test_monotonic_offset() -> Result<()> {
+        let offsets: std::collections::HashMap<String, LinuxTimeOffset> = [(
+            "monotonic".to_owned(),
+            LinuxTimeOffsetBuilder::default()
+                .secs(2i64)
+                .nanosecs(5u32)
+                .build()?,
+        )]
+        .into();
+        let time_ns = LinuxNamespaceBuilder::default()
+            .typ(LinuxNamespaceType::Time)
+            .build()?;
+        let linux = LinuxBuilder::default()
+            .namespaces(vec![time_ns])
+            .time_offsets(offsets.clone())
+            .build()?;
+        assert_eq!(monotonic_offset(&linux), 2_000_000_005);
+
+        // Joined time namespaces are not offset by the spec.
+        let time_ns = LinuxNamespaceBuilder::default()
+            .typ(LinuxNamespaceType::Time)
+            .path("/proc/1/ns/time")
+            .build()?;
+        let linux = LinuxBuilder::default()
+            .namespaces(vec![time_ns])
+            .time_offsets(offsets)
+            .build()?;
+        assert_eq!(monotonic_offset(&linux), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_save_load() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        assert_eq!(StartupTimings::load(tmp.path())?, None);
+
+        let mut timings = StartupTimings::default();
+        timings.extend([
+            timing(Phase::PivotRoot, 30, 45),
+            timing(Phase::Clone, 10, 20),
+        ]);
+        assert_eq!(timings.phases[0].phase, Phase::Clone);
+        assert_eq!(timings.phases[1].duration(), Duration::from_nanos(15));
+        assert_eq!(timings.total(), Duration::from_nanos(35));
+
+        timings.save(tmp.path())?;
+        assert_eq!(StartupTimings::load(tmp.path())?, Some(timings));
+        Ok(())
+    }
+}
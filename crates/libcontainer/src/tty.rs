@@ -13,8 +13,8 @@
 //! See: https://github.com/opencontainers/runc/blob/v1.4.0/libcontainer/rootfs_linux.go
 
 use std::env;
-use std::io::IoSlice;
-use std::os::fd::OwnedFd;
+use std::io::{IoSlice, IoSliceMut};
+use std::os::fd::{FromRawFd, OwnedFd};
 use std::os::unix::fs::{OpenOptionsExt, symlink};
 use std::os::unix::io::AsRawFd;
 use std::os::unix::prelude::RawFd;
@@ -81,6 +81,8 @@ pub enum TTYError {
     CreatePseudoTerminal { source: nix::Error },
     #[error("failed to send pty master")]
     SendPtyMaster { source: nix::Error },
+    #[error("failed to receive pty master")]
+    ReceivePtyMaster { source: nix::Error },
     #[error("could not close console socket")]
     CloseConsoleSocket { source: nix::Error },
     #[error("failed to create /dev/console")]
@@ -97,7 +99,6 @@ pub enum TTYError {
 
 type Result<T> = std::result::Result<T, TTYError>;
 
-// TODO: Handling when there isn't console-socket.
 pub fn setup_console_socket(
     container_dir: &Path,
     console_socket_path: &Path,
@@ -147,6 +148,49 @@ pub fn setup_console_socket(
     Ok(csocketfd)
 }
 
+/// Creates a connected pair of sockets for a container running in the
+/// foreground with a terminal but without a console socket. The first socket
+/// stays with the caller to receive the pty master with
+/// [`receive_pty_master`], the second one is passed to the container process
+/// in place of a console socket.
+pub fn setup_console_socketpair() -> Result<(OwnedFd, OwnedFd)> {
+    socket::socketpair(
+        socket::AddressFamily::Unix,
+        socket::SockType::Stream,
+        None,
+        socket::SockFlag::SOCK_CLOEXEC,
+    )
+    .map_err(|err| TTYError::CreateConsoleSocketFd { source: err })
+}
+
+/// Receives the pty master sent by the container process over a socket
+/// created by [`setup_console_socketpair`]. Returns `None` if the other end
+/// was closed without sending a pty master, i.e. the container process does
+/// not use a terminal.
+pub fn receive_pty_master(socket: &OwnedFd) -> Result<Option<OwnedFd>> {
+    let mut buf = [0u8; 4096];
+    let mut iov = [IoSliceMut::new(&mut buf)];
+    let mut cmsg_buf = nix::cmsg_space!(RawFd);
+    let msg = socket::recvmsg::<UnixAddr>(
+        socket.as_raw_fd(),
+        &mut iov,
+        Some(&mut cmsg_buf),
+        socket::MsgFlags::MSG_CMSG_CLOEXEC,
+    )
+    .map_err(|err| TTYError::ReceivePtyMaster { source: err })?;
+
+    let master = msg
+        .cmsgs()
+        .map_err(|err| TTYError::ReceivePtyMaster { source: err })?
+        .find_map(|cmsg| match cmsg {
+            socket::ControlMessageOwned::ScmRights(fds) => fds.first().copied(),
+            _ => None,
+        })
+        .map(|fd| unsafe { OwnedFd::from_raw_fd(fd) });
+
+    Ok(master)
+}
+
 /// Device numbers from Linux kernel headers.
 /// TTYAUX_MAJOR from <linux/major.h>
 const PTMX_MAJOR: u64 = 5;
@@ -443,6 +487,29 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_receive_pty_master() -> Result<()> {
+        let (runtime, container) = setup_console_socketpair()?;
+        let file = File::open("/dev/null")?;
+        let iov = [IoSlice::new(PTMX_PATH)];
+        let fds = [file.as_raw_fd()];
+        let cmsg = socket::ControlMessage::ScmRights(&fds);
+        socket::sendmsg::<UnixAddr>(
+            container.as_raw_fd(),
+            &iov,
+            &[cmsg],
+            socket::MsgFlags::empty(),
+            None,
+        )?;
+        let master = receive_pty_master(&runtime)?;
+        assert!(master.is_some());
+
+        // the container process closes the socket without a terminal
+        drop(container);
+        assert!(receive_pty_master(&runtime)?.is_none());
+        Ok(())
+    }
+
     #[test]
     #[serial]
     fn test_setup_console() -> Result<()> {
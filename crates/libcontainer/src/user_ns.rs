@@ -123,8 +123,29 @@ pub enum MappingError {
     NoIDMapping,
     #[error("failed to write id mapping")]
     WriteIDMapping(#[source] std::io::Error),
+    #[error("{0} id mappings exceed the limit of {MAX_ID_MAPPINGS} mappings of the kernel")]
+    TooManyMappings(usize),
+    #[error("id mapping '{0}' maps no ids")]
+    EmptyMapping(String),
+    #[error("id mapping '{0}' exceeds the range of 32 bit ids")]
+    MappingOverflow(String),
+    #[error("id mappings '{first}' and '{second}' overlap in the {side} ids")]
+    OverlappingMappings {
+        first: String,
+        second: String,
+        side: &'static str,
+    },
+    #[error("{binary:?} failed with {status}: {stderr}")]
+    MapBinaryFailed {
+        binary: PathBuf,
+        status: std::process::ExitStatus,
+        stderr: String,
+    },
 }
 
+/// Maximum number of lines of a uid_map or gid_map file
+pub const MAX_ID_MAPPINGS: usize = 340;
+
 #[derive(Debug, Clone, Default)]
 pub struct UserNamespaceConfig {
     /// Location of the newuidmap binary
@@ -161,7 +182,11 @@ impl UserNamespaceConfig {
                 err
             })?;
             let mut user_ns_config = UserNamespaceConfig::try_from(linux)?;
-            if let Some((uid_binary, gid_binary)) = lookup_map_binaries(linux)? {
+            // root writes any number of mappings directly, the helpers are
+            // only needed to map subordinate ids of an unprivileged user
+            if user_ns_config.privileged {
+                tracing::debug!("privileged container writes id mappings directly");
+            } else if let Some((uid_binary, gid_binary)) = lookup_map_binaries(linux)? {
                 user_ns_config.newuidmap = Some(uid_binary);
                 user_ns_config.newgidmap = Some(gid_binary);
             }
@@ -383,11 +408,18 @@ pub fn lookup_map_binaries(
         return Ok(None);
     }
 
+    require_map_binaries().map(Some)
+}
+
+/// Looks up the location of the newuidmap and newgidmap binaries, which are
+/// also required to write a single mapping of subordinate ids as an
+/// unprivileged user
+pub fn require_map_binaries() -> std::result::Result<(PathBuf, PathBuf), MappingError> {
     let uidmap = lookup_map_binary("newuidmap")?;
     let gidmap = lookup_map_binary("newgidmap")?;
 
     match (uidmap, gidmap) {
-        (Some(newuidmap), Some(newgidmap)) => Ok(Some((newuidmap, newgidmap))),
+        (Some(newuidmap), Some(newgidmap)) => Ok((newuidmap, newgidmap)),
         _ => Err(MappingError::BinaryNotFound),
     }
 }
@@ -407,20 +439,22 @@ fn write_id_mapping(
     map_binary: Option<&Path>,
 ) -> std::result::Result<(), MappingError> {
     tracing::debug!("Write ID mapping: {:?}", mappings);
+    validate_id_mappings(mappings)?;
 
-    match mappings.len() {
-        0 => return Err(MappingError::NoIDMapping),
-        1 => {
+    match map_binary {
+        None => {
+            // The kernel only accepts the mappings in a single write.
             let mapping = mappings
-                .first()
-                .and_then(|m| format!("{} {} {}", m.container_id(), m.host_id(), m.size()).into())
-                .unwrap();
+                .iter()
+                .map(format_id_mapping)
+                .collect::<Vec<_>>()
+                .join("\n");
             std::fs::write(map_file, &mapping).map_err(|err| {
                 tracing::error!(?err, ?map_file, ?mapping, "failed to write uid/gid mapping");
                 MappingError::WriteIDMapping(err)
             })?;
         }
-        _ => {
+        Some(map_binary) => {
             let args: Vec<String> = mappings
                 .iter()
                 .flat_map(|m| {
@@ -432,10 +466,7 @@ fn write_id_mapping(
                 })
                 .collect();
 
-            // we can be certain here that map_binary will not be None,
-            // as in the lookup_map_binaries function, we return error
-            // if there are mappings.len() > 1 and binaries are not present
-            Command::new(map_binary.unwrap())
+            let output = Command::new(map_binary)
                 .arg(pid.to_string())
                 .args(args)
                 .output()
@@ -443,12 +474,70 @@ fn write_id_mapping(
                     tracing::error!(?err, ?map_binary, "failed to execute newuidmap/newgidmap");
                     MappingError::Execute(err)
                 })?;
+            if !output.status.success() {
+                return Err(MappingError::MapBinaryFailed {
+                    binary: map_binary.to_owned(),
+                    status: output.status,
+                    stderr: String::from_utf8_lossy(&output.stderr).trim().to_owned(),
+                });
+            }
         }
     }
 
     Ok(())
 }
 
+fn format_id_mapping(mapping: &LinuxIdMapping) -> String {
+    format!(
+        "{} {} {}",
+        mapping.container_id(),
+        mapping.host_id(),
+        mapping.size()
+    )
+}
+
+/// Checks the mappings against the rules of the kernel, which would only
+/// reject the whole map file with EINVAL
+fn validate_id_mappings(mappings: &[LinuxIdMapping]) -> std::result::Result<(), MappingError> {
+    if mappings.is_empty() {
+        return Err(MappingError::NoIDMapping);
+    }
+    if mappings.len() > MAX_ID_MAPPINGS {
+        return Err(MappingError::TooManyMappings(mappings.len()));
+    }
+
+    let end = |start: u32, size: u32| start as u64 + size as u64;
+    for (i, mapping) in mappings.iter().enumerate() {
+        if mapping.size() == 0 {
+            return Err(MappingError::EmptyMapping(format_id_mapping(mapping)));
+        }
+        if end(mapping.container_id(), mapping.size()) > u32::MAX as u64
+            || end(mapping.host_id(), mapping.size()) > u32::MAX as u64
+        {
+            return Err(MappingError::MappingOverflow(format_id_mapping(mapping)));
+        }
+
+        for other in &mappings[..i] {
+            let overlaps = |a: u32, b: u32| {
+                (a as u64) < end(b, other.size()) && (b as u64) < end(a, mapping.size())
+            };
+            let side = if overlaps(mapping.container_id(), other.container_id()) {
+                "container"
+            } else if overlaps(mapping.host_id(), other.host_id()) {
+                "host"
+            } else {
+                continue;
+            };
+            return Err(MappingError::OverlappingMappings {
+                first: format_id_mapping(other),
+                second: format_id_mapping(mapping),
+                side,
+            });
+        }
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use std::fs;
@@ -739,4 +828,60 @@ mod tests {
         );
         Ok(())
     }
+
+    fn mapping(container_id: u32, host_id: u32, size: u32) -> LinuxIdMapping {
+        LinuxIdMappingBuilder::default()
+            .container_id(container_id)
+            .host_id(host_id)
+            .size(size)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_validate_id_mappings() {
+        assert!(validate_id_mappings(&[mapping(0, 1000, 1), mapping(1, 100000, 65536)]).is_ok());
+        assert!(matches!(
+            validate_id_mappings(&[]),
+            Err(MappingError::NoIDMapping)
+        ));
+        let many: Vec<_> = (0..=MAX_ID_MAPPINGS as u32)
+            .map(|i| mapping(i, 1000 + i, 1))
+            .collect();
+        assert!(matches!(
+            validate_id_mappings(&many),
+            Err(MappingError::TooManyMappings(341))
+        ));
+        assert!(validate_id_mappings(&many[..MAX_ID_MAPPINGS]).is_ok());
+        assert!(matches!(
+            validate_id_mappings(&[mapping(0, 1000, 0)]),
+            Err(MappingError::EmptyMapping(m)) if m == "0 1000 0"
+        ));
+        assert!(matches!(
+            validate_id_mappings(&[mapping(0, u32::MAX - 1, 2)]),
+            Err(MappingError::MappingOverflow(_))
+        ));
+        assert!(matches!(
+            validate_id_mappings(&[mapping(0, 1000, 10), mapping(5, 2000, 10)]),
+            Err(MappingError::OverlappingMappings {
+                side: "container",
+                ..
+            })
+        ));
+        assert!(matches!(
+            validate_id_mappings(&[mapping(0, 1000, 10), mapping(10, 1009, 10)]),
+            Err(MappingError::OverlappingMappings { first, second, side: "host" })
+                if first == "0 1000 10" && second == "10 1009 10"
+        ));
+    }
+
+    #[test]
+    fn test_write_multiple_mappings_directly() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let map_file = tmp.path().join("uid_map");
+        let mappings = [mapping(0, 1000, 1), mapping(1, 100000, 65536)];
+        write_id_mapping(getpid(), &map_file, &mappings, None)?;
+        assert_eq!(fs::read_to_string(&map_file)?, "0 1000 1\n1 100000 65536");
+        Ok(())
+    }
 }
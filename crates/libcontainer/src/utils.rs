@@ -264,6 +264,45 @@ pub fn is_in_new_userns() -> Result<bool, std::io::Error> {
     Ok(!content.contains("4294967295"))
 }
 
+/// Selects whether a container runs in rootless mode, that is whether
+/// failures to apply cgroup limits are tolerated. The mode does not change
+/// what the caller is allowed to do: the spec is validated against
+/// [`rootless_required`] whatever the mode.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RootlessMode {
+    /// Rootless mode is used when the caller is not root, or is in a user
+    /// namespace
+    #[default]
+    Auto,
+    Enabled,
+    Disabled,
+}
+
+impl RootlessMode {
+    pub fn resolve(self, syscall: &dyn Syscall) -> Result<bool, std::io::Error> {
+        match self {
+            RootlessMode::Auto => rootless_required(syscall),
+            RootlessMode::Enabled => Ok(true),
+            RootlessMode::Disabled => Ok(false),
+        }
+    }
+}
+
+impl std::str::FromStr for RootlessMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(RootlessMode::Auto),
+            "true" => Ok(RootlessMode::Enabled),
+            "false" => Ok(RootlessMode::Disabled),
+            _ => Err(format!(
+                "invalid rootless mode {s:?}, expected 'true', 'false' or 'auto'"
+            )),
+        }
+    }
+}
+
 /// Checks if rootless mode needs to be used
 pub fn rootless_required(syscall: &dyn Syscall) -> Result<bool, std::io::Error> {
     if !syscall.get_euid().is_root() {
@@ -272,7 +311,9 @@ pub fn rootless_required(syscall: &dyn Syscall) -> Result<bool, std::io::Error>
     is_in_new_userns()
 }
 
-/// checks if given spec is valid for current user namespace setup
+/// checks if given spec is valid for current user namespace setup. It
+/// depends on the privileges of the caller only, not on the [`RootlessMode`]
+/// of the container, so that `--rootless=false` does not skip it.
 pub fn validate_spec_for_new_user_ns(
     spec: &Spec,
     syscall: &dyn Syscall,
@@ -430,6 +471,7 @@ mod tests {
 
     use super::*;
     use crate::syscall::syscall::create_syscall;
+    use crate::syscall::test::TestHelperSyscall;
     use crate::test_utils;
 
     #[test]
@@ -569,6 +611,28 @@ mod tests {
         })
     }
 
+    #[test]
+    fn test_rootless_mode() -> Result<()> {
+        assert_eq!("auto".parse::<RootlessMode>(), Ok(RootlessMode::Auto));
+        assert_eq!("true".parse::<RootlessMode>(), Ok(RootlessMode::Enabled));
+        assert_eq!("false".parse::<RootlessMode>(), Ok(RootlessMode::Disabled));
+        assert!("yes".parse::<RootlessMode>().is_err());
+
+        let syscall = create_syscall();
+        assert!(RootlessMode::Enabled.resolve(&*syscall)?);
+        // the caller is told apart through its syscall
+        let unprivileged = TestHelperSyscall::default();
+        unprivileged.set_id(Uid::from_raw(1000), Gid::from_raw(1000))?;
+        assert!(RootlessMode::Auto.resolve(&unprivileged)?);
+        assert!(!RootlessMode::Disabled.resolve(&unprivileged)?);
+        assert!(!RootlessMode::Disabled.resolve(&*syscall)?);
+        assert_eq!(
+            RootlessMode::Auto.resolve(&*syscall)?,
+            rootless_required(&*syscall)?
+        );
+        Ok(())
+    }
+
     #[test]
     fn test_dev_valid_name() {
         assert!(!dev_valid_name(""));
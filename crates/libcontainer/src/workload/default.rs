@@ -6,6 +6,9 @@ use oci_spec::runtime::Spec;
 
 use super::{Executor, ExecutorError, ExecutorValidationError};
 
+/// Name of the executor of regular container workloads
+pub const EXECUTOR_NAME: &str = "default";
+
 #[derive(Clone)]
 pub struct DefaultExecutor {}
 
@@ -4,6 +4,7 @@ use std::env;
 use oci_spec::runtime::Spec;
 
 pub mod default;
+pub mod registry;
 
 pub static EMPTY: Vec<String> = Vec::new();
 
@@ -0,0 +1,240 @@
+//! Registry of the executors a runtime is built with. Each executor registers
+//! with a name and a matcher, and the registry selects the executor for the
+//! workload of a container, either explicitly by annotation or by asking the
+//! matchers in order of priority.
+use std::collections::HashMap;
+
+use oci_spec::runtime::Spec;
+
+use super::{Executor, ExecutorError, ExecutorValidationError};
+
+/// Annotation naming the handler of the workload, e.g. `wasm` or the name of
+/// a registered executor such as `wasmtime`
+pub const OCI_HANDLER_ANNOTATION: &str = "run.oci.handler";
+/// Annotation set on wasm images, `compat` marks a wasm workload
+pub const WASM_VARIANT_ANNOTATION: &str = "module.wasm.image/variant";
+
+/// Decides whether an executor can run the workload of a spec
+pub type Matcher = fn(&Spec) -> bool;
+
+/// Matches workloads that are marked as wasm by annotations
+pub fn is_wasm_workload(spec: &Spec) -> bool {
+    if let Some(annotations) = spec.annotations() {
+        if let Some(handler) = annotations.get(OCI_HANDLER_ANNOTATION) {
+            return handler == "wasm";
+        }
+
+        if let Some(variant) = annotations.get(WASM_VARIANT_ANNOTATION) {
+            return variant == "compat";
+        }
+    }
+
+    false
+}
+
+/// Matches any workload, used for the executor of last resort
+pub fn any_workload(_: &Spec) -> bool {
+    true
+}
+
+#[derive(Clone)]
+struct Entry {
+    name: &'static str,
+    executor: Box<dyn Executor>,
+    matcher: Matcher,
+}
+
+/// Executor that delegates to the registered executor selected for a spec.
+///
+/// The `run.oci.handler` annotation selects an executor by name. Otherwise
+/// the matchers are asked in order of priority, which is the order of
+/// registration unless changed with [`ExecutorRegistry::with_priority`].
+#[derive(Clone, Default)]
+pub struct ExecutorRegistry {
+    entries: Vec<Entry>,
+}
+
+impl ExecutorRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers an executor. An executor registered earlier under the same
+    /// name is replaced, keeping its priority.
+    pub fn register(
+        &mut self,
+        name: &'static str,
+        executor: Box<dyn Executor>,
+        matcher: Matcher,
+    ) -> &mut Self {
+        let entry = Entry {
+            name,
+            executor,
+            matcher,
+        };
+        match self.entries.iter_mut().find(|e| e.name == name) {
+            Some(existing) => *existing = entry,
+            None => self.entries.push(entry),
+        }
+        self
+    }
+
+    /// Orders the executors by the given names. Executors that are not
+    /// listed keep their relative order after the listed ones, unknown names
+    /// are ignored.
+    pub fn with_priority(mut self, names: &[&str]) -> Self {
+        let rank: HashMap<&str, usize> = names
+            .iter()
+            .enumerate()
+            .map(|(i, name)| (*name, i))
+            .collect();
+        // sort_by_key is stable, so unlisted executors keep their order
+        self.entries
+            .sort_by_key(|e| rank.get(e.name).copied().unwrap_or(names.len()));
+        self
+    }
+
+    /// Names of the registered executors, in order of priority
+    pub fn names(&self) -> Vec<&'static str> {
+        self.entries.iter().map(|e| e.name).collect()
+    }
+
+    /// Selects the executor for the workload of the spec
+    pub fn select(&self, spec: &Spec) -> Option<(&'static str, &dyn Executor)> {
+        let handler = spec
+            .annotations()
+            .as_ref()
+            .and_then(|a| a.get(OCI_HANDLER_ANNOTATION));
+        let entry = handler
+            .and_then(|handler| self.entries.iter().find(|e| e.name == handler))
+            .or_else(|| self.entries.iter().find(|e| (e.matcher)(spec)))?;
+
+        Some((entry.name, entry.executor.as_ref()))
+    }
+}
+
+impl Executor for ExecutorRegistry {
+    fn exec(&self, spec: &Spec) -> Result<(), ExecutorError> {
+        let (name, executor) = self
+            .select(spec)
+            .ok_or(ExecutorError::CantHandle("registry"))?;
+        tracing::debug!(executor = name, "executing workload");
+        executor.exec(spec)
+    }
+
+    fn validate(&self, spec: &Spec) -> Result<(), ExecutorValidationError> {
+        let (_, executor) = self
+            .select(spec)
+            .ok_or(ExecutorValidationError::CantHandle("registry"))?;
+        executor.validate(spec)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::{Context, Result};
+    use oci_spec::runtime::SpecBuilder;
+
+    use super::*;
+
+    #[derive(Clone)]
+    struct NamedExecutor(&'static str);
+
+    impl Executor for NamedExecutor {
+        fn exec(&self, _: &Spec) -> Result<(), ExecutorError> {
+            Err(ExecutorError::Other(self.0.to_owned()))
+        }
+
+        fn validate(&self, _: &Spec) -> Result<(), ExecutorValidationError> {
+            Ok(())
+        }
+    }
+
+    fn spec_with_annotation(key: &str, value: &str) -> Result<Spec> {
+        SpecBuilder::default()
+            .annotations(HashMap::from([(key.to_owned(), value.to_owned())]))
+            .build()
+            .context("build spec")
+    }
+
+    fn registry() -> ExecutorRegistry {
+        let mut registry = ExecutorRegistry::new();
+        registry
+            .register(
+                "wasmer",
+                Box::new(NamedExecutor("wasmer")),
+                is_wasm_workload,
+            )
+            .register(
+                "wasmtime",
+                Box::new(NamedExecutor("wasmtime")),
+                is_wasm_workload,
+            )
+            .register("default", Box::new(NamedExecutor("default")), any_workload);
+        registry
+    }
+
+    fn selected(registry: &ExecutorRegistry, spec: &Spec) -> Option<&'static str> {
+        registry.select(spec).map(|(name, _)| name)
+    }
+
+    #[test]
+    fn test_is_wasm_workload() -> Result<()> {
+        assert!(is_wasm_workload(&spec_with_annotation(
+            OCI_HANDLER_ANNOTATION,
+            "wasm"
+        )?));
+        assert!(is_wasm_workload(&spec_with_annotation(
+            WASM_VARIANT_ANNOTATION,
+            "compat"
+        )?));
+        assert!(!is_wasm_workload(&SpecBuilder::default().build()?));
+        Ok(())
+    }
+
+    #[test]
+    fn test_select() -> Result<()> {
+        let registry = registry();
+        assert_eq!(registry.names(), vec!["wasmer", "wasmtime", "default"]);
+
+        // by priority
+        let spec = spec_with_annotation(OCI_HANDLER_ANNOTATION, "wasm")?;
+        assert_eq!(selected(&registry, &spec), Some("wasmer"));
+        let spec = SpecBuilder::default().build()?;
+        assert_eq!(selected(&registry, &spec), Some("default"));
+
+        // by name
+        let spec = spec_with_annotation(OCI_HANDLER_ANNOTATION, "wasmtime")?;
+        assert_eq!(selected(&registry, &spec), Some("wasmtime"));
+        let spec = spec_with_annotation(OCI_HANDLER_ANNOTATION, "unknown")?;
+        assert_eq!(selected(&registry, &spec), Some("default"));
+
+        assert!(
+            ExecutorRegistry::new()
+                .select(&SpecBuilder::default().build()?)
+                .is_none()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_with_priority() -> Result<()> {
+        let registry = registry().with_priority(&["wasmtime", "unknown"]);
+        assert_eq!(registry.names(), vec!["wasmtime", "wasmer", "default"]);
+
+        let spec = spec_with_annotation(WASM_VARIANT_ANNOTATION, "compat")?;
+        assert_eq!(selected(&registry, &spec), Some("wasmtime"));
+        match registry.exec(&spec) {
+            Err(ExecutorError::Other(name)) => assert_eq!(name, "wasmtime"),
+            _ => panic!("expected the wasmtime executor"),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_register_replaces() {
+        let mut registry = registry();
+        registry.register("wasmer", Box::new(NamedExecutor("other")), any_workload);
+        assert_eq!(registry.names(), vec!["wasmer", "wasmtime", "default"]);
+    }
+}
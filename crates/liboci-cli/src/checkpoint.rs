@@ -1,21 +1,24 @@
 use std::path::PathBuf;
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
 
 /// Checkpoint a running container
 // Reference: https://github.com/opencontainers/runc/blob/main/man/runc-checkpoint.8.md
 // Unimplemented options vs runc: https://github.com/youki-dev/youki/issues/3394
 #[derive(Parser, Debug)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 pub struct Checkpoint {
+    #[command(subcommand)]
+    pub command: Option<CheckpointCmd>,
     /// Path for saving criu image files
     #[arg(long, default_value = "checkpoint")]
     pub image_path: PathBuf,
     /// Path for saving work files and logs
     #[arg(long)]
     pub work_path: Option<PathBuf>,
-    // TODO: Path for previous criu image file in pre-dump
-    // #[arg(long)]
-    // pub parent_path: Option<PathBuf>,
+    /// Path for previous criu image files in pre-dump, relative to the image path
+    #[arg(long)]
+    pub parent_path: Option<PathBuf>,
     /// Leave the process running after checkpointing
     #[arg(long)]
     pub leave_running: bool,
@@ -34,29 +37,42 @@ pub struct Checkpoint {
     /// Allow shell jobs
     #[arg(long)]
     pub shell_job: bool,
-    // TODO: Use lazy migration mechanism
-    // #[arg(long)]
-    // pub lazy_pages: bool,
+    /// Use userfaultfd to lazily restore memory pages
+    #[arg(long, requires = "page_server")]
+    pub lazy_pages: bool,
     // TODO: Pass a file descriptor fd to criu. Is u32 the right type?
     // #[arg(long)]
     // pub status_fd: Option<u32>,
-    // TODO: Start a page server at the given URL
-    // #[arg(long)]
-    // pub page_server: Option<String>,
+    /// ADDRESS:PORT of the page server
+    #[arg(long)]
+    pub page_server: Option<String>,
     /// Allow file locks
     #[arg(long)]
     pub file_locks: bool,
-    // TODO: Do a pre-dump
-    // #[arg(long)]
-    // pub pre_dump: bool,
+    /// Dump container's memory information only, leave the container running after this
+    #[arg(long, conflicts_with = "lazy_pages")]
+    pub pre_dump: bool,
     #[arg(long, default_value = "soft", value_parser = clap::builder::PossibleValuesParser::new(["ignore", "full", "strict", "soft"]))]
     pub manage_cgroups_mode: String,
     // TODO: Checkpoint a namespace, but don't save its properties
     // #[arg(long)]
     // pub empty_ns: bool,
-    // TODO: Enable auto-deduplication
-    // #[arg(long)]
-    // pub auto_dedup: bool,
+    /// Enable auto deduplication of memory images
+    #[arg(long)]
+    pub auto_dedup: bool,
     #[arg(value_parser = clap::builder::NonEmptyStringValueParser::new(), required = true)]
-    pub container_id: String,
+    pub container_id: Option<String>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum CheckpointCmd {
+    Inspect(CheckpointInspect),
+}
+
+/// Validate a checkpoint and show which container it belongs to
+#[derive(Parser, Debug)]
+pub struct CheckpointInspect {
+    /// Path of the criu image files
+    #[arg(long, default_value = "checkpoint")]
+    pub image_path: PathBuf,
 }
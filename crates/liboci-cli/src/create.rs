@@ -26,8 +26,27 @@ pub struct Create {
     /// Pass N additional file descriptors to the container (stdio + $LISTEN_FDS + N in total)
     #[arg(long, default_value = "0")]
     pub preserve_fds: i32,
+    #[command(flatten)]
+    pub container_log: ContainerLog,
+    /// Keep a monitor process as the parent of the container, to record its exit and restart it
+    #[arg(long)]
+    pub shim: bool,
 
     /// Name of the container instance to be started
     #[arg(value_parser = clap::builder::NonEmptyStringValueParser::new(), required = true)]
     pub container_id: String,
 }
+
+/// Forwarding of the stdout and stderr of the container to a log file
+#[derive(Args, Debug, Default, Clone)]
+pub struct ContainerLog {
+    /// Write the stdout and stderr of the container to this file in the CRI log format
+    #[arg(long)]
+    pub container_log: Option<PathBuf>,
+    /// Rotate the container log once it would grow over this size, e.g. 10M
+    #[arg(long)]
+    pub container_log_max_size: Option<String>,
+    /// Number of rotated container logs to keep
+    #[arg(long)]
+    pub container_log_max_files: Option<usize>,
+}
@@ -55,6 +55,16 @@ pub struct Exec {
     /// Execute a process in a sub-cgroup
     #[arg(long)]
     pub cgroup: Option<String>,
+    /// Record the process under this ID, instead of a random one. A detached
+    /// process is then monitored to record its exit status
+    #[arg(long)]
+    pub exec_id: Option<String>,
+    /// List the processes executed in the container instead
+    #[arg(long, conflicts_with_all = ["command", "process", "exec_id"])]
+    pub list: bool,
+    /// Specify the format of the list (table or json, defaults to table)
+    #[arg(long)]
+    pub format: Option<String>,
     /// Container identifier
     #[arg(value_parser = clap::builder::NonEmptyStringValueParser::new(), required = true)]
     pub container_id: String,
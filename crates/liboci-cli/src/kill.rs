@@ -11,4 +11,10 @@ pub struct Kill {
     /// Send the signal to all processes inside the container
     #[arg(short, long)]
     pub all: bool,
+    /// Wait for the container process to exit and record its exit
+    #[arg(long)]
+    pub wait: bool,
+    /// Send the signal to the process executed in the container under this ID
+    #[arg(long, conflicts_with_all = ["all", "wait"])]
+    pub exec: Option<String>,
 }
@@ -1,7 +1,7 @@
 use std::fmt::Debug;
 use std::path::PathBuf;
 
-use clap::{Args, Subcommand};
+use clap::{Args, Subcommand, ValueEnum};
 
 // Subcommands that are specified in https://github.com/opencontainers/runtime-tools/blob/master/docs/command-line-interface.md
 
@@ -11,7 +11,7 @@ mod kill;
 mod start;
 mod state;
 
-pub use create::Create;
+pub use create::{ContainerLog, Create};
 pub use delete::Delete;
 pub use kill::Kill;
 pub use start::Start;
@@ -30,7 +30,7 @@ mod run;
 mod spec;
 mod update;
 
-pub use checkpoint::Checkpoint;
+pub use checkpoint::{Checkpoint, CheckpointCmd, CheckpointInspect};
 pub use events::Events;
 pub use exec::Exec;
 pub use features::Features;
@@ -61,6 +61,7 @@ pub enum StandardCmd {
 // and other runtimes.
 #[derive(Subcommand, Debug)]
 pub enum CommonCmd {
+    #[command(alias = "checkpoint")]
     Checkpointt(Checkpoint),
     Events(Events),
     Exec(Exec),
@@ -93,4 +94,22 @@ pub struct GlobalOpts {
     /// Enable systemd cgroup manager, rather then use the cgroupfs directly.
     #[arg(short, long)]
     pub systemd_cgroup: bool,
+    /// ignore cgroup permission errors
+    #[arg(long, value_enum, default_value_t)]
+    pub rootless: Rootless,
+    /// path to the criu binary used for checkpoint and restore (default: "criu")
+    #[arg(long)]
+    pub criu: Option<PathBuf>,
+}
+
+/// Value of the `--rootless` flag, `auto` uses rootless mode when the caller
+/// is not root or runs in a user namespace
+#[derive(ValueEnum, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Rootless {
+    #[value(name = "true")]
+    Enabled,
+    #[value(name = "false")]
+    Disabled,
+    #[default]
+    Auto,
 }
@@ -2,6 +2,8 @@ use std::path::PathBuf;
 
 use clap::Args;
 
+use crate::ContainerLog;
+
 /// Create a container and immediately start it
 #[derive(Args, Debug)]
 pub struct Run {
@@ -27,6 +29,11 @@ pub struct Run {
     /// Pass N additional file descriptors to the container (stdio + $LISTEN_FDS + N in total)
     #[arg(long, default_value = "0")]
     pub preserve_fds: i32,
+    #[command(flatten)]
+    pub container_log: ContainerLog,
+    /// Keep a monitor process as the parent of the detached container, to record its exit and restart it
+    #[arg(long, requires = "detach", conflicts_with_all = ["keep", "no_subreaper"])]
+    pub shim: bool,
     /// Keep container's state directory and cgroup after the container exits
     #[arg(long)]
     pub keep: bool,
@@ -6,4 +6,7 @@ pub struct Start {
     /// Container identifier
     #[arg(value_parser = clap::builder::NonEmptyStringValueParser::new(), required = true)]
     pub container_id: String,
+    /// Wait until the container reports READY=1 on its NOTIFY_SOCKET
+    #[arg(long)]
+    pub wait_ready: bool,
 }
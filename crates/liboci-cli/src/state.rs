@@ -6,4 +6,7 @@ pub struct State {
     /// Container identifier
     #[arg(value_parser = clap::builder::NonEmptyStringValueParser::new(), required = true)]
     pub container_id: String,
+    /// Also show the start-up latency breakdown of the container
+    #[arg(long)]
+    pub verbose: bool,
 }
@@ -1,14 +1,26 @@
 //! Contains functionality of pause container command
 use std::path::PathBuf;
 
-use anyhow::{Context, Result};
-use liboci_cli::Checkpoint;
+use anyhow::{Context, Result, bail};
+use libcontainer::container::checkpoint_descriptor::{CheckpointDescriptor, HostInfo};
+use liboci_cli::{Checkpoint, CheckpointCmd, CheckpointInspect};
 
 use crate::commands::load_container;
 
-pub fn checkpoint(args: Checkpoint, root_path: PathBuf) -> Result<()> {
-    tracing::debug!("start checkpointing container {}", args.container_id);
-    let mut container = load_container(root_path, &args.container_id)?;
+pub fn checkpoint(
+    args: Checkpoint,
+    root_path: PathBuf,
+    criu_path: Option<PathBuf>,
+    cgroup_root: Option<PathBuf>,
+) -> Result<()> {
+    if let Some(CheckpointCmd::Inspect(inspect_args)) = args.command {
+        return inspect(inspect_args, criu_path, cgroup_root);
+    }
+
+    // clap requires the container id if no subcommand is given
+    let container_id = args.container_id.context("container id is required")?;
+    tracing::debug!("start checkpointing container {}", container_id);
+    let mut container = load_container(root_path, &container_id)?;
     let opts = libcontainer::container::CheckpointOptions {
         ext_unix_sk: args.ext_unix_sk,
         file_locks: args.file_locks,
@@ -20,10 +32,48 @@ pub fn checkpoint(args: Checkpoint, root_path: PathBuf) -> Result<()> {
         work_path: args.work_path,
         manage_cgroups_mode: parse_cgroups_mode(&args.manage_cgroups_mode)?,
         link_remap: args.link_remap,
+        criu_path,
+        pre_dump: args.pre_dump,
+        parent_path: args.parent_path,
+        auto_dedup: args.auto_dedup,
+        page_server: args
+            .page_server
+            .as_deref()
+            .map(parse_page_server)
+            .transpose()?,
+        lazy_pages: args.lazy_pages,
     };
     container
         .checkpoint(&opts)
-        .with_context(|| format!("failed to checkpoint container {}", args.container_id))
+        .with_context(|| format!("failed to checkpoint container {}", container_id))
+}
+
+/// Prints the descriptor of a checkpoint and validates it against this host
+fn inspect(
+    args: CheckpointInspect,
+    criu_path: Option<PathBuf>,
+    cgroup_root: Option<PathBuf>,
+) -> Result<()> {
+    let descriptor = CheckpointDescriptor::load(&args.image_path)
+        .with_context(|| format!("{:?} does not contain a youki checkpoint", args.image_path))?;
+    println!("{}", serde_json::to_string_pretty(&descriptor)?);
+
+    let host = HostInfo::current(criu_path.as_deref(), cgroup_root.as_deref())?;
+    let issues = descriptor.validate(&args.image_path, &host);
+    for issue in &issues {
+        if issue.is_fatal() {
+            eprintln!("error: {issue}");
+        } else {
+            eprintln!("warning: {issue}");
+        }
+    }
+    if issues.iter().any(|issue| issue.is_fatal()) {
+        bail!(
+            "checkpoint in {:?} cannot be restored on this host",
+            args.image_path
+        );
+    }
+    Ok(())
 }
 
 fn parse_cgroups_mode(s: &str) -> Result<rust_criu::CgMode, anyhow::Error> {
@@ -36,6 +86,18 @@ fn parse_cgroups_mode(s: &str) -> Result<rust_criu::CgMode, anyhow::Error> {
     }
 }
 
+fn parse_page_server(s: &str) -> Result<(String, i32)> {
+    let (address, port) = s
+        .rsplit_once(':')
+        .with_context(|| format!("page server {s:?} must be ADDRESS:PORT"))?;
+    let port = port
+        .parse()
+        .with_context(|| format!("invalid page server port {port:?}"))?;
+    // IPv6 addresses are written in brackets, CRIU expects them without
+    let address = address.trim_start_matches('[').trim_end_matches(']');
+    Ok((address.to_owned(), port))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -67,4 +129,18 @@ mod tests {
         assert!(parse_cgroups_mode("unknown").is_err());
         assert!(parse_cgroups_mode("").is_err());
     }
+
+    #[test]
+    fn test_parse_page_server() {
+        assert_eq!(
+            parse_page_server("192.168.0.2:27000").unwrap(),
+            ("192.168.0.2".to_owned(), 27000)
+        );
+        assert_eq!(
+            parse_page_server("[::1]:27000").unwrap(),
+            ("::1".to_owned(), 27000)
+        );
+        assert!(parse_page_server("192.168.0.2").is_err());
+        assert!(parse_page_server("192.168.0.2:port").is_err());
+    }
 }
@@ -2,30 +2,91 @@
 use std::path::PathBuf;
 
 use anyhow::Result;
+use libcontainer::container::Container;
 use libcontainer::container::builder::ContainerBuilder;
 use libcontainer::syscall::syscall::SyscallType;
+use libcontainer::utils::RootlessMode;
 use liboci_cli::Create;
 
-use crate::workload::executor::default_executor;
+use super::{save_log_monitor_pid, spawn_log_monitor};
+use crate::observability;
+use crate::shim;
+use crate::workload::executor::bundle_executor;
 
 // One thing to note is that in the end, container is just another process in Linux
 // it has specific/different control group, namespace, using which program executing in it
 // can be given impression that is is running on a complete system, but on the system which
 // it is running, it is just another process, and has attributes such as pid, file descriptors, etc.
 // associated with it like any other process.
-pub fn create(args: Create, root_path: PathBuf, systemd_cgroup: bool) -> Result<()> {
-    ContainerBuilder::new(args.container_id.clone(), SyscallType::default())
-        .with_executor(default_executor())
+pub fn create(
+    args: Create,
+    root_path: PathBuf,
+    systemd_cgroup: bool,
+    cgroup_root: Option<PathBuf>,
+    rootless: RootlessMode,
+) -> Result<()> {
+    let annotations = observability::bundle_annotations(&args.bundle);
+    let _span =
+        observability::command_span("create", &args.container_id, annotations.as_ref()).entered();
+
+    if args.shim {
+        return shim::spawn(
+            || {
+                create_container(
+                    &args,
+                    root_path.clone(),
+                    systemd_cgroup,
+                    cgroup_root.clone(),
+                    rootless,
+                )
+            },
+            None,
+            rootless,
+        );
+    }
+
+    create_container(&args, root_path, systemd_cgroup, cgroup_root, rootless)?;
+    Ok(())
+}
+
+fn create_container(
+    args: &Create,
+    root_path: PathBuf,
+    systemd_cgroup: bool,
+    cgroup_root: Option<PathBuf>,
+    rootless: RootlessMode,
+) -> Result<Container> {
+    let log_monitor = spawn_log_monitor(
+        &args.bundle,
+        &args.container_log,
+        args.console_socket.as_ref(),
+    )?;
+    let mut builder = ContainerBuilder::new(args.container_id.clone(), SyscallType::default());
+    let mut log_monitor_pid = None;
+    if let Some(monitor) = log_monitor {
+        builder = builder
+            .with_stdout(monitor.stdout)
+            .with_stderr(monitor.stderr);
+        log_monitor_pid = Some(monitor.pid);
+    }
+
+    let container = builder
+        .with_executor(bundle_executor(&root_path, &args.bundle))
         .with_pid_file(args.pid_file.as_ref())?
         .with_console_socket(args.console_socket.as_ref())
         .with_root_path(root_path)?
         .with_preserved_fds(args.preserve_fds)
+        .with_no_new_keyring(args.no_new_keyring)
+        .with_rootless(rootless)
         .validate_id()?
         .as_init(&args.bundle)
+        .with_notify_socket(std::env::var_os("NOTIFY_SOCKET"))
         .with_systemd(systemd_cgroup)
+        .with_cgroup_root(cgroup_root)
         .with_detach(true)
         .with_no_pivot(args.no_pivot)
         .build()?;
+    save_log_monitor_pid(&container, log_monitor_pid)?;
 
-    Ok(())
+    Ok(container)
 }
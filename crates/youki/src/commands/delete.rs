@@ -4,6 +4,7 @@ use anyhow::{Context, Result};
 use liboci_cli::Delete;
 
 use crate::commands::{container_exists, load_container};
+use crate::observability;
 
 pub fn delete(args: Delete, root_path: PathBuf) -> Result<()> {
     tracing::debug!("start deleting {}", args.container_id);
@@ -12,6 +13,12 @@ pub fn delete(args: Delete, root_path: PathBuf) -> Result<()> {
     }
 
     let mut container = load_container(root_path, &args.container_id)?;
+    let _span = observability::command_span(
+        "delete",
+        &args.container_id,
+        container.state.annotations.as_ref(),
+    )
+    .entered();
     container
         .delete(args.force)
         .with_context(|| format!("failed to delete container {}", args.container_id))
@@ -0,0 +1,203 @@
+//! Explains how the cgroup v2 device controller treats accesses of a container
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use anyhow::{Context, Result, bail};
+use clap::{Args, Subcommand};
+use libcgroups::v2::devices::Devices as DevicesController;
+use libcgroups::v2::devices::emulator::Emulator;
+use libcgroups::v2::devices::program::Program;
+use oci_spec::runtime::{LinuxDeviceCgroup, LinuxDeviceType, Spec};
+
+/// Inspect the device cgroup policy of a bundle without starting a container
+#[derive(Args, Debug)]
+pub struct Devices {
+    #[command(subcommand)]
+    pub cmd: DevicesCmd,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum DevicesCmd {
+    Check(Check),
+    Dump(Dump),
+}
+
+/// Check whether the container may access a device
+#[derive(Args, Debug)]
+pub struct Check {
+    /// Path to the bundle directory, containing config.json
+    #[arg(short, long, default_value = ".")]
+    pub bundle: PathBuf,
+    /// Type of the device, 'c' for char and 'b' for block devices
+    #[arg(value_parser = parse_device_type)]
+    pub device_type: LinuxDeviceType,
+    /// Device number, as MAJOR:MINOR
+    #[arg(value_parser = parse_device_number)]
+    pub device: (u32, u32),
+    /// Requested access, any combination of 'r', 'w' and 'm'
+    #[arg(short, long, default_value = "rw", value_parser = parse_access)]
+    pub access: String,
+}
+
+/// Print the effective device rules and the disassembly of the generated BPF program
+#[derive(Args, Debug)]
+pub struct Dump {
+    /// Path to the bundle directory, containing config.json
+    #[arg(short, long, default_value = ".")]
+    pub bundle: PathBuf,
+}
+
+pub fn devices(args: Devices) -> Result<()> {
+    match args.cmd {
+        DevicesCmd::Check(check) => {
+            let emulator = load_emulator(&check.bundle)?;
+            let (major, minor) = check.device;
+            println!(
+                "{}",
+                explain(&emulator, check.device_type, major, minor, &check.access)
+            );
+        }
+        DevicesCmd::Dump(dump) => {
+            let emulator = load_emulator(&dump.bundle)?;
+            println!(
+                "default: {}",
+                if emulator.default_allow {
+                    "allow"
+                } else {
+                    "deny"
+                }
+            );
+            println!("rules (the last matching rule wins):");
+            for rule in &emulator.rules {
+                println!("  {}", format_rule(rule));
+            }
+
+            let prog = Program::from_rules(&emulator.rules, emulator.default_allow)
+                .context("failed to generate BPF program")?;
+            println!("program:");
+            for (pc, insn) in prog.disassemble().iter().enumerate() {
+                println!("{pc:4}: {insn}");
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn load_emulator(bundle: &Path) -> Result<Emulator> {
+    let config = bundle.join("config.json");
+    let spec = Spec::load(&config)
+        .with_context(|| format!("failed to load spec from {}", config.display()))?;
+    let devices = spec
+        .linux()
+        .as_ref()
+        .and_then(|linux| linux.resources().as_ref())
+        .and_then(|resources| resources.devices().clone());
+
+    Ok(DevicesController::emulator(&devices))
+}
+
+fn explain(
+    emulator: &Emulator,
+    typ: LinuxDeviceType,
+    major: u32,
+    minor: u32,
+    access: &str,
+) -> String {
+    let verdict = |allow: bool| if allow { "allowed" } else { "denied" };
+    let device = format!("{} {major}:{minor} {access}", typ.as_str());
+    match emulator.matching_rule(typ, major, minor, access) {
+        Some(rule) => format!(
+            "{device}: {} by rule '{}'",
+            verdict(rule.allow()),
+            format_rule(rule)
+        ),
+        None => format!(
+            "{device}: {} by default, no rule matches",
+            verdict(emulator.default_allow)
+        ),
+    }
+}
+
+/// Formats a rule like an entry of devices.list in cgroup v1
+fn format_rule(rule: &LinuxDeviceCgroup) -> String {
+    let number = |n: Option<i64>| match n {
+        Some(n) if n >= 0 => n.to_string(),
+        _ => "*".to_owned(),
+    };
+    format!(
+        "{} {} {}:{} {}",
+        if rule.allow() { "allow" } else { "deny" },
+        rule.typ().unwrap_or_default().as_str(),
+        number(rule.major()),
+        number(rule.minor()),
+        rule.access().as_deref().unwrap_or_default()
+    )
+}
+
+fn parse_device_type(s: &str) -> Result<LinuxDeviceType> {
+    match LinuxDeviceType::from_str(s) {
+        Ok(typ @ (LinuxDeviceType::C | LinuxDeviceType::B)) => Ok(typ),
+        _ => bail!("device type must be 'c' or 'b', got '{s}'"),
+    }
+}
+
+fn parse_device_number(s: &str) -> Result<(u32, u32)> {
+    let (major, minor) = s
+        .split_once(':')
+        .with_context(|| format!("device number must be MAJOR:MINOR, got '{s}'"))?;
+    Ok((
+        major.parse().context("invalid major number")?,
+        minor.parse().context("invalid minor number")?,
+    ))
+}
+
+fn parse_access(s: &str) -> Result<String> {
+    if let Some(c) = s.chars().find(|c| !matches!(c, 'r' | 'w' | 'm')) {
+        bail!("invalid access '{c}', expected any combination of 'r', 'w' and 'm'");
+    }
+    Ok(s.to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use oci_spec::runtime::LinuxDeviceCgroupBuilder;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_args() {
+        assert_eq!(parse_device_type("c").unwrap(), LinuxDeviceType::C);
+        assert!(parse_device_type("a").is_err());
+        assert_eq!(parse_device_number("10:200").unwrap(), (10, 200));
+        assert!(parse_device_number("10").is_err());
+        assert!(parse_device_number("10:x").is_err());
+        assert_eq!(parse_access("rw").unwrap(), "rw");
+        assert!(parse_access("rx").is_err());
+    }
+
+    #[test]
+    fn test_explain() {
+        let nvidia = LinuxDeviceCgroupBuilder::default()
+            .allow(true)
+            .typ(LinuxDeviceType::C)
+            .major(195)
+            .access("rw")
+            .build()
+            .unwrap();
+        let emulator = DevicesController::emulator(&Some(vec![nvidia]));
+
+        assert_eq!(
+            explain(&emulator, LinuxDeviceType::C, 195, 0, "rw"),
+            "c 195:0 rw: allowed by rule 'allow c 195:* rw'"
+        );
+        assert_eq!(
+            explain(&emulator, LinuxDeviceType::C, 10, 200, "rw"),
+            "c 10:200 rw: allowed by rule 'allow c 10:200 rwm'"
+        );
+        assert_eq!(
+            explain(&emulator, LinuxDeviceType::B, 8, 0, "r"),
+            "b 8:0 r: denied by default, no rule matches"
+        );
+    }
+}
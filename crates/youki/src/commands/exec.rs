@@ -1,51 +1,196 @@
-use std::path::PathBuf;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
 
-use anyhow::Result;
+use anyhow::{Result, bail};
+use chrono::{DateTime, Local};
+use libcontainer::container::Container;
 use libcontainer::container::builder::ContainerBuilder;
+use libcontainer::exec_session::ExecSession;
 use libcontainer::syscall::syscall::SyscallType;
+use libcontainer::utils::RootlessMode;
 use liboci_cli::Exec;
 use nix::sys::wait::{WaitStatus, waitpid};
+use nix::unistd::Pid;
+use serde::Serialize;
+use tabwriter::TabWriter;
 
-use crate::workload::executor::default_executor;
+use super::run::{exit_code, handle_foreground};
+use super::{construct_container_root, load_container};
+use crate::console::Console;
+use crate::shim;
+use crate::workload::executor::{bundle_executor, default_executor};
 
-pub fn exec(args: Exec, root_path: PathBuf) -> Result<i32> {
+pub fn exec(args: Exec, root_path: PathBuf, rootless: RootlessMode) -> Result<i32> {
+    if args.list {
+        list(&args, root_path)?;
+        return Ok(0);
+    }
+    if args.format.is_some() {
+        bail!("--format is only valid with --list");
+    }
+
+    let container_root = construct_container_root(&root_path, &args.container_id)?;
+    let exec_id = args
+        .exec_id
+        .clone()
+        .unwrap_or_else(ExecSession::generate_id);
+    // A missing container is reported when the process is built
+    let bundle = Container::load(container_root.clone())
+        .ok()
+        .map(|container| container.bundle().clone());
     let user = args.user.map(|(u, _)| u);
     let group = args.user.and_then(|(_, g)| g);
 
-    let pid = ContainerBuilder::new(args.container_id.clone(), SyscallType::default())
-        .with_executor(default_executor())
-        .with_root_path(root_path)?
-        .with_console_socket(args.console_socket.as_ref())
-        .with_pid_file(args.pid_file.as_ref())?
-        .with_preserved_fds(args.preserve_fds)
-        .validate_id()?
-        .as_tenant()
-        .with_detach(args.detach)
-        .with_cwd(args.cwd.as_ref())
-        .with_env(args.env.clone().into_iter().collect())
-        .with_process(args.process.as_ref())
-        .with_no_new_privs(args.no_new_privs)
-        .with_container_args(args.command.clone())
-        .with_additional_gids(args.additional_gids)
-        .with_user(user)
-        .with_group(group)
-        .with_capabilities(args.cap)
-        .with_ignore_paused(args.ignore_paused)
-        .with_sub_cgroup(args.cgroup)
-        .with_apparmor(args.apparmor)
-        .build()?;
+    // Without a console socket, youki proxies the terminal of the process.
+    let (terminal_socket, container_terminal_socket) =
+        if !args.detach && args.console_socket.is_none() {
+            let (socket, container_socket) = Console::sockets()?;
+            (Some(socket), Some(container_socket))
+        } else {
+            (None, None)
+        };
+
+    let build = || -> Result<Pid> {
+        let pid = ContainerBuilder::new(args.container_id.clone(), SyscallType::default())
+            .with_executor(match &bundle {
+                Some(bundle) => bundle_executor(&root_path, bundle),
+                None => default_executor(None),
+            })
+            .with_root_path(root_path.clone())?
+            .with_console_socket(args.console_socket.as_ref())
+            .with_terminal_socket(container_terminal_socket)
+            .with_pid_file(args.pid_file.as_ref())?
+            .with_preserved_fds(args.preserve_fds)
+            .with_rootless(rootless)
+            .validate_id()?
+            .as_tenant()
+            .with_detach(args.detach)
+            .with_cwd(args.cwd.as_ref())
+            .with_env(args.env.clone().into_iter().collect())
+            .with_process(args.process.as_ref())
+            .with_no_new_privs(args.no_new_privs)
+            .with_container_args(args.command.clone())
+            .with_additional_gids(args.additional_gids.clone())
+            .with_user(user)
+            .with_group(group)
+            .with_capabilities(args.cap.clone())
+            .with_ignore_paused(args.ignore_paused)
+            .with_sub_cgroup(args.cgroup.clone())
+            .with_apparmor(args.apparmor.clone())
+            .with_exec_id(Some(exec_id.clone()))
+            .build()?;
+        Ok(pid)
+    };
 
     // See https://github.com/youki-dev/youki/pull/1252 for a detailed explanation
     // basically, if there is any error in starting exec, the build above will return error
     // however, if the process does start, and detach is given, we do not wait for it
-    // if not detached, then we wait for it using waitpid below
+    // if not detached, then we wait for it using waitpid below. A detached process
+    // is reparented as runc does, so that the caller reaping it gets its exit
+    // status, unless it is given an ID: a monitor then waits for it to record
+    // its exit.
     if args.detach {
+        if args.exec_id.is_some() {
+            shim::spawn_exec(build, |status| {
+                record_exit(&container_root, &exec_id, status)
+            })?;
+        } else {
+            build()?;
+        }
         return Ok(0);
     }
 
-    match waitpid(pid, None)? {
-        WaitStatus::Exited(_, status) => Ok(status),
-        WaitStatus::Signaled(_, sig, _) => Ok(sig as i32),
-        _ => Ok(0),
+    let pid = build()?;
+    let status = if let Some(socket) = &terminal_socket
+        && let Some(mut console) = Console::receive(socket)?
+    {
+        // Forward window size changes while waiting for the process.
+        let status = handle_foreground(pid, Some(&console));
+        console.wait();
+        status?
+    } else {
+        waitpid(pid, None)?
+    };
+
+    record_exit(&container_root, &exec_id, status);
+    Ok(exit_code(status))
+}
+
+fn record_exit(container_root: &Path, exec_id: &str, status: WaitStatus) {
+    let recorded = ExecSession::load(container_root, exec_id).and_then(|mut session| {
+        session.record_exit(status)?;
+        session.save(container_root)
+    });
+    if let Err(err) = recorded {
+        tracing::warn!(?err, exec_id, "failed to record the exit of the process");
+    }
+}
+
+/// Status of an exec session, whose exit is unknown if its process was not
+/// reaped by youki
+fn session_status(session: &ExecSession) -> &'static str {
+    if session.finished_at.is_some() {
+        "exited"
+    } else if session.is_running() {
+        "running"
+    } else {
+        "unknown"
     }
 }
+
+#[derive(Serialize)]
+struct ExecSessionExporter<'a> {
+    #[serde(flatten)]
+    session: &'a ExecSession,
+    status: &'static str,
+}
+
+/// Lists the processes executed in a container
+fn list(args: &Exec, root_path: PathBuf) -> Result<()> {
+    let format = args.format.as_deref().unwrap_or("table");
+    if !matches!(format, "table" | "json") {
+        bail!("invalid format {format:?}, expected table or json");
+    }
+
+    let container = load_container(root_path, &args.container_id)?;
+    let sessions = container.exec_sessions()?;
+    if format == "json" {
+        let sessions: Vec<_> = sessions
+            .iter()
+            .map(|session| ExecSessionExporter {
+                session,
+                status: session_status(session),
+            })
+            .collect();
+        println!("{}", serde_json::to_string_pretty(&sessions)?);
+        return Ok(());
+    }
+
+    let mut tab_writer = TabWriter::new(io::stdout());
+    writeln!(
+        &mut tab_writer,
+        "ID\tPID\tSTATUS\tSTARTED\tUSER\tTTY\tCOMMAND"
+    )?;
+    for session in &sessions {
+        let status = match session.exit_code {
+            Some(code) => format!("exited ({code})"),
+            None => session_status(session).to_owned(),
+        };
+        let started: DateTime<Local> = DateTime::from(session.started_at);
+        writeln!(
+            &mut tab_writer,
+            "{}\t{}\t{}\t{}\t{}:{}\t{}\t{}",
+            session.id,
+            session.pid,
+            status,
+            started.to_rfc3339_opts(chrono::SecondsFormat::Secs, false),
+            session.uid,
+            session.gid,
+            session.tty,
+            session.args.join(" ")
+        )?;
+    }
+    tab_writer.flush()?;
+
+    Ok(())
+}
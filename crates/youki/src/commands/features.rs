@@ -1,4 +1,6 @@
 //! Contains Functionality of `features` container command
+use std::collections::HashMap;
+
 use anyhow::Result;
 use libcontainer::oci_spec::runtime::{
     ApparmorBuilder, CgroupBuilder, FeaturesBuilder, IDMapBuilder, IntelRdtBuilder,
@@ -7,6 +9,11 @@ use libcontainer::oci_spec::runtime::{
 use libcontainer::syscall::linux::MountOption;
 use liboci_cli::Features;
 
+use crate::workload::executor::default_executor;
+
+/// Annotation listing the executors youki is built with, in order of priority
+const EXECUTORS_ANNOTATION: &str = "io.github.youki-dev.youki.executors";
+
 // Function to query and return capabilities
 fn query_caps() -> Result<Vec<String>> {
     Ok(caps::all().iter().map(|cap| format!("{:?}", cap)).collect())
@@ -96,6 +103,10 @@ pub fn features(_: Features) -> Result<()> {
         .hooks(known_hooks())
         .mount_options(MountOption::known_options())
         .linux(linux)
+        .annotations(HashMap::from([(
+            EXECUTORS_ANNOTATION.to_owned(),
+            default_executor(None).names().join(","),
+        )]))
         .build()
         .unwrap();
 
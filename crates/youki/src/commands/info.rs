@@ -10,12 +10,15 @@ use clap::Args;
 use libcgroups::{common::CgroupSetup, v2::controller_type::ControllerType};
 use libcontainer::user_ns;
 use procfs::{CpuInfo, Current, Meminfo};
+
+use crate::workload::executor::default_executor;
 /// Show information about the system
 #[derive(Args, Debug)]
 pub struct Info {}
 
 pub fn info(_: Info) -> Result<()> {
     print_youki();
+    print_executors();
     print_kernel();
     print_os();
     print_hardware();
@@ -50,6 +53,15 @@ pub fn print_youki() {
     );
 }
 
+/// Prints the executors youki is built with, in order of priority
+pub fn print_executors() {
+    println!(
+        "{:<18}{}",
+        "Executors",
+        default_executor(None).names().join(", ")
+    );
+}
+
 /// Print Kernel Release, Version and Architecture
 pub fn print_kernel() {
     let uname = nix::sys::utsname::uname().unwrap();
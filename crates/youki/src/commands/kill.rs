@@ -3,16 +3,23 @@ use std::convert::TryInto;
 use std::path::PathBuf;
 
 use anyhow::{Result, anyhow};
-use libcontainer::container::ContainerStatus;
+use libcontainer::container::{Container, ContainerStatus};
 use libcontainer::signal::Signal;
 use liboci_cli::Kill;
+use nix::sys::signal::Signal as NixSignal;
+use nix::sys::wait::WaitStatus;
 
 use crate::commands::load_container;
 
 pub fn kill(args: Kill, root_path: PathBuf) -> Result<()> {
     let mut container = load_container(root_path, &args.container_id)?;
     let signal: Signal = args.signal.as_str().try_into()?;
+    if let Some(exec_id) = &args.exec {
+        container.exec_session(exec_id)?.kill(signal)?;
+        return Ok(());
+    }
     match container.kill(signal, args.all) {
+        Ok(_) if args.wait => wait_for_exit(&mut container, signal),
         Ok(_) => Ok(()),
         Err(e) => {
             // see https://github.com/youki-dev/youki/issues/1314
@@ -23,3 +30,21 @@ pub fn kill(args: Kill, root_path: PathBuf) -> Result<()> {
         }
     }
 }
+
+fn wait_for_exit(container: &mut Container, signal: Signal) -> Result<()> {
+    let pid = container.pid();
+    container.wait()?;
+    // The exit status is recorded by the monitor of the container, if any.
+    // Otherwise youki is not the parent of the container process, and only
+    // knows how it ended if it could not survive the signal.
+    if container.finished_at().is_some() {
+        return Ok(());
+    }
+    match pid {
+        Some(pid) if signal == NixSignal::SIGKILL.into() => {
+            container.record_exit(WaitStatus::Signaled(pid, NixSignal::SIGKILL, false))?
+        }
+        _ => container.record_stop()?,
+    }
+    Ok(())
+}
@@ -4,19 +4,25 @@ use std::io::Write;
 use std::path::PathBuf;
 use std::{fs, io};
 
-use anyhow::Result;
+use anyhow::{Result, bail};
 use chrono::{DateTime, Local};
 use libcontainer::container::Container;
 use libcontainer::container::state::State;
 use liboci_cli::List;
 use tabwriter::TabWriter;
 
+use crate::commands::state::StateExporter;
+
 /// lists all existing containers
-pub fn list(_: List, root_path: PathBuf) -> Result<()> {
+pub fn list(args: List, root_path: PathBuf) -> Result<()> {
+    if !matches!(args.format.as_str(), "table" | "json") {
+        bail!("invalid format {:?}, expected table or json", args.format);
+    }
+
     let root_path = fs::canonicalize(root_path)?;
-    let mut content = String::new();
+    let mut containers = Vec::new();
     // all containers' data is stored in their respective dir in root directory
-    // so we iterate through each and print the various info
+    // so we iterate through each and collect their state
     for container_dir in fs::read_dir(root_path)? {
         let container_dir = container_dir?.path();
         let state_file = State::file_path(&container_dir);
@@ -24,7 +30,20 @@ pub fn list(_: List, root_path: PathBuf) -> Result<()> {
             continue;
         }
 
-        let container = Container::load(container_dir)?;
+        containers.push(Container::load(container_dir)?);
+    }
+
+    if args.format == "json" {
+        let states: Vec<StateExporter> = containers
+            .iter()
+            .map(|container| StateExporter::from(&container.state))
+            .collect();
+        println!("{}", serde_json::to_string_pretty(&states)?);
+        return Ok(());
+    }
+
+    let mut content = String::new();
+    for container in &containers {
         let pid = if let Some(pid) = container.pid() {
             pid.to_string()
         } else {
@@ -4,11 +4,19 @@ use std::path::{Path, PathBuf};
 use anyhow::{Context, Result, bail};
 use libcgroups::common::AnyCgroupManager;
 use libcontainer::container::Container;
+use libcontainer::log_sink::{
+    LOG_MAX_FILES_ANNOTATION, LOG_MAX_SIZE_ANNOTATION, LOG_PATH_ANNOTATION, LogMonitor, LogSink,
+};
+use libcontainer::oci_spec::runtime::Spec;
+use liboci_cli::ContainerLog;
+use nix::unistd::Pid;
 
 pub mod checkpoint;
 pub mod completion;
 pub mod create;
 pub mod delete;
+#[cfg(feature = "v2")]
+pub mod devices;
 pub mod events;
 pub mod exec;
 pub mod features;
@@ -47,6 +55,73 @@ fn load_container<P: AsRef<Path>>(root_path: P, container_id: &str) -> Result<Co
         .with_context(|| format!("could not load state for container {container_id}"))
 }
 
+/// File in the container directory holding the pid of the log monitor, to be
+/// signaled with SIGUSR1 to reopen the log file
+const LOG_MONITOR_PID_FILE: &str = "log_monitor.pid";
+
+/// Starts forwarding the output of the container to the log file set by the
+/// flags, or else by the annotations of the bundle
+fn spawn_log_monitor(
+    bundle: &Path,
+    log: &ContainerLog,
+    console_socket: Option<&PathBuf>,
+) -> Result<Option<LogMonitor>> {
+    // A spec failing to load fails the build of the container later on.
+    let spec = Spec::load(bundle.join("config.json")).ok();
+    let mut annotations = spec
+        .as_ref()
+        .and_then(|spec| spec.annotations().clone())
+        .unwrap_or_default();
+    let overrides = [
+        (
+            LOG_PATH_ANNOTATION,
+            log.container_log
+                .as_ref()
+                .map(|path| path.to_string_lossy().into_owned()),
+        ),
+        (LOG_MAX_SIZE_ANNOTATION, log.container_log_max_size.clone()),
+        (
+            LOG_MAX_FILES_ANNOTATION,
+            log.container_log_max_files.map(|files| files.to_string()),
+        ),
+    ];
+    for (key, value) in overrides {
+        if let Some(value) = value {
+            annotations.insert(key.to_owned(), value);
+        }
+    }
+
+    let Some(sink) = LogSink::from_annotations(&annotations)? else {
+        if log.container_log_max_size.is_some() || log.container_log_max_files.is_some() {
+            bail!("the container log options require a container log path");
+        }
+        return Ok(None);
+    };
+    if console_socket.is_some() {
+        bail!("a container log cannot be combined with a console socket");
+    }
+    // The output of a container with a terminal goes to the terminal.
+    if spec
+        .as_ref()
+        .and_then(|spec| spec.process().as_ref())
+        .and_then(|process| process.terminal())
+        .unwrap_or(false)
+    {
+        bail!("a container log cannot be combined with a terminal");
+    }
+    Ok(Some(sink.spawn().with_context(|| {
+        format!("failed to forward output to {}", sink.path().display())
+    })?))
+}
+
+fn save_log_monitor_pid(container: &Container, monitor: Option<Pid>) -> Result<()> {
+    if let Some(pid) = monitor {
+        fs::write(container.root.join(LOG_MONITOR_PID_FILE), pid.to_string())
+            .context("failed to save the pid of the log monitor")?;
+    }
+    Ok(())
+}
+
 fn container_exists<P: AsRef<Path>>(root_path: P, container_id: &str) -> Result<bool> {
     let container_root = construct_container_root(root_path, container_id)?;
     Ok(container_root.exists())
@@ -62,6 +137,8 @@ fn create_cgroup_manager<P: AsRef<Path>>(
             cgroup_path: container.spec()?.cgroup_path,
             systemd_cgroup: container.systemd(),
             container_name: container.id().to_string(),
+            cgroup_root: container.cgroup_root().cloned(),
+            rootless: container.rootless(),
         },
     )?)
 }
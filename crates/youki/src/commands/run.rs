@@ -1,32 +1,94 @@
+use std::os::fd::OwnedFd;
 use std::path::PathBuf;
 
 use anyhow::{Context, Result};
 use libcontainer::container::builder::ContainerBuilder;
+use libcontainer::container::{Container, ContainerStatus, StartOptions};
 use libcontainer::syscall::syscall::SyscallType;
+use libcontainer::utils::RootlessMode;
 use liboci_cli::Run;
+use nix::errno::Errno;
+use nix::sys::prctl;
 use nix::sys::signal::{self, kill};
 use nix::sys::signalfd::SigSet;
 use nix::sys::wait::{WaitPidFlag, WaitStatus, waitpid};
 use nix::unistd::Pid;
 
-use crate::workload::executor::default_executor;
+use super::{save_log_monitor_pid, spawn_log_monitor};
+use crate::console::Console;
+use crate::observability;
+use crate::shim;
+use crate::workload::executor::bundle_executor;
 
-pub fn run(args: Run, root_path: PathBuf, systemd_cgroup: bool) -> Result<i32> {
-    let mut container = ContainerBuilder::new(args.container_id.clone(), SyscallType::default())
-        .with_executor(default_executor())
-        .with_pid_file(args.pid_file.as_ref())?
-        .with_console_socket(args.console_socket.as_ref())
-        .with_root_path(root_path)?
-        .with_preserved_fds(args.preserve_fds)
-        .validate_id()?
-        .as_init(&args.bundle)
-        .with_systemd(systemd_cgroup)
-        .with_detach(args.detach)
-        .with_no_pivot(args.no_pivot)
-        .build()?;
+pub fn run(
+    args: Run,
+    root_path: PathBuf,
+    systemd_cgroup: bool,
+    cgroup_root: Option<PathBuf>,
+    rootless: RootlessMode,
+) -> Result<i32> {
+    let annotations = observability::bundle_annotations(&args.bundle);
+    let _span =
+        observability::command_span("run", &args.container_id, annotations.as_ref()).entered();
+
+    if args.shim {
+        return shim::spawn(
+            || {
+                create_container(
+                    &args,
+                    root_path.clone(),
+                    systemd_cgroup,
+                    cgroup_root.clone(),
+                    rootless,
+                    None,
+                )
+            },
+            Some(StartOptions {
+                notify_socket: std::env::var_os("NOTIFY_SOCKET").map(PathBuf::from),
+                wait_ready: false,
+                foreground: false,
+            }),
+            rootless,
+        )
+        .map(|_| 0);
+    }
+
+    // Processes orphaned in the container are reparented to youki instead of
+    // the host init, so that `handle_foreground` reaps them.
+    if !args.detach && !args.no_subreaper {
+        prctl::set_child_subreaper(true).context("failed to set child subreaper")?;
+    }
+
+    // Without a console socket, youki proxies the terminal of the container.
+    let (terminal_socket, container_terminal_socket) =
+        if !args.detach && args.console_socket.is_none() {
+            let (socket, container_socket) = Console::sockets()?;
+            (Some(socket), Some(container_socket))
+        } else {
+            (None, None)
+        };
+
+    let mut container = create_container(
+        &args,
+        root_path,
+        systemd_cgroup,
+        cgroup_root,
+        rootless,
+        container_terminal_socket,
+    )?;
+
+    let mut console = match &terminal_socket {
+        Some(socket) => Console::receive(socket)?,
+        None => None,
+    };
 
     container
-        .start()
+        .start_with_options(&StartOptions {
+            notify_socket: std::env::var_os("NOTIFY_SOCKET").map(PathBuf::from),
+            wait_ready: false,
+            // The terminal of the container is proxied by threads
+            foreground: !args.detach,
+        })
         .with_context(|| format!("failed to start container {}", args.container_id))?;
 
     if args.detach {
@@ -40,19 +102,76 @@ pub fn run(args: Run, root_path: PathBuf, systemd_cgroup: bool) -> Result<i32> {
         container.pid().is_some(),
         "expects a container init pid in the container state"
     );
-    let foreground_result = handle_foreground(container.pid().unwrap());
-    // execute the destruction action after the container finishes running
-    container.delete(true)?;
+    let foreground_result = handle_foreground(container.pid().unwrap(), console.as_ref());
+    match &foreground_result {
+        // record the exit for the poststop hooks, and for a later `state`
+        // with `--keep`
+        Ok(status) => container.record_exit(*status)?,
+        Err(_) => container.set_status(ContainerStatus::Stopped).save()?,
+    }
+    if !args.keep {
+        // execute the destruction action after the container finishes running
+        container.delete(true)?;
+    }
+    if let Some(console) = &mut console {
+        console.wait();
+    }
     // return result
-    foreground_result
+    foreground_result.map(exit_code)
+}
+
+fn create_container(
+    args: &Run,
+    root_path: PathBuf,
+    systemd_cgroup: bool,
+    cgroup_root: Option<PathBuf>,
+    rootless: RootlessMode,
+    terminal_socket: Option<OwnedFd>,
+) -> Result<Container> {
+    let log_monitor = spawn_log_monitor(
+        &args.bundle,
+        &args.container_log,
+        args.console_socket.as_ref(),
+    )?;
+    let mut builder = ContainerBuilder::new(args.container_id.clone(), SyscallType::default());
+    let mut log_monitor_pid = None;
+    if let Some(monitor) = log_monitor {
+        builder = builder
+            .with_stdout(monitor.stdout)
+            .with_stderr(monitor.stderr);
+        log_monitor_pid = Some(monitor.pid);
+    }
+
+    let container = builder
+        .with_executor(bundle_executor(&root_path, &args.bundle))
+        .with_pid_file(args.pid_file.as_ref())?
+        .with_console_socket(args.console_socket.as_ref())
+        .with_terminal_socket(terminal_socket)
+        .with_root_path(root_path)?
+        .with_preserved_fds(args.preserve_fds)
+        .with_no_new_keyring(args.no_new_keyring)
+        .with_rootless(rootless)
+        .validate_id()?
+        .as_init(&args.bundle)
+        .with_notify_socket(std::env::var_os("NOTIFY_SOCKET"))
+        .with_systemd(systemd_cgroup)
+        .with_cgroup_root(cgroup_root)
+        .with_detach(args.detach)
+        .with_no_pivot(args.no_pivot)
+        .build()?;
+    save_log_monitor_pid(&container, log_monitor_pid)?;
+
+    Ok(container)
 }
 
 // handle_foreground will match the `runc` behavior running the foreground mode.
-// The youki main process will wait and reap the container init process. The
-// youki main process also forwards most of the signals to the container init
-// process.
-#[tracing::instrument(level = "trace")]
-fn handle_foreground(init_pid: Pid) -> Result<i32> {
+// The youki main process will wait and reap the container init process, and
+// any other descendant reparented to it as a subreaper. The exit status of the
+// init process is returned. The youki main process also forwards most of the signals to the
+// container init process. If youki proxies the terminal of the container, window size
+// changes are forwarded to it.
+#[tracing::instrument(level = "trace", skip(console))]
+pub(super) fn handle_foreground(init_pid: Pid, console: Option<&Console>) -> Result<WaitStatus> {
     tracing::trace!("waiting for container init process to exit");
     // We mask all signals here and forward most of the signals to the container
     // init process.
@@ -72,20 +191,14 @@ fn handle_foreground(init_pid: Pid) -> Result<i32> {
                 tracing::trace!("reaping child processes");
                 loop {
                     match waitpid(None, Some(WaitPidFlag::WNOHANG))? {
-                        WaitStatus::Exited(pid, status) => {
+                        status @ (WaitStatus::Exited(pid, _) | WaitStatus::Signaled(pid, _, _)) => {
                             if pid.eq(&init_pid) {
+                                reap_orphans()?;
                                 return Ok(status);
                             }
 
                             // Else, some random child process exited, ignoring...
                         }
-                        WaitStatus::Signaled(pid, signal, _) => {
-                            if pid.eq(&init_pid) {
-                                return Ok(signal as i32);
-                            }
-
-                            // Else, some random child process exited, ignoring...
-                        }
                         WaitStatus::StillAlive => {
                             // No more child to reap.
                             break;
@@ -99,7 +212,11 @@ fn handle_foreground(init_pid: Pid) -> Result<i32> {
                 // the container process. Here, we just ignore the signal.
             }
             signal::SIGWINCH => {
-                // TODO: resize the terminal
+                if let Some(console) = console {
+                    let _ = console.resize().map_err(|err| {
+                        tracing::warn!(?err, "failed to resize the terminal");
+                    });
+                }
             }
             signal => {
                 tracing::trace!(?signal, "forwarding signal");
@@ -116,6 +233,28 @@ fn handle_foreground(init_pid: Pid) -> Result<i32> {
     }
 }
 
+// Reaps the orphans that exited along with the container init process, so
+// that none is left as a zombie. Orphans still running are left to the host
+// init once youki exits.
+fn reap_orphans() -> Result<()> {
+    loop {
+        match waitpid(None, Some(WaitPidFlag::WNOHANG)) {
+            Ok(WaitStatus::StillAlive) | Err(Errno::ECHILD) => return Ok(()),
+            Ok(_) => {}
+            Err(err) => return Err(err).context("failed to reap orphaned processes"),
+        }
+    }
+}
+
+/// Exit code of a process, or 128 + signal number if it was killed by a signal
+pub(super) fn exit_code(status: WaitStatus) -> i32 {
+    match status {
+        WaitStatus::Exited(_, code) => code,
+        WaitStatus::Signaled(_, signal, _) => 128 + signal as i32,
+        _ => 0,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::time::Duration;
@@ -162,7 +301,7 @@ mod tests {
                 match unsafe { unistd::fork()? } {
                     unistd::ForkResult::Parent { child } => {
                         // Inside P1.
-                        let _ = handle_foreground(child).map_err(|err| {
+                        let _ = handle_foreground(child, None).map_err(|err| {
                             // Since we are in a child process, we want to use trace to log the error.
                             let _ = tracing_subscriber::fmt()
                                 .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
@@ -204,7 +343,7 @@ mod tests {
                 match unsafe { unistd::fork()? } {
                     unistd::ForkResult::Parent { child } => {
                         // Inside P1.
-                        handle_foreground(child)?;
+                        handle_foreground(child, None)?;
                         wait::waitpid(child, None)?;
                     }
                     unistd::ForkResult::Child => {
@@ -217,4 +356,46 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_foreground_reap_orphans() -> Result<()> {
+        // P1 mocks youki main as a subreaper and P2 mocks the container init
+        // process, which leaves an exited P3 behind and is killed by a signal.
+        // P3 is reparented to P1 as a zombie. P1 must reap both and report
+        // 128 + SIGKILL.
+        match unsafe { unistd::fork()? } {
+            unistd::ForkResult::Parent { child } => {
+                // Inside P0
+                let status = wait::waitpid(child, None)?;
+                assert_eq!(status, WaitStatus::Exited(child, 128 + 9));
+            }
+            unistd::ForkResult::Child => {
+                prctl::set_child_subreaper(true)?;
+                match unsafe { unistd::fork()? } {
+                    unistd::ForkResult::Parent { child } => {
+                        // Inside P1.
+                        let code = handle_foreground(child, None).map_or(-1, exit_code);
+                        // No child is left, not even a zombie. WNOWAIT leaves
+                        // a zombie in place instead of reaping it.
+                        let left = wait::waitid(
+                            wait::Id::All,
+                            WaitPidFlag::WEXITED | WaitPidFlag::WNOHANG | WaitPidFlag::WNOWAIT,
+                        );
+                        std::process::exit(if left == Err(Errno::ECHILD) { code } else { -1 });
+                    }
+                    unistd::ForkResult::Child => {
+                        // Inside P2.
+                        if let unistd::ForkResult::Child = unsafe { unistd::fork()? } {
+                            // Inside P3.
+                            std::process::exit(0);
+                        }
+                        std::thread::sleep(Duration::from_secs(1));
+                        signal::raise(signal::SIGKILL)?;
+                    }
+                };
+            }
+        };
+
+        Ok(())
+    }
 }
@@ -3,13 +3,25 @@
 use std::path::PathBuf;
 
 use anyhow::{Context, Result};
+use libcontainer::container::StartOptions;
 use liboci_cli::Start;
 
 use crate::commands::load_container;
+use crate::observability;
 
 pub fn start(args: Start, root_path: PathBuf) -> Result<()> {
     let mut container = load_container(root_path, &args.container_id)?;
+    let _span = observability::command_span(
+        "start",
+        &args.container_id,
+        container.state.annotations.as_ref(),
+    )
+    .entered();
     container
-        .start()
+        .start_with_options(&StartOptions {
+            notify_socket: std::env::var_os("NOTIFY_SOCKET").map(PathBuf::from),
+            wait_ready: args.wait_ready,
+            foreground: false,
+        })
         .with_context(|| format!("failed to start container {}", args.container_id))
 }
@@ -4,6 +4,8 @@ use std::path::{Path, PathBuf};
 use anyhow::Result;
 use chrono::{DateTime, Utc};
 use libcontainer::container::state::{ContainerStatus, State as ContainerState};
+use libcontainer::health::Health;
+use libcontainer::timings::{Phase, Process, StartupTimings};
 use liboci_cli::State;
 use serde::Serialize;
 
@@ -11,7 +13,13 @@ use crate::commands::load_container;
 
 pub fn state(args: State, root_path: PathBuf) -> Result<()> {
     let container = load_container(root_path, &args.container_id)?;
-    let export = StateExporter::from(&container.state);
+    let mut export = StateExporter::from(&container.state);
+    let startup_timings = if args.verbose {
+        container.startup_timings()?
+    } else {
+        None
+    };
+    export.startup_timings = startup_timings.as_ref().map(TimingsExporter::from);
     println!("{}", serde_json::to_string_pretty(&export)?);
     std::process::exit(0);
 }
@@ -31,6 +39,64 @@ pub struct StateExporter<'a> {
     pub created: Option<&'a DateTime<Utc>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub owner: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exit_code: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exit_signal: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub finished_at: Option<&'a DateTime<Utc>>,
+    // Only known once the exit of the container is recorded
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub oom_killed: Option<bool>,
+    // Only shown once the monitor of the container restarted it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub restart_count: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub health: Option<&'a Health>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub startup_timings: Option<TimingsExporter>,
+}
+
+/// Start-up latency breakdown, with the phases relative to the start of the
+/// first one
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct TimingsExporter {
+    pub total_us: u128,
+    pub phases: Vec<PhaseExporter>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct PhaseExporter {
+    pub phase: Phase,
+    pub process: Process,
+    pub offset_us: u64,
+    pub duration_us: u128,
+}
+
+impl From<&StartupTimings> for TimingsExporter {
+    fn from(timings: &StartupTimings) -> Self {
+        let first = timings
+            .phases
+            .iter()
+            .map(|timing| timing.start_ns)
+            .min()
+            .unwrap_or(0);
+        Self {
+            total_us: timings.total().as_micros(),
+            phases: timings
+                .phases
+                .iter()
+                .map(|timing| PhaseExporter {
+                    phase: timing.phase,
+                    process: timing.process,
+                    offset_us: (timing.start_ns - first) / 1000,
+                    duration_us: timing.duration().as_micros(),
+                })
+                .collect(),
+        }
+    }
 }
 
 impl<'a> From<&'a ContainerState> for StateExporter<'a> {
@@ -44,6 +110,13 @@ impl<'a> From<&'a ContainerState> for StateExporter<'a> {
             annotations: state.annotations.as_ref(),
             created: state.created.as_ref(),
             owner: state.creator,
+            exit_code: state.exit_code,
+            exit_signal: state.exit_signal,
+            finished_at: state.finished_at.as_ref(),
+            oom_killed: state.finished_at.map(|_| state.oom_killed),
+            restart_count: (state.restart_count > 0).then_some(state.restart_count),
+            health: state.health.as_ref(),
+            startup_timings: None,
         }
     }
 }
@@ -0,0 +1,141 @@
+//! Terminal of a container running in the foreground without a console socket.
+//! Similar to `runc run`, youki receives the pty master of the container and
+//! proxies it to its own stdio.
+use std::fs::File;
+use std::io;
+use std::os::fd::{AsFd, AsRawFd, OwnedFd};
+use std::thread::{self, JoinHandle};
+
+use anyhow::{Context, Result};
+use libcontainer::tty;
+use nix::libc;
+use nix::sys::signal::SigSet;
+use nix::sys::termios::{self, SetArg, Termios};
+use nix::unistd::isatty;
+
+pub struct Console {
+    master: OwnedFd,
+    /// Attributes of the caller's terminal, restored on drop
+    saved_termios: Option<Termios>,
+    output: Option<JoinHandle<()>>,
+}
+
+impl Console {
+    /// Creates the sockets over which the container sends its pty master.
+    /// The first one is kept to [`Console::receive`] the master, the second
+    /// one is handed to the container builder.
+    pub fn sockets() -> Result<(OwnedFd, OwnedFd)> {
+        tty::setup_console_socketpair().context("failed to create terminal socket")
+    }
+
+    /// Receives the pty master of a container that has been created, if its
+    /// process uses a terminal.
+    pub fn receive(socket: &OwnedFd) -> Result<Option<Self>> {
+        tty::receive_pty_master(socket)
+            .context("failed to receive pty master")?
+            .map(Self::new)
+            .transpose()
+    }
+
+    /// Puts the caller's terminal into raw mode and starts copying between
+    /// the stdio of youki and the pty master.
+    pub fn new(master: OwnedFd) -> Result<Self> {
+        let stdin = io::stdin();
+        let saved_termios = if isatty(stdin.as_raw_fd()).unwrap_or(false) {
+            let saved = termios::tcgetattr(&stdin).context("failed to get terminal attributes")?;
+            let mut raw = saved.clone();
+            termios::cfmakeraw(&mut raw);
+            termios::tcsetattr(&stdin, SetArg::TCSANOW, &raw)
+                .context("failed to set terminal into raw mode")?;
+            Some(saved)
+        } else {
+            None
+        };
+
+        let mut console = Self {
+            master,
+            saved_termios,
+            output: None,
+        };
+        console.resize()?;
+
+        // Use unbuffered copies of stdio, so partial lines such as shell
+        // prompts are shown immediately.
+        let mut input = File::from(stdin.as_fd().try_clone_to_owned()?);
+        let mut pty_input = File::from(console.master.try_clone()?);
+        thread::spawn(move || {
+            block_signals();
+            let _ = io::copy(&mut input, &mut pty_input);
+        });
+
+        let mut output = File::from(io::stdout().as_fd().try_clone_to_owned()?);
+        let mut pty_output = File::from(console.master.try_clone()?);
+        console.output = Some(thread::spawn(move || {
+            block_signals();
+            // Reading the master fails with EIO once the container closed
+            // all its ends of the terminal.
+            let _ = io::copy(&mut pty_output, &mut output);
+        }));
+
+        Ok(console)
+    }
+
+    /// Copies the window size of the caller's terminal to the terminal of the
+    /// container.
+    pub fn resize(&self) -> Result<()> {
+        if self.saved_termios.is_none() {
+            return Ok(());
+        }
+
+        let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+        if unsafe { libc::ioctl(io::stdin().as_raw_fd(), libc::TIOCGWINSZ, &mut size) } < 0 {
+            return Err(io::Error::last_os_error()).context("failed to get window size");
+        }
+        if unsafe { libc::ioctl(self.master.as_raw_fd(), libc::TIOCSWINSZ, &size) } < 0 {
+            return Err(io::Error::last_os_error()).context("failed to set window size");
+        }
+
+        Ok(())
+    }
+
+    /// Waits until all output of the container has been copied.
+    pub fn wait(&mut self) {
+        if let Some(output) = self.output.take() {
+            let _ = output.join();
+        }
+    }
+}
+
+impl Drop for Console {
+    fn drop(&mut self) {
+        if let Some(saved) = &self.saved_termios
+            && let Err(err) = termios::tcsetattr(io::stdin(), SetArg::TCSANOW, saved)
+        {
+            tracing::warn!(?err, "failed to restore terminal attributes");
+        }
+    }
+}
+
+// Signals are handled by the main thread, see `handle_foreground`.
+fn block_signals() {
+    let _ = SigSet::all().thread_block();
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use super::*;
+
+    #[test]
+    fn test_console_wait_for_output() -> Result<()> {
+        let pty = nix::pty::openpty(None, None)?;
+        let mut console = Console::new(pty.master)?;
+
+        // Copying the output ends once the container closed its end of the
+        // terminal.
+        drop(pty.slave);
+        console.wait();
+        Ok(())
+    }
+}
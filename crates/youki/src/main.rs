@@ -2,14 +2,21 @@
 //! Container Runtime written in Rust, inspired by [railcar](https://github.com/oracle/railcar)
 //! This crate provides a container runtime which can be used by a high-level container runtime to run containers.
 mod commands;
+mod console;
 mod observability;
+#[cfg(feature = "otel")]
+mod otel;
 mod rootpath;
+mod shim;
 mod workload;
 
+use std::path::PathBuf;
+
 use anyhow::{Context, Result};
 use clap::{Args, CommandFactory, Parser, Subcommand};
 use libcontainer::syscall::syscall::create_syscall;
-use liboci_cli::{CommonCmd, GlobalOpts, StandardCmd};
+use libcontainer::utils::RootlessMode;
+use liboci_cli::{CommonCmd, GlobalOpts, Rootless, StandardCmd};
 
 use crate::commands::info;
 
@@ -22,6 +29,13 @@ struct YoukiExtendOpts {
     /// set the log level (default is 'error')
     #[arg(long)]
     pub log_level: Option<String>,
+    /// mount point of the cgroup hierarchy (default is '/sys/fs/cgroup')
+    #[arg(long)]
+    pub cgroup_root: Option<PathBuf>,
+    /// export spans as OTLP to a collector socket (unix:///path) or a file (file:///path)
+    #[cfg(feature = "otel")]
+    #[arg(long)]
+    pub otel_endpoint: Option<String>,
 }
 
 // High-level commandline option definition
@@ -58,6 +72,8 @@ enum YoukiSubCommand {
     // Youki specific extensions
     Info(info::Info),
     Completion(commands::completion::Completion),
+    #[cfg(feature = "v2")]
+    Devices(commands::devices::Devices),
 }
 
 /// This is the entry point in the container runtime. The binary is run by a high-level container runtime,
@@ -95,13 +111,19 @@ fn main() -> Result<()> {
         std::env::args_os()
     );
 
-    let root_path = rootpath::determine(opts.global.root, &*syscall)?;
+    let rootless = match opts.global.rootless {
+        Rootless::Enabled => RootlessMode::Enabled,
+        Rootless::Disabled => RootlessMode::Disabled,
+        Rootless::Auto => RootlessMode::Auto,
+    };
+    let root_path = rootpath::determine(opts.global.root, rootless, &*syscall)?;
     let systemd_cgroup = opts.global.systemd_cgroup;
+    let cgroup_root = opts.youki_extend.cgroup_root;
 
     let cmd_result = match opts.subcmd {
         Some(YoukiSubCommand::Standard(cmd)) => match *cmd {
             StandardCmd::Create(create) => {
-                commands::create::create(create, root_path, systemd_cgroup)
+                commands::create::create(create, root_path, systemd_cgroup, cgroup_root, rootless)
             }
             StandardCmd::Start(start) => commands::start::start(start, root_path),
             StandardCmd::Kill(kill) => commands::kill::kill(kill, root_path),
@@ -109,11 +131,14 @@ fn main() -> Result<()> {
             StandardCmd::State(state) => commands::state::state(state, root_path),
         },
         Some(YoukiSubCommand::Common(cmd)) => match *cmd {
-            CommonCmd::Checkpointt(checkpoint) => {
-                commands::checkpoint::checkpoint(checkpoint, root_path)
-            }
+            CommonCmd::Checkpointt(checkpoint) => commands::checkpoint::checkpoint(
+                checkpoint,
+                root_path,
+                opts.global.criu,
+                cgroup_root,
+            ),
             CommonCmd::Events(events) => commands::events::events(events, root_path),
-            CommonCmd::Exec(exec) => match commands::exec::exec(exec, root_path) {
+            CommonCmd::Exec(exec) => match commands::exec::exec(exec, root_path, rootless) {
                 Ok(exit_code) => std::process::exit(exit_code),
                 Err(e) => {
                     tracing::error!("error in executing command: {:?}", e);
@@ -125,13 +150,15 @@ fn main() -> Result<()> {
             CommonCmd::Pause(pause) => commands::pause::pause(pause, root_path),
             CommonCmd::Ps(ps) => commands::ps::ps(ps, root_path),
             CommonCmd::Resume(resume) => commands::resume::resume(resume, root_path),
-            CommonCmd::Run(run) => match commands::run::run(run, root_path, systemd_cgroup) {
-                Ok(exit_code) => std::process::exit(exit_code),
-                Err(e) => {
-                    tracing::error!("error in executing command: {:?}", e);
-                    std::process::exit(-1);
+            CommonCmd::Run(run) => {
+                match commands::run::run(run, root_path, systemd_cgroup, cgroup_root, rootless) {
+                    Ok(exit_code) => std::process::exit(exit_code),
+                    Err(e) => {
+                        tracing::error!("error in executing command: {:?}", e);
+                        std::process::exit(-1);
+                    }
                 }
-            },
+            }
             CommonCmd::Spec(spec) => commands::spec_json::spec(spec, &*syscall),
             CommonCmd::Update(update) => commands::update::update(update, root_path),
         },
@@ -140,6 +167,8 @@ fn main() -> Result<()> {
         Some(YoukiSubCommand::Completion(completion)) => {
             commands::completion::completion(completion, &mut app)
         }
+        #[cfg(feature = "v2")]
+        Some(YoukiSubCommand::Devices(devices)) => commands::devices::devices(devices),
         None => app
             .print_help()
             .map_err(|e| anyhow::anyhow!("failed to print help: {e}")),
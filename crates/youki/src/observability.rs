@@ -1,6 +1,7 @@
 use std::borrow::Cow;
+use std::collections::HashMap;
 use std::fs::OpenOptions;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 
 use anyhow::{Context, Result, bail};
@@ -51,6 +52,9 @@ pub struct ObservabilityConfig {
     pub log_format: Option<String>,
     #[allow(dead_code)]
     pub systemd_log: bool,
+    /// OTLP destination of the spans, `file:///path` or `unix:///path`
+    #[allow(dead_code)]
+    pub otel_endpoint: Option<String>,
 }
 
 impl From<&crate::Opts> for ObservabilityConfig {
@@ -61,6 +65,10 @@ impl From<&crate::Opts> for ObservabilityConfig {
             log_file: opts.global.log.to_owned(),
             log_format: opts.global.log_format.to_owned(),
             systemd_log: opts.youki_extend.systemd_log,
+            #[cfg(feature = "otel")]
+            otel_endpoint: opts.youki_extend.otel_endpoint.to_owned(),
+            #[cfg(not(feature = "otel"))]
+            otel_endpoint: None,
         }
     }
 }
@@ -94,9 +102,23 @@ where
     } else {
         None
     };
+
+    // Spans are exported whatever the log level is, the level only filters
+    // the logs.
+    #[cfg(feature = "otel")]
+    let otel = match config.otel_endpoint.as_deref() {
+        Some(endpoint) => Some(
+            crate::otel::layer(endpoint)?
+                .with_filter(log_level_filter.max(tracing_subscriber::filter::LevelFilter::INFO)),
+        ),
+        None => None,
+    };
+    #[cfg(not(feature = "otel"))]
+    let otel: Option<tracing_subscriber::layer::Identity> = None;
+
     let subscriber = tracing_subscriber::registry()
-        .with(log_level_filter)
-        .with(systemd_journald);
+        .with(otel)
+        .with(systemd_journald.with_filter(log_level_filter));
 
     // I really dislike how we have to specify individual branch for each
     // combination, but I can't find any better way to do this. The tracing
@@ -112,7 +134,8 @@ where
                         // https://github.com/youki-dev/youki/issues/3435
                         .with_ansi(false)
                         .without_time()
-                        .with_writer(std::io::stderr),
+                        .with_writer(std::io::stderr)
+                        .with_filter(log_level_filter),
                 )
                 .try_init()
                 .map_err(|e| anyhow::anyhow!("failed to init logger: {}", e))?;
@@ -125,7 +148,8 @@ where
                         .json()
                         .flatten_event(true)
                         .with_span_list(false)
-                        .with_writer(std::io::stderr),
+                        .with_writer(std::io::stderr)
+                        .with_filter(log_level_filter),
                 )
                 .try_init()
                 .map_err(|e| anyhow::anyhow!("failed to init logger: {}", e))?;
@@ -139,7 +163,11 @@ where
                 .open(path)
                 .with_context(|| "failed to open log file")?;
             subscriber
-                .with(tracing_subscriber::fmt::layer().with_writer(file))
+                .with(
+                    tracing_subscriber::fmt::layer()
+                        .with_writer(file)
+                        .with_filter(log_level_filter),
+                )
                 .try_init()
                 .map_err(|e| anyhow::anyhow!("failed to init logger: {}", e))?;
         }
@@ -157,7 +185,8 @@ where
                         .json()
                         .flatten_event(true)
                         .with_span_list(false)
-                        .with_writer(file),
+                        .with_writer(file)
+                        .with_filter(log_level_filter),
                 )
                 .try_init()
                 .map_err(|e| anyhow::anyhow!("failed to init logger: {}", e))?;
@@ -167,6 +196,35 @@ where
     Ok(())
 }
 
+/// Returns the span of a youki command on a container. With the `otel`
+/// feature, the span continues the trace of the caller, given by the
+/// `TRACEPARENT` environment variable or the annotations of the container.
+pub fn command_span(
+    command: &'static str,
+    container_id: &str,
+    annotations: Option<&HashMap<String, String>>,
+) -> tracing::Span {
+    let span = tracing::info_span!("youki", command, container_id);
+    #[cfg(feature = "otel")]
+    crate::otel::set_parent(&span, annotations);
+    #[cfg(not(feature = "otel"))]
+    let _ = annotations;
+    span
+}
+
+/// Returns the annotations of the spec of a bundle, if the trace of the
+/// caller can be taken from them
+pub fn bundle_annotations(bundle: &Path) -> Option<HashMap<String, String>> {
+    if cfg!(feature = "otel") {
+        libcontainer::oci_spec::runtime::Spec::load(bundle.join("config.json"))
+            .ok()?
+            .annotations()
+            .clone()
+    } else {
+        None
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::path::Path;
@@ -0,0 +1,398 @@
+//! Export of the spans of youki as OTLP, enabled by the `otel` feature.
+//!
+//! Spans are exported one by one when they close, without a background
+//! thread, so that the spans of the processes forked by youki are exported
+//! by those processes, and are all out before the init process execs the
+//! payload. The destination is opened before the container is created:
+//! processes that run after pivot_root reach it through the inherited
+//! descriptor.
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::os::fd::{AsRawFd, OwnedFd};
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result, bail};
+use opentelemetry::propagation::TextMapPropagator;
+use opentelemetry::trace::{SpanId, TraceId};
+use opentelemetry::trace::{SpanKind, Status, TracerProvider};
+use opentelemetry::{KeyValue, Value};
+use opentelemetry_sdk::Resource;
+use opentelemetry_sdk::error::{OTelSdkError, OTelSdkResult};
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::{
+    IdGenerator, RandomIdGenerator, SdkTracerProvider, SpanData, SpanExporter,
+};
+use serde_json::{Value as Json, json};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::Layer;
+use tracing_subscriber::registry::LookupSpan;
+
+/// Annotation carrying the W3C trace context of the request that created the
+/// container
+pub const TRACEPARENT_ANNOTATION: &str = "io.github.youki-dev.youki.traceparent";
+/// Annotation carrying the W3C trace state that goes with the trace context
+pub const TRACESTATE_ANNOTATION: &str = "io.github.youki-dev.youki.tracestate";
+
+const SCOPE_NAME: &str = "youki";
+// An export must not block the container, a collector that does not answer
+// in time loses the span.
+const SOCKET_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Where the spans are exported to
+#[derive(Debug)]
+enum Destination {
+    /// OTLP/JSON lines, appended to a file
+    File(File),
+    /// OTLP/HTTP with a JSON body, posted to a collector listening on a unix
+    /// socket. The descriptor is an O_PATH descriptor of the socket.
+    Socket(OwnedFd),
+}
+
+impl Destination {
+    /// Opens `file:///path` or `unix:///path`
+    fn open(endpoint: &str) -> Result<Self> {
+        if let Some(path) = endpoint.strip_prefix("file://") {
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .with_context(|| format!("failed to open trace file {path}"))?;
+            Ok(Self::File(file))
+        } else if let Some(path) = endpoint.strip_prefix("unix://") {
+            let socket = OpenOptions::new()
+                .read(true)
+                .custom_flags(nix::fcntl::OFlag::O_PATH.bits())
+                .open(path)
+                .with_context(|| format!("failed to open collector socket {path}"))?;
+            Ok(Self::Socket(socket.into()))
+        } else {
+            bail!("unsupported OTLP endpoint {endpoint}, expected file:// or unix://")
+        }
+    }
+
+    fn send(&self, body: &[u8]) -> std::io::Result<()> {
+        match self {
+            // a single write to a file opened with O_APPEND, so that the lines
+            // of concurrent processes do not interleave
+            Self::File(file) => {
+                let mut line = body.to_vec();
+                line.push(b'\n');
+                (&*file).write_all(&line)
+            }
+            Self::Socket(fd) => {
+                let path = PathBuf::from(format!("/proc/self/fd/{}", fd.as_raw_fd()));
+                post(&path, body)
+            }
+        }
+    }
+}
+
+/// Posts the spans to the OTLP/HTTP traces endpoint of a collector
+fn post(socket: &Path, body: &[u8]) -> std::io::Result<()> {
+    let mut stream = UnixStream::connect(socket)?;
+    stream.set_read_timeout(Some(SOCKET_TIMEOUT))?;
+    stream.set_write_timeout(Some(SOCKET_TIMEOUT))?;
+    let header = format!(
+        "POST /v1/traces HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        body.len()
+    );
+    stream.write_all(header.as_bytes())?;
+    stream.write_all(body)?;
+
+    let mut status = [0; 12];
+    stream.read_exact(&mut status)?;
+    // "HTTP/1.1 200"
+    match &status[9..] {
+        [b'2', _, _] => Ok(()),
+        code => Err(std::io::Error::other(format!(
+            "collector answered {}",
+            String::from_utf8_lossy(code)
+        ))),
+    }
+}
+
+/// Random ids that differ between the processes forked by youki, which
+/// inherit the state of the random generator of their parent
+#[derive(Debug, Default)]
+struct ForkSafeIdGenerator(RandomIdGenerator);
+
+impl ForkSafeIdGenerator {
+    fn salt() -> u64 {
+        (std::process::id() as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15)
+    }
+}
+
+impl IdGenerator for ForkSafeIdGenerator {
+    fn new_trace_id(&self) -> TraceId {
+        let id = u128::from_be_bytes(self.0.new_trace_id().to_bytes());
+        TraceId::from(id ^ Self::salt() as u128)
+    }
+
+    fn new_span_id(&self) -> SpanId {
+        let id = u64::from_be_bytes(self.0.new_span_id().to_bytes());
+        SpanId::from(id ^ Self::salt())
+    }
+}
+
+#[derive(Debug)]
+struct OtlpExporter {
+    destination: Destination,
+    resource: Vec<Json>,
+}
+
+impl SpanExporter for OtlpExporter {
+    async fn export(&self, batch: Vec<SpanData>) -> OTelSdkResult {
+        let body = encode(&self.resource, &batch);
+        self.destination
+            .send(body.to_string().as_bytes())
+            .map_err(|err| OTelSdkError::InternalFailure(err.to_string()))
+    }
+
+    fn set_resource(&mut self, resource: &Resource) {
+        self.resource = resource
+            .iter()
+            .map(|(key, value)| attribute(key.as_str(), value))
+            .collect();
+    }
+}
+
+/// Encodes the spans as an OTLP/JSON ExportTraceServiceRequest
+fn encode(resource: &[Json], spans: &[SpanData]) -> Json {
+    // The spans of a youki command come from several processes.
+    let mut resource = resource.to_vec();
+    resource.push(attribute(
+        "process.pid",
+        &Value::I64(std::process::id().into()),
+    ));
+
+    json!({
+        "resourceSpans": [{
+            "resource": { "attributes": resource },
+            "scopeSpans": [{
+                "scope": { "name": SCOPE_NAME },
+                "spans": spans.iter().map(encode_span).collect::<Vec<_>>(),
+            }],
+        }],
+    })
+}
+
+fn encode_span(span: &SpanData) -> Json {
+    let mut encoded = json!({
+        "traceId": span.span_context.trace_id().to_string(),
+        "spanId": span.span_context.span_id().to_string(),
+        "name": span.name,
+        "kind": match span.span_kind {
+            SpanKind::Internal => 1,
+            SpanKind::Server => 2,
+            SpanKind::Client => 3,
+            SpanKind::Producer => 4,
+            SpanKind::Consumer => 5,
+        },
+        "startTimeUnixNano": unix_nanos(span.start_time),
+        "endTimeUnixNano": unix_nanos(span.end_time),
+        "attributes": span
+            .attributes
+            .iter()
+            .map(|kv| attribute(kv.key.as_str(), &kv.value))
+            .collect::<Vec<_>>(),
+        "events": span
+            .events
+            .iter()
+            .map(|event| json!({
+                "timeUnixNano": unix_nanos(event.timestamp),
+                "name": event.name,
+                "attributes": event
+                    .attributes
+                    .iter()
+                    .map(|kv| attribute(kv.key.as_str(), &kv.value))
+                    .collect::<Vec<_>>(),
+            }))
+            .collect::<Vec<_>>(),
+        "status": match &span.status {
+            Status::Unset => json!({ "code": 0 }),
+            Status::Ok => json!({ "code": 1 }),
+            Status::Error { description } => json!({ "code": 2, "message": description }),
+        },
+    });
+    if span.parent_span_id != SpanId::INVALID {
+        encoded["parentSpanId"] = span.parent_span_id.to_string().into();
+    }
+    encoded
+}
+
+fn attribute(key: &str, value: &Value) -> Json {
+    let value = match value {
+        Value::Bool(b) => json!({ "boolValue": b }),
+        // int64 is a string in the JSON mapping of protobuf
+        Value::I64(i) => json!({ "intValue": i.to_string() }),
+        Value::F64(f) => json!({ "doubleValue": f }),
+        Value::String(s) => json!({ "stringValue": s.as_str() }),
+        // arrays and other values are sent as their text
+        value => json!({ "stringValue": value.to_string() }),
+    };
+    json!({ "key": key, "value": value })
+}
+
+fn unix_nanos(time: SystemTime) -> String {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos()
+        .to_string()
+}
+
+/// Returns the layer exporting the spans to `endpoint`
+pub fn layer<S>(endpoint: &str) -> Result<impl Layer<S>>
+where
+    S: tracing::Subscriber + for<'span> LookupSpan<'span>,
+{
+    let exporter = OtlpExporter {
+        destination: Destination::open(endpoint)?,
+        resource: Vec::new(),
+    };
+    let provider = SdkTracerProvider::builder()
+        .with_simple_exporter(exporter)
+        .with_id_generator(ForkSafeIdGenerator::default())
+        .with_resource(
+            Resource::builder()
+                .with_service_name("youki")
+                .with_attribute(KeyValue::new("service.version", env!("CARGO_PKG_VERSION")))
+                .build(),
+        )
+        .build();
+    Ok(tracing_opentelemetry::layer().with_tracer(provider.tracer(SCOPE_NAME)))
+}
+
+/// Continues the trace of the caller in `span`. The trace context is taken
+/// from the `TRACEPARENT` and `TRACESTATE` environment variables, or from the
+/// annotations of the container.
+pub fn set_parent(span: &tracing::Span, annotations: Option<&HashMap<String, String>>) {
+    let Some(carrier) = trace_context(annotations) else {
+        return;
+    };
+    let parent = TraceContextPropagator::new().extract(&carrier);
+    if let Err(err) = span.set_parent(parent) {
+        tracing::debug!(?err, "failed to set the parent of the span");
+    }
+}
+
+fn trace_context(annotations: Option<&HashMap<String, String>>) -> Option<HashMap<String, String>> {
+    let annotation = |key| annotations.and_then(|a| a.get(key)).cloned();
+    let (traceparent, tracestate) = match std::env::var("TRACEPARENT") {
+        Ok(traceparent) => (traceparent, std::env::var("TRACESTATE").ok()),
+        Err(_) => (
+            annotation(TRACEPARENT_ANNOTATION)?,
+            annotation(TRACESTATE_ANNOTATION),
+        ),
+    };
+
+    let mut carrier = HashMap::from([("traceparent".to_owned(), traceparent)]);
+    if let Some(tracestate) = tracestate {
+        carrier.insert("tracestate".to_owned(), tracestate);
+    }
+    Some(carrier)
+}
+
+#[cfg(test)]
+mod tests {
+    use nix::unistd::{ForkResult, fork};
+    use opentelemetry::trace::{SpanContext, TraceFlags, TraceState};
+    use opentelemetry_sdk::trace::{SpanEvents, SpanLinks};
+
+    use super::*;
+
+    #[test]
+    fn test_encode() {
+        let start = UNIX_EPOCH + Duration::from_secs(1);
+        let span = SpanData {
+            span_context: SpanContext::new(
+                TraceId::from(0x0af7651916cd43dd8448eb211c80319c),
+                SpanId::from(0xb7ad6b7169203331),
+                TraceFlags::SAMPLED,
+                false,
+                TraceState::default(),
+            ),
+            parent_span_id: SpanId::from(0x00f067aa0ba902b7),
+            parent_span_is_remote: true,
+            span_kind: SpanKind::Internal,
+            name: "create".into(),
+            start_time: start,
+            end_time: start + Duration::from_millis(5),
+            attributes: vec![KeyValue::new("container_id", "test")],
+            dropped_attributes_count: 0,
+            events: SpanEvents::default(),
+            links: SpanLinks::default(),
+            status: Status::Unset,
+            instrumentation_scope: Default::default(),
+        };
+
+        let encoded = encode(&[], &[span]);
+        let spans = &encoded["resourceSpans"][0]["scopeSpans"][0]["spans"];
+        assert_eq!(spans[0]["traceId"], "0af7651916cd43dd8448eb211c80319c");
+        assert_eq!(spans[0]["spanId"], "b7ad6b7169203331");
+        assert_eq!(spans[0]["parentSpanId"], "00f067aa0ba902b7");
+        assert_eq!(spans[0]["startTimeUnixNano"], "1000000000");
+        assert_eq!(spans[0]["endTimeUnixNano"], "1005000000");
+        assert_eq!(
+            spans[0]["attributes"][0],
+            json!({ "key": "container_id", "value": { "stringValue": "test" } })
+        );
+    }
+
+    #[test]
+    fn test_fork_safe_ids() -> Result<()> {
+        let generator = ForkSafeIdGenerator::default();
+        // seeds the random generator of the thread before the fork
+        generator.new_span_id();
+        let (read, write) = nix::unistd::pipe()?;
+        // SAFETY: the child only writes to the pipe before it exits.
+        match unsafe { fork()? } {
+            ForkResult::Child => {
+                let id = generator.new_span_id().to_bytes();
+                let _ = nix::unistd::write(&write, &id);
+                std::process::exit(0)
+            }
+            ForkResult::Parent { child } => {
+                drop(write);
+                let mut child_id = [0; 8];
+                File::from(read).read_exact(&mut child_id)?;
+                nix::sys::wait::waitpid(child, None)?;
+                assert_ne!(generator.new_span_id().to_bytes(), child_id);
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_trace_context() {
+        let annotations = HashMap::from([(
+            TRACEPARENT_ANNOTATION.to_owned(),
+            "00-0af7651916cd43dd8448eb211c80319c-00f067aa0ba902b7-01".to_owned(),
+        )]);
+        if std::env::var_os("TRACEPARENT").is_none() {
+            assert_eq!(
+                trace_context(Some(&annotations)),
+                Some(HashMap::from([(
+                    "traceparent".to_owned(),
+                    "00-0af7651916cd43dd8448eb211c80319c-00f067aa0ba902b7-01".to_owned(),
+                )]))
+            );
+            assert_eq!(trace_context(None), None);
+        }
+    }
+
+    #[test]
+    fn test_file_destination() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("traces.jsonl");
+        let destination = Destination::open(&format!("file://{}", path.display()))?;
+        destination.send(b"{}")?;
+        destination.send(b"{}")?;
+        assert_eq!(std::fs::read_to_string(&path)?, "{}\n{}\n");
+        assert!(Destination::open("http://localhost:4318").is_err());
+        Ok(())
+    }
+}
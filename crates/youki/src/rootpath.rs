@@ -2,12 +2,13 @@ use std::fs;
 use std::path::{Path, PathBuf};
 
 use anyhow::{Result, bail};
-use libcontainer::utils::{create_dir_all_with_mode, rootless_required};
+use libcontainer::utils::{RootlessMode, create_dir_all_with_mode};
 use nix::libc;
 use nix::sys::stat::Mode;
 
 pub fn determine(
     root_path: Option<PathBuf>,
+    rootless: RootlessMode,
     syscall: &dyn libcontainer::syscall::Syscall,
 ) -> Result<PathBuf> {
     let uid = syscall.get_uid().as_raw();
@@ -20,7 +21,7 @@ pub fn determine(
         return Ok(path);
     }
 
-    if !rootless_required(syscall)? {
+    if !rootless.resolve(syscall)? {
         let path = get_default_not_rootless_path();
         create_dir_all_with_mode(&path, uid, Mode::S_IRWXU)?;
         return Ok(path);
@@ -99,8 +100,8 @@ mod tests {
         let specified_path = tmp.path().join("provided_path");
         let non_abs_path = specified_path.join("../provided_path");
         let syscall = create_syscall();
-        let path =
-            determine(Some(non_abs_path), &*syscall).context("failed with specified path")?;
+        let path = determine(Some(non_abs_path), RootlessMode::Auto, &*syscall)
+            .context("failed with specified path")?;
         assert_eq!(path, specified_path);
         Ok(())
     }
@@ -114,8 +115,8 @@ mod tests {
         std::fs::create_dir(&specified_path).context("failed to create dir")?;
         let non_abs_path = specified_path.join("../provided_path");
         let syscall = create_syscall();
-        let path =
-            determine(Some(non_abs_path), &*syscall).context("failed with specified path")?;
+        let path = determine(Some(non_abs_path), RootlessMode::Auto, &*syscall)
+            .context("failed with specified path")?;
         assert_eq!(path, specified_path);
         Ok(())
     }
@@ -130,8 +131,8 @@ mod tests {
 
         {
             let expected_path = super::get_default_not_rootless_path();
-            let path =
-                determine(None, &*syscall).context("failed with default non rootless path")?;
+            let path = determine(None, RootlessMode::Auto, &*syscall)
+                .context("failed with default non rootless path")?;
             assert_eq!(path, expected_path);
             assert!(path.exists());
             fs::remove_dir_all(&expected_path).context("failed to remove dir")?;
@@ -141,7 +142,7 @@ mod tests {
             fs::create_dir(&expected_path).context("failed to create dir")?;
             fs::set_permissions(&expected_path, Permissions::from_mode(Mode::S_IRUSR.bits()))
                 .context("failed to set invalid permissions")?;
-            assert!(determine(None, &*syscall).is_err());
+            assert!(determine(None, RootlessMode::Auto, &*syscall).is_err());
             fs::remove_dir_all(&expected_path).context("failed to remove dir")?;
         }
 
@@ -158,7 +159,8 @@ mod tests {
         let tmp = tempfile::tempdir()?;
         let xdg_dir = tmp.path().join("xdg_runtime");
         unsafe { std::env::set_var("XDG_RUNTIME_DIR", &xdg_dir) };
-        let path = determine(None, &*syscall).context("failed with $XDG_RUNTIME_DIR path")?;
+        let path = determine(None, RootlessMode::Auto, &*syscall)
+            .context("failed with $XDG_RUNTIME_DIR path")?;
         assert_eq!(path, xdg_dir.join("youki"));
         assert!(path.exists());
         unsafe { std::env::remove_var("XDG_RUNTIME_DIR") };
@@ -169,7 +171,8 @@ mod tests {
         scopeguard::defer!({
             let _ = fs::remove_dir_all(&default_rootless_path);
         });
-        let path = determine(None, &*syscall).context("failed with default rootless path")?;
+        let path = determine(None, RootlessMode::Auto, &*syscall)
+            .context("failed with default rootless path")?;
         assert_eq!(path, default_rootless_path);
         assert!(path.exists());
 
@@ -190,7 +193,8 @@ mod tests {
         let home_path = tmp.path().join("youki_home");
         fs::create_dir_all(&home_path).context("failed to create fake home path")?;
         unsafe { std::env::set_var("HOME", &home_path) };
-        let path = determine(None, &*syscall).context("failed with $HOME path")?;
+        let path =
+            determine(None, RootlessMode::Auto, &*syscall).context("failed with $HOME path")?;
         assert_eq!(path, home_path.join(".youki/run"));
         assert!(path.exists());
         unsafe { std::env::remove_var("HOME") };
@@ -198,7 +202,8 @@ mod tests {
         // Use /tmp dir
         let uid = syscall.get_uid().as_raw();
         let expected_temp_path = PathBuf::from(format!("/tmp/youki-{uid}"));
-        let path = determine(None, &*syscall).context("failed with temp path")?;
+        let path =
+            determine(None, RootlessMode::Auto, &*syscall).context("failed with temp path")?;
         assert_eq!(path, expected_temp_path);
         // Set invalid permissions to temp path so determine_root_path fails.
         fs::set_permissions(
@@ -206,7 +211,7 @@ mod tests {
             Permissions::from_mode(Mode::S_IRUSR.bits()),
         )
         .context("failed to set invalid permissions")?;
-        assert!(determine(None, &*syscall).is_err());
+        assert!(determine(None, RootlessMode::Auto, &*syscall).is_err());
         fs::remove_dir_all(&expected_temp_path).context("failed to remove dir")?;
 
         Ok(())
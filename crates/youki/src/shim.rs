@@ -0,0 +1,430 @@
+//! Monitor process of a detached container (`--shim`). Without it, the
+//! container process is reparented away from youki once the command returns
+//! and its exit is never observed. The monitor stays the parent of the
+//! container process, records its exit in the state of the container, runs
+//! the poststop hooks, restarts the container per its restart policy and
+//! runs its health check. A process executed in the container by a detached
+//! `exec` gets a monitor as well, recording its exit.
+use std::ffi::CStr;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::os::fd::{AsFd, AsRawFd, OwnedFd};
+use std::thread;
+use std::time::Instant;
+
+use anyhow::{Context, Result, bail};
+use libcontainer::container::builder::ContainerBuilder;
+use libcontainer::container::{Container, ContainerStatus, StartOptions};
+use libcontainer::health::{Health, HealthCheck};
+use libcontainer::restart::{BACKOFF_RESET, RestartPolicy, backoff};
+use libcontainer::syscall::syscall::SyscallType;
+use libcontainer::utils::RootlessMode;
+use nix::errno::Errno;
+use nix::fcntl::OFlag;
+use nix::poll::{PollFd, PollFlags, PollTimeout, poll};
+use nix::sys::prctl;
+use nix::sys::signal::{self, SigSet, Signal};
+use nix::sys::signalfd::{SfdFlags, SignalFd};
+use nix::sys::wait::{WaitPidFlag, WaitStatus, waitpid};
+use nix::unistd::{self, ForkResult, Pid};
+
+use crate::workload::executor::bundle_executor;
+
+// Reports up to PIPE_BUF bytes are written atomically.
+const MAX_REPORT_SIZE: usize = 4096;
+
+/// Creates a container through `create` in a monitor process, and starts it
+/// with `start` if set. youki returns once the container is created, while
+/// the monitor keeps running until the container is deleted or is not to be
+/// restarted anymore. Health check probes are run in the container with
+/// `rootless`.
+pub fn spawn<F>(mut create: F, start: Option<StartOptions>, rootless: RootlessMode) -> Result<()>
+where
+    F: FnMut() -> Result<Container>,
+{
+    let options = start.clone();
+    fork_monitor(
+        c"youki:[SHIM]",
+        move || {
+            prctl::set_child_subreaper(true).context("failed to set child subreaper")?;
+            let mut container = create()?;
+            let annotations = container.state.annotations.clone().unwrap_or_default();
+            let policy = RestartPolicy::from_annotations(&annotations)?;
+            let check = HealthCheck::from_annotations(&annotations)?;
+            reset_health(&mut container, check.as_ref())?;
+            if let Some(options) = &options {
+                container.start_with_options(options)?;
+            }
+            Ok((create, container, policy, check))
+        },
+        |(create, container, policy, check)| {
+            monitor(create, container, policy, check, start, rootless)
+        },
+    )
+}
+
+/// Executes a process in a container through `exec` in a monitor process,
+/// which stays the parent of the process and hands its exit status to
+/// `on_exit`. youki returns once the process is executed.
+pub fn spawn_exec<F, G>(exec: F, on_exit: G) -> Result<()>
+where
+    F: FnOnce() -> Result<Pid>,
+    G: FnOnce(WaitStatus),
+{
+    fork_monitor(c"youki:[EXEC]", exec, |pid| {
+        loop {
+            match waitpid(pid, None) {
+                Ok(status @ (WaitStatus::Exited(..) | WaitStatus::Signaled(..))) => {
+                    on_exit(status);
+                    return Ok(());
+                }
+                Ok(_) | Err(Errno::EINTR) => {}
+                Err(err) => return Err(err).context("failed to wait for the process"),
+            }
+        }
+    })
+}
+
+/// Forks a monitor process named `name`, which runs `setup` and reports its
+/// outcome to youki, then leaves youki and runs `monitor` with the outcome.
+/// youki returns once `setup` is done.
+fn fork_monitor<T, S, M>(name: &CStr, setup: S, monitor: M) -> Result<()>
+where
+    S: FnOnce() -> Result<T>,
+    M: FnOnce(T) -> Result<()>,
+{
+    // The child of a multi-threaded process may only make async-signal safe
+    // calls, while the monitor creates the container.
+    let threads = procfs::process::Process::myself()
+        .and_then(|process| process.stat())
+        .map(|stat| stat.num_threads)
+        .context("failed to count threads")?;
+    if threads > 1 {
+        bail!("cannot fork the monitor of a multi-threaded process");
+    }
+
+    let (read, write) = unistd::pipe2(OFlag::O_CLOEXEC).context("failed to create pipe")?;
+    // SAFETY: the process has a single thread.
+    match unsafe { unistd::fork() }.context("failed to fork the monitor")? {
+        ForkResult::Parent { .. } => {
+            drop(write);
+            // The container process holds the write end until it executes,
+            // so the report is read in one go instead of until EOF.
+            let mut report = [0u8; MAX_REPORT_SIZE];
+            let len = File::from(read)
+                .read(&mut report)
+                .context("failed to read the report of the monitor")?;
+            match &report[..len] {
+                [0] => Ok(()),
+                [] => bail!("the monitor exited before reporting"),
+                [_, message @ ..] => bail!("{}", String::from_utf8_lossy(message)),
+            }
+        }
+        ForkResult::Child => {
+            drop(read);
+            let code = match run_monitor(name, setup, monitor, write) {
+                Ok(()) => 0,
+                Err(err) => {
+                    tracing::error!(?err, "failed to monitor the container");
+                    1
+                }
+            };
+            std::process::exit(code);
+        }
+    }
+}
+
+fn run_monitor<T, S, M>(name: &CStr, setup: S, monitor: M, report: OwnedFd) -> Result<()>
+where
+    S: FnOnce() -> Result<T>,
+    M: FnOnce(T) -> Result<()>,
+{
+    let mut report = File::from(report);
+    let value = match setup() {
+        Ok(value) => {
+            report.write_all(&[0])?;
+            value
+        }
+        Err(err) => {
+            let mut message = vec![1];
+            message.extend(format!("{err:?}").bytes().take(MAX_REPORT_SIZE - 1));
+            report.write_all(&message)?;
+            return Err(err);
+        }
+    };
+    drop(report);
+    detach(name)?;
+    monitor(value)
+}
+
+fn monitor<F>(
+    mut create: F,
+    mut container: Container,
+    policy: RestartPolicy,
+    check: Option<HealthCheck>,
+    start: Option<StartOptions>,
+    rootless: RootlessMode,
+) -> Result<()>
+where
+    F: FnMut() -> Result<Container>,
+{
+    // Number of restarts in a row of a container exiting right away
+    let mut failures = 0;
+    loop {
+        let started = Instant::now();
+        let status = supervise(&mut container, check.as_ref(), rootless)?;
+        let pid = container.pid();
+        // A delete holds the lock of the container until its directory is
+        // removed, the exit is recorded by the delete then.
+        let Some(lock) = container.lock()? else {
+            tracing::debug!("container was deleted, stop monitoring");
+            return Ok(());
+        };
+        container.refresh_state()?;
+        container.record_exit(status)?;
+        if let Err(err) = container.run_poststop_hooks() {
+            tracing::warn!(?err, "failed to run poststop hooks");
+        }
+        drop(lock);
+
+        let restart_count = container.state.restart_count;
+        if !policy.should_restart(container.exit_code().unwrap_or_default(), restart_count) {
+            return Ok(());
+        }
+
+        if started.elapsed() >= BACKOFF_RESET {
+            failures = 0;
+        }
+        thread::sleep(backoff(failures));
+        failures += 1;
+        // The container may have been deleted, or even created again, while
+        // sleeping.
+        let Some(lock) = container.lock()? else {
+            tracing::debug!("container was deleted, stop monitoring");
+            return Ok(());
+        };
+        if container.refresh_state()?.pid() != pid {
+            tracing::debug!("container was replaced, stop monitoring");
+            return Ok(());
+        }
+        tracing::info!(%policy, restart_count, "restarting the container");
+        container.delete_locked(true, &lock)?;
+        container = create()?;
+        container.state.restart_count = restart_count + 1;
+        reset_health(&mut container, check.as_ref())?;
+        container.start_with_options(&start.clone().unwrap_or_default())?;
+        drop(lock);
+    }
+}
+
+fn reset_health(container: &mut Container, check: Option<&HealthCheck>) -> Result<()> {
+    container.state.health = check.map(|_| Health::default());
+    container.save()?;
+    Ok(())
+}
+
+/// Runs the health check of the container, if any, until the container
+/// process exits, and reaps the children of the monitor meanwhile. The other
+/// children are probes and processes orphaned in the container.
+fn supervise(
+    container: &mut Container,
+    check: Option<&HealthCheck>,
+    rootless: RootlessMode,
+) -> Result<WaitStatus> {
+    let init_pid = container.pid().context("container has no pid")?;
+    let mut mask = SigSet::empty();
+    mask.add(Signal::SIGCHLD);
+    // SIGCHLD is only blocked while waiting, so that the probes do not
+    // inherit the blocked signal. A SIGCHLD lost meanwhile is caught up by
+    // reaping before waiting.
+    let sfd = SignalFd::with_flags(&mask, SfdFlags::SFD_NONBLOCK | SfdFlags::SFD_CLOEXEC)
+        .context("failed to create signalfd")?;
+
+    let mut next_probe = check.map(|check| Instant::now() + check.interval);
+    // Pid and deadline of the running probe
+    let mut probe: Option<(Pid, Instant)> = None;
+    loop {
+        if let Some(check) = check {
+            let now = Instant::now();
+            if let Some((pid, deadline)) = probe
+                && now >= deadline
+            {
+                tracing::debug!(%pid, "health check timed out");
+                let _ = signal::kill(pid, Signal::SIGKILL);
+                record_health(container, check, None);
+                probe = None;
+            }
+            if probe.is_none()
+                && let Some(at) = next_probe
+                && now >= at
+            {
+                next_probe = Some(now + check.interval);
+                if is_running(container) {
+                    match spawn_probe(container, check, rootless) {
+                        Ok(pid) => probe = Some((pid, now + check.timeout)),
+                        Err(err) => {
+                            tracing::warn!(?err, "failed to run the health check");
+                            record_health(container, check, None);
+                        }
+                    }
+                }
+            }
+        }
+
+        mask.thread_block().context("failed to block SIGCHLD")?;
+        loop {
+            match waitpid(None, Some(WaitPidFlag::WNOHANG)) {
+                Ok(status @ (WaitStatus::Exited(pid, _) | WaitStatus::Signaled(pid, _, _)))
+                    if pid == init_pid =>
+                {
+                    mask.thread_unblock().context("failed to unblock SIGCHLD")?;
+                    if let Some((pid, _)) = probe {
+                        let _ = signal::kill(pid, Signal::SIGKILL);
+                    }
+                    return Ok(status);
+                }
+                Ok(WaitStatus::Exited(pid, code)) if probe.is_some_and(|(p, _)| p == pid) => {
+                    probe = None;
+                    record_health(container, check.unwrap(), Some(code));
+                }
+                Ok(WaitStatus::Signaled(pid, sig, _)) if probe.is_some_and(|(p, _)| p == pid) => {
+                    probe = None;
+                    record_health(container, check.unwrap(), Some(128 + sig as i32));
+                }
+                Ok(WaitStatus::StillAlive) => break,
+                Ok(_) | Err(Errno::EINTR) => {}
+                Err(err) => return Err(err).context("failed to wait for the container process"),
+            }
+        }
+
+        let deadline = probe.map(|(_, deadline)| deadline).or(next_probe);
+        let timeout = match deadline {
+            Some(deadline) => {
+                PollTimeout::try_from(deadline.saturating_duration_since(Instant::now()))
+                    .unwrap_or(PollTimeout::MAX)
+            }
+            None => PollTimeout::NONE,
+        };
+        let mut fds = [PollFd::new(sfd.as_fd(), PollFlags::POLLIN)];
+        match poll(&mut fds, timeout) {
+            Ok(_) | Err(Errno::EINTR) => {}
+            Err(err) => return Err(err).context("failed to wait for SIGCHLD"),
+        }
+        while let Ok(Some(_)) = sfd.read_signal() {}
+        mask.thread_unblock().context("failed to unblock SIGCHLD")?;
+    }
+}
+
+fn is_running(container: &mut Container) -> bool {
+    match container.refresh_status() {
+        Ok(()) => container.status() == ContainerStatus::Running,
+        Err(err) => {
+            tracing::warn!(?err, "failed to refresh the status of the container");
+            false
+        }
+    }
+}
+
+/// Runs the command of the health check in the container, as a child of the
+/// monitor
+fn spawn_probe(container: &Container, check: &HealthCheck, rootless: RootlessMode) -> Result<Pid> {
+    let root_path = container
+        .root
+        .parent()
+        .context("container directory has no parent")?;
+    let pid = ContainerBuilder::new(container.id().to_owned(), SyscallType::default())
+        .with_executor(bundle_executor(root_path, container.bundle()))
+        .with_root_path(root_path)?
+        .with_rootless(rootless)
+        .as_tenant()
+        .with_detach(true)
+        .with_container_args(check.command.clone())
+        .build()?;
+    Ok(pid)
+}
+
+// The state is reloaded first, as other youki commands save it too. It is
+// not recorded once the container is being deleted.
+fn record_health(container: &mut Container, check: &HealthCheck, exit_code: Option<i32>) {
+    let recorded = container.lock().and_then(|lock| {
+        let Some(_lock) = lock else {
+            return Ok(());
+        };
+        let container = container.refresh_state()?;
+        container
+            .state
+            .health
+            .get_or_insert_default()
+            .record(exit_code, check.retries);
+        container.save()
+    });
+    if let Err(err) = recorded {
+        tracing::warn!(?err, "failed to record the health of the container");
+    }
+}
+
+/// Leaves the session of youki and its stdio, which are only held by the
+/// container process from now on
+fn detach(name: &CStr) -> Result<()> {
+    let _ = prctl::set_name(name);
+    unistd::setsid().context("failed to create a new session")?;
+    let null = File::options()
+        .read(true)
+        .write(true)
+        .open("/dev/null")
+        .context("failed to open /dev/null")?;
+    for fd in 0..=2 {
+        unistd::dup2(null.as_raw_fd(), fd).context("failed to redirect stdio")?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn test_supervise() -> Result<()> {
+        // P1 mocks the monitor, P2 the container process leaving an orphaned
+        // P3 behind. P1 must report the exit of P2, whatever P3 does.
+        match unsafe { unistd::fork()? } {
+            ForkResult::Parent { child } => {
+                let status = waitpid(child, None)?;
+                assert_eq!(status, WaitStatus::Exited(child, 0));
+            }
+            ForkResult::Child => {
+                let code = (|| -> Result<i32> {
+                    prctl::set_child_subreaper(true)?;
+                    let init = match unsafe { unistd::fork()? } {
+                        ForkResult::Parent { child } => child,
+                        ForkResult::Child => {
+                            if let ForkResult::Child = unsafe { unistd::fork()? } {
+                                std::process::exit(0);
+                            }
+                            thread::sleep(Duration::from_millis(100));
+                            signal::raise(Signal::SIGKILL)?;
+                            unreachable!();
+                        }
+                    };
+                    let tmp = tempfile::tempdir()?;
+                    let mut container = Container::new(
+                        "container",
+                        ContainerStatus::Running,
+                        Some(init.as_raw()),
+                        tmp.path(),
+                        tmp.path(),
+                    )?;
+                    match supervise(&mut container, None, RootlessMode::default())? {
+                        WaitStatus::Signaled(pid, Signal::SIGKILL, _) if pid == init => Ok(0),
+                        _ => Ok(1),
+                    }
+                })()
+                .unwrap_or(2);
+                std::process::exit(code);
+            }
+        }
+        Ok(())
+    }
+}
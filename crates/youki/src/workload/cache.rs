@@ -0,0 +1,397 @@
+//! Cache of ahead-of-time compiled wasm modules, shared by the containers of
+//! a youki root directory. Entries are keyed by the digest of the module and
+//! of the engine that compiled it, so that an entry is never loaded by an
+//! engine with another version or other target features.
+//!
+//! The directory of the cache is opened before the container is created.
+//! The wasm executors run in the init process of the container after
+//! pivot_root, where they reach the cache through the inherited descriptor.
+//! The descriptor is closed before the guest module runs, it must not be
+//! reachable from the container through /proc/1/fd.
+use std::fs::{self, DirBuilder, File};
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::os::fd::{AsRawFd, OwnedFd};
+use std::os::unix::fs::DirBuilderExt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, SystemTime};
+
+use libcontainer::oci_spec::runtime::Spec;
+#[cfg(feature = "wasm-wasmer")]
+use libcontainer::workload::{Executor, ExecutorError, ExecutorValidationError};
+use sha2::{Digest, Sha256};
+
+/// Annotation enabling the cache for the module of a container
+pub const CACHE_ANNOTATION: &str = "io.github.youki-dev.youki.wasm.cache";
+/// Annotation asking to compile the module at create time, implies the cache
+pub const PRECOMPILE_ANNOTATION: &str = "io.github.youki-dev.youki.wasm.precompile";
+/// Total size of the entries above which the least recently used are evicted
+pub const DEFAULT_MAX_SIZE: u64 = 1 << 30;
+
+// Container ids cannot contain '@', so the cache never clashes with the
+// directory of a container.
+const CACHE_DIR: &str = "@wasm-cache";
+const TMP_PREFIX: &str = ".tmp-";
+// Temporary files of writers that died before renaming them
+const STALE_TMP_AGE: Duration = Duration::from_secs(60 * 60);
+
+/// Handle on the cache directory. Clones share the descriptor, closing one
+/// closes all of them.
+#[derive(Debug, Clone)]
+pub struct ModuleCache {
+    dir: Arc<Mutex<Option<OwnedFd>>>,
+    max_size: u64,
+}
+
+/// Returns true if the spec opts in to the cache
+pub fn enabled(spec: &Spec) -> bool {
+    annotation_is_true(spec, CACHE_ANNOTATION) || precompile_requested(spec)
+}
+
+/// Returns true if the module should be compiled when the container is
+/// created
+pub fn precompile_requested(spec: &Spec) -> bool {
+    annotation_is_true(spec, PRECOMPILE_ANNOTATION)
+}
+
+fn annotation_is_true(spec: &Spec, key: &str) -> bool {
+    spec.annotations()
+        .as_ref()
+        .and_then(|a| a.get(key))
+        .is_some_and(|v| v == "true")
+}
+
+impl ModuleCache {
+    /// Opens the cache under the root directory, creating it if needed.
+    /// Returns None if the cache cannot be used, containers then compile
+    /// their modules on every start.
+    pub fn open(root_path: &Path) -> Option<Self> {
+        let path = root_path.join(CACHE_DIR);
+        let dir = DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(&path)
+            .and_then(|_| File::open(&path));
+        match dir {
+            Ok(dir) => Some(Self {
+                dir: Arc::new(Mutex::new(Some(dir.into()))),
+                max_size: DEFAULT_MAX_SIZE,
+            }),
+            Err(err) => {
+                tracing::warn!(?err, ?path, "could not open wasm module cache");
+                None
+            }
+        }
+    }
+
+    /// Returns the key of a module compiled by an engine. The parts of
+    /// `engine` must identify the version, configuration and target of the
+    /// engine. Each part is prefixed by its length, so that parts cannot run
+    /// into each other.
+    pub fn key(engine: &[&[u8]], module: &[u8]) -> String {
+        let mut digest = Sha256::new();
+        for part in engine.iter().copied().chain([module]) {
+            digest.update((part.len() as u64).to_le_bytes());
+            digest.update(part);
+        }
+        format!("{:x}", digest.finalize())
+    }
+
+    /// Closes the directory of the cache, for all the clones of the handle.
+    /// The cache is then disabled, entries that were loaded stay usable.
+    pub fn close(&self) {
+        self.dir
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take();
+    }
+
+    // The path stays valid after pivot_root, as long as /proc is mounted.
+    fn dir(&self) -> io::Result<PathBuf> {
+        match self
+            .dir
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .as_ref()
+        {
+            Some(dir) => Ok(PathBuf::from(format!("/proc/self/fd/{}", dir.as_raw_fd()))),
+            None => Err(io::Error::other("wasm module cache is closed")),
+        }
+    }
+
+    /// Returns the path of the entry, marking it as recently used
+    pub fn get(&self, key: &str) -> Option<PathBuf> {
+        let path = self.dir().ok()?.join(key);
+        match fs::metadata(&path) {
+            Ok(metadata) if metadata.is_file() => {}
+            Ok(_) => return None,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return None,
+            // e.g. the process runs in a user namespace that does not map
+            // the owner of the directory
+            Err(err) => {
+                tracing::warn!(?err, key, "could not access wasm module cache");
+                return None;
+            }
+        }
+        // best effort, a stale time only makes the entry evicted earlier
+        if let Err(err) = File::options()
+            .write(true)
+            .open(&path)
+            .and_then(|f| f.set_modified(SystemTime::now()))
+        {
+            tracing::debug!(?err, key, "could not update access time of cache entry");
+        }
+        Some(path)
+    }
+
+    /// Adds an entry, `write` creates the entry at the given path. The entry
+    /// is written to a temporary file first, synced and renamed, so that
+    /// readers never see partial entries, even after a crash.
+    pub fn insert(
+        &self,
+        key: &str,
+        write: impl FnOnce(&Path) -> io::Result<()>,
+    ) -> io::Result<PathBuf> {
+        let dir = self.dir()?;
+        let tmp = create_tmp(&dir)?;
+        let path = dir.join(key);
+        let result = write(&tmp)
+            .and_then(|_| File::open(&tmp)?.sync_all())
+            .and_then(|_| fs::rename(&tmp, &path))
+            .and_then(|_| File::open(&dir)?.sync_all());
+        if let Err(err) = result {
+            let _ = fs::remove_file(&tmp);
+            return Err(err);
+        }
+
+        self.evict();
+        Ok(path)
+    }
+
+    /// Removes the least recently used entries until the cache fits in its
+    /// maximum size, and temporary files left behind by writers.
+    fn evict(&self) {
+        let entries = match self.dir().and_then(fs::read_dir) {
+            Ok(entries) => entries,
+            Err(err) => {
+                tracing::warn!(?err, "could not read wasm module cache");
+                return;
+            }
+        };
+
+        let now = SystemTime::now();
+        let mut files = Vec::new();
+        let mut total = 0;
+        for entry in entries.flatten() {
+            let Ok(metadata) = entry.metadata() else {
+                continue;
+            };
+            if !metadata.is_file() {
+                continue;
+            }
+            let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+            if entry.file_name().to_string_lossy().starts_with(TMP_PREFIX) {
+                if now
+                    .duration_since(modified)
+                    .is_ok_and(|age| age > STALE_TMP_AGE)
+                {
+                    let _ = fs::remove_file(entry.path());
+                }
+                continue;
+            }
+            total += metadata.len();
+            files.push((modified, entry.path(), metadata.len()));
+        }
+
+        files.sort();
+        for (_, path, len) in files {
+            if total <= self.max_size {
+                break;
+            }
+            match fs::remove_file(&path) {
+                Ok(()) => total -= len,
+                // evicted by another container
+                Err(err) if err.kind() == io::ErrorKind::NotFound => total -= len,
+                Err(err) => tracing::warn!(?err, ?path, "could not evict cache entry"),
+            }
+        }
+    }
+}
+
+/// Creates a temporary file with a random name in the cache. The pid cannot
+/// tell writers apart, it is the same in the pid namespace of each container.
+fn create_tmp(dir: &Path) -> io::Result<PathBuf> {
+    loop {
+        let tmp = dir.join(format!("{TMP_PREFIX}{:016x}", fastrand::u64(..)));
+        match File::options().write(true).create_new(true).open(&tmp) {
+            Ok(_) => return Ok(tmp),
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/// Returns the digest of a value that only implements [`Hash`], such as the
+/// compatibility hash of an engine
+pub fn hash_digest(value: impl Hash) -> [u8; 32] {
+    let mut hasher = DigestHasher(Sha256::new());
+    value.hash(&mut hasher);
+    hasher.0.finalize().into()
+}
+
+/// Feeds the bytes of a hashed value into a digest
+struct DigestHasher(Sha256);
+
+impl Hasher for DigestHasher {
+    // Only used by Hash implementations that hash their own hashes, the
+    // digest is read from the hasher.
+    fn finish(&self) -> u64 {
+        let digest = self.0.clone().finalize();
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(&digest[..8]);
+        u64::from_le_bytes(bytes)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        self.0.update(bytes);
+    }
+}
+
+/// Executor of a runtime that does not use the cache, which closes the cache
+/// before running the workload
+#[cfg(feature = "wasm-wasmer")]
+#[derive(Clone)]
+pub struct Uncached<E> {
+    executor: E,
+    cache: Option<ModuleCache>,
+}
+
+#[cfg(feature = "wasm-wasmer")]
+impl<E> Uncached<E> {
+    pub fn new(executor: E, cache: Option<ModuleCache>) -> Self {
+        Self { executor, cache }
+    }
+}
+
+#[cfg(feature = "wasm-wasmer")]
+impl<E: Executor + Clone + 'static> Executor for Uncached<E> {
+    fn exec(&self, spec: &Spec) -> Result<(), ExecutorError> {
+        if let Some(cache) = &self.cache {
+            cache.close();
+        }
+        self.executor.exec(spec)
+    }
+
+    fn validate(&self, spec: &Spec) -> Result<(), ExecutorValidationError> {
+        self.executor.validate(spec)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use anyhow::Result;
+    use libcontainer::oci_spec::runtime::SpecBuilder;
+
+    use super::*;
+
+    fn spec_with_annotation(key: &str, value: &str) -> Result<Spec> {
+        Ok(SpecBuilder::default()
+            .annotations(HashMap::from([(key.to_owned(), value.to_owned())]))
+            .build()?)
+    }
+
+    #[test]
+    fn test_enabled() -> Result<()> {
+        assert!(enabled(&spec_with_annotation(CACHE_ANNOTATION, "true")?));
+        assert!(enabled(&spec_with_annotation(
+            PRECOMPILE_ANNOTATION,
+            "true"
+        )?));
+        assert!(!enabled(&spec_with_annotation(CACHE_ANNOTATION, "false")?));
+        assert!(!enabled(&SpecBuilder::default().build()?));
+        assert!(!precompile_requested(&spec_with_annotation(
+            CACHE_ANNOTATION,
+            "true"
+        )?));
+        Ok(())
+    }
+
+    #[test]
+    fn test_key() {
+        let key = ModuleCache::key(&[b"engine", b"1"], b"\0asm");
+        assert_eq!(key.len(), 64);
+        assert_eq!(key, ModuleCache::key(&[b"engine", b"1"], b"\0asm"));
+        assert_ne!(key, ModuleCache::key(&[b"engine", b"2"], b"\0asm"));
+        assert_ne!(key, ModuleCache::key(&[b"engine", b"1"], b"\0asm\x01"));
+        // the parts are delimited
+        assert_ne!(key, ModuleCache::key(&[b"engine1", b""], b"\0asm"));
+        assert_ne!(key, ModuleCache::key(&[b"engine", b"1\0asm"], b""));
+
+        assert_eq!(hash_digest(("engine", 1)), hash_digest(("engine", 1)));
+        assert_ne!(hash_digest(("engine", 1)), hash_digest(("engine", 2)));
+    }
+
+    #[test]
+    fn test_insert_and_get() -> Result<()> {
+        let root = tempfile::tempdir()?;
+        let cache = ModuleCache::open(root.path()).expect("open cache");
+        assert!(cache.get("a").is_none());
+
+        let path = cache.insert("a", |path| fs::write(path, "compiled"))?;
+        assert_eq!(fs::read_to_string(&path)?, "compiled");
+        assert_eq!(cache.get("a"), Some(path));
+
+        let err = cache.insert("b", |_| Err(io::Error::other("compile failed")));
+        assert!(err.is_err());
+        assert!(cache.get("b").is_none());
+        assert_eq!(fs::read_dir(root.path().join(CACHE_DIR))?.count(), 1);
+
+        // closing a clone closes the cache
+        cache.clone().close();
+        assert!(cache.get("a").is_none());
+        assert!(
+            cache
+                .insert("c", |path| fs::write(path, "compiled"))
+                .is_err()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_evict() -> Result<()> {
+        let root = tempfile::tempdir()?;
+        let mut cache = ModuleCache::open(root.path()).expect("open cache");
+        cache.max_size = 8;
+        let dir = root.path().join(CACHE_DIR);
+
+        let stale = dir.join(format!("{TMP_PREFIX}1-c"));
+        fs::write(&stale, "")?;
+        File::options()
+            .write(true)
+            .open(&stale)?
+            .set_modified(SystemTime::now() - STALE_TMP_AGE * 2)?;
+
+        cache.insert("a", |path| fs::write(path, "1234"))?;
+        File::options()
+            .write(true)
+            .open(dir.join("a"))?
+            .set_modified(SystemTime::now() - Duration::from_secs(60))?;
+        cache.insert("b", |path| fs::write(path, "1234"))?;
+        assert!(cache.get("a").is_some());
+        assert!(!stale.exists());
+
+        // b is now the least recently used entry
+        File::options()
+            .write(true)
+            .open(dir.join("b"))?
+            .set_modified(SystemTime::now() - Duration::from_secs(60))?;
+        cache.insert("c", |path| fs::write(path, "1234"))?;
+        assert!(cache.get("a").is_some());
+        assert!(cache.get("b").is_none());
+        assert!(cache.get("c").is_some());
+        Ok(())
+    }
+}
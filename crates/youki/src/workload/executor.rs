@@ -1,59 +1,179 @@
+use std::path::Path;
+
+#[cfg(any(feature = "wasm-wasmedge", feature = "wasm-wasmtime"))]
 use libcontainer::oci_spec::runtime::Spec;
-use libcontainer::workload::{Executor, ExecutorError, ExecutorValidationError};
-
-#[derive(Clone)]
-pub struct DefaultExecutor {}
-
-impl Executor for DefaultExecutor {
-    fn exec(&self, spec: &Spec) -> Result<(), ExecutorError> {
-        #[cfg(feature = "wasm-wasmer")]
-        match super::wasmer::get_executor().exec(spec) {
-            Ok(_) => return Ok(()),
-            Err(ExecutorError::CantHandle(_)) => (),
-            Err(err) => return Err(err),
-        }
-        #[cfg(feature = "wasm-wasmedge")]
-        match super::wasmedge::get_executor().exec(spec) {
-            Ok(_) => return Ok(()),
-            Err(ExecutorError::CantHandle(_)) => (),
-            Err(err) => return Err(err),
-        }
-        #[cfg(feature = "wasm-wasmtime")]
-        match super::wasmtime::get_executor().exec(spec) {
-            Ok(_) => return Ok(()),
-            Err(ExecutorError::CantHandle(_)) => (),
-            Err(err) => return Err(err),
-        }
+use libcontainer::workload::default;
+#[cfg(any(
+    feature = "wasm-wasmer",
+    feature = "wasm-wasmedge",
+    feature = "wasm-wasmtime"
+))]
+use libcontainer::workload::registry::is_wasm_workload;
+use libcontainer::workload::registry::{ExecutorRegistry, any_workload};
 
-        // Leave the default executor as the last option, which executes normal
-        // container workloads.
-        libcontainer::workload::default::get_executor().exec(spec)
+/// Environment variable listing executors by name, separated by commas, that
+/// are tried before the others, e.g. `wasmtime,wasmer`
+pub const EXECUTOR_PRIORITY_ENV: &str = "YOUKI_EXECUTOR_PRIORITY";
+
+/// Returns the registry of the executors youki is built with. The wasm
+/// executors are tried in the order wasmer, wasmedge and wasmtime, unless the
+/// order is changed by [`EXECUTOR_PRIORITY_ENV`] or one is named by the
+/// `run.oci.handler` annotation.
+///
+/// The executors that cache compiled wasm modules keep their cache under
+/// `root_path`, which is opened right away. Without it, or if the cache cannot
+/// be opened, modules are compiled on every start.
+pub fn default_executor(root_path: Option<&Path>) -> ExecutorRegistry {
+    #[cfg(any(feature = "wasm-wasmedge", feature = "wasm-wasmtime"))]
+    let cache = root_path.and_then(super::cache::ModuleCache::open);
+    #[cfg(not(any(feature = "wasm-wasmedge", feature = "wasm-wasmtime")))]
+    let _ = root_path;
+
+    let mut registry = ExecutorRegistry::new();
+    #[cfg(feature = "wasm-wasmer")]
+    {
+        // The wasmer executor does not use the cache, but inherits it
+        #[cfg(any(feature = "wasm-wasmedge", feature = "wasm-wasmtime"))]
+        let executor = super::cache::Uncached::new(super::wasmer::get_executor(), cache.clone());
+        #[cfg(not(any(feature = "wasm-wasmedge", feature = "wasm-wasmtime")))]
+        let executor = super::wasmer::get_executor();
+        registry.register(
+            super::wasmer::EXECUTOR_NAME,
+            Box::new(executor),
+            is_wasm_workload,
+        );
     }
+    #[cfg(feature = "wasm-wasmedge")]
+    registry.register(
+        super::wasmedge::EXECUTOR_NAME,
+        Box::new(super::wasmedge::get_executor(cache.clone())),
+        is_wasm_workload,
+    );
+    #[cfg(feature = "wasm-wasmtime")]
+    registry.register(
+        super::wasmtime::EXECUTOR_NAME,
+        Box::new(super::wasmtime::get_executor(cache.clone())),
+        is_wasm_workload,
+    );
 
-    fn validate(&self, spec: &Spec) -> Result<(), ExecutorValidationError> {
-        #[cfg(feature = "wasm-wasmer")]
-        match super::wasmer::get_executor().validate(spec) {
-            Ok(_) => return Ok(()),
-            Err(ExecutorValidationError::CantHandle(_)) => (),
-            Err(err) => return Err(err),
-        }
-        #[cfg(feature = "wasm-wasmedge")]
-        match super::wasmedge::get_executor().validate(spec) {
-            Ok(_) => return Ok(()),
-            Err(ExecutorValidationError::CantHandle(_)) => (),
-            Err(err) => return Err(err),
+    // Leave the default executor as the last option, which executes normal
+    // container workloads.
+    registry.register(
+        default::EXECUTOR_NAME,
+        default::get_executor(),
+        any_workload,
+    );
+    with_priority(
+        registry,
+        std::env::var(EXECUTOR_PRIORITY_ENV).ok().as_deref(),
+    )
+}
+
+/// Returns the registry of the executors for the container of a bundle. The
+/// cache of compiled wasm modules under `root_path` is only opened if the
+/// spec of the bundle opts in to it, other containers never touch it.
+pub fn bundle_executor(root_path: &Path, bundle: &Path) -> ExecutorRegistry {
+    #[cfg(any(feature = "wasm-wasmedge", feature = "wasm-wasmtime"))]
+    {
+        // An invalid spec is reported when the container is built
+        let cached =
+            Spec::load(bundle.join("config.json")).is_ok_and(|spec| super::cache::enabled(&spec));
+        default_executor(cached.then_some(root_path))
+    }
+    #[cfg(not(any(feature = "wasm-wasmedge", feature = "wasm-wasmtime")))]
+    {
+        let _ = (root_path, bundle);
+        default_executor(None)
+    }
+}
+
+// Orders the executors by a list of names separated by commas
+fn with_priority(registry: ExecutorRegistry, priority: Option<&str>) -> ExecutorRegistry {
+    let Some(priority) = priority else {
+        return registry;
+    };
+    let names: Vec<&str> = priority
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .collect();
+    registry.with_priority(&names)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use anyhow::Result;
+    use libcontainer::oci_spec::runtime::{Spec, SpecBuilder};
+    use libcontainer::workload::registry::OCI_HANDLER_ANNOTATION;
+    use libcontainer::workload::{Executor, ExecutorError, ExecutorValidationError};
+
+    use super::*;
+
+    #[derive(Clone)]
+    struct NamedExecutor;
+
+    impl Executor for NamedExecutor {
+        fn exec(&self, _: &Spec) -> Result<(), ExecutorError> {
+            Ok(())
         }
-        #[cfg(feature = "wasm-wasmtime")]
-        match super::wasmtime::get_executor().validate(spec) {
-            Ok(_) => return Ok(()),
-            Err(ExecutorValidationError::CantHandle(_)) => (),
-            Err(err) => return Err(err),
+
+        fn validate(&self, _: &Spec) -> Result<(), ExecutorValidationError> {
+            Ok(())
         }
+    }
 
-        libcontainer::workload::default::get_executor().validate(spec)
+    #[test]
+    fn test_default_executor_last() {
+        let names = default_executor(None).names();
+        assert_eq!(names.last(), Some(&default::EXECUTOR_NAME));
     }
-}
 
-pub fn default_executor() -> DefaultExecutor {
-    DefaultExecutor {}
+    #[cfg(any(feature = "wasm-wasmedge", feature = "wasm-wasmtime"))]
+    #[test]
+    fn test_bundle_executor_opens_cache_on_demand() -> Result<()> {
+        let root = tempfile::tempdir()?;
+        let bundle = tempfile::tempdir()?;
+        let cache_dir = root.path().join("@wasm-cache");
+
+        SpecBuilder::default()
+            .build()?
+            .save(bundle.path().join("config.json"))?;
+        bundle_executor(root.path(), bundle.path());
+        assert!(!cache_dir.exists());
+
+        SpecBuilder::default()
+            .annotations(HashMap::from([(
+                crate::workload::cache::CACHE_ANNOTATION.to_owned(),
+                "true".to_owned(),
+            )]))
+            .build()?
+            .save(bundle.path().join("config.json"))?;
+        bundle_executor(root.path(), bundle.path());
+        assert!(cache_dir.is_dir());
+        Ok(())
+    }
+
+    #[test]
+    fn test_with_priority() -> Result<()> {
+        let mut registry = ExecutorRegistry::new();
+        registry
+            .register("first", Box::new(NamedExecutor), any_workload)
+            .register("second", Box::new(NamedExecutor), any_workload);
+        let spec = SpecBuilder::default()
+            .annotations(HashMap::from([(
+                OCI_HANDLER_ANNOTATION.to_owned(),
+                "wasm".to_owned(),
+            )]))
+            .build()?;
+        let selected = |registry: &ExecutorRegistry| registry.select(&spec).map(|(name, _)| name);
+
+        let registry = with_priority(registry, None);
+        assert_eq!(selected(&registry), Some("first"));
+        let registry = with_priority(registry, Some(" second, ,unknown"));
+        assert_eq!(registry.names(), vec!["second", "first"]);
+        assert_eq!(selected(&registry), Some("second"));
+        Ok(())
+    }
 }
@@ -1,4 +1,12 @@
+#[cfg(any(feature = "wasm-wasmedge", feature = "wasm-wasmtime"))]
+mod cache;
 pub mod executor;
+#[cfg(any(
+    feature = "wasm-wasmer",
+    feature = "wasm-wasmedge",
+    feature = "wasm-wasmtime"
+))]
+mod wasi;
 #[cfg(feature = "wasm-wasmedge")]
 mod wasmedge;
 #[cfg(feature = "wasm-wasmer")]
@@ -0,0 +1,158 @@
+//! Parts of the WASI environment and the resource limits of a wasm module
+//! that are derived from the spec, shared by the wasm executors. The
+//! executors run inside the container, so paths of the container are paths
+//! of the "host" from the point of view of the wasm runtime.
+use std::path::PathBuf;
+
+use libcontainer::oci_spec::runtime::{Mount, Spec};
+
+/// Size of a page of wasm linear memory
+pub const WASM_PAGE_SIZE: u64 = 64 * 1024;
+/// Maximum number of pages of a 32 bit linear memory
+pub const WASM_MAX_PAGES: u64 = 65536;
+
+/// A directory of the container that is preopened for the module
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Preopen {
+    /// Path of the directory in the container
+    pub host: PathBuf,
+    /// Path under which the module sees the directory
+    pub guest: PathBuf,
+}
+
+impl Preopen {
+    fn new(host: impl Into<PathBuf>, guest: impl Into<PathBuf>) -> Self {
+        Self {
+            host: host.into(),
+            guest: guest.into(),
+        }
+    }
+}
+
+/// Returns the directories to preopen for the module: the root of the
+/// container, the destinations of bind mounted directories and the working
+/// directory of the process as `.`.
+pub fn preopens(spec: &Spec) -> Vec<Preopen> {
+    let mut preopens = vec![Preopen::new("/", "/")];
+
+    // bind mounts of single files cannot be preopened, they are still
+    // reachable through the root of the container
+    let binds = spec
+        .mounts()
+        .iter()
+        .flatten()
+        .filter(|m| is_bind(m) && m.destination().is_dir())
+        .map(|m| Preopen::new(m.destination(), m.destination()));
+    for preopen in binds {
+        if !preopens.contains(&preopen) {
+            preopens.push(preopen);
+        }
+    }
+
+    if let Some(process) = spec.process() {
+        preopens.push(Preopen::new(process.cwd(), "."));
+    }
+
+    preopens
+}
+
+fn is_bind(mount: &Mount) -> bool {
+    mount.typ().as_deref() == Some("bind")
+        || mount
+            .options()
+            .iter()
+            .flatten()
+            .any(|o| o == "bind" || o == "rbind")
+}
+
+/// Returns the memory limit of the container in bytes, if one is set
+pub fn memory_limit(spec: &Spec) -> Option<u64> {
+    spec.linux()
+        .as_ref()
+        .and_then(|l| l.resources().as_ref())
+        .and_then(|r| r.memory().as_ref())
+        .and_then(|m| m.limit())
+        .filter(|limit| *limit > 0)
+        .map(|limit| limit as u64)
+}
+
+/// Returns the number of pages of linear memory that fit in `limit` bytes
+pub fn memory_pages(limit: u64) -> u32 {
+    (limit / WASM_PAGE_SIZE).min(WASM_MAX_PAGES) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use libcontainer::oci_spec::runtime::{
+        LinuxBuilder, LinuxMemoryBuilder, LinuxResourcesBuilder, MountBuilder, ProcessBuilder,
+        SpecBuilder,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_preopens() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let file = dir.path().join("file");
+        std::fs::write(&file, "")?;
+
+        let spec = SpecBuilder::default()
+            .process(ProcessBuilder::default().cwd("/app").build()?)
+            .mounts(vec![
+                MountBuilder::default()
+                    .destination(dir.path())
+                    .typ("bind")
+                    .build()?,
+                MountBuilder::default()
+                    .destination(dir.path())
+                    .options(vec!["rbind".to_owned()])
+                    .build()?,
+                MountBuilder::default()
+                    .destination(&file)
+                    .typ("bind")
+                    .build()?,
+                MountBuilder::default()
+                    .destination("/proc")
+                    .typ("proc")
+                    .build()?,
+            ])
+            .build()?;
+
+        assert_eq!(
+            preopens(&spec),
+            vec![
+                Preopen::new("/", "/"),
+                Preopen::new(dir.path(), dir.path()),
+                Preopen::new("/app", "."),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_memory_limit() -> Result<()> {
+        let spec_with_limit = |limit| -> Result<Spec> {
+            Ok(SpecBuilder::default()
+                .linux(
+                    LinuxBuilder::default()
+                        .resources(
+                            LinuxResourcesBuilder::default()
+                                .memory(LinuxMemoryBuilder::default().limit(limit).build()?)
+                                .build()?,
+                        )
+                        .build()?,
+                )
+                .build()?)
+        };
+
+        assert_eq!(memory_limit(&spec_with_limit(1 << 20)?), Some(1 << 20));
+        assert_eq!(memory_limit(&spec_with_limit(-1)?), None);
+        assert_eq!(memory_limit(&SpecBuilder::default().build()?), None);
+
+        assert_eq!(memory_pages(1 << 20), 16);
+        assert_eq!(memory_pages(WASM_PAGE_SIZE - 1), 0);
+        assert_eq!(memory_pages(u64::MAX), WASM_MAX_PAGES as u32);
+        Ok(())
+    }
+}
@@ -1,22 +1,28 @@
 use std::collections::HashMap;
+use std::path::PathBuf;
 
 use libcontainer::oci_spec::runtime::Spec;
 use libcontainer::workload::{Executor, ExecutorError, ExecutorValidationError};
-use wasmedge_sdk::error::{CoreError, CoreExecutionError, WasmEdgeError};
+use wasmedge_sdk::config::{
+    CommonConfigOptions, CompilerConfigOptions, ConfigBuilder, RuntimeConfigOptions,
+};
+use wasmedge_sdk::error::{CoreCommonError, CoreError, CoreExecutionError, WasmEdgeError};
+use wasmedge_sdk::utils::CoreVersion;
 use wasmedge_sdk::wasi::WasiModule;
-use wasmedge_sdk::{Module, Store, Vm, params};
+use wasmedge_sdk::{Compiler, CompilerOutputFormat, Module, Store, Vm, params};
 
-const EXECUTOR_NAME: &str = "wasmedge";
+use super::cache::{self, ModuleCache};
+use super::wasi;
+
+pub const EXECUTOR_NAME: &str = "wasmedge";
 
 #[derive(Clone)]
-pub struct WasmedgeExecutor {}
+pub struct WasmedgeExecutor {
+    cache: Option<ModuleCache>,
+}
 
 impl Executor for WasmedgeExecutor {
     fn exec(&self, spec: &Spec) -> Result<(), ExecutorError> {
-        if !can_handle(spec) {
-            return Err(ExecutorError::CantHandle(EXECUTOR_NAME));
-        }
-
         tracing::debug!("executing workload with wasmedge handler");
 
         // parse wasi parameters
@@ -26,68 +32,163 @@ impl Executor for WasmedgeExecutor {
             cmd = stripped.to_string();
         }
         let envs = env_to_wasi(spec);
+        let preopens: Vec<String> = wasi::preopens(spec)
+            .iter()
+            .map(|p| format!("{}:{}", p.guest.display(), p.host.display()))
+            .collect();
 
         // initialize the wasi module with the parsed parameters
         let mut wasi_module = WasiModule::create(
             Some(args.iter().map(|s| s as &str).collect()),
             Some(envs.iter().map(|s| s as &str).collect()),
-            None,
+            Some(preopens.iter().map(|s| s as &str).collect()),
         )
         .map_err(|err| ExecutorError::Other(format!("failed to create wasi module: {:?}", err)))?;
 
         let mut instances = HashMap::new();
         instances.insert(wasi_module.name().to_string(), wasi_module.as_mut());
 
+        let mut config = ConfigBuilder::new(CommonConfigOptions::default());
+        if let Some(limit) = wasi::memory_limit(spec) {
+            config = config.with_runtime_config(
+                RuntimeConfigOptions::default().max_memory_pages(wasi::memory_pages(limit)),
+            );
+        }
+        let config = config
+            .build()
+            .map_err(|err| ExecutorError::Other(format!("failed to create config: {}", err)))?;
+
         // create a vm
         let mut vm = Vm::new(
-            Store::new(None, instances)
+            Store::new(Some(&config), instances)
                 .map_err(|err| ExecutorError::Other(format!("failed to create store: {}", err)))?,
         );
 
-        let module = Module::from_file(None, cmd).map_err(|err| {
+        let cache = self.cache.as_ref().filter(|_| cache::enabled(spec));
+        let module = match cache.and_then(|cache| compile(cache, &cmd)) {
+            Some(compiled) => Module::from_file(None, compiled),
+            None => Module::from_file(None, cmd),
+        };
+        // The guest must not reach the cache through the inherited descriptor
+        if let Some(cache) = &self.cache {
+            cache.close();
+        }
+        let module = module.map_err(|err| {
             ExecutorError::Other(format!("failed to load wasm module: {:?}", err))
         })?;
         vm.register_module(Some("main"), module).map_err(|err| {
             ExecutorError::Other(format!("failed to register wasm module: {:?}", err))
         })?;
 
-        vm.run_func(Some("main"), "_start", params!())
-            .map_err(|err| match *err {
+        if let Err(err) = vm.run_func(Some("main"), "_start", params!()) {
+            match *err {
+                // The module called proc_exit, the wasi module keeps its exit code.
+                WasmEdgeError::Core(CoreError::Common(CoreCommonError::Terminated)) => {}
                 // This case indicates that the wasm code panicked.
                 WasmEdgeError::Core(CoreError::Execution(CoreExecutionError::Unreachable)) => {
                     std::process::exit(101)
                 }
-                _ => ExecutorError::Execution(err),
-            })?;
+                _ => return Err(ExecutorError::Execution(err)),
+            }
+        }
+        drop(vm);
 
-        std::process::exit(0)
+        std::process::exit(wasi_module.exit_code() as i32)
     }
 
     fn validate(&self, spec: &Spec) -> Result<(), ExecutorValidationError> {
-        if !can_handle(spec) {
-            return Err(ExecutorValidationError::CantHandle(EXECUTOR_NAME));
+        // Compile the module while the container is created, so that it is
+        // loaded from the cache when the container starts.
+        if let Some(cache) = &self.cache
+            && cache::precompile_requested(spec)
+            && let Some(module) = get_args(spec).first()
+        {
+            let module = module
+                .strip_prefix(std::path::MAIN_SEPARATOR)
+                .unwrap_or(module);
+            compile(cache, module);
         }
 
         Ok(())
     }
 }
 
-pub fn get_executor() -> WasmedgeExecutor {
-    WasmedgeExecutor {}
+pub fn get_executor(cache: Option<ModuleCache>) -> WasmedgeExecutor {
+    WasmedgeExecutor { cache }
 }
 
-fn can_handle(spec: &Spec) -> bool {
-    if let Some(annotations) = spec.annotations() {
-        if let Some(handler) = annotations.get("run.oci.handler") {
-            return handler == "wasm";
+/// Returns the path of the AOT compiled module in the cache, compiling it if
+/// it is not cached yet. Returns None if the module could not be compiled or
+/// cached, it is then run by the interpreter.
+fn compile(cache: &ModuleCache, module: &str) -> Option<PathBuf> {
+    let bytes = match std::fs::read(module) {
+        Ok(bytes) => bytes,
+        Err(err) => {
+            tracing::warn!(?err, module, "could not read wasm module");
+            return None;
         }
+    };
+
+    // Native code is compiled for the CPU of the host, its features are part
+    // of the key in case the cache is moved or the CPU changes.
+    let version = CoreVersion::version_string();
+    let key = ModuleCache::key(
+        &[
+            version.as_bytes(),
+            std::env::consts::ARCH.as_bytes(),
+            cpu_features().as_bytes(),
+        ],
+        &bytes,
+    );
+    if let Some(path) = cache.get(&key) {
+        return Some(path);
+    }
 
-        if let Some(variant) = annotations.get("module.wasm.image/variant") {
-            return variant == "compat";
+    // The universal wasm format keeps the original module next to the
+    // compiled code, so that WasmEdge can fall back to interpreting it.
+    let result = cache.insert(&key, |path| {
+        let config = ConfigBuilder::new(CommonConfigOptions::default())
+            .with_compiler_config(
+                CompilerConfigOptions::default().out_format(CompilerOutputFormat::Wasm),
+            )
+            .build()
+            .map_err(std::io::Error::other)?;
+        let compiler = Compiler::new(Some(&config)).map_err(std::io::Error::other)?;
+        let (Some(dir), Some(name)) = (path.parent(), path.file_name()) else {
+            return Err(std::io::Error::other("invalid cache path"));
+        };
+        let out = compiler
+            .compile_from_bytes(&bytes, name.to_string_lossy(), dir)
+            .map_err(std::io::Error::other)?;
+        std::fs::rename(out, path)
+    });
+
+    match result {
+        Ok(path) => Some(path),
+        Err(err) => {
+            tracing::warn!(?err, module, "could not compile wasm module ahead of time");
+            None
         }
     }
+}
 
-    false
+/// Returns the features of the CPU as listed by the kernel, empty if they
+/// cannot be read
+fn cpu_features() -> String {
+    let cpuinfo = match std::fs::read_to_string("/proc/cpuinfo") {
+        Ok(cpuinfo) => cpuinfo,
+        Err(err) => {
+            tracing::warn!(?err, "could not read cpu features");
+            return String::new();
+        }
+    };
+    // "flags" on x86, "Features" on arm, the first processor is enough
+    cpuinfo
+        .lines()
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| matches!(name.trim(), "flags" | "Features"))
+        .map(|(_, features)| features.trim().to_owned())
+        .unwrap_or_default()
 }
 
 fn get_args(spec: &Spec) -> &[String] {
@@ -1,23 +1,27 @@
 use std::error::Error;
+use std::ptr::NonNull;
 use std::sync::Arc;
 
 use libcontainer::oci_spec::runtime::Spec;
 use libcontainer::workload::{EMPTY, Executor, ExecutorError, ExecutorValidationError};
-use wasmer::{Instance, Module, Store};
+use wasmer::sys::vm::{VMConfig, VMMemory, VMMemoryDefinition, VMTable, VMTableDefinition};
+use wasmer::sys::{BaseTunables, NativeEngineExt, Target, Tunables};
+use wasmer::{
+    Engine, Instance, MemoryError, MemoryStyle, MemoryType, Module, Pages, Store, TableStyle,
+    TableType,
+};
 use wasmer_wasix::runtime::task_manager::tokio::TokioTaskManager;
-use wasmer_wasix::{PluggableRuntime, WasiEnv, WasiError};
+use wasmer_wasix::{PluggableRuntime, WasiEnv, WasiError, default_fs_backing};
 
-const EXECUTOR_NAME: &str = "wasmer";
+use super::wasi;
+
+pub const EXECUTOR_NAME: &str = "wasmer";
 
 #[derive(Clone)]
 pub struct WasmerExecutor {}
 
 impl Executor for WasmerExecutor {
     fn exec(&self, spec: &Spec) -> Result<(), ExecutorError> {
-        if !can_handle(spec) {
-            return Err(ExecutorError::CantHandle(EXECUTOR_NAME));
-        }
-
         tracing::debug!("executing workload with wasmer handler");
         let process = spec.process().as_ref();
 
@@ -57,15 +61,32 @@ impl Executor for WasmerExecutor {
         let task_manager = TokioTaskManager::new(rt);
         let runtime = PluggableRuntime::new(Arc::new(task_manager));
 
-        let mut store = Store::default();
+        let mut engine = Engine::default();
+        if let Some(limit) = wasi::memory_limit(spec) {
+            let base = BaseTunables::for_target(&Target::default());
+            engine.set_tunables(LimitingTunables::new(base, wasi::memory_pages(limit)));
+        }
+        let mut store = Store::new(engine);
         let module = Module::from_file(&store, &args[0]).map_err(|err| {
             tracing::error!(err = ?err, file = ?args[0], "could not load wasm module from file");
             ExecutorError::Other("could not load wasm module from file".to_string())
         })?;
 
-        let mut wasi_env = WasiEnv::builder("youki_wasm_app")
+        let mut builder = WasiEnv::builder("youki_wasm_app")
             .args(args.iter().skip(1))
             .envs(env)
+            .fs(default_fs_backing());
+        for preopen in wasi::preopens(spec) {
+            let guest = preopen.guest.to_string_lossy();
+            builder = builder.map_dir(&guest, &preopen.host).map_err(|err| {
+                ExecutorError::Other(format!("could not preopen {:?}: {}", preopen.host, err))
+            })?;
+        }
+        if let Some(process) = spec.process() {
+            builder = builder.current_dir(process.cwd());
+        }
+
+        let mut wasi_env = builder
             .runtime(Arc::new(runtime))
             .finalize(&mut store)
             .map_err(|err| ExecutorError::Other(format!("could not create wasi env: {}", err)))?;
@@ -105,11 +126,7 @@ impl Executor for WasmerExecutor {
         std::process::exit(0)
     }
 
-    fn validate(&self, spec: &Spec) -> Result<(), ExecutorValidationError> {
-        if !can_handle(spec) {
-            return Err(ExecutorValidationError::CantHandle(EXECUTOR_NAME));
-        }
-
+    fn validate(&self, _: &Spec) -> Result<(), ExecutorValidationError> {
         Ok(())
     }
 }
@@ -118,63 +135,90 @@ pub fn get_executor() -> WasmerExecutor {
     WasmerExecutor {}
 }
 
-fn can_handle(spec: &Spec) -> bool {
-    if let Some(annotations) = spec.annotations() {
-        if let Some(handler) = annotations.get("run.oci.handler") {
-            return handler == "wasm";
+/// Tunables that cap the linear memories of a module at a number of pages.
+/// Memories declaring a larger minimum fail to be created, memories without
+/// a maximum or with a larger one get the limit as maximum.
+struct LimitingTunables<T: Tunables> {
+    limit: Pages,
+    base: T,
+}
+
+impl<T: Tunables> LimitingTunables<T> {
+    fn new(base: T, limit: u32) -> Self {
+        Self {
+            limit: Pages(limit),
+            base,
         }
+    }
 
-        if let Some(variant) = annotations.get("module.wasm.image/variant") {
-            return variant == "compat";
+    fn adjust_memory(&self, requested: &MemoryType) -> MemoryType {
+        let mut adjusted = *requested;
+        if requested.maximum.is_none_or(|max| max > self.limit) {
+            adjusted.maximum = Some(self.limit);
         }
+        adjusted
     }
 
-    false
+    fn validate_memory(&self, ty: &MemoryType) -> Result<(), MemoryError> {
+        if ty.minimum > self.limit {
+            return Err(MemoryError::Generic(format!(
+                "minimum of {} pages exceeds the memory limit of {} pages",
+                ty.minimum.0, self.limit.0
+            )));
+        }
+        Ok(())
+    }
 }
 
-#[cfg(test)]
-mod tests {
-    use std::collections::HashMap;
-
-    use anyhow::{Context, Result};
-    use libcontainer::oci_spec::runtime::SpecBuilder;
-
-    use super::*;
-
-    #[test]
-    fn test_can_handle_oci_handler() -> Result<()> {
-        let mut annotations = HashMap::with_capacity(1);
-        annotations.insert("run.oci.handler".to_owned(), "wasm".to_owned());
-        let spec = SpecBuilder::default()
-            .annotations(annotations)
-            .build()
-            .context("build spec")?;
-
-        assert!(can_handle(&spec));
-
-        Ok(())
+impl<T: Tunables> Tunables for LimitingTunables<T> {
+    fn memory_style(&self, memory: &MemoryType) -> MemoryStyle {
+        self.base.memory_style(&self.adjust_memory(memory))
     }
 
-    #[test]
-    fn test_can_handle_compat_wasm_spec() -> Result<()> {
-        let mut annotations = HashMap::with_capacity(1);
-        annotations.insert("module.wasm.image/variant".to_owned(), "compat".to_owned());
-        let spec = SpecBuilder::default()
-            .annotations(annotations)
-            .build()
-            .context("build spec")?;
+    fn table_style(&self, table: &TableType) -> TableStyle {
+        self.base.table_style(table)
+    }
 
-        assert!(can_handle(&spec));
+    fn create_host_memory(
+        &self,
+        ty: &MemoryType,
+        style: &MemoryStyle,
+    ) -> Result<VMMemory, MemoryError> {
+        let adjusted = self.adjust_memory(ty);
+        self.validate_memory(&adjusted)?;
+        self.base.create_host_memory(&adjusted, style)
+    }
 
-        Ok(())
+    unsafe fn create_vm_memory(
+        &self,
+        ty: &MemoryType,
+        style: &MemoryStyle,
+        vm_definition_location: NonNull<VMMemoryDefinition>,
+    ) -> Result<VMMemory, MemoryError> {
+        let adjusted = self.adjust_memory(ty);
+        self.validate_memory(&adjusted)?;
+        // SAFETY: the caller upholds the contract of the base tunables
+        unsafe {
+            self.base
+                .create_vm_memory(&adjusted, style, vm_definition_location)
+        }
     }
 
-    #[test]
-    fn test_can_handle_no_execute() -> Result<()> {
-        let spec = SpecBuilder::default().build().context("build spec")?;
+    fn create_host_table(&self, ty: &TableType, style: &TableStyle) -> Result<VMTable, String> {
+        self.base.create_host_table(ty, style)
+    }
 
-        assert!(!can_handle(&spec));
+    unsafe fn create_vm_table(
+        &self,
+        ty: &TableType,
+        style: &TableStyle,
+        vm_definition_location: NonNull<VMTableDefinition>,
+    ) -> Result<VMTable, String> {
+        // SAFETY: the caller upholds the contract of the base tunables
+        unsafe { self.base.create_vm_table(ty, style, vm_definition_location) }
+    }
 
-        Ok(())
+    fn vmconfig(&self) -> &VMConfig {
+        self.base.vmconfig()
     }
 }
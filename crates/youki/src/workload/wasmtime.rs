@@ -1,20 +1,54 @@
 use libcontainer::oci_spec::runtime::Spec;
 use libcontainer::workload::{EMPTY, Executor, ExecutorError, ExecutorValidationError};
-use wasi_common::I32Exit;
-use wasi_common::sync::{WasiCtxBuilder, add_to_linker};
-use wasmtime::{Engine, Linker, Module, Store};
+use wasmtime::component::{Component, ResourceTable};
+use wasmtime::{Engine, Linker, Module, Store, StoreLimits, StoreLimitsBuilder};
+use wasmtime_wasi::p2::bindings::sync::Command;
+use wasmtime_wasi::{DirPerms, FilePerms, WasiCtxView, WasiView};
 
-const EXECUTOR_NAME: &str = "wasmtime";
+use super::cache::{self, ModuleCache};
+use super::wasi;
+
+pub const EXECUTOR_NAME: &str = "wasmtime";
+
+// Preamble of the binary format, the layer distinguishes core modules from
+// components
+const WASM_MAGIC: &[u8] = b"\0asm";
+const WASM_COMPONENT_LAYER: [u8; 2] = [1, 0];
 
 #[derive(Clone)]
-pub struct WasmtimeExecutor {}
+pub struct WasmtimeExecutor {
+    cache: Option<ModuleCache>,
+}
 
-impl Executor for WasmtimeExecutor {
-    fn exec(&self, spec: &Spec) -> Result<(), ExecutorError> {
-        if !can_handle(spec) {
-            return Err(ExecutorError::CantHandle(EXECUTOR_NAME));
+enum Compiled {
+    Module(Module),
+    Component(Component),
+}
+
+/// Store data of a core module, linked with WASI preview1
+struct ModuleState {
+    wasi: wasi_common::WasiCtx,
+    limits: StoreLimits,
+}
+
+/// Store data of a component, linked with WASI preview2
+struct ComponentState {
+    wasi: wasmtime_wasi::WasiCtx,
+    table: ResourceTable,
+    limits: StoreLimits,
+}
+
+impl WasiView for ComponentState {
+    fn ctx(&mut self) -> WasiCtxView<'_> {
+        WasiCtxView {
+            ctx: &mut self.wasi,
+            table: &mut self.table,
         }
+    }
+}
 
+impl Executor for WasmtimeExecutor {
+    fn exec(&self, spec: &Spec) -> Result<(), ExecutorError> {
         tracing::debug!("executing workload with wasmtime handler");
         let process = spec.process().as_ref();
 
@@ -23,24 +57,7 @@ impl Executor for WasmtimeExecutor {
             .as_ref()
             .and_then(|p| p.args().as_ref())
             .unwrap_or(&EMPTY);
-        if args.is_empty() {
-            tracing::error!("at least one process arg must be specified");
-            return Err(ExecutorError::InvalidArg);
-        }
-
-        if !args[0].ends_with(".wasm") && !args[0].ends_with(".wat") {
-            tracing::error!(
-                "first argument must be a wasm or wat module, but was {}",
-                args[0]
-            );
-            return Err(ExecutorError::InvalidArg);
-        }
-
-        let mut cmd = args[0].clone();
-        let stripped = args[0].strip_prefix(std::path::MAIN_SEPARATOR);
-        if let Some(cmd_stripped) = stripped {
-            cmd = cmd_stripped.to_string();
-        }
+        let cmd = module_file(args)?;
 
         let envs: Vec<(String, String)> = process
             .and_then(|p| p.env().as_ref())
@@ -52,73 +69,326 @@ impl Executor for WasmtimeExecutor {
             })
             .collect();
 
-        let engine = Engine::default();
-        let module = Module::from_file(&engine, &cmd).map_err(|err| {
-            tracing::error!(err = ?err, file = ?cmd, "could not load wasm module from file");
-            ExecutorError::Other("could not load wasm module from file".to_string())
-        })?;
-
-        let mut linker = Linker::new(&engine);
-        add_to_linker(&mut linker, |s| s).map_err(|err| {
-            tracing::error!(err = ?err, "cannot add wasi context to linker");
-            ExecutorError::Other("cannot add wasi context to linker".to_string())
+        let bytes = std::fs::read(&cmd).map_err(|err| {
+            tracing::error!(err = ?err, file = ?cmd, "could not read wasm module from file");
+            ExecutorError::Other("could not read wasm module from file".to_string())
         })?;
 
-        let wasi = WasiCtxBuilder::new()
-            .inherit_stdio()
-            .args(args)
-            .map_err(|err| {
-                ExecutorError::Other(format!("cannot add args to wasi context: {}", err))
-            })?
-            .envs(&envs)
-            .map_err(|err| {
-                ExecutorError::Other(format!("cannot add envs to wasi context: {}", err))
-            })?
-            .build();
-
-        let mut store = Store::new(&engine, wasi);
-
-        let instance = linker.instantiate(&mut store, &module).map_err(|err| {
-            tracing::error!(err = ?err, "wasm module could not be instantiated");
-            ExecutorError::Other("wasm module could not be instantiated".to_string())
-        })?;
-        let start = instance.get_func(&mut store, "_start").ok_or_else(|| {
-            ExecutorError::Other("could not retrieve wasm module main function".into())
-        })?;
+        let mut limits = StoreLimitsBuilder::new();
+        if let Some(limit) = wasi::memory_limit(spec) {
+            let size = wasi::memory_pages(limit) as u64 * wasi::WASM_PAGE_SIZE;
+            limits = limits.memory_size(size as usize);
+        }
+        let limits = limits.build();
 
-        start.call(&mut store, &[], &mut []).map_err(|err| {
-            match err.downcast_ref::<I32Exit>() {
-                Some(exit) => std::process::exit(exit.0),
-                None => ExecutorError::Execution(err.into()),
+        let engine = Engine::default();
+        let cache = self.cache.as_ref().filter(|_| cache::enabled(spec));
+        let compiled = compile(&engine, &bytes, cache);
+        // The guest must not reach the cache through the inherited descriptor
+        if let Some(cache) = &self.cache {
+            cache.close();
+        }
+        let code = match compiled? {
+            Compiled::Module(module) => run_module(&engine, &module, spec, args, &envs, limits)?,
+            Compiled::Component(component) => {
+                run_component(&engine, &component, spec, args, &envs, limits)?
             }
-        })?;
+        };
 
-        std::process::exit(0)
+        std::process::exit(code)
     }
 
     fn validate(&self, spec: &Spec) -> Result<(), ExecutorValidationError> {
-        if !can_handle(spec) {
-            return Err(ExecutorValidationError::CantHandle(EXECUTOR_NAME));
+        // Compile the module while the container is created, so that it is
+        // loaded from the cache when the container starts.
+        if let Some(cache) = &self.cache
+            && cache::precompile_requested(spec)
+        {
+            let args = spec
+                .process()
+                .as_ref()
+                .and_then(|p| p.args().as_ref())
+                .unwrap_or(&EMPTY);
+            let result = module_file(args)
+                .and_then(|cmd| {
+                    std::fs::read(&cmd)
+                        .map_err(|err| ExecutorError::Other(format!("could not read {cmd}: {err}")))
+                })
+                .and_then(|bytes| compile(&Engine::default(), &bytes, Some(cache)));
+            if let Err(err) = result {
+                tracing::warn!(?err, "could not precompile wasm module");
+            }
         }
 
         Ok(())
     }
 }
 
-pub fn get_executor() -> WasmtimeExecutor {
-    WasmtimeExecutor {}
+pub fn get_executor(cache: Option<ModuleCache>) -> WasmtimeExecutor {
+    WasmtimeExecutor { cache }
+}
+
+/// Returns the path of the module given as first argument of the process
+fn module_file(args: &[String]) -> Result<String, ExecutorError> {
+    if args.is_empty() {
+        tracing::error!("at least one process arg must be specified");
+        return Err(ExecutorError::InvalidArg);
+    }
+
+    if !args[0].ends_with(".wasm") && !args[0].ends_with(".wat") {
+        tracing::error!(
+            "first argument must be a wasm or wat module, but was {}",
+            args[0]
+        );
+        return Err(ExecutorError::InvalidArg);
+    }
+
+    let mut cmd = args[0].clone();
+    let stripped = args[0].strip_prefix(std::path::MAIN_SEPARATOR);
+    if let Some(cmd_stripped) = stripped {
+        cmd = cmd_stripped.to_string();
+    }
+    Ok(cmd)
 }
 
-fn can_handle(spec: &Spec) -> bool {
-    if let Some(annotations) = spec.annotations() {
-        if let Some(handler) = annotations.get("run.oci.handler") {
-            return handler == "wasm";
+/// Compiles a module or component, using the cache if given. Compiled code
+/// is added to the cache, failures of the cache only cost the compilation.
+fn compile(
+    engine: &Engine,
+    bytes: &[u8],
+    cache: Option<&ModuleCache>,
+) -> Result<Compiled, ExecutorError> {
+    let component = is_component(bytes);
+    let Some(cache) = cache else {
+        return if component {
+            Component::new(engine, bytes).map(Compiled::Component)
+        } else {
+            Module::new(engine, bytes).map(Compiled::Module)
+        }
+        .map_err(|err| {
+            tracing::error!(err = ?err, "could not compile wasm module");
+            ExecutorError::Other("could not compile wasm module".to_string())
+        });
+    };
+
+    let key = cache_key(engine, bytes);
+    if let Some(path) = cache.get(&key) {
+        // SAFETY: entries are only written by youki from the output of
+        // Engine::precompile_*, by an engine with the same compatibility hash.
+        let loaded = unsafe {
+            if component {
+                Component::deserialize_file(engine, &path).map(Compiled::Component)
+            } else {
+                Module::deserialize_file(engine, &path).map(Compiled::Module)
+            }
+        };
+        match loaded {
+            Ok(compiled) => {
+                tracing::debug!(key, "loaded wasm module from cache");
+                return Ok(compiled);
+            }
+            Err(err) => tracing::warn!(?err, key, "could not load cached wasm module"),
         }
+    }
 
-        if let Some(variant) = annotations.get("module.wasm.image/variant") {
-            return variant == "compat";
+    let precompiled = if component {
+        engine.precompile_component(bytes)
+    } else {
+        engine.precompile_module(bytes)
+    }
+    .map_err(|err| {
+        tracing::error!(err = ?err, "could not compile wasm module");
+        ExecutorError::Other("could not compile wasm module".to_string())
+    })?;
+    if let Err(err) = cache.insert(&key, |path| std::fs::write(path, &precompiled)) {
+        tracing::warn!(?err, key, "could not add wasm module to cache");
+    }
+
+    // SAFETY: the bytes were just produced by the same engine
+    unsafe {
+        if component {
+            Component::deserialize(engine, &precompiled).map(Compiled::Component)
+        } else {
+            Module::deserialize(engine, &precompiled).map(Compiled::Module)
         }
     }
+    .map_err(|err| {
+        tracing::error!(err = ?err, "could not load compiled wasm module");
+        ExecutorError::Other("could not load compiled wasm module".to_string())
+    })
+}
+
+/// Returns the key of a module in the cache. The compatibility hash of the
+/// engine covers its version, configuration and target features.
+fn cache_key(engine: &Engine, bytes: &[u8]) -> String {
+    let engine_digest = cache::hash_digest(engine.precompile_compatibility_hash());
+    ModuleCache::key(&[&engine_digest], bytes)
+}
+
+/// Returns true if `bytes` hold a component, in binary or text format
+fn is_component(bytes: &[u8]) -> bool {
+    match bytes.strip_prefix(WASM_MAGIC) {
+        Some(header) => header.get(2..4) == Some(&WASM_COMPONENT_LAYER),
+        None => String::from_utf8_lossy(bytes)
+            .trim_start()
+            .starts_with("(component"),
+    }
+}
+
+/// Runs a core module with WASI preview1 and returns its exit code
+fn run_module(
+    engine: &Engine,
+    module: &Module,
+    spec: &Spec,
+    args: &[String],
+    envs: &[(String, String)],
+    limits: StoreLimits,
+) -> Result<i32, ExecutorError> {
+    use wasi_common::I32Exit;
+    use wasi_common::sync::{Dir, WasiCtxBuilder, add_to_linker, ambient_authority};
+
+    let mut linker = Linker::new(engine);
+    add_to_linker(&mut linker, |s: &mut ModuleState| &mut s.wasi).map_err(|err| {
+        tracing::error!(err = ?err, "cannot add wasi context to linker");
+        ExecutorError::Other("cannot add wasi context to linker".to_string())
+    })?;
+
+    let mut builder = WasiCtxBuilder::new();
+    for preopen in wasi::preopens(spec) {
+        let dir = Dir::open_ambient_dir(&preopen.host, ambient_authority()).map_err(|err| {
+            ExecutorError::Other(format!(
+                "cannot open directory {:?} to preopen: {}",
+                preopen.host, err
+            ))
+        })?;
+        builder
+            .preopened_dir(dir, &preopen.guest)
+            .map_err(|err| ExecutorError::Other(format!("cannot preopen directory: {}", err)))?;
+    }
+    let wasi = builder
+        .inherit_stdio()
+        .args(args)
+        .map_err(|err| ExecutorError::Other(format!("cannot add args to wasi context: {}", err)))?
+        .envs(envs)
+        .map_err(|err| ExecutorError::Other(format!("cannot add envs to wasi context: {}", err)))?
+        .build();
+
+    let mut store = Store::new(engine, ModuleState { wasi, limits });
+    store.limiter(|s| &mut s.limits);
+
+    let instance = linker.instantiate(&mut store, module).map_err(|err| {
+        tracing::error!(err = ?err, "wasm module could not be instantiated");
+        ExecutorError::Other("wasm module could not be instantiated".to_string())
+    })?;
+    let start = instance.get_func(&mut store, "_start").ok_or_else(|| {
+        ExecutorError::Other("could not retrieve wasm module main function".into())
+    })?;
+
+    match start.call(&mut store, &[], &mut []) {
+        Ok(()) => Ok(0),
+        Err(err) => match err.downcast_ref::<I32Exit>() {
+            Some(exit) => Ok(exit.0),
+            None => Err(ExecutorError::Execution(err.into())),
+        },
+    }
+}
+
+/// Runs a `wasi:cli/command` component with WASI preview2 and returns its
+/// exit code. Sockets are created in the network namespace of the
+/// container, so the component gets the network of the container.
+fn run_component(
+    engine: &Engine,
+    component: &Component,
+    spec: &Spec,
+    args: &[String],
+    envs: &[(String, String)],
+    limits: StoreLimits,
+) -> Result<i32, ExecutorError> {
+    use wasmtime::component::Linker;
+    use wasmtime_wasi::I32Exit;
 
-    false
+    let mut linker = Linker::new(engine);
+    wasmtime_wasi::p2::add_to_linker_sync(&mut linker).map_err(|err| {
+        tracing::error!(err = ?err, "cannot add wasi context to linker");
+        ExecutorError::Other("cannot add wasi context to linker".to_string())
+    })?;
+
+    let mut builder = wasmtime_wasi::WasiCtx::builder();
+    for preopen in wasi::preopens(spec) {
+        builder
+            .preopened_dir(
+                &preopen.host,
+                preopen.guest.to_string_lossy(),
+                DirPerms::all(),
+                FilePerms::all(),
+            )
+            .map_err(|err| {
+                ExecutorError::Other(format!(
+                    "cannot preopen directory {:?}: {}",
+                    preopen.host, err
+                ))
+            })?;
+    }
+    let wasi = builder
+        .inherit_stdio()
+        .inherit_network()
+        .allow_ip_name_lookup(true)
+        .args(args)
+        .envs(envs)
+        .build();
+
+    let mut store = Store::new(
+        engine,
+        ComponentState {
+            wasi,
+            table: ResourceTable::new(),
+            limits,
+        },
+    );
+    store.limiter(|s| &mut s.limits);
+
+    let command = Command::instantiate(&mut store, component, &linker).map_err(|err| {
+        tracing::error!(err = ?err, "wasm component could not be instantiated");
+        ExecutorError::Other("wasm component could not be instantiated".to_string())
+    })?;
+
+    match command.wasi_cli_run().call_run(&mut store) {
+        Ok(Ok(())) => Ok(0),
+        Ok(Err(())) => Ok(1),
+        Err(err) => match err.downcast_ref::<I32Exit>() {
+            Some(exit) => Ok(exit.0),
+            None => Err(ExecutorError::Execution(err.into())),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_component() {
+        assert!(is_component(b"\0asm\x0d\0\x01\0"));
+        assert!(!is_component(b"\0asm\x01\0\0\0"));
+        assert!(is_component(b"\n  (component\n)"));
+        assert!(!is_component(b"(module)"));
+        assert!(!is_component(b""));
+    }
+
+    #[test]
+    fn test_compile_cached() -> anyhow::Result<()> {
+        let root = tempfile::tempdir()?;
+        let cache = ModuleCache::open(root.path()).expect("open cache");
+        let engine = Engine::default();
+        let module = b"(module (func (export \"_start\")))";
+
+        for _ in 0..2 {
+            match compile(&engine, module, Some(&cache))? {
+                Compiled::Module(module) => assert!(module.get_export("_start").is_some()),
+                Compiled::Component(_) => panic!("expected a core module"),
+            }
+        }
+        assert!(cache.get(&cache_key(&engine, module)).is_some());
+        Ok(())
+    }
 }